@@ -70,6 +70,9 @@ const locationConstraintSchema = constraint(
     object({
       country: string,
     }),
+    object({
+      custom_list: string,
+    }),
   ),
 );
 
@@ -303,6 +306,7 @@ const tunnelStateSchema = oneOf(
             'no_matching_bridge_relay',
             'no_wireguard_key',
             'custom_tunnel_host_resultion_error',
+            'custom_tunnel_multiple_peers',
           ),
         }),
       ),
@@ -384,6 +388,24 @@ const keygenEventSchema = oneOf(
   }),
 );
 
+const tunnelStatsSchema = object({
+  tx_bytes: number,
+  rx_bytes: number,
+  last_handshake: maybe(string),
+});
+
+const networkIdentitySchema = object({
+  interface: maybe(string),
+  gateway_mac: maybe(string),
+  ssid: maybe(string),
+});
+
+const trustedNetworkEventSchema = object({
+  network: networkIdentitySchema,
+  rule: maybe(networkIdentitySchema),
+  target_state: enumeration('secured', 'unsecured'),
+});
+
 const daemonEventSchema = oneOf(
   object({
     tunnel_state: tunnelStateSchema,
@@ -400,6 +422,12 @@ const daemonEventSchema = oneOf(
   object({
     app_version_info: appVersionInfoSchema,
   }),
+  object({
+    tunnel_stats: tunnelStatsSchema,
+  }),
+  object({
+    trusted_network: trustedNetworkEventSchema,
+  }),
 );
 
 export class ResponseParseError extends Error {
@@ -52,6 +52,9 @@ function getRelayName(
           );
         }
       }
+    } else if ('customList' in location) {
+      // TRANSLATORS: The selected location label displayed on the main view, when a user selected a custom list of locations.
+      return messages.pgettext('connect-container', 'Custom list');
     }
 
     return 'Unknown';
@@ -29,7 +29,8 @@ export type TunnelParameterError =
   | 'no_matching_relay'
   | 'no_matching_bridge_relay'
   | 'no_wireguard_key'
-  | 'custom_tunnel_host_resultion_error';
+  | 'custom_tunnel_host_resultion_error'
+  | 'custom_tunnel_multiple_peers';
 
 export type ErrorStateCause =
   | {
@@ -97,7 +98,27 @@ export type DaemonEvent =
   | { settings: ISettings }
   | { relayList: IRelayList }
   | { wireguardKey: KeygenEvent }
-  | { appVersionInfo: IAppVersionInfo };
+  | { appVersionInfo: IAppVersionInfo }
+  | { tunnelStats: ITunnelStats }
+  | { trustedNetwork: ITrustedNetworkEvent };
+
+export interface ITunnelStats {
+  txBytes: number;
+  rxBytes: number;
+  lastHandshake?: string;
+}
+
+export interface INetworkIdentity {
+  interface?: string;
+  gatewayMac?: string;
+  ssid?: string;
+}
+
+export interface ITrustedNetworkEvent {
+  network: INetworkIdentity;
+  rule?: INetworkIdentity;
+  targetState: 'secured' | 'unsecured';
+}
 
 export interface ITunnelStateRelayInfo {
   endpoint: ITunnelEndpoint;
@@ -119,7 +140,8 @@ export interface IErrorState {
 export type RelayLocation =
   | { hostname: [string, string, string] }
   | { city: [string, string] }
-  | { country: string };
+  | { country: string }
+  | { customList: string };
 
 export interface IOpenVpnConstraints {
   port: Constraint<number>;
@@ -357,8 +379,10 @@ export function relayLocationComponents(location: RelayLocation): string[] {
     return [location.country];
   } else if ('city' in location) {
     return location.city;
-  } else {
+  } else if ('hostname' in location) {
     return location.hostname;
+  } else {
+    return [location.customList];
   }
 }
 
@@ -181,5 +181,10 @@ function getTunnelParameterMessage(err: TunnelParameterError): string {
         'in-app-notifications',
         'Failed to resolve host of custom tunnel. Consider changing the settings',
       );
+    case 'custom_tunnel_multiple_peers':
+      return messages.pgettext(
+        'in-app-notifications',
+        'Custom WireGuard relays with more than one peer are not supported on Windows. Consider changing the settings',
+      );
   }
 }
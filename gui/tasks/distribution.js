@@ -50,7 +50,6 @@ const config = {
       { from: distAssets('mullvad-setup'), to: '.' },
       { from: distAssets('libtalpid_openvpn_plugin.dylib'), to: '.' },
       { from: distAssets('binaries/x86_64-apple-darwin/openvpn'), to: '.' },
-      { from: distAssets('binaries/x86_64-apple-darwin/sslocal'), to: '.' },
       { from: distAssets('uninstall_macos.sh'), to: './uninstall.sh' },
       { from: distAssets('shell-completions/_mullvad'), to: '.' },
       { from: distAssets('shell-completions/mullvad.fish'), to: '.' },
@@ -94,7 +93,6 @@ const config = {
       { from: root('windows/winnet/bin/x64-Release/winnet.dll'), to: '.' },
       { from: root('windows/winutil/bin/x64-Release/winutil.dll'), to: '.' },
       { from: distAssets('binaries/x86_64-pc-windows-msvc/openvpn.exe'), to: '.' },
-      { from: distAssets('binaries/x86_64-pc-windows-msvc/sslocal.exe'), to: '.' },
       { from: root('build/lib/x86_64-pc-windows-msvc/libwg.dll'), to: '.' },
     ],
   },
@@ -110,7 +108,6 @@ const config = {
       { from: distAssets('mullvad-setup'), to: '.' },
       { from: distAssets('libtalpid_openvpn_plugin.so'), to: '.' },
       { from: distAssets('binaries/x86_64-unknown-linux-gnu/openvpn'), to: '.' },
-      { from: distAssets('binaries/x86_64-unknown-linux-gnu/sslocal'), to: '.' },
       { from: distAssets('linux/mullvad-daemon.conf'), to: '.' },
       { from: distAssets('linux/mullvad-daemon.service'), to: '.' },
     ],
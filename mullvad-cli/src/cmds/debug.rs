@@ -0,0 +1,43 @@
+use crate::{new_rpc_client, Command, Result};
+use clap::value_t_or_exit;
+
+pub struct Debug;
+
+impl Command for Debug {
+    fn name(&self) -> &'static str {
+        "debug"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Debug tools for the daemon")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("log-level")
+                    .about("Change the log level of the daemon until it is restarted")
+                    .arg(
+                        clap::Arg::with_name("level")
+                            .required(true)
+                            .possible_values(&["off", "error", "warn", "info", "debug", "trace"]),
+                    ),
+            )
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        if let Some(log_level_matches) = matches.subcommand_matches("log-level") {
+            let level = value_t_or_exit!(log_level_matches.value_of("level"), String);
+            self.set_log_level(&level)
+        } else {
+            unreachable!("No debug command given");
+        }
+    }
+}
+
+impl Debug {
+    fn set_log_level(&self, level: &str) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        rpc.set_log_level(level)?;
+        println!("Changed log level to {}", level);
+        Ok(())
+    }
+}
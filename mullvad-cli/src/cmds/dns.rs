@@ -0,0 +1,116 @@
+use crate::{new_rpc_client, Command, Result};
+use clap::values_t;
+use mullvad_types::settings::{DnsContentBlockers, DnsOptions};
+use std::net::IpAddr;
+
+pub struct Dns;
+
+impl Command for Dns {
+    fn name(&self) -> &'static str {
+        "dns"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Configure the DNS servers used in the tunnel")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("set")
+                    .about("Set the DNS servers to use")
+                    .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        clap::SubCommand::with_name("default")
+                            .about("Use the DNS server of the relay")
+                            .arg(
+                                clap::Arg::with_name("block ads")
+                                    .long("block-ads")
+                                    .help("Block domains known to be used for ads"),
+                            )
+                            .arg(
+                                clap::Arg::with_name("block trackers")
+                                    .long("block-trackers")
+                                    .help("Block domains known to be used for tracking"),
+                            )
+                            .arg(
+                                clap::Arg::with_name("block malware")
+                                    .long("block-malware")
+                                    .help("Block domains known to be used by malware"),
+                            ),
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("custom")
+                            .about(
+                                "Use custom DNS servers. Servers on the local network are only \
+                                 used if local network sharing is allowed",
+                            )
+                            .arg(
+                                clap::Arg::with_name("servers")
+                                    .required(true)
+                                    .multiple(true)
+                                    .help("The IP addresses of the DNS servers"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("get").about("Display the current DNS settings"),
+            )
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("set", Some(set_matches)) => match set_matches.subcommand() {
+                ("default", Some(default_matches)) => self.set(DnsOptions {
+                    custom: false,
+                    content_blockers: DnsContentBlockers {
+                        block_ads: default_matches.is_present("block ads"),
+                        block_trackers: default_matches.is_present("block trackers"),
+                        block_malware: default_matches.is_present("block malware"),
+                    },
+                    ..self.get_dns_options()?
+                }),
+                ("custom", Some(custom_matches)) => {
+                    let addresses = values_t!(custom_matches.values_of("servers"), IpAddr)
+                        .unwrap_or_else(|e| e.exit());
+                    self.set(DnsOptions {
+                        custom: true,
+                        addresses,
+                        ..self.get_dns_options()?
+                    })
+                }
+                _ => unreachable!("unhandled command"),
+            },
+            ("get", Some(_)) => self.get(),
+            _ => unreachable!("No dns command given"),
+        }
+    }
+}
+
+impl Dns {
+    fn set(&self, dns_options: DnsOptions) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        rpc.set_dns_options(dns_options)?;
+        println!("Updated DNS settings");
+        Ok(())
+    }
+
+    fn get(&self) -> Result<()> {
+        let dns_options = self.get_dns_options()?;
+        if dns_options.custom {
+            let addresses = dns_options
+                .addresses
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            println!("Custom DNS servers: {}", addresses.join(", "));
+        } else {
+            println!("Using the DNS server of the relay");
+            println!("Blocking: {}", dns_options.content_blockers);
+        }
+        Ok(())
+    }
+
+    fn get_dns_options(&self) -> Result<DnsOptions> {
+        let mut rpc = new_rpc_client()?;
+        Ok(rpc.get_settings()?.tunnel_options.dns_options)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use clap::value_t_or_exit;
+use ipnetwork::IpNetwork;
+use std::{fmt, str::FromStr};
+use talpid_types::net::FirewallExemption as Exemption;
+
+pub struct FirewallExemption;
+
+impl Command for FirewallExemption {
+    fn name(&self) -> &'static str {
+        "firewall-exemption"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about(
+                "Manage networks and ports that bypass the tunnel. Traffic to them is also let \
+                 through while the firewall blocks all other traffic",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("add")
+                    .about("Add a firewall exemption")
+                    .arg(
+                        clap::Arg::with_name("network")
+                            .help("Network to exempt, e.g. 192.168.100.0/24 or 2001:db8::1")
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("protocol")
+                            .long("protocol")
+                            .takes_value(true)
+                            .possible_values(&["tcp", "udp"])
+                            .help("Only exempt traffic using this protocol"),
+                    )
+                    .arg(
+                        clap::Arg::with_name("ports")
+                            .long("ports")
+                            .takes_value(true)
+                            .requires("protocol")
+                            .help("Only exempt traffic to this port or port range, e.g. 8000-8080"),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("remove")
+                    .about("Remove a firewall exemption")
+                    .arg(
+                        clap::Arg::with_name("index")
+                            .help("Index of the exemption, as shown by 'list'")
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("clear").about("Remove all firewall exemptions"),
+            )
+            .subcommand(clap::SubCommand::with_name("list").about("List firewall exemptions"))
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("add", Some(matches)) => {
+                let exemption = Exemption {
+                    network: value_t_or_exit!(matches.value_of("network"), IpNetwork),
+                    protocol: optional_value(matches, "protocol"),
+                    ports: optional_value(matches, "ports"),
+                };
+                let mut rpc = new_rpc_client()?;
+                let mut exemptions = rpc.get_settings()?.firewall_exemptions;
+                if !exemptions.contains(&exemption) {
+                    exemptions.push(exemption);
+                }
+                rpc.set_firewall_exemptions(exemptions)?;
+                Ok(())
+            }
+            ("remove", Some(matches)) => {
+                let index = value_t_or_exit!(matches.value_of("index"), usize);
+                let mut rpc = new_rpc_client()?;
+                let mut exemptions = rpc.get_settings()?.firewall_exemptions;
+                if index >= exemptions.len() {
+                    return Err(Error::InvalidCommand(
+                        "There is no exemption with that index",
+                    ));
+                }
+                exemptions.remove(index);
+                rpc.set_firewall_exemptions(exemptions)?;
+                Ok(())
+            }
+            ("clear", Some(_)) => {
+                new_rpc_client()?.set_firewall_exemptions(Vec::new())?;
+                Ok(())
+            }
+            ("list", Some(_)) => {
+                let exemptions = new_rpc_client()?.get_settings()?.firewall_exemptions;
+                println!("Firewall exemptions:");
+                for (index, exemption) in exemptions.iter().enumerate() {
+                    println!("    {}: {}", index, exemption);
+                }
+                Ok(())
+            }
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+fn optional_value<T>(matches: &clap::ArgMatches<'_>, name: &str) -> Option<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    if matches.is_present(name) {
+        Some(value_t_or_exit!(matches.value_of(name), T))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{new_rpc_client, Command, Result};
+use chrono::Local;
+use mullvad_types::connection_history::Session;
+
+pub struct History;
+
+impl Command for History {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Shows past tunnel sessions, most recent first")
+    }
+
+    fn run(&self, _: &clap::ArgMatches<'_>) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let sessions = rpc.get_connection_history()?;
+        if sessions.is_empty() {
+            println!("No sessions have been recorded");
+        }
+        for session in sessions {
+            print_session(&session);
+        }
+        Ok(())
+    }
+}
+
+fn print_session(session: &Session) {
+    let time_format = "%Y-%m-%d %H:%M:%S";
+    let start = session.start.with_timezone(&Local).format(time_format);
+    let end = match session.end {
+        Some(end) => end.with_timezone(&Local).format(time_format).to_string(),
+        // The session is ongoing, or the daemon stopped before it ended
+        None => "...".to_owned(),
+    };
+    println!(
+        "{} - {}: {} ({})",
+        start,
+        end,
+        session
+            .hostname
+            .as_ref()
+            .map(String::as_str)
+            .unwrap_or("custom endpoint"),
+        session.tunnel_type
+    );
+    if let Some(traffic) = session.traffic {
+        println!(
+            "\tSent: {}, received: {}",
+            format_bytes(traffic.tx_bytes),
+            format_bytes(traffic.rx_bytes)
+        );
+    }
+    if let Some(ref reason) = session.disconnect_reason {
+        println!("\tEnded: {}", reason);
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
@@ -0,0 +1,55 @@
+use crate::{new_rpc_client, Command, Result};
+use clap::value_t_or_exit;
+
+pub struct Metrics;
+
+impl Command for Metrics {
+    fn name(&self) -> &'static str {
+        "metrics"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Control the Prometheus metrics exporter of the daemon")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("set")
+                    .about("Enable or disable serving metrics on http://127.0.0.1:9633/metrics")
+                    .arg(
+                        clap::Arg::with_name("policy")
+                            .required(true)
+                            .possible_values(&["on", "off"]),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("get").about("Display whether metrics are served"),
+            )
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        if let Some(set_matches) = matches.subcommand_matches("set") {
+            let enabled = value_t_or_exit!(set_matches.value_of("policy"), String);
+            self.set(enabled == "on")
+        } else if let Some(_matches) = matches.subcommand_matches("get") {
+            self.get()
+        } else {
+            unreachable!("No metrics command given");
+        }
+    }
+}
+
+impl Metrics {
+    fn set(&self, enabled: bool) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        rpc.set_metrics(enabled)?;
+        println!("Changed metrics setting");
+        Ok(())
+    }
+
+    fn get(&self) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let enabled = rpc.get_settings()?.metrics;
+        println!("Metrics: {}", if enabled { "on" } else { "off" });
+        Ok(())
+    }
+}
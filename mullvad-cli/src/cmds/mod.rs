@@ -19,12 +19,32 @@ pub use self::bridge::Bridge;
 mod connect;
 pub use self::connect::Connect;
 
+mod debug;
+pub use self::debug::Debug;
+
 mod disconnect;
 pub use self::disconnect::Disconnect;
 
+mod dns;
+pub use self::dns::Dns;
+
+#[cfg(target_os = "linux")]
+mod firewall_exemption;
+#[cfg(target_os = "linux")]
+pub use self::firewall_exemption::FirewallExemption;
+
+mod history;
+pub use self::history::History;
+
 mod lan;
 pub use self::lan::Lan;
 
+mod metrics;
+pub use self::metrics::Metrics;
+
+mod port_forward;
+pub use self::port_forward::PortForward;
+
 mod reconnect;
 pub use self::reconnect::Reconnect;
 
@@ -34,14 +54,28 @@ pub use self::relay::Relay;
 mod reset;
 pub use self::reset::Reset;
 
+mod schedule;
+pub use self::schedule::Schedule;
+
+mod settings;
+pub use self::settings::Settings;
+
 #[cfg(target_os = "linux")]
 mod split_tunnel;
 #[cfg(target_os = "linux")]
 pub use self::split_tunnel::SplitTunnel;
 
+mod socks_proxy;
+pub use self::socks_proxy::SocksProxy;
+
 mod status;
 pub use self::status::Status;
 
+#[cfg(target_os = "linux")]
+mod trusted_network;
+#[cfg(target_os = "linux")]
+pub use self::trusted_network::TrustedNetwork;
+
 mod tunnel;
 pub use self::tunnel::Tunnel;
 
@@ -57,14 +91,26 @@ pub fn get_commands() -> HashMap<&'static str, Box<dyn Command>> {
         Box::new(BlockWhenDisconnected),
         Box::new(Bridge),
         Box::new(Connect),
+        Box::new(Debug),
         Box::new(Disconnect),
+        Box::new(Dns),
+        #[cfg(target_os = "linux")]
+        Box::new(FirewallExemption),
+        Box::new(History),
+        Box::new(Metrics),
+        Box::new(PortForward),
         Box::new(Reconnect),
         Box::new(Lan),
         Box::new(Relay),
         Box::new(Reset),
+        Box::new(Schedule),
+        Box::new(Settings),
+        Box::new(SocksProxy),
         #[cfg(target_os = "linux")]
         Box::new(SplitTunnel),
         Box::new(Status),
+        #[cfg(target_os = "linux")]
+        Box::new(TrustedNetwork),
         Box::new(Tunnel),
         Box::new(Version),
     ];
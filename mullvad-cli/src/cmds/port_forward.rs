@@ -0,0 +1,69 @@
+use crate::{new_rpc_client, Command, Result};
+use clap::value_t_or_exit;
+
+pub struct PortForward;
+
+impl Command for PortForward {
+    fn name(&self) -> &'static str {
+        "port-forward"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about(
+                "Manage ports that are forwarded to this device over WireGuard. A port is \
+                 forwarded on all relays in a city, and is bound to the current WireGuard key",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("add")
+                    .about("Forward a new port on the relays in a city")
+                    .arg(
+                        clap::Arg::with_name("country")
+                            .help("The two letter country code")
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("city")
+                            .help("The three letter city code")
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("remove")
+                    .about("Stop forwarding a port")
+                    .arg(
+                        clap::Arg::with_name("port")
+                            .help("The forwarded port")
+                            .required(true),
+                    ),
+            )
+            .subcommand(clap::SubCommand::with_name("list").about("List forwarded ports"))
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("add", Some(matches)) => {
+                let country = matches.value_of("country").unwrap().to_lowercase();
+                let city = matches.value_of("city").unwrap().to_lowercase();
+                let port_forward = new_rpc_client()?.add_port_forward(country, city)?;
+                println!("Forwarded port {}", port_forward);
+                Ok(())
+            }
+            ("remove", Some(matches)) => {
+                let port = value_t_or_exit!(matches.value_of("port"), u16);
+                new_rpc_client()?.remove_port_forward(port)?;
+                Ok(())
+            }
+            ("list", Some(_)) => {
+                let port_forwards = new_rpc_client()?.get_port_forwards()?;
+                println!("Forwarded ports:");
+                for port_forward in port_forwards {
+                    println!("    {}", port_forward);
+                }
+                Ok(())
+            }
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
@@ -7,8 +7,10 @@ use std::{
 };
 
 use mullvad_types::{
+    custom_list::CustomListsSettings,
     relay_constraints::{
-        Constraint, OpenVpnConstraints, RelayConstraintsUpdate, RelaySettingsUpdate,
+        Constraint, LocationConstraint, OpenVpnConstraints, Ownership, Providers,
+        RelayConstraintsUpdate, RelaySettings, RelaySettingsUpdate, SelectionMode,
         WireguardConstraints,
     },
     ConnectionConfig, CustomTunnelEndpoint,
@@ -77,6 +79,19 @@ impl Command for Relay {
                                         .takes_value(true)
                                         .multiple(true)
                                         .required(false),
+                                )
+                                .arg(
+                                    clap::Arg::with_name("preshared-key")
+                                        .help("Read a base64 encoded pre-shared key from standard input after the private key")
+                                        .long("preshared-key")
+                                        .required(false),
+                                )
+                                .arg(
+                                    clap::Arg::with_name("keepalive")
+                                        .help("Persistent keepalive interval in seconds")
+                                        .long("keepalive")
+                                        .takes_value(true)
+                                        .required(false),
                                 ),
                             )
                             .subcommand(clap::SubCommand::with_name("openvpn")
@@ -128,6 +143,8 @@ impl Command for Relay {
                             .arg(clap::Arg::with_name("port").required(true).index(2))
                             .arg(
                                 clap::Arg::with_name("transport protocol")
+                                    .help("Transport protocol. For WireGuard, TCP means \
+                                           tunneling the traffic using UDP-over-TCP")
                                     .long("protocol")
                                     .required(false)
                                     .default_value("any")
@@ -143,7 +160,68 @@ impl Command for Relay {
                                     .index(1)
                                     .possible_values(&["any", "wireguard", "openvpn", ]),
                                     )
-                                ),
+                                )
+                    .subcommand(
+                        clap::SubCommand::with_name("entry")
+                            .about("Set the entry relay used for WireGuard multihop")
+                            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                            .subcommand(
+                                location::get_subcommand()
+                                    .about("Enable multihop and set the country or city to \
+                                           select entry relays from")
+                            )
+                            .subcommand(
+                                clap::SubCommand::with_name("off")
+                                    .about("Disable multihop")
+                            )
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("providers")
+                            .about("Set hosting providers to select relays from. Use the 'list' \
+                                   command to show available alternatives.")
+                            .arg(
+                                clap::Arg::with_name("provider")
+                                    .help("The hosting provider(s) to use, or 'any' for no \
+                                           preference.")
+                                    .multiple(true)
+                                    .required(true)
+                            )
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("ownership")
+                            .about("Filters relays based on ownership")
+                            .arg(
+                                clap::Arg::with_name("ownership")
+                                    .help("Servers to select from: 'owned' for Mullvad-owned \
+                                           servers, 'rented' for rented ones, or 'any'.")
+                                    .required(true)
+                                    .index(1)
+                                    .possible_values(&["any", "owned", "rented"]),
+                            )
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("custom-list")
+                            .about("Only select relays from the locations in a custom list")
+                            .arg(
+                                clap::Arg::with_name("name")
+                                    .help("The name of the custom list")
+                                    .required(true)
+                                    .index(1),
+                            )
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("selection-mode")
+                            .about("Set how a relay is picked among the matching relays")
+                            .arg(
+                                clap::Arg::with_name("mode")
+                                    .help("'weighted' picks relays at random based on their \
+                                           weight, 'latency' prefers the relays with the lowest \
+                                           latency, measured while disconnected.")
+                                    .required(true)
+                                    .index(1)
+                                    .possible_values(&["weighted", "latency"]),
+                            )
+                    ),
             )
             .subcommand(clap::SubCommand::with_name("get"))
             .subcommand(
@@ -153,6 +231,44 @@ impl Command for Relay {
                 clap::SubCommand::with_name("update")
                     .about("Update the list of available countries and cities"),
             )
+            .subcommand(
+                clap::SubCommand::with_name("list-custom")
+                    .about("Manage custom lists of locations. Shows all custom lists if no \
+                            subcommand is given")
+                    .subcommand(
+                        clap::SubCommand::with_name("create")
+                            .about("Create an empty custom list")
+                            .arg(custom_list_name_arg()),
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("delete")
+                            .about("Delete a custom list")
+                            .arg(custom_list_name_arg()),
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("rename")
+                            .about("Rename a custom list")
+                            .arg(custom_list_name_arg())
+                            .arg(
+                                clap::Arg::with_name("new-name")
+                                    .help("The new name of the custom list")
+                                    .required(true)
+                                    .index(2),
+                            ),
+                    )
+                    .subcommand(location::with_location_args(
+                        clap::SubCommand::with_name("add")
+                            .about("Add a country, city or relay to a custom list")
+                            .arg(custom_list_name_arg()),
+                        2,
+                    ))
+                    .subcommand(location::with_location_args(
+                        clap::SubCommand::with_name("remove")
+                            .about("Remove a country, city or relay from a custom list")
+                            .arg(custom_list_name_arg()),
+                        2,
+                    )),
+            )
     }
 
     fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
@@ -164,6 +280,8 @@ impl Command for Relay {
             self.list()
         } else if matches.subcommand_matches("update").is_some() {
             self.update()
+        } else if let Some(list_custom_matches) = matches.subcommand_matches("list-custom") {
+            self.list_custom(list_custom_matches)
         } else {
             unreachable!("No relay command given");
         }
@@ -187,6 +305,16 @@ impl Relay {
             self.set_tunnel(tunnel_matches)
         } else if let Some(tunnel_matches) = matches.subcommand_matches("tunnel-protocol") {
             self.set_tunnel_protocol(tunnel_matches)
+        } else if let Some(entry_matches) = matches.subcommand_matches("entry") {
+            self.set_entry(entry_matches)
+        } else if let Some(providers_matches) = matches.subcommand_matches("providers") {
+            self.set_providers(providers_matches)
+        } else if let Some(ownership_matches) = matches.subcommand_matches("ownership") {
+            self.set_ownership(ownership_matches)
+        } else if let Some(custom_list_matches) = matches.subcommand_matches("custom-list") {
+            self.set_custom_list(custom_list_matches)
+        } else if let Some(mode_matches) = matches.subcommand_matches("selection-mode") {
+            self.set_selection_mode(mode_matches)
         } else {
             unreachable!("No set relay command given");
         }
@@ -210,11 +338,11 @@ impl Relay {
             value_t!(matches.value_of("protocol"), TransportProtocol).unwrap_or_else(|e| e.exit());
         CustomTunnelEndpoint::new(
             host,
-            ConnectionConfig::OpenVpn(openvpn::ConnectionConfig {
-                endpoint: Endpoint::new(Ipv4Addr::UNSPECIFIED, port, protocol),
+            ConnectionConfig::OpenVpn(openvpn::ConnectionConfig::new(
+                Endpoint::new(Ipv4Addr::UNSPECIFIED, port, protocol),
                 username,
                 password,
-            }),
+            )),
         )
     }
 
@@ -241,6 +369,21 @@ impl Relay {
         }
         let private_key = Self::validate_wireguard_key(&private_key_str).into();
         let peer_public_key = Self::validate_wireguard_key(&peer_key_str).into();
+        let preshared_key = if matches.is_present("preshared-key") {
+            let mut preshared_key_str = String::new();
+            println!("Reading pre-shared key from standard input");
+            let _ = io::stdin().lock().read_line(&mut preshared_key_str);
+            Some(Self::validate_wireguard_key(&preshared_key_str).into())
+        } else {
+            None
+        };
+        let persistent_keepalive = match value_t!(matches.value_of("keepalive"), u16) {
+            Ok(interval) => Some(interval),
+            Err(e) => match e.kind {
+                clap::ErrorKind::ArgumentNotFound => None,
+                _ => e.exit(),
+            },
+        };
 
 
         CustomTunnelEndpoint::new(
@@ -254,14 +397,19 @@ impl Relay {
                     public_key: peer_public_key,
                     allowed_ips: all_of_the_internet(),
                     endpoint: SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port),
+                    preshared_key,
+                    persistent_keepalive,
                 },
+                additional_peers: vec![],
                 ipv4_gateway,
                 ipv6_gateway,
+                entry_endpoint: None,
+                udp_over_tcp_endpoint: None,
             }),
         )
     }
 
-    fn validate_wireguard_key(key_str: &str) -> [u8; 32] {
+    pub(super) fn validate_wireguard_key(key_str: &str) -> [u8; 32] {
         let key_bytes = base64::decode(key_str.trim()).unwrap_or_else(|e| {
             eprintln!("Failed to decode wireguard key: {}", e);
             std::process::exit(1);
@@ -296,13 +444,10 @@ impl Relay {
 
         match vpn_protocol {
             "wireguard" => {
-                if let Constraint::Only(TransportProtocol::Tcp) = protocol {
-                    return Err(Error::InvalidCommand("WireGuard does not support TCP"));
-                }
                 self.update_constraints(RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
                     location: None,
                     tunnel_protocol: None,
-                    wireguard_constraints: Some(WireguardConstraints { port }),
+                    wireguard_constraints: Some(WireguardConstraints { port, protocol }),
                     ..Default::default()
                 }))
             }
@@ -331,14 +476,167 @@ impl Relay {
         }))
     }
 
+    fn set_entry(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let entry_location = match matches.subcommand() {
+            ("location", Some(location_matches)) => {
+                Some(location::get_constraint(location_matches))
+            }
+            ("off", _) => None,
+            _ => unreachable!("No entry relay command given"),
+        };
+
+        self.update_constraints(RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+            entry_location: Some(entry_location),
+            ..Default::default()
+        }))
+    }
+
+    fn set_providers(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let providers: Vec<String> = matches
+            .values_of("provider")
+            .unwrap()
+            .map(String::from)
+            .collect();
+        let providers = if providers.iter().any(|provider| provider == "any") {
+            if providers.len() > 1 {
+                return Err(Error::InvalidCommand(
+                    "'any' can't be combined with specific providers",
+                ));
+            }
+            Constraint::Any
+        } else {
+            Constraint::Only(Providers::new(providers.into_iter()).unwrap())
+        };
+
+        self.update_constraints(RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+            providers: Some(providers),
+            ..Default::default()
+        }))
+    }
+
+    fn set_ownership(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let ownership = match matches.value_of("ownership").unwrap() {
+            "owned" => Constraint::Only(Ownership::MullvadOwned),
+            "rented" => Constraint::Only(Ownership::Rented),
+            "any" => Constraint::Any,
+            _ => unreachable!(),
+        };
+
+        self.update_constraints(RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+            ownership: Some(ownership),
+            ..Default::default()
+        }))
+    }
+
+    fn set_custom_list(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let name = matches.value_of("name").unwrap();
+        let mut rpc = new_rpc_client()?;
+        let custom_lists = rpc.get_settings()?.custom_lists;
+        let id = match custom_lists.find_by_name(name) {
+            Some(list) => list.id.clone(),
+            None => return Err(Error::InvalidCommand("No custom list with the given name")),
+        };
+
+        self.update_constraints(RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+            location: Some(Constraint::Only(LocationConstraint::CustomList(id))),
+            ..Default::default()
+        }))
+    }
+
+    fn set_selection_mode(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let selection_mode = match matches.value_of("mode").unwrap() {
+            "weighted" => SelectionMode::Weighted,
+            "latency" => SelectionMode::Latency,
+            _ => unreachable!(),
+        };
+
+        self.update_constraints(RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+            selection_mode: Some(selection_mode),
+            ..Default::default()
+        }))
+    }
+
     fn get(&self) -> Result<()> {
         let mut rpc = new_rpc_client()?;
-        let constraints = rpc.get_settings()?.get_relay_settings();
+        let settings = rpc.get_settings()?;
+        let constraints = settings.get_relay_settings();
         println!("Current constraints: {}", constraints);
+        if let RelaySettings::Normal(constraints) = constraints {
+            if let Constraint::Only(LocationConstraint::CustomList(id)) = constraints.location {
+                match settings.custom_lists.get(&id) {
+                    Some(list) => Self::print_custom_list(&list.name, &list.locations),
+                    None => println!("The custom list no longer exists"),
+                }
+            }
+        }
 
         Ok(())
     }
 
+    fn list_custom(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        match matches.subcommand() {
+            ("create", Some(create_matches)) => {
+                let name = create_matches.value_of("name").unwrap();
+                rpc.create_custom_list(name.to_owned())?;
+                println!("Created custom list \"{}\"", name);
+            }
+            ("delete", Some(delete_matches)) => {
+                let name = delete_matches.value_of("name").unwrap();
+                rpc.delete_custom_list(name.to_owned())?;
+                println!("Deleted custom list \"{}\"", name);
+            }
+            ("rename", Some(rename_matches)) => {
+                let name = rename_matches.value_of("name").unwrap();
+                let new_name = rename_matches.value_of("new-name").unwrap();
+                rpc.rename_custom_list(name.to_owned(), new_name.to_owned())?;
+                println!("Renamed custom list \"{}\" to \"{}\"", name, new_name);
+            }
+            ("add", Some(add_matches)) => {
+                let name = add_matches.value_of("name").unwrap();
+                let location = Self::custom_list_location(add_matches)?;
+                rpc.add_custom_list_location(name.to_owned(), location)?;
+                println!("Custom list updated");
+            }
+            ("remove", Some(remove_matches)) => {
+                let name = remove_matches.value_of("name").unwrap();
+                let location = Self::custom_list_location(remove_matches)?;
+                rpc.remove_custom_list_location(name.to_owned(), location)?;
+                println!("Custom list updated");
+            }
+            _ => Self::print_custom_lists(&rpc.get_settings()?.custom_lists),
+        }
+        Ok(())
+    }
+
+    fn custom_list_location(matches: &clap::ArgMatches<'_>) -> Result<LocationConstraint> {
+        match location::get_constraint(matches) {
+            Constraint::Only(location) => Ok(location),
+            Constraint::Any => Err(Error::InvalidCommand(
+                "Custom lists can only contain countries, cities and relays",
+            )),
+        }
+    }
+
+    fn print_custom_lists(custom_lists: &CustomListsSettings) {
+        if custom_lists.lists().is_empty() {
+            println!("No custom lists");
+        }
+        for list in custom_lists.lists() {
+            Self::print_custom_list(&list.name, &list.locations);
+        }
+    }
+
+    fn print_custom_list(name: &str, locations: &[LocationConstraint]) {
+        println!("{}", name);
+        if locations.is_empty() {
+            println!("\t(empty)");
+        }
+        for location in locations {
+            println!("\t{}", location);
+        }
+    }
+
     fn list(&self) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         let mut locations = rpc.get_relay_locations()?;
@@ -392,9 +690,14 @@ impl Relay {
                         (false, true) => "WireGuard",
                         _ => unreachable!("Bug in relay filtering earlier on"),
                     };
+                    let ownership = if relay.owned {
+                        "Mullvad-owned"
+                    } else {
+                        "rented"
+                    };
                     println!(
-                        "\t\t{} ({}) - {}",
-                        relay.hostname, relay.ipv4_addr_in, support_msg
+                        "\t\t{} ({}) - {}, hosted by {} ({})",
+                        relay.hostname, relay.ipv4_addr_in, support_msg, relay.provider, ownership
                     );
                 }
             }
@@ -411,6 +714,13 @@ impl Relay {
 }
 
 
+fn custom_list_name_arg() -> clap::Arg<'static, 'static> {
+    clap::Arg::with_name("name")
+        .help("The name of the custom list")
+        .required(true)
+        .index(1)
+}
+
 fn parse_port_constraint(raw_port: &str) -> Result<Constraint<u16>> {
     match raw_port.to_lowercase().as_str() {
         "any" => Ok(Constraint::Any),
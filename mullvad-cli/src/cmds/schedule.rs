@@ -0,0 +1,114 @@
+use crate::{location, new_rpc_client, Command, Error, Result};
+use clap::value_t_or_exit;
+use mullvad_types::schedule::{CronExpression, ScheduleAction, ScheduleRule};
+
+pub struct Schedule;
+
+impl Command for Schedule {
+    fn name(&self) -> &'static str {
+        "schedule"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Connect or disconnect automatically at given times")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("connect")
+                    .about("Add a rule that connects at the given times")
+                    .arg(create_when_arg())
+                    .subcommand(location::get_subcommand().about(
+                        "Switch to the given country or city before connecting. Use the \
+                         'relay list' command to show available alternatives.",
+                    )),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("disconnect")
+                    .about("Add a rule that disconnects at the given times")
+                    .arg(create_when_arg()),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("remove")
+                    .about("Remove a schedule rule")
+                    .arg(
+                        clap::Arg::with_name("index")
+                            .help("Index of the rule, as shown by 'list'")
+                            .required(true),
+                    ),
+            )
+            .subcommand(clap::SubCommand::with_name("clear").about("Remove all schedule rules"))
+            .subcommand(clap::SubCommand::with_name("list").about("List schedule rules"))
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("connect", Some(matches)) => {
+                let when = value_t_or_exit!(matches.value_of("when"), CronExpression);
+                let rule = match matches.subcommand_matches("location") {
+                    Some(location_matches) => {
+                        ScheduleRule::connect_to(when, location::get_constraint(location_matches))
+                    }
+                    None => ScheduleRule {
+                        when,
+                        action: ScheduleAction::Connect,
+                        relay_settings: None,
+                    },
+                };
+                self.add_rule(rule)
+            }
+            ("disconnect", Some(matches)) => {
+                let when = value_t_or_exit!(matches.value_of("when"), CronExpression);
+                self.add_rule(ScheduleRule {
+                    when,
+                    action: ScheduleAction::Disconnect,
+                    relay_settings: None,
+                })
+            }
+            ("remove", Some(matches)) => {
+                let index = value_t_or_exit!(matches.value_of("index"), usize);
+                let mut rpc = new_rpc_client()?;
+                let mut rules = rpc.get_settings()?.schedule;
+                if index >= rules.len() {
+                    return Err(Error::InvalidCommand("There is no rule with that index"));
+                }
+                rules.remove(index);
+                rpc.set_schedule(rules)?;
+                Ok(())
+            }
+            ("clear", Some(_)) => {
+                new_rpc_client()?.set_schedule(Vec::new())?;
+                Ok(())
+            }
+            ("list", Some(_)) => {
+                let rules = new_rpc_client()?.get_settings()?.schedule;
+                println!("Schedule:");
+                for (index, rule) in rules.iter().enumerate() {
+                    println!("    {}: {}", index, rule);
+                }
+                Ok(())
+            }
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+impl Schedule {
+    fn add_rule(&self, rule: ScheduleRule) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let mut rules = rpc.get_settings()?.schedule;
+        if !rules.contains(&rule) {
+            rules.push(rule);
+        }
+        rpc.set_schedule(rules)?;
+        Ok(())
+    }
+}
+
+fn create_when_arg() -> clap::Arg<'static, 'static> {
+    clap::Arg::with_name("when")
+        .help(
+            "When to apply the rule, as a cron expression with the fields minute, hour, day of \
+             month, month and day of week. E.g. \"0 9 * * 1-5\" for 09:00 on workdays",
+        )
+        .required(true)
+}
@@ -0,0 +1,57 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use std::fs;
+
+pub struct Settings;
+
+impl Command for Settings {
+    fn name(&self) -> &'static str {
+        "settings"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Export or import the daemon settings")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("export")
+                    .about("Write the current settings to a file")
+                    .arg(clap::Arg::with_name("file").required(true))
+                    .arg(
+                        clap::Arg::with_name("include-account")
+                            .long("include-account")
+                            .help("Include the account number in the exported settings"),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("import")
+                    .about(
+                        "Replace the current settings with ones from a file. Settings exported \
+                         by older versions are upgraded. If the file does not contain an account \
+                         number, the current one is kept",
+                    )
+                    .arg(clap::Arg::with_name("file").required(true)),
+            )
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("export", Some(matches)) => {
+                let path = matches.value_of("file").unwrap();
+                let settings =
+                    new_rpc_client()?.export_settings(matches.is_present("include-account"))?;
+                fs::write(path, settings).map_err(|e| Error::FileError(path.to_owned(), e))?;
+                println!("Exported settings to {}", path);
+                Ok(())
+            }
+            ("import", Some(matches)) => {
+                let path = matches.value_of("file").unwrap();
+                let settings =
+                    fs::read_to_string(path).map_err(|e| Error::FileError(path.to_owned(), e))?;
+                new_rpc_client()?.import_settings(settings)?;
+                println!("Imported settings from {}", path);
+                Ok(())
+            }
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{new_rpc_client, Command, Result};
+use clap::value_t_or_exit;
+
+pub struct SocksProxy;
+
+impl Command for SocksProxy {
+    fn name(&self) -> &'static str {
+        "socks-proxy"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Control the SOCKS5 proxy that forwards connections through the tunnel")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("set")
+                    .about("Enable or disable the SOCKS5 proxy on 127.0.0.1:1080")
+                    .arg(
+                        clap::Arg::with_name("policy")
+                            .required(true)
+                            .possible_values(&["on", "off"]),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("get")
+                    .about("Display whether the SOCKS5 proxy is enabled"),
+            )
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        if let Some(set_matches) = matches.subcommand_matches("set") {
+            let enabled = value_t_or_exit!(set_matches.value_of("policy"), String);
+            self.set(enabled == "on")
+        } else if let Some(_matches) = matches.subcommand_matches("get") {
+            self.get()
+        } else {
+            unreachable!("No socks-proxy command given");
+        }
+    }
+}
+
+impl SocksProxy {
+    fn set(&self, enabled: bool) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        rpc.set_socks_proxy(enabled)?;
+        println!("Changed SOCKS5 proxy setting");
+        Ok(())
+    }
+
+    fn get(&self) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let enabled = rpc.get_settings()?.socks_proxy;
+        println!("SOCKS5 proxy: {}", if enabled { "on" } else { "off" });
+        Ok(())
+    }
+}
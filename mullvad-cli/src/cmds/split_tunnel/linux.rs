@@ -1,5 +1,7 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{new_rpc_client, Command, Error, Result};
 use clap::value_t_or_exit;
+use std::{fs, path::PathBuf};
+use talpid_types::net::SplitTunnelMode;
 
 pub struct SplitTunnel;
 
@@ -13,11 +15,15 @@ impl Command for SplitTunnel {
             .about("Manage split tunneling")
             .setting(clap::AppSettings::SubcommandRequiredElseHelp)
             .subcommand(create_pid_subcommand())
+            .subcommand(create_app_subcommand())
+            .subcommand(create_mode_subcommand())
     }
 
     fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
         match matches.subcommand() {
             ("pid", Some(pid_matches)) => Self::handle_pid_cmd(pid_matches),
+            ("app", Some(app_matches)) => Self::handle_app_cmd(app_matches),
+            ("mode", Some(mode_matches)) => Self::handle_mode_cmd(mode_matches),
             _ => unreachable!("unhandled comand"),
         }
     }
@@ -37,6 +43,43 @@ fn create_pid_subcommand() -> clap::App<'static, 'static> {
         .subcommand(clap::SubCommand::with_name("list"))
 }
 
+fn create_app_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("app")
+        .about(
+            "Manage applications to split from the tunnel. All processes started from the \
+             executable are split, including ones started later",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            clap::SubCommand::with_name("add").arg(clap::Arg::with_name("path").required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("remove").arg(clap::Arg::with_name("path").required(true)),
+        )
+        .subcommand(clap::SubCommand::with_name("list"))
+}
+
+fn create_mode_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("mode")
+        .about("Control whether split processes bypass the tunnel or are the only ones using it")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            clap::SubCommand::with_name("set")
+                .about(
+                    "Change the split tunneling mode. In exclude mode, split processes bypass \
+                     the tunnel. In include mode, only split processes use the tunnel",
+                )
+                .arg(
+                    clap::Arg::with_name("mode")
+                        .required(true)
+                        .possible_values(&["exclude", "include"]),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("get").about("Display the current split tunneling mode"),
+        )
+}
+
 impl SplitTunnel {
     fn handle_pid_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
         match matches.subcommand() {
@@ -67,4 +110,53 @@ impl SplitTunnel {
             _ => unreachable!("unhandled command"),
         }
     }
+
+    fn handle_app_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("add", Some(matches)) => {
+                let path = PathBuf::from(matches.value_of("path").unwrap());
+                let path = fs::canonicalize(path)
+                    .map_err(|_| Error::InvalidCommand("The executable does not exist"))?;
+                new_rpc_client()?.add_split_tunnel_app(&path)?;
+                Ok(())
+            }
+            ("remove", Some(matches)) => {
+                let path = PathBuf::from(matches.value_of("path").unwrap());
+                // The daemon stores resolved paths, but the executable may no longer exist
+                let path = fs::canonicalize(&path).unwrap_or(path);
+                new_rpc_client()?.remove_split_tunnel_app(&path)?;
+                Ok(())
+            }
+            ("list", Some(_)) => {
+                let apps = new_rpc_client()?.get_settings()?.split_tunnel.apps;
+                println!("Split applications:");
+
+                for app in apps.iter() {
+                    println!("    {}", app.display());
+                }
+
+                Ok(())
+            }
+            _ => unreachable!("unhandled command"),
+        }
+    }
+    fn handle_mode_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("set", Some(matches)) => {
+                let mode = match matches.value_of("mode").unwrap() {
+                    "include" => SplitTunnelMode::Include,
+                    _ => SplitTunnelMode::Exclude,
+                };
+                new_rpc_client()?.set_split_tunnel_mode(mode)?;
+                println!("Changed split tunneling mode");
+                Ok(())
+            }
+            ("get", Some(_)) => {
+                let mode = new_rpc_client()?.get_settings()?.split_tunnel.mode;
+                println!("Split tunneling mode: {}", mode);
+                Ok(())
+            }
+            _ => unreachable!("unhandled command"),
+        }
+    }
 }
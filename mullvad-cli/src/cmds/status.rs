@@ -1,3 +1,4 @@
+use super::history::format_bytes;
 use crate::{new_rpc_client, Command, Error, Result};
 use futures::{Future, Stream};
 use mullvad_ipc_client::DaemonRpcClient;
@@ -20,6 +21,11 @@ impl Command for Status {
                     .short("l")
                     .help("Prints the current location and IP. Based on GeoIP lookups"),
             )
+            .arg(
+                clap::Arg::with_name("verbose")
+                    .short("v")
+                    .help("Also prints the DNS settings in use"),
+            )
             .subcommand(
                 clap::SubCommand::with_name("listen")
                     .about("Listen for VPN tunnel state changes")
@@ -36,6 +42,9 @@ impl Command for Status {
         let state = rpc.get_state()?;
 
         print_state(&state);
+        if matches.is_present("verbose") {
+            print_dns_settings(&mut rpc)?;
+        }
         if matches.is_present("location") {
             print_location(&mut rpc)?;
         }
@@ -80,6 +89,18 @@ impl Command for Status {
                             println!("{}", key_event);
                         }
                     }
+                    DaemonEvent::TunnelStats(stats) => {
+                        if verbose {
+                            println!(
+                                "Tunnel traffic: {} sent, {} received",
+                                format_bytes(stats.tx_bytes),
+                                format_bytes(stats.rx_bytes)
+                            );
+                        }
+                    }
+                    DaemonEvent::TrustedNetwork(event) => {
+                        println!("{}", event);
+                    }
                 }
             }
         }
@@ -101,6 +122,24 @@ fn print_state(state: &TunnelState) {
     }
 }
 
+fn print_dns_settings(rpc: &mut DaemonRpcClient) -> Result<()> {
+    let dns_options = rpc.get_settings()?.tunnel_options.dns_options;
+    if dns_options.custom {
+        let addresses = dns_options
+            .addresses
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        println!("DNS: custom servers {}", addresses.join(", "));
+    } else {
+        println!(
+            "DNS: relay server, blocking {}",
+            dns_options.content_blockers
+        );
+    }
+    Ok(())
+}
+
 fn print_error_state(error_state: &ErrorState) {
     if !error_state.is_blocking() {
         eprintln!("Mullvad daemon failed to setup firewall rules!");
@@ -142,6 +181,9 @@ fn print_location(rpc: &mut DaemonRpcClient) -> Result<()> {
     if let Some(hostname) = location.hostname {
         println!("Relay: {}", hostname);
     }
+    if let Some(entry_hostname) = location.entry_hostname {
+        println!("Entry relay: {}", entry_hostname);
+    }
     if let Some(ipv4) = location.ipv4 {
         println!("IPv4: {}", ipv4);
     }
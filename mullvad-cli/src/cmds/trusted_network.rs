@@ -0,0 +1,116 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use clap::value_t_or_exit;
+use mullvad_types::trusted_network::TrustedNetwork as TrustedNetworkRule;
+
+pub struct TrustedNetwork;
+
+impl Command for TrustedNetwork {
+    fn name(&self) -> &'static str {
+        "trusted-network"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about(
+                "Manage trusted networks. The tunnel is disconnected automatically on trusted \
+                 networks and connected automatically on all other networks",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("add")
+                    .about(
+                        "Add a trusted network rule. A network matches the rule if it matches \
+                         every given property",
+                    )
+                    .arg(
+                        clap::Arg::with_name("ssid")
+                            .long("ssid")
+                            .takes_value(true)
+                            .help("SSID of the Wi-Fi network"),
+                    )
+                    .arg(
+                        clap::Arg::with_name("gateway-mac")
+                            .long("gateway-mac")
+                            .takes_value(true)
+                            .help("MAC address of the default gateway"),
+                    )
+                    .arg(
+                        clap::Arg::with_name("interface")
+                            .long("interface")
+                            .takes_value(true)
+                            .help("Network interface used by the default route"),
+                    )
+                    .group(
+                        clap::ArgGroup::with_name("properties")
+                            .args(&["ssid", "gateway-mac", "interface"])
+                            .multiple(true)
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("remove")
+                    .about("Remove a trusted network rule")
+                    .arg(
+                        clap::Arg::with_name("index")
+                            .help("Index of the rule, as shown by 'list'")
+                            .required(true),
+                    ),
+            )
+            .subcommand(clap::SubCommand::with_name("clear").about("Remove all trusted networks"))
+            .subcommand(clap::SubCommand::with_name("list").about("List trusted network rules"))
+            .subcommand(
+                clap::SubCommand::with_name("current")
+                    .about("Display the properties of the current network"),
+            )
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("add", Some(matches)) => {
+                let rule = TrustedNetworkRule {
+                    ssid: matches.value_of("ssid").map(str::to_owned),
+                    gateway_mac: matches.value_of("gateway-mac").map(str::to_lowercase),
+                    interface: matches.value_of("interface").map(str::to_owned),
+                };
+                let mut rpc = new_rpc_client()?;
+                let mut rules = rpc.get_settings()?.trusted_networks;
+                if !rules.contains(&rule) {
+                    rules.push(rule);
+                }
+                rpc.set_trusted_networks(rules)?;
+                Ok(())
+            }
+            ("remove", Some(matches)) => {
+                let index = value_t_or_exit!(matches.value_of("index"), usize);
+                let mut rpc = new_rpc_client()?;
+                let mut rules = rpc.get_settings()?.trusted_networks;
+                if index >= rules.len() {
+                    return Err(Error::InvalidCommand("There is no rule with that index"));
+                }
+                rules.remove(index);
+                rpc.set_trusted_networks(rules)?;
+                Ok(())
+            }
+            ("clear", Some(_)) => {
+                new_rpc_client()?.set_trusted_networks(Vec::new())?;
+                Ok(())
+            }
+            ("list", Some(_)) => {
+                let rules = new_rpc_client()?.get_settings()?.trusted_networks;
+                println!("Trusted networks:");
+                for (index, rule) in rules.iter().enumerate() {
+                    println!("    {}: {}", index, rule);
+                }
+                Ok(())
+            }
+            ("current", Some(_)) => {
+                match new_rpc_client()?.get_network_identity()? {
+                    Some(network) => println!("Current network: {}", network),
+                    None => println!("Current network: unknown"),
+                }
+                Ok(())
+            }
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
@@ -1,7 +1,17 @@
-use crate::{new_rpc_client, Command, Result};
+use super::history::format_bytes;
+use crate::{new_rpc_client, Command, Error, Result};
+use chrono::Local;
 use clap::value_t;
+use std::{
+    fs,
+    io::{self, BufRead},
+};
 
-use mullvad_types::settings::TunnelOptions;
+use mullvad_types::{
+    ovpn::OvpnConfig, relay_constraints::RelaySettingsUpdate, settings::TunnelOptions,
+    wg_quick::WgQuickConfig,
+};
+use talpid_types::net::wireguard::PublicKey;
 
 pub struct Tunnel;
 
@@ -17,6 +27,7 @@ impl Command for Tunnel {
             .subcommand(create_openvpn_subcommand())
             .subcommand(create_wireguard_subcommand())
             .subcommand(create_ipv6_subcommand())
+            .subcommand(create_stats_subcommand())
     }
 
     fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
@@ -24,6 +35,7 @@ impl Command for Tunnel {
             ("openvpn", Some(openvpn_matches)) => Self::handle_openvpn_cmd(openvpn_matches),
             ("wireguard", Some(wg_matches)) => Self::handle_wireguard_cmd(wg_matches),
             ("ipv6", Some(ipv6_matches)) => Self::handle_ipv6_cmd(ipv6_matches),
+            ("stats", Some(stats_matches)) => Self::handle_stats_cmd(stats_matches),
             _ => {
                 unreachable!("unhandled comand");
             }
@@ -37,6 +49,28 @@ fn create_wireguard_subcommand() -> clap::App<'static, 'static> {
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .subcommand(create_wireguard_mtu_subcommand())
         .subcommand(create_wireguard_keys_subcommand())
+        .subcommand(
+            clap::SubCommand::with_name("export-config")
+                .about(
+                    "Print a wg-quick configuration for connecting to a relay with the current \
+                     key. Uses the most recently selected relay unless one is given",
+                )
+                .arg(
+                    clap::Arg::with_name("relay")
+                        .long("relay")
+                        .takes_value(true)
+                        .value_name("HOST")
+                        .help("The hostname of the relay to connect to"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("import-config")
+                .about(
+                    "Use a wg-quick configuration as a custom relay. The first DNS server in the \
+                     configuration is used as the tunnel gateway",
+                )
+                .arg(clap::Arg::with_name("file").required(true)),
+        )
 }
 
 fn create_wireguard_mtu_subcommand() -> clap::App<'static, 'static> {
@@ -57,6 +91,31 @@ fn create_wireguard_keys_subcommand() -> clap::App<'static, 'static> {
         .subcommand(clap::SubCommand::with_name("check"))
         .subcommand(clap::SubCommand::with_name("regenerate"))
         .subcommand(create_wireguard_keys_rotation_interval_subcommand())
+        .subcommand(
+            clap::SubCommand::with_name("list")
+                .about("List the keys on your account and the keys that were previously used"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("revoke")
+                .about("Remove a key from your account. The current key cannot be revoked")
+                .arg(
+                    clap::Arg::with_name("key")
+                        .help("The public key to revoke")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("restore")
+                .about(
+                    "Start using a previously used key again. The current key remains on your \
+                     account until it is revoked",
+                )
+                .arg(
+                    clap::Arg::with_name("key")
+                        .help("The public key to restore")
+                        .required(true),
+                ),
+        )
 }
 
 fn create_wireguard_keys_rotation_interval_subcommand() -> clap::App<'static, 'static> {
@@ -76,6 +135,21 @@ fn create_openvpn_subcommand() -> clap::App<'static, 'static> {
         .about("Manage options for OpenVPN tunnels")
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .subcommand(create_openvpn_mssfix_subcommand())
+        .subcommand(
+            clap::SubCommand::with_name("import-config")
+                .about(
+                    "Use an .ovpn file with inline certificates and keys as a custom relay. If \
+                     the server requires a username and password, the password is read from \
+                     standard input",
+                )
+                .arg(clap::Arg::with_name("file").required(true))
+                .arg(
+                    clap::Arg::with_name("username")
+                        .help("Username for servers that use auth-user-pass")
+                        .long("username")
+                        .takes_value(true),
+                ),
+        )
 }
 
 fn create_openvpn_mssfix_subcommand() -> clap::App<'static, 'static> {
@@ -103,10 +177,34 @@ fn create_ipv6_subcommand() -> clap::App<'static, 'static> {
         )
 }
 
+fn create_stats_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("stats")
+        .about("Show traffic statistics of the current tunnel")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::SubCommand::with_name("get"))
+        .subcommand(
+            clap::SubCommand::with_name("events")
+                .about(
+                    "Configure periodic broadcasting of the statistics to daemon event listeners",
+                )
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(clap::SubCommand::with_name("get"))
+                .subcommand(
+                    clap::SubCommand::with_name("set").arg(
+                        clap::Arg::with_name("enable")
+                            .required(true)
+                            .takes_value(true)
+                            .possible_values(&["on", "off"]),
+                    ),
+                ),
+        )
+}
+
 impl Tunnel {
     fn handle_openvpn_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
         match matches.subcommand() {
             ("mssfix", Some(mssfix_matches)) => Self::handle_openvpn_mssfix_cmd(mssfix_matches),
+            ("import-config", Some(matches)) => Self::process_openvpn_import_config(matches),
             _ => unreachable!("unhandled command"),
         }
     }
@@ -140,9 +238,15 @@ impl Tunnel {
                     ("reset", _) => Self::process_wireguard_rotation_interval_reset(),
                     _ => unreachable!("unhandled command"),
                 },
+                ("list", _) => Self::process_wireguard_key_list(),
+                ("revoke", Some(matches)) => Self::process_wireguard_key_revoke(matches),
+                ("restore", Some(matches)) => Self::process_wireguard_key_restore(matches),
                 _ => unreachable!("unhandled command"),
             },
 
+            ("export-config", Some(matches)) => Self::process_wireguard_export_config(matches),
+            ("import-config", Some(matches)) => Self::process_wireguard_import_config(matches),
+
             _ => unreachable!("unhandled command"),
         }
     }
@@ -175,6 +279,58 @@ impl Tunnel {
         Ok(())
     }
 
+    fn process_wireguard_export_config(matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let hostname = matches.value_of("relay").map(str::to_owned);
+        let config = new_rpc_client()?.get_wireguard_config(hostname)?;
+        print!("{}", config);
+        Ok(())
+    }
+
+    fn process_wireguard_import_config(matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let path = matches.value_of("file").unwrap();
+        let config: WgQuickConfig = fs::read_to_string(path)
+            .map_err(|e| Error::FileError(path.to_owned(), e))?
+            .parse()
+            .map_err(Error::InvalidWireguardConfig)?;
+        let custom_endpoint = config
+            .to_custom_tunnel_endpoint()
+            .map_err(Error::InvalidWireguardConfig)?;
+
+        let mut rpc = new_rpc_client()?;
+        rpc.update_relay_settings(RelaySettingsUpdate::CustomTunnelEndpoint(custom_endpoint))?;
+        if config.mtu.is_some() {
+            rpc.set_wireguard_mtu(config.mtu)?;
+        }
+        println!("Imported WireGuard configuration from {}", path);
+        Ok(())
+    }
+
+    fn process_openvpn_import_config(matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let path = matches.value_of("file").unwrap();
+        let config: OvpnConfig = fs::read_to_string(path)
+            .map_err(|e| Error::FileError(path.to_owned(), e))?
+            .parse()
+            .map_err(Error::InvalidOpenVpnConfig)?;
+
+        let (username, password) = if config.custom_config.auth_user_pass {
+            let username = matches.value_of("username").ok_or(Error::InvalidCommand(
+                "The server requires a username, set it with --username",
+            ))?;
+            let mut password = String::new();
+            println!("Reading password from standard input");
+            let _ = io::stdin().lock().read_line(&mut password);
+            (username.to_owned(), password.trim().to_owned())
+        } else {
+            (String::new(), String::new())
+        };
+
+        let custom_endpoint = config.to_custom_tunnel_endpoint(username, password);
+        let mut rpc = new_rpc_client()?;
+        rpc.update_relay_settings(RelaySettingsUpdate::CustomTunnelEndpoint(custom_endpoint))?;
+        println!("Imported OpenVPN configuration from {}", path);
+        Ok(())
+    }
+
     fn process_wireguard_key_check() -> Result<()> {
         let mut rpc = new_rpc_client()?;
         match rpc.get_wireguard_key()? {
@@ -205,6 +361,45 @@ impl Tunnel {
         Ok(())
     }
 
+    fn process_wireguard_key_list() -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        for key in rpc.list_wireguard_keys()? {
+            println!(
+                "{}{}",
+                key.key,
+                if key.active { " (current key)" } else { "" }
+            );
+            println!(
+                "    Created on : {}",
+                key.created
+                    .map(|created| created.with_timezone(&Local).to_string())
+                    .unwrap_or_else(|| "unknown".to_owned())
+            );
+            println!(
+                "    On account : {}",
+                if key.registered { "yes" } else { "no" }
+            );
+            println!("    Restorable : {}", if key.stored { "yes" } else { "no" });
+        }
+        Ok(())
+    }
+
+    fn process_wireguard_key_revoke(matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let key = super::Relay::validate_wireguard_key(matches.value_of("key").unwrap());
+        let mut rpc = new_rpc_client()?;
+        rpc.revoke_wireguard_key(PublicKey::from(key))?;
+        println!("Revoked key");
+        Ok(())
+    }
+
+    fn process_wireguard_key_restore(matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let key = super::Relay::validate_wireguard_key(matches.value_of("key").unwrap());
+        let mut rpc = new_rpc_client()?;
+        let key = rpc.restore_wireguard_key(PublicKey::from(key))?;
+        println!("Current key is now {}", key.key);
+        Ok(())
+    }
+
     fn process_wireguard_rotation_interval_get() -> Result<()> {
         let tunnel_options = Self::get_tunnel_options()?;
         println!(
@@ -297,4 +492,55 @@ impl Tunnel {
         println!("IPv6 setting has been updated");
         Ok(())
     }
+
+    fn handle_stats_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("get", Some(_)) => Self::process_stats_get(),
+            ("events", Some(matches)) => match matches.subcommand() {
+                ("get", _) => Self::process_stats_events_get(),
+                ("set", Some(matches)) => Self::process_stats_events_set(matches),
+                _ => unreachable!("unhandled command"),
+            },
+            _ => unreachable!("unhandled command"),
+        }
+    }
+
+    fn process_stats_get() -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        match rpc.get_tunnel_stats()? {
+            Some(stats) => {
+                println!("Sent: {}", format_bytes(stats.tx_bytes));
+                println!("Received: {}", format_bytes(stats.rx_bytes));
+                if let Some(last_handshake) = stats.last_handshake {
+                    println!(
+                        "Latest handshake: {}",
+                        last_handshake
+                            .with_timezone(&Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                    );
+                }
+            }
+            None => println!("No tunnel statistics available"),
+        }
+        Ok(())
+    }
+
+    fn process_stats_events_get() -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let enabled = rpc.get_settings()?.tunnel_stats_events;
+        println!(
+            "Tunnel stats events: {}",
+            if enabled { "on" } else { "off" }
+        );
+        Ok(())
+    }
+
+    fn process_stats_events_set(matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let enabled = matches.value_of("enable").unwrap() == "on";
+
+        let mut rpc = new_rpc_client()?;
+        rpc.set_tunnel_stats_events(enabled)?;
+        println!("Tunnel stats events setting has been updated");
+        Ok(())
+    }
 }
@@ -1,25 +1,33 @@
 use mullvad_types::relay_constraints::{Constraint, LocationConstraint};
 
 pub fn get_subcommand() -> clap::App<'static, 'static> {
-    clap::SubCommand::with_name("location")
-        .arg(
-            clap::Arg::with_name("country")
-                .help("The two letter country code, or 'any' for no preference.")
-                .required(true)
-                .index(1)
-                .validator(country_code_validator),
-        )
-        .arg(
-            clap::Arg::with_name("city")
-                .help("The three letter city code")
-                .index(2)
-                .validator(city_code_validator),
-        )
-        .arg(
-            clap::Arg::with_name("hostname")
-                .help("The hostname")
-                .index(3),
-        )
+    with_location_args(clap::SubCommand::with_name("location"), 1)
+}
+
+/// Adds the country, city and hostname arguments to `app`, with the country at the given
+/// positional index.
+pub fn with_location_args(
+    app: clap::App<'static, 'static>,
+    first_index: u64,
+) -> clap::App<'static, 'static> {
+    app.arg(
+        clap::Arg::with_name("country")
+            .help("The two letter country code, or 'any' for no preference.")
+            .required(true)
+            .index(first_index)
+            .validator(country_code_validator),
+    )
+    .arg(
+        clap::Arg::with_name("city")
+            .help("The three letter city code")
+            .index(first_index + 1)
+            .validator(city_code_validator),
+    )
+    .arg(
+        clap::Arg::with_name("hostname")
+            .help("The hostname")
+            .index(first_index + 2),
+    )
 }
 
 pub fn get_constraint(matches: &clap::ArgMatches<'_>) -> Constraint<LocationConstraint> {
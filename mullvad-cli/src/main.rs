@@ -27,6 +27,15 @@ pub enum Error {
     /// The given command is not correct in some way
     #[error(display = "Invalid command: {}", _0)]
     InvalidCommand(&'static str),
+
+    #[error(display = "Failed to access {}", _0)]
+    FileError(String, #[error(source)] io::Error),
+
+    #[error(display = "Invalid WireGuard configuration")]
+    InvalidWireguardConfig(#[error(source)] mullvad_types::wg_quick::Error),
+
+    #[error(display = "Invalid OpenVPN configuration")]
+    InvalidOpenVpnConfig(#[error(source)] mullvad_types::ovpn::Error),
 }
 
 pub fn new_rpc_client() -> Result<DaemonRpcClient> {
@@ -7,7 +7,7 @@ use std::{
     io::{self, Seek, Write},
     path::Path,
 };
-use talpid_types::ErrorExt;
+use talpid_types::{net::wireguard, ErrorExt};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -26,6 +26,8 @@ pub enum Error {
 
 static ACCOUNT_HISTORY_FILE: &str = "account-history.json";
 static ACCOUNT_HISTORY_LIMIT: usize = 3;
+/// Number of previously used WireGuard keys that are kept for each account.
+static KEY_HISTORY_LIMIT: usize = 5;
 
 /// A trivial MRU cache of account data
 pub struct AccountHistory {
@@ -73,10 +75,7 @@ impl AccountHistory {
                 );
                 Self::try_old_format(&mut reader)?
                     .into_iter()
-                    .map(|account| AccountEntry {
-                        account,
-                        wireguard: None,
-                    })
+                    .map(AccountEntry::new)
                     .collect()
             }
             Ok(accounts) => accounts,
@@ -147,11 +146,7 @@ impl AccountHistory {
     /// added.
     pub fn bump_history(&mut self, account: &AccountToken) -> Result<()> {
         if self.get(account)?.is_none() {
-            let new_entry = AccountEntry {
-                account: account.to_string(),
-                wireguard: None,
-            };
-            self.insert(new_entry)?;
+            self.insert(AccountEntry::new(account.to_string()))?;
         }
         Ok(())
     }
@@ -263,4 +258,125 @@ impl AccountHistory {
 pub struct AccountEntry {
     pub account: AccountToken,
     pub wireguard: Option<WireguardData>,
+    /// Keys that were previously used by the account, most recent first.
+    #[serde(default)]
+    pub previous_wireguard: Vec<WireguardData>,
+}
+
+impl AccountEntry {
+    pub fn new(account: AccountToken) -> Self {
+        AccountEntry {
+            account,
+            wireguard: None,
+            previous_wireguard: Vec::new(),
+        }
+    }
+
+    /// Sets the key that is used for tunnels. The key that was used before is moved to the key
+    /// history.
+    pub fn set_wireguard_key(&mut self, data: WireguardData) {
+        let new_key = data.private_key.public_key();
+        self.previous_wireguard
+            .retain(|old_data| old_data.private_key.public_key() != new_key);
+        if let Some(old_data) = self.wireguard.replace(data) {
+            if old_data.private_key.public_key() != new_key {
+                self.previous_wireguard.insert(0, old_data);
+            }
+        }
+        self.previous_wireguard.truncate(KEY_HISTORY_LIMIT);
+    }
+
+    /// Returns the data for a previously used key.
+    pub fn find_previous_wireguard_key(
+        &self,
+        key: &wireguard::PublicKey,
+    ) -> Option<&WireguardData> {
+        self.previous_wireguard
+            .iter()
+            .find(|data| &data.private_key.public_key() == key)
+    }
+
+    /// Removes a previously used key from the key history.
+    pub fn forget_wireguard_key(&mut self, key: &wireguard::PublicKey) {
+        self.previous_wireguard
+            .retain(|data| &data.private_key.public_key() != key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::offset::Utc;
+    use mullvad_types::wireguard::AssociatedAddresses;
+
+    fn new_wireguard_data() -> WireguardData {
+        WireguardData {
+            private_key: wireguard::PrivateKey::new_from_random(),
+            addresses: AssociatedAddresses {
+                ipv4_address: "10.64.0.2/32".parse().unwrap(),
+                ipv6_address: "fc00:bbbb:bbbb:bb01::2/128".parse().unwrap(),
+            },
+            created: Utc::now(),
+        }
+    }
+
+    fn public_keys(data: &[WireguardData]) -> Vec<wireguard::PublicKey> {
+        data.iter()
+            .map(|data| data.private_key.public_key())
+            .collect()
+    }
+
+    #[test]
+    fn test_key_history() {
+        let mut entry = AccountEntry::new("1234".to_owned());
+        let keys: Vec<_> = (0..KEY_HISTORY_LIMIT + 2)
+            .map(|_| new_wireguard_data())
+            .collect();
+        for data in &keys {
+            entry.set_wireguard_key(data.clone());
+        }
+
+        let mut expected = keys.clone();
+        expected.reverse();
+        let active = expected.remove(0);
+        expected.truncate(KEY_HISTORY_LIMIT);
+        assert_eq!(
+            entry
+                .wireguard
+                .as_ref()
+                .map(|data| data.private_key.public_key()),
+            Some(active.private_key.public_key())
+        );
+        assert_eq!(
+            public_keys(&entry.previous_wireguard),
+            public_keys(&expected)
+        );
+
+        // Restoring a previous key swaps it with the active key
+        let restored = expected[1].clone();
+        entry.set_wireguard_key(restored.clone());
+        assert_eq!(
+            entry
+                .wireguard
+                .as_ref()
+                .map(|data| data.private_key.public_key()),
+            Some(restored.private_key.public_key())
+        );
+        assert_eq!(
+            public_keys(&entry.previous_wireguard),
+            vec![
+                active.private_key.public_key(),
+                expected[0].private_key.public_key(),
+                expected[2].private_key.public_key(),
+                expected[3].private_key.public_key(),
+                expected[4].private_key.public_key(),
+            ]
+        );
+
+        entry.forget_wireguard_key(&active.private_key.public_key());
+        assert!(entry
+            .find_previous_wireguard_key(&active.private_key.public_key())
+            .is_none());
+        assert_eq!(entry.previous_wireguard.len(), KEY_HISTORY_LIMIT - 1);
+    }
 }
@@ -0,0 +1,228 @@
+//! Keeps a bounded record of past tunnel sessions in the cache directory.
+
+use chrono::Utc;
+use mullvad_types::connection_history::{DisconnectReason, Session, TrafficStats};
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use talpid_types::{net::TunnelType, ErrorExt};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Unable to read connection history file")]
+    Read(#[error(source)] io::Error),
+
+    #[error(display = "Failed to deserialize connection history")]
+    Deserialize(#[error(source)] serde_json::Error),
+
+    #[error(display = "Failed to serialize connection history")]
+    Serialize(#[error(source)] serde_json::Error),
+
+    #[error(display = "Unable to write connection history file")]
+    Write(#[error(source)] io::Error),
+}
+
+static CONNECTION_HISTORY_FILE: &str = "connection-history.json";
+static CONNECTION_HISTORY_TEMP_FILE: &str = "connection-history.json.tmp";
+/// Maximum number of sessions that are kept. The oldest sessions are removed first.
+const CONNECTION_HISTORY_LIMIT: usize = 100;
+/// How often the traffic counters of the ongoing session are written to disk, so that they
+/// survive a crash of the daemon.
+const TRAFFIC_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Past tunnel sessions, with the most recent session first.
+pub struct ConnectionHistory {
+    path: PathBuf,
+    temp_path: PathBuf,
+    sessions: VecDeque<Session>,
+    /// Whether the first session in `sessions` is still ongoing.
+    is_connected: bool,
+    last_saved: Instant,
+}
+
+impl ConnectionHistory {
+    /// Loads the history from the cache directory. A missing or unreadable history file results
+    /// in an empty history.
+    pub fn load(cache_dir: &Path) -> Self {
+        let path = cache_dir.join(CONNECTION_HISTORY_FILE);
+        let sessions = match Self::read_sessions(&path) {
+            Ok(sessions) => sessions,
+            Err(Error::Read(ref error)) if error.kind() == io::ErrorKind::NotFound => {
+                VecDeque::new()
+            }
+            Err(error) => {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to load connection history")
+                );
+                VecDeque::new()
+            }
+        };
+        ConnectionHistory {
+            path,
+            temp_path: cache_dir.join(CONNECTION_HISTORY_TEMP_FILE),
+            sessions,
+            is_connected: false,
+            last_saved: Instant::now(),
+        }
+    }
+
+    fn read_sessions(path: &Path) -> Result<VecDeque<Session>> {
+        let file = File::open(path).map_err(Error::Read)?;
+        serde_json::from_reader(io::BufReader::new(file)).map_err(Error::Deserialize)
+    }
+
+    /// Returns all recorded sessions, with the most recent session first.
+    pub fn sessions(&self) -> Vec<Session> {
+        self.sessions.iter().cloned().collect()
+    }
+
+    /// Records the start of a new session. Any ongoing session is ended first.
+    pub fn start_session(&mut self, hostname: Option<String>, tunnel_type: TunnelType) {
+        self.end_session(DisconnectReason::Reconnect);
+
+        self.sessions.push_front(Session {
+            hostname,
+            tunnel_type,
+            start: Utc::now(),
+            end: None,
+            traffic: None,
+            disconnect_reason: None,
+        });
+        self.sessions.truncate(CONNECTION_HISTORY_LIMIT);
+        self.is_connected = true;
+        self.save();
+    }
+
+    /// Updates the traffic counters of the ongoing session. The history is saved if it has not
+    /// been saved for a while.
+    pub fn update_traffic(&mut self, traffic: TrafficStats) {
+        if let Some(session) = self.ongoing_session() {
+            session.traffic = Some(traffic);
+            if self.last_saved.elapsed() >= TRAFFIC_SAVE_INTERVAL {
+                self.save();
+            }
+        }
+    }
+
+    /// Records the end of the ongoing session. Does nothing if there is no ongoing session.
+    pub fn end_session(&mut self, reason: DisconnectReason) {
+        if let Some(session) = self.ongoing_session() {
+            session.end = Some(Utc::now());
+            session.disconnect_reason = Some(reason);
+            self.is_connected = false;
+            self.save();
+        }
+    }
+
+    fn ongoing_session(&mut self) -> Option<&mut Session> {
+        if self.is_connected {
+            self.sessions.front_mut()
+        } else {
+            None
+        }
+    }
+
+    fn save(&mut self) {
+        self.last_saved = Instant::now();
+        if let Err(error) = self.write_sessions() {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to save connection history")
+            );
+        }
+    }
+
+    /// Writes the sessions to a temporary file that then replaces the history file, so that the
+    /// history file is never left partially written.
+    fn write_sessions(&self) -> Result<()> {
+        let mut file = File::create(&self.temp_path).map_err(Error::Write)?;
+        serde_json::to_writer_pretty(&mut file, &self.sessions).map_err(Error::Serialize)?;
+        file.sync_all().map_err(Error::Write)?;
+        fs::rename(&self.temp_path, &self.path).map_err(Error::Write)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sessions_are_persisted() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut history = ConnectionHistory::load(temp_dir.path());
+        assert!(history.sessions().is_empty());
+
+        history.start_session(Some("se1-wireguard".to_owned()), TunnelType::Wireguard);
+        history.update_traffic(TrafficStats {
+            tx_bytes: 100,
+            rx_bytes: 200,
+        });
+        history.start_session(None, TunnelType::OpenVpn);
+        history.end_session(DisconnectReason::Disconnected);
+        // Ending a session twice has no effect
+        history.end_session(DisconnectReason::Reconnect);
+
+        let sessions = ConnectionHistory::load(temp_dir.path()).sessions();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].hostname, None);
+        assert_eq!(
+            sessions[0].disconnect_reason,
+            Some(DisconnectReason::Disconnected)
+        );
+        assert_eq!(sessions[1].hostname.as_deref(), Some("se1-wireguard"));
+        assert_eq!(
+            sessions[1].traffic,
+            Some(TrafficStats {
+                tx_bytes: 100,
+                rx_bytes: 200,
+            })
+        );
+        assert_eq!(
+            sessions[1].disconnect_reason,
+            Some(DisconnectReason::Reconnect)
+        );
+        assert!(sessions.iter().all(|session| session.end.is_some()));
+    }
+
+    #[test]
+    fn test_traffic_is_persisted_periodically() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut history = ConnectionHistory::load(temp_dir.path());
+        history.start_session(None, TunnelType::Wireguard);
+
+        let traffic = TrafficStats {
+            tx_bytes: 100,
+            rx_bytes: 200,
+        };
+        history.update_traffic(traffic);
+        assert_eq!(
+            ConnectionHistory::load(temp_dir.path()).sessions()[0].traffic,
+            None
+        );
+
+        history.last_saved -= TRAFFIC_SAVE_INTERVAL;
+        history.update_traffic(traffic);
+        let sessions = ConnectionHistory::load(temp_dir.path()).sessions();
+        assert_eq!(sessions[0].traffic, Some(traffic));
+        assert_eq!(sessions[0].end, None);
+        assert!(!temp_dir.path().join(CONNECTION_HISTORY_TEMP_FILE).exists());
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut history = ConnectionHistory::load(temp_dir.path());
+        for _ in 0..CONNECTION_HISTORY_LIMIT + 5 {
+            history.start_session(None, TunnelType::Wireguard);
+        }
+        assert_eq!(history.sessions().len(), CONNECTION_HISTORY_LIMIT);
+    }
+}
@@ -6,19 +6,26 @@ extern crate serde;
 
 
 mod account_history;
+mod connection_history;
 pub mod exception_logging;
 mod geoip;
 pub mod logging;
 #[cfg(not(target_os = "android"))]
 pub mod management_interface;
+mod metrics;
+mod relay_latency;
 mod relays;
 #[cfg(not(target_os = "android"))]
 pub mod rpc_uniqueness_check;
+mod schedule;
 mod settings;
 pub mod version;
 mod version_check;
 
-use futures::future::{abortable, AbortHandle};
+use futures::{
+    compat::Future01CompatExt,
+    future::{abortable, AbortHandle},
+};
 use futures01::{
     future::{self, Executor},
     stream::Wait,
@@ -29,19 +36,27 @@ use futures01::{
     Future, Stream,
 };
 use log::{debug, error, info, warn};
-use mullvad_rpc::AccountsProxy;
+use mullvad_rpc::{AccountsProxy, PortForwardProxy};
+#[cfg(target_os = "linux")]
+use mullvad_types::trusted_network::TrustedNetwork;
 use mullvad_types::{
     account::{AccountData, AccountToken, VoucherSubmission},
+    connection_history::{DisconnectReason, Session, TrafficStats},
+    custom_list::{self, CustomListsSettings},
     endpoint::MullvadEndpoint,
-    location::GeoIpLocation,
+    location::{CityCode, CountryCode, GeoIpLocation},
+    port_forward::PortForward,
     relay_constraints::{
-        BridgeSettings, BridgeState, Constraint, InternalBridgeConstraints, RelaySettings,
-        RelaySettingsUpdate,
+        BridgeSettings, BridgeState, Constraint, InternalBridgeConstraints, LocationConstraint,
+        RelayConstraints, RelaySettings, RelaySettingsUpdate, SelectionMode,
     },
     relay_list::{Relay, RelayList},
-    settings::Settings,
-    states::{TargetState, TunnelState},
+    schedule::{ScheduleAction, ScheduleRule},
+    settings::{DnsOptions, Settings},
+    states::{TargetState, TunnelState, TunnelStats},
+    trusted_network::TrustedNetworkEvent,
     version::{AppVersion, AppVersionInfo},
+    wg_quick::{WgQuickConfig, WgQuickPeer},
     wireguard::KeygenEvent,
 };
 use settings::SettingsPersister;
@@ -52,9 +67,10 @@ use std::{
     io,
     marker::PhantomData,
     mem,
+    net::{Ipv4Addr, Ipv6Addr},
     path::PathBuf,
     sync::{mpsc, Arc, Weak},
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 #[cfg(target_os = "linux")]
 use talpid_core::split_tunnel;
@@ -64,9 +80,13 @@ use talpid_core::{
 };
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
+#[cfg(target_os = "linux")]
+use talpid_types::net::{FirewallExemption, NetworkIdentity, SplitTunnelMode};
 use talpid_types::{
     net::{openvpn, TransportProtocol, TunnelParameters, TunnelType},
-    tunnel::{ErrorStateCause, ParameterGenerationError, TunnelStateTransition},
+    tunnel::{
+        ActionAfterDisconnect, ErrorStateCause, ParameterGenerationError, TunnelStateTransition,
+    },
     ErrorExt,
 };
 
@@ -89,6 +109,15 @@ const FIRST_KEY_PUSH_TIMEOUT: Duration = Duration::from_secs(5);
 /// Delay between generating a new WireGuard key and reconnecting
 const WG_RECONNECT_DELAY: Duration = Duration::from_secs(30);
 
+/// How often the traffic counters of a connected tunnel are read
+const TUNNEL_STATS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the schedule rules are checked. Rules have a resolution of one minute.
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Port used in exported WireGuard configurations, if the relay accepts it
+const WIREGUARD_DEFAULT_PORT: u16 = 51820;
+
 #[derive(err_derive::Error, Debug)]
 #[error(no_from)]
 pub enum Error {
@@ -148,6 +177,62 @@ pub enum Error {
     OpenCachedTargetState(#[error(source)] io::Error),
 }
 
+/// Errors that can occur when managing port forwards.
+#[derive(err_derive::Error, Debug)]
+pub enum PortForwardError {
+    #[error(display = "No account token or WireGuard key is set")]
+    NoKey,
+
+    #[error(display = "Failed to update port forwards")]
+    RestError(#[error(source)] mullvad_rpc::rest::Error),
+}
+
+/// Errors that can occur when enabling or disabling the SOCKS5 proxy.
+#[derive(err_derive::Error, Debug)]
+pub enum SocksProxyError {
+    #[cfg(not(target_os = "android"))]
+    #[error(display = "Failed to start the SOCKS5 proxy")]
+    Start(#[error(source)] talpid_core::socks_proxy::Error),
+
+    #[error(display = "Failed to save settings")]
+    SaveSettings(#[error(source)] settings::Error),
+}
+
+/// Errors that can occur when managing the WireGuard keys of an account.
+#[derive(err_derive::Error, Debug)]
+pub enum WireguardKeyError {
+    #[error(display = "No account token is set")]
+    NoAccount,
+
+    #[error(display = "The key is currently in use")]
+    KeyInUse,
+
+    #[error(display = "The private key is not stored by the daemon")]
+    UnknownKey,
+
+    #[error(display = "Failed to update the account history")]
+    AccountHistory(#[error(source)] account_history::Error),
+
+    #[error(display = "Failed to manage WireGuard keys")]
+    KeyManager(#[error(source)] wireguard::Error),
+}
+
+/// Errors that can occur when exporting a WireGuard configuration.
+#[derive(err_derive::Error, Debug)]
+pub enum WireguardConfigError {
+    #[error(display = "No account token or WireGuard key is set")]
+    NoKey,
+
+    #[error(display = "No relay has been selected yet")]
+    NoRelay,
+
+    #[error(display = "There is no relay with the hostname {}", _0)]
+    UnknownRelay(String),
+
+    #[error(display = "{} is not a WireGuard relay", _0)]
+    NotWireguardRelay(String),
+}
+
 /// Enum representing commands that can be sent to the daemon.
 pub enum DaemonCommand {
     /// Set target state. Does nothing if the daemon already has the state that is being set.
@@ -177,6 +262,19 @@ pub enum DaemonCommand {
     RemoveAccountFromHistory(oneshot::Sender<()>, AccountToken),
     /// Clear account history
     ClearAccountHistory(oneshot::Sender<()>),
+    /// Request the history of past tunnel sessions, most recent first
+    GetConnectionHistory(oneshot::Sender<Vec<Session>>),
+    /// Request the traffic counters of the current tunnel
+    GetTunnelStats(oneshot::Sender<Option<TunnelStats>>),
+    /// Set whether to periodically broadcast the traffic counters of the tunnel
+    SetTunnelStatsEvents(oneshot::Sender<()>, bool),
+    /// Set whether to run a SOCKS5 proxy on localhost that forwards connections through the
+    /// tunnel
+    SetSocksProxy(oneshot::Sender<Result<(), SocksProxyError>>, bool),
+    /// Change the log level of the daemon until it is restarted
+    SetLogLevel(oneshot::Sender<()>, log::LevelFilter),
+    /// Set whether to serve metrics on localhost
+    SetMetrics(oneshot::Sender<()>, bool),
     /// Get the list of countries and cities where there are relays.
     GetRelayLocations(oneshot::Sender<RelayList>),
     /// Trigger an asynchronous relay list update. This returns before the relay list is actually
@@ -185,15 +283,27 @@ pub enum DaemonCommand {
     /// Set which account token to use for subsequent connection attempts.
     SetAccount(oneshot::Sender<()>, Option<AccountToken>),
     /// Place constraints on the type of tunnel and relay
-    UpdateRelaySettings(oneshot::Sender<()>, RelaySettingsUpdate),
+    UpdateRelaySettings(
+        oneshot::Sender<std::result::Result<(), settings::Error>>,
+        RelaySettingsUpdate,
+    ),
     /// Set the allow LAN setting.
-    SetAllowLan(oneshot::Sender<()>, bool),
+    SetAllowLan(
+        oneshot::Sender<std::result::Result<(), settings::Error>>,
+        bool,
+    ),
     /// Set the beta program setting.
     SetShowBetaReleases(oneshot::Sender<()>, bool),
     /// Set the block_when_disconnected setting.
-    SetBlockWhenDisconnected(oneshot::Sender<()>, bool),
+    SetBlockWhenDisconnected(
+        oneshot::Sender<std::result::Result<(), settings::Error>>,
+        bool,
+    ),
     /// Set the auto-connect setting.
-    SetAutoConnect(oneshot::Sender<()>, bool),
+    SetAutoConnect(
+        oneshot::Sender<std::result::Result<(), settings::Error>>,
+        bool,
+    ),
     /// Set the mssfix argument for OpenVPN
     SetOpenVpnMssfix(oneshot::Sender<()>, Option<u16>),
     /// Set proxy details for OpenVPN
@@ -207,19 +317,83 @@ pub enum DaemonCommand {
         BridgeState,
     ),
     /// Set if IPv6 should be enabled in the tunnel
-    SetEnableIpv6(oneshot::Sender<()>, bool),
+    SetEnableIpv6(
+        oneshot::Sender<std::result::Result<(), settings::Error>>,
+        bool,
+    ),
+    /// Set the DNS servers to use in the tunnel
+    SetDnsOptions(
+        oneshot::Sender<std::result::Result<(), settings::Error>>,
+        DnsOptions,
+    ),
     /// Set MTU for wireguard tunnels
     SetWireguardMtu(oneshot::Sender<()>, Option<u16>),
     /// Set automatic key rotation interval for wireguard tunnels
     SetWireguardRotationInterval(oneshot::Sender<()>, Option<u32>),
     /// Get the daemon settings
     GetSettings(oneshot::Sender<Settings>),
+    /// Create an empty custom list with the given name. Returns the ID of the new list.
+    CreateCustomList(
+        oneshot::Sender<std::result::Result<custom_list::Id, custom_list::Error>>,
+        String,
+    ),
+    /// Delete the custom list with the given name
+    DeleteCustomList(
+        oneshot::Sender<std::result::Result<(), custom_list::Error>>,
+        String,
+    ),
+    /// Rename the custom list with the given name
+    RenameCustomList(
+        oneshot::Sender<std::result::Result<(), custom_list::Error>>,
+        String,
+        String,
+    ),
+    /// Add a location to the custom list with the given name
+    AddCustomListLocation(
+        oneshot::Sender<std::result::Result<(), custom_list::Error>>,
+        String,
+        LocationConstraint,
+    ),
+    /// Remove a location from the custom list with the given name
+    RemoveCustomListLocation(
+        oneshot::Sender<std::result::Result<(), custom_list::Error>>,
+        String,
+        LocationConstraint,
+    ),
     /// Generate new wireguard key
     GenerateWireguardKey(oneshot::Sender<wireguard::KeygenEvent>),
     /// Return a public key of the currently set wireguard private key, if there is one
     GetWireguardKey(oneshot::Sender<Option<wireguard::PublicKey>>),
     /// Verify if the currently set wireguard key is valid.
     VerifyWireguardKey(oneshot::Sender<bool>),
+    /// List the wireguard keys that are registered on the account or stored by the daemon
+    ListWireguardKeys(oneshot::Sender<Result<Vec<wireguard::AccountKey>, WireguardKeyError>>),
+    /// Remove a wireguard key that is not in use from the account
+    RevokeWireguardKey(
+        oneshot::Sender<Result<(), WireguardKeyError>>,
+        talpid_types::net::wireguard::PublicKey,
+    ),
+    /// Start using a previously used wireguard key again
+    RestoreWireguardKey(
+        oneshot::Sender<Result<wireguard::PublicKey, WireguardKeyError>>,
+        talpid_types::net::wireguard::PublicKey,
+    ),
+    /// Get a wg-quick configuration for connecting to a relay with the current wireguard key.
+    /// The most recently selected relay is used unless a hostname is given.
+    GetWireguardConfig(
+        oneshot::Sender<Result<WgQuickConfig, WireguardConfigError>>,
+        Option<String>,
+    ),
+    /// Get the ports that are forwarded to the current wireguard key
+    GetPortForwards(oneshot::Sender<Result<Vec<PortForward>, PortForwardError>>),
+    /// Forward a new port to the current wireguard key on the relays in a city
+    AddPortForward(
+        oneshot::Sender<Result<PortForward, PortForwardError>>,
+        CountryCode,
+        CityCode,
+    ),
+    /// Stop forwarding a port to the current wireguard key
+    RemovePortForward(oneshot::Sender<Result<(), PortForwardError>>, u16),
     /// Get information about the currently running and latest app versions
     GetVersionInfo(oneshot::Sender<AppVersionInfo>),
     /// Get current version of the app
@@ -239,6 +413,28 @@ pub enum DaemonCommand {
     /// Clear list of processes excluded from the tunnel
     #[cfg(target_os = "linux")]
     ClearSplitTunnelProcesses(oneshot::Sender<()>),
+    /// Exclude all processes started from an executable from the tunnel
+    #[cfg(target_os = "linux")]
+    AddSplitTunnelApp(oneshot::Sender<()>, PathBuf),
+    /// Stop excluding processes started from an executable from the tunnel
+    #[cfg(target_os = "linux")]
+    RemoveSplitTunnelApp(oneshot::Sender<()>, PathBuf),
+    /// Set whether split processes bypass the tunnel or are the only ones using it
+    #[cfg(target_os = "linux")]
+    SetSplitTunnelMode(oneshot::Sender<()>, SplitTunnelMode),
+    /// Set the networks on which the tunnel is disconnected automatically
+    #[cfg(target_os = "linux")]
+    SetTrustedNetworks(oneshot::Sender<()>, Vec<TrustedNetwork>),
+    /// Get the identity of the network the host is connected to
+    #[cfg(target_os = "linux")]
+    GetNetworkIdentity(oneshot::Sender<Option<NetworkIdentity>>),
+    /// Set the networks and ports that bypass the tunnel and the firewall
+    #[cfg(target_os = "linux")]
+    SetFirewallExemptions(oneshot::Sender<()>, Vec<FirewallExemption>),
+    /// Set the rules that connect or disconnect the tunnel at given times
+    SetSchedule(oneshot::Sender<()>, Vec<ScheduleRule>),
+    /// Replace all settings with imported ones
+    ImportSettings(oneshot::Sender<Result<(), settings::Error>>, Settings),
     /// Makes the daemon exit the main loop and quit.
     Shutdown,
     /// Saves the target tunnel state and enters a blocking state. The state is restored
@@ -273,6 +469,17 @@ pub(crate) enum InternalDaemonEvent {
     ),
     /// The background job fetching new `AppVersionInfo`s got a new info object.
     NewAppVersionInfo(AppVersionInfo),
+    /// The traffic counters of the connected tunnel were read.
+    TunnelStats(talpid_core::tunnel::TunnelStats),
+    /// The host connected to a different network.
+    #[cfg(target_os = "linux")]
+    NetworkIdentity(NetworkIdentity),
+    /// Time to check whether any schedule rule is due.
+    ScheduleTick,
+    /// The ports forwarded to a wireguard key were fetched.
+    PortForwards(talpid_types::net::wireguard::PublicKey, Vec<PortForward>),
+    /// Request from the metrics server to obtain the current metrics.
+    GetMetrics(mpsc::Sender<metrics::Metrics>),
 }
 
 impl From<TunnelStateTransition> for InternalDaemonEvent {
@@ -293,6 +500,19 @@ impl From<AppVersionInfo> for InternalDaemonEvent {
     }
 }
 
+impl From<talpid_core::tunnel::TunnelStats> for InternalDaemonEvent {
+    fn from(stats: talpid_core::tunnel::TunnelStats) -> Self {
+        InternalDaemonEvent::TunnelStats(stats)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<NetworkIdentity> for InternalDaemonEvent {
+    fn from(network: NetworkIdentity) -> Self {
+        InternalDaemonEvent::NetworkIdentity(network)
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 enum DaemonExecutionState {
     Running,
@@ -451,6 +671,12 @@ pub trait EventListener {
 
     /// Notify clients of a key generation event.
     fn notify_key_event(&self, key_event: KeygenEvent);
+
+    /// Notify clients of the current traffic counters of the tunnel.
+    fn notify_tunnel_stats(&self, stats: TunnelStats);
+
+    /// Notify clients that the daemon connected or disconnected because the network changed.
+    fn notify_trusted_network_event(&self, event: TrustedNetworkEvent);
 }
 
 pub struct Daemon<L: EventListener> {
@@ -460,13 +686,24 @@ pub struct Daemon<L: EventListener> {
     state: DaemonExecutionState,
     #[cfg(target_os = "linux")]
     exclude_pids: split_tunnel::PidManager,
+    #[cfg(target_os = "linux")]
+    split_tunnel_apps: split_tunnel::AppWatcher,
+    #[cfg(target_os = "linux")]
+    network_identity: Option<NetworkIdentity>,
     rx: Wait<UnboundedReceiver<InternalDaemonEvent>>,
     tx: DaemonEventSender,
     reconnection_job: Option<AbortHandle>,
+    tunnel_stats_job: Option<AbortHandle>,
+    metrics_counters: metrics::MetricsCounters,
+    metrics_server: Option<metrics::MetricsServer>,
+    scheduler: schedule::Scheduler,
     event_listener: L,
     settings: SettingsPersister,
     account_history: account_history::AccountHistory,
+    connection_history: connection_history::ConnectionHistory,
     accounts_proxy: AccountsProxy,
+    port_forward_proxy: PortForwardProxy,
+    port_forwards: Vec<PortForward>,
     rpc_runtime: mullvad_rpc::MullvadRpcRuntime,
     rpc_handle: mullvad_rpc::rest::MullvadRestHandle,
     wireguard_key_manager: wireguard::KeyManager,
@@ -474,6 +711,7 @@ pub struct Daemon<L: EventListener> {
     core_handle: event_loop::CoreHandle,
     relay_selector: relays::RelaySelector,
     last_generated_relay: Option<Relay>,
+    last_generated_entry_relay: Option<Relay>,
     last_generated_bridge_relay: Option<Relay>,
     app_version_info: AppVersionInfo,
     shutdown_callbacks: Vec<Box<dyn FnOnce()>>,
@@ -523,6 +761,7 @@ where
         if version::is_beta_version() {
             let _ = settings.set_show_beta_releases(true);
         }
+        relay_selector.set_custom_lists(settings.custom_lists.clone());
 
         let app_version_info = version_check::load_cache(&cache_dir);
         let (version_updater, version_updater_handle) = version_check::VersionUpdater::new(
@@ -536,6 +775,7 @@ where
         let account_history =
             account_history::AccountHistory::new(&cache_dir, &settings_dir, rpc_handle.clone())
                 .map_err(Error::LoadAccountHistory)?;
+        let connection_history = connection_history::ConnectionHistory::load(&cache_dir);
 
         // Restore the tunnel to a previous state
         let target_cache = cache_dir.join(TARGET_START_STATE_FILE);
@@ -563,11 +803,19 @@ where
         let tunnel_command_tx = tunnel_state_machine::spawn(
             settings.allow_lan,
             settings.block_when_disconnected,
+            settings.tunnel_options.dns_options.servers(),
+            settings.firewall_exemptions.clone(),
+            #[cfg(target_os = "linux")]
+            settings.split_tunnel.mode,
+            #[cfg(not(target_os = "android"))]
+            settings.socks_proxy,
             tunnel_parameters_generator,
             log_dir,
             resource_dir,
             cache_dir.clone(),
             internal_event_tx.to_specialized_sender(),
+            #[cfg(target_os = "linux")]
+            internal_event_tx.to_specialized_sender(),
             tunnel_state_machine_shutdown_tx,
             #[cfg(target_os = "android")]
             android_context,
@@ -593,27 +841,47 @@ where
             TargetState::Unsecured
         };
 
+        #[cfg(target_os = "linux")]
+        let exclude_pids = split_tunnel::PidManager::new().map_err(Error::InitSplitTunneling)?;
+        #[cfg(target_os = "linux")]
+        let split_tunnel_apps = split_tunnel::AppWatcher::start(
+            exclude_pids.clone(),
+            settings.split_tunnel.apps.clone(),
+        );
+
         let mut daemon = Daemon {
             tunnel_command_tx,
             tunnel_state: TunnelState::Disconnected,
             target_state: initial_target_state,
             state: DaemonExecutionState::Running,
             #[cfg(target_os = "linux")]
-            exclude_pids: split_tunnel::PidManager::new().map_err(Error::InitSplitTunneling)?,
+            exclude_pids,
+            #[cfg(target_os = "linux")]
+            split_tunnel_apps,
+            #[cfg(target_os = "linux")]
+            network_identity: None,
             rx: internal_event_rx.wait(),
             tx: internal_event_tx,
             reconnection_job: None,
+            tunnel_stats_job: None,
+            metrics_counters: metrics::MetricsCounters::default(),
+            metrics_server: None,
+            scheduler: schedule::Scheduler::new(Box::new(schedule::SystemClock)),
             event_listener,
             settings,
             account_history,
+            connection_history,
             rpc_runtime,
             accounts_proxy: AccountsProxy::new(rpc_handle.clone()),
+            port_forward_proxy: PortForwardProxy::new(rpc_handle.clone()),
+            port_forwards: Vec::new(),
             rpc_handle,
             wireguard_key_manager,
             version_updater_handle,
             core_handle,
             relay_selector,
             last_generated_relay: None,
+            last_generated_entry_relay: None,
             last_generated_bridge_relay: None,
             app_version_info,
             shutdown_callbacks: vec![],
@@ -622,6 +890,11 @@ where
         };
 
         daemon.ensure_wireguard_keys_for_current_account();
+        daemon.spawn_schedule_ticker();
+        daemon.refresh_port_forwards();
+        if daemon.settings.metrics {
+            daemon.set_metrics_server(true);
+        }
 
         if let Some(token) = daemon.settings.get_account_token() {
             daemon.wireguard_key_manager.set_rotation_interval(
@@ -644,6 +917,8 @@ where
     pub fn run(mut self) -> Result<(), Error> {
         if self.target_state == TargetState::Secured {
             self.connect_tunnel();
+        } else {
+            self.measure_relay_latencies();
         }
         while let Some(Ok(event)) = self.rx.next() {
             self.handle_event(event);
@@ -714,6 +989,12 @@ where
             NewAppVersionInfo(app_version_info) => {
                 self.handle_new_app_version_info(app_version_info)
             }
+            TunnelStats(stats) => self.handle_tunnel_stats(stats),
+            #[cfg(target_os = "linux")]
+            NetworkIdentity(network) => self.handle_network_identity(network),
+            ScheduleTick => self.handle_schedule_tick(),
+            PortForwards(key, port_forwards) => self.handle_port_forwards(key, port_forwards),
+            GetMetrics(tx) => self.handle_get_metrics(tx),
         }
     }
 
@@ -729,6 +1010,8 @@ where
             _ => (),
         };
 
+        self.record_connection_history(&tunnel_state_transition);
+
         let tunnel_state = match tunnel_state_transition {
             TunnelStateTransition::Disconnected => TunnelState::Disconnected,
             TunnelStateTransition::Connecting(endpoint) => TunnelState::Connecting {
@@ -748,6 +1031,7 @@ where
 
         self.unschedule_reconnect();
 
+        logging::set_tunnel_state(&tunnel_state);
         debug!("New tunnel state: {:?}", tunnel_state);
         match tunnel_state {
             TunnelState::Disconnected => self.state.disconnected(),
@@ -771,8 +1055,196 @@ where
             _ => {}
         }
 
+        self.metrics_counters
+            .record_transition(&self.tunnel_state, &tunnel_state);
         self.tunnel_state = tunnel_state.clone();
         self.event_listener.notify_new_state(tunnel_state);
+
+        self.measure_relay_latencies();
+    }
+
+    /// Records the start and end of tunnel sessions in the connection history, and reads the
+    /// traffic counters of the tunnel while it is connected.
+    fn record_connection_history(&mut self, tunnel_state_transition: &TunnelStateTransition) {
+        if let Some(job) = self.tunnel_stats_job.take() {
+            job.abort();
+        }
+
+        match tunnel_state_transition {
+            TunnelStateTransition::Connected(endpoint) => {
+                let hostname = self
+                    .last_generated_relay
+                    .as_ref()
+                    .map(|relay| relay.hostname.clone());
+                self.connection_history
+                    .start_session(hostname, endpoint.tunnel_type);
+                self.schedule_tunnel_stats_sampling();
+            }
+            TunnelStateTransition::Disconnecting(ActionAfterDisconnect::Nothing)
+            | TunnelStateTransition::Disconnected => self
+                .connection_history
+                .end_session(DisconnectReason::Disconnected),
+            TunnelStateTransition::Disconnecting(ActionAfterDisconnect::Reconnect)
+            | TunnelStateTransition::Connecting(_) => self
+                .connection_history
+                .end_session(DisconnectReason::Reconnect),
+            // The session is ended once the cause is known, when entering the error state
+            TunnelStateTransition::Disconnecting(ActionAfterDisconnect::Block) => (),
+            TunnelStateTransition::Error(error_state) => self
+                .connection_history
+                .end_session(DisconnectReason::Error(error_state.cause().clone())),
+        }
+    }
+
+    fn schedule_tunnel_stats_sampling(&mut self) {
+        let tunnel_command_tx = Arc::downgrade(&self.tunnel_command_tx);
+        let stats_tx = self.tx.to_specialized_sender();
+        let (future, abort_handle) = abortable(Box::pin(async move {
+            loop {
+                tokio02::time::delay_for(TUNNEL_STATS_INTERVAL).await;
+
+                let (tx, rx) = oneshot::channel();
+                match tunnel_command_tx.upgrade() {
+                    Some(command_tx) => {
+                        if command_tx
+                            .unbounded_send(TunnelCommand::GetTunnelStats(tx))
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                    None => break,
+                }
+                // OpenVPN only reports counters for some events, so they may not be available yet
+                let stats = match rx.compat().await {
+                    Ok(Some(stats)) => stats,
+                    Ok(None) => continue,
+                    Err(_) => break,
+                };
+                if stats_tx.send(stats).is_err() {
+                    break;
+                }
+            }
+        }));
+
+        self.spawn_future(future);
+        self.tunnel_stats_job = Some(abort_handle);
+    }
+
+    fn handle_tunnel_stats(&mut self, stats: talpid_core::tunnel::TunnelStats) {
+        self.connection_history.update_traffic(TrafficStats {
+            tx_bytes: stats.tx_bytes,
+            rx_bytes: stats.rx_bytes,
+        });
+        if self.settings.tunnel_stats_events {
+            self.event_listener
+                .notify_tunnel_stats(convert_tunnel_stats(stats));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn handle_network_identity(&mut self, network: NetworkIdentity) {
+        if self.network_identity.as_ref() == Some(&network) {
+            return;
+        }
+        debug!("Connected to network: {}", network);
+        self.network_identity = Some(network);
+        self.apply_trusted_networks();
+    }
+
+    /// Disconnects if the current network matches a trusted network rule, and connects
+    /// otherwise. Does nothing if there are no rules, no account, or if the host is offline.
+    #[cfg(target_os = "linux")]
+    fn apply_trusted_networks(&mut self) {
+        let network = match &self.network_identity {
+            Some(network) if !network.is_unknown() => network.clone(),
+            _ => return,
+        };
+        if self.settings.trusted_networks.is_empty() || self.settings.get_account_token().is_none()
+        {
+            return;
+        }
+
+        let rule = self
+            .settings
+            .trusted_networks
+            .iter()
+            .find(|rule| rule.matches(&network))
+            .cloned();
+        let target_state = if rule.is_some() {
+            TargetState::Unsecured
+        } else {
+            TargetState::Secured
+        };
+        let event = TrustedNetworkEvent {
+            network,
+            rule,
+            target_state,
+        };
+        info!("{}", event);
+        self.event_listener.notify_trusted_network_event(event);
+        self.set_target_state(target_state);
+    }
+
+    fn spawn_schedule_ticker(&mut self) {
+        let tick_tx = self.tx.clone();
+        self.spawn_future(async move {
+            let mut interval = tokio02::time::interval(SCHEDULE_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                if tick_tx.send(InternalDaemonEvent::ScheduleTick).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    fn handle_schedule_tick(&mut self) {
+        let rule = match self.scheduler.poll(&self.settings.schedule) {
+            Some(rule) => rule.clone(),
+            None => return,
+        };
+        info!("Applying schedule rule {}", rule);
+
+        match rule.action {
+            ScheduleAction::Connect => {
+                if self.settings.get_account_token().is_none() {
+                    warn!("Not connecting since no account token is set");
+                    return;
+                }
+                if let Some(update) = rule.relay_settings {
+                    match self.settings.update_relay_settings(update) {
+                        Ok(true) => {
+                            self.event_listener
+                                .notify_settings(self.settings.to_settings());
+                            self.reconnect_tunnel();
+                            self.measure_relay_latencies();
+                        }
+                        Ok(false) => (),
+                        Err(e) => error!(
+                            "{}",
+                            e.display_chain_with_msg("Unable to apply scheduled relay settings")
+                        ),
+                    }
+                }
+                self.set_target_state(TargetState::Secured);
+            }
+            ScheduleAction::Disconnect => self.set_target_state(TargetState::Unsecured),
+        }
+    }
+
+    /// Measures the latency to relays if relays are selected by latency. This is only done while
+    /// disconnected and not blocking traffic, since the relays can't be reached otherwise.
+    fn measure_relay_latencies(&self) {
+        if self.tunnel_state != TunnelState::Disconnected || self.settings.block_when_disconnected
+        {
+            return;
+        }
+        if let RelaySettings::Normal(constraints) = self.settings.get_relay_settings() {
+            if constraints.selection_mode == SelectionMode::Latency {
+                self.relay_selector.measure_latencies(&constraints);
+            }
+        }
     }
 
     fn handle_generate_tunnel_parameters(
@@ -784,12 +1256,23 @@ where
             let result = match self.settings.get_relay_settings() {
                 RelaySettings::CustomTunnelEndpoint(custom_relay) => {
                     self.last_generated_relay = None;
+                    self.last_generated_entry_relay = None;
                     custom_relay
                         // TODO(emilsp): generate proxy settings for custom tunnels
                         .to_tunnel_parameters(self.settings.tunnel_options.clone(), None)
-                        .map_err(|e| {
-                            log::error!("Failed to resolve hostname for custom tunnel config: {}", e);
-                            ParameterGenerationError::CustomTunnelHostResultionError
+                        .map_err(|e| match e {
+                            #[cfg(windows)]
+                            mullvad_types::custom_tunnel::Error::MultiplePeersNotSupported => {
+                                log::error!("{}", e);
+                                ParameterGenerationError::CustomTunnelMultiplePeers
+                            }
+                            _ => {
+                                log::error!(
+                                    "Failed to resolve hostname for custom tunnel config: {}",
+                                    e
+                                );
+                                ParameterGenerationError::CustomTunnelHostResultionError
+                            }
                         })
                 }
                 RelaySettings::Normal(constraints) => self
@@ -805,14 +1288,16 @@ where
                             .is_some(),
                     )
                     .map_err(|_| ParameterGenerationError::NoMatchingRelay)
-                    .and_then(|(relay, endpoint)| {
+                    .and_then(|(relay, entry_relay, endpoint)| {
                         let result = self.create_tunnel_parameters(
                             &relay,
                             endpoint,
+                            &constraints,
                             account_token,
                             retry_attempt,
                         );
                         self.last_generated_relay = Some(relay);
+                        self.last_generated_entry_relay = entry_relay;
                         match result {
                             Ok(result) => Ok(result),
                             Err(Error::NoKeyAvailable) => {
@@ -845,6 +1330,7 @@ where
         &mut self,
         relay: &Relay,
         endpoint: MullvadEndpoint,
+        relay_constraints: &RelayConstraints,
         account_token: String,
         retry_attempt: u32,
     ) -> Result<TunnelParameters, Error> {
@@ -857,6 +1343,8 @@ where
                     BridgeSettings::Normal(settings) => {
                         let bridge_constraints = InternalBridgeConstraints {
                             location: settings.location.clone(),
+                            providers: relay_constraints.providers.clone(),
+                            ownership: relay_constraints.ownership,
                             // FIXME: This is temporary while talpid-core only supports TCP proxies
                             transport_protocol: Constraint::Only(TransportProtocol::Tcp),
                         };
@@ -917,6 +1405,8 @@ where
                 peer,
                 ipv4_gateway,
                 ipv6_gateway,
+                entry_endpoint,
+                udp_over_tcp_endpoint,
             } => {
                 let wg_data = self
                     .account_history
@@ -935,8 +1425,11 @@ where
                     connection: wireguard::ConnectionConfig {
                         tunnel,
                         peer,
+                        additional_peers: vec![],
                         ipv4_gateway,
                         ipv6_gateway: Some(ipv6_gateway),
+                        entry_endpoint,
+                        udp_over_tcp_endpoint,
                     },
                     options: tunnel_options.wireguard,
                     generic_options: tunnel_options.generic,
@@ -1003,6 +1496,12 @@ where
                 self.on_remove_account_from_history(tx, account_token)
             }
             ClearAccountHistory(tx) => self.on_clear_account_history(tx),
+            GetConnectionHistory(tx) => self.on_get_connection_history(tx),
+            GetTunnelStats(tx) => self.on_get_tunnel_stats(tx),
+            SetTunnelStatsEvents(tx, enabled) => self.on_set_tunnel_stats_events(tx, enabled),
+            SetSocksProxy(tx, enabled) => self.on_set_socks_proxy(tx, enabled),
+            SetLogLevel(tx, log_level) => self.on_set_log_level(tx, log_level),
+            SetMetrics(tx, enabled) => self.on_set_metrics(tx, enabled),
             UpdateRelaySettings(tx, update) => self.on_update_relay_settings(tx, update),
             SetAllowLan(tx, allow_lan) => self.on_set_allow_lan(tx, allow_lan),
             SetShowBetaReleases(tx, enabled) => self.on_set_show_beta_releases(tx, enabled),
@@ -1016,14 +1515,31 @@ where
             }
             SetBridgeState(tx, bridge_state) => self.on_set_bridge_state(tx, bridge_state),
             SetEnableIpv6(tx, enable_ipv6) => self.on_set_enable_ipv6(tx, enable_ipv6),
+            SetDnsOptions(tx, dns_options) => self.on_set_dns_options(tx, dns_options),
             SetWireguardMtu(tx, mtu) => self.on_set_wireguard_mtu(tx, mtu),
             SetWireguardRotationInterval(tx, interval) => {
                 self.on_set_wireguard_rotation_interval(tx, interval)
             }
             GetSettings(tx) => self.on_get_settings(tx),
+            CreateCustomList(tx, name) => self.on_create_custom_list(tx, name),
+            DeleteCustomList(tx, name) => self.on_delete_custom_list(tx, name),
+            RenameCustomList(tx, name, new_name) => self.on_rename_custom_list(tx, name, new_name),
+            AddCustomListLocation(tx, name, location) => {
+                self.on_add_custom_list_location(tx, name, location)
+            }
+            RemoveCustomListLocation(tx, name, location) => {
+                self.on_remove_custom_list_location(tx, name, location)
+            }
             GenerateWireguardKey(tx) => self.on_generate_wireguard_key(tx),
             GetWireguardKey(tx) => self.on_get_wireguard_key(tx),
             VerifyWireguardKey(tx) => self.on_verify_wireguard_key(tx),
+            ListWireguardKeys(tx) => self.on_list_wireguard_keys(tx),
+            RevokeWireguardKey(tx, key) => self.on_revoke_wireguard_key(tx, key),
+            RestoreWireguardKey(tx, key) => self.on_restore_wireguard_key(tx, key),
+            GetWireguardConfig(tx, hostname) => self.on_get_wireguard_config(tx, hostname),
+            GetPortForwards(tx) => self.on_get_port_forwards(tx),
+            AddPortForward(tx, country, city) => self.on_add_port_forward(tx, country, city),
+            RemovePortForward(tx, port) => self.on_remove_port_forward(tx, port),
             GetVersionInfo(tx) => self.on_get_version_info(tx),
             GetCurrentVersion(tx) => self.on_get_current_version(tx),
             #[cfg(not(target_os = "android"))]
@@ -1036,6 +1552,22 @@ where
             RemoveSplitTunnelProcess(tx, pid) => self.on_remove_split_tunnel_process(tx, pid),
             #[cfg(target_os = "linux")]
             ClearSplitTunnelProcesses(tx) => self.on_clear_split_tunnel_processes(tx),
+            #[cfg(target_os = "linux")]
+            AddSplitTunnelApp(tx, path) => self.on_add_split_tunnel_app(tx, path),
+            #[cfg(target_os = "linux")]
+            RemoveSplitTunnelApp(tx, path) => self.on_remove_split_tunnel_app(tx, path),
+            #[cfg(target_os = "linux")]
+            SetSplitTunnelMode(tx, mode) => self.on_set_split_tunnel_mode(tx, mode),
+            #[cfg(target_os = "linux")]
+            SetTrustedNetworks(tx, rules) => self.on_set_trusted_networks(tx, rules),
+            #[cfg(target_os = "linux")]
+            GetNetworkIdentity(tx) => self.on_get_network_identity(tx),
+            #[cfg(target_os = "linux")]
+            SetFirewallExemptions(tx, exemptions) => {
+                self.on_set_firewall_exemptions(tx, exemptions)
+            }
+            SetSchedule(tx, rules) => self.on_set_schedule(tx, rules),
+            ImportSettings(tx, settings) => self.on_import_settings(tx, settings),
             Shutdown => self.trigger_shutdown_event(),
             PrepareRestart => self.on_prepare_restart(),
         }
@@ -1069,16 +1601,14 @@ where
                     .get(&account)
                     .ok()
                     .and_then(|entry| entry)
-                    .unwrap_or_else(|| account_history::AccountEntry {
-                        account: account.clone(),
-                        wireguard: None,
-                    });
-                account_entry.wireguard = Some(data);
+                    .unwrap_or_else(|| account_history::AccountEntry::new(account.clone()));
+                account_entry.set_wireguard_key(data);
                 match self.account_history.insert(account_entry) {
                     Ok(_) => {
                         if let Some(TunnelType::Wireguard) = self.get_connected_tunnel_type() {
                             self.schedule_reconnect(WG_RECONNECT_DELAY);
                         }
+                        self.refresh_port_forwards();
                         self.event_listener
                             .notify_key_event(KeygenEvent::NewKey(public_key))
                     }
@@ -1199,6 +1729,10 @@ where
             .last_generated_bridge_relay
             .as_ref()
             .map(|bridge| bridge.hostname.clone());
+        let entry_hostname = self
+            .last_generated_entry_relay
+            .as_ref()
+            .map(|entry| entry.hostname.clone());
         let location = relay.location.as_ref().cloned().unwrap();
         let hostname = relay.hostname.clone();
 
@@ -1212,6 +1746,7 @@ where
             mullvad_exit_ip: true,
             hostname: Some(hostname),
             bridge_hostname,
+            entry_hostname,
         })
     }
 
@@ -1320,6 +1855,7 @@ where
             }
 
             self.ensure_wireguard_keys_for_current_account();
+            self.refresh_port_forwards();
 
             if let Some(token) = account_token {
                 // update automatic rotation
@@ -1348,6 +1884,28 @@ where
         }
     }
 
+    fn on_get_connection_history(&mut self, tx: oneshot::Sender<Vec<Session>>) {
+        Self::oneshot_send(
+            tx,
+            self.connection_history.sessions(),
+            "get_connection_history response",
+        );
+    }
+
+    fn on_get_tunnel_stats(&mut self, tx: oneshot::Sender<Option<TunnelStats>>) {
+        let (stats_tx, stats_rx) = oneshot::channel();
+        self.send_tunnel_command(TunnelCommand::GetTunnelStats(stats_tx));
+        self.spawn_future(async move {
+            let stats = match stats_rx.compat().await {
+                Ok(stats) => stats.map(convert_tunnel_stats),
+                Err(_) => None,
+            };
+            if tx.send(stats).is_err() {
+                warn!("Unable to send get_tunnel_stats response to the daemon command sender");
+            }
+        });
+    }
+
     fn on_clear_account_history(&mut self, tx: oneshot::Sender<()>) {
         match self.account_history.clear() {
             Ok(_) => {
@@ -1449,132 +2007,651 @@ where
         }
     }
 
-    fn on_update_relay_settings(&mut self, tx: oneshot::Sender<()>, update: RelaySettingsUpdate) {
-        let save_result = self.settings.update_relay_settings(update);
-        match save_result {
+    #[cfg(target_os = "linux")]
+    fn on_add_split_tunnel_app(&mut self, tx: oneshot::Sender<()>, path: PathBuf) {
+        match self.settings.add_split_tunnel_app(path) {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, (), "update_relay_settings response");
+                Self::oneshot_send(tx, (), "add_split_tunnel_app response");
                 if settings_changed {
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
-                    info!("Initiating tunnel restart because the relay settings changed");
-                    self.reconnect_tunnel();
+                    self.on_split_tunnel_apps_changed();
                 }
             }
             Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
         }
     }
 
-    fn on_set_allow_lan(&mut self, tx: oneshot::Sender<()>, allow_lan: bool) {
-        let save_result = self.settings.set_allow_lan(allow_lan);
-        match save_result {
+    #[cfg(target_os = "linux")]
+    fn on_remove_split_tunnel_app(&mut self, tx: oneshot::Sender<()>, path: PathBuf) {
+        match self.settings.remove_split_tunnel_app(&path) {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, (), "set_allow_lan response");
+                Self::oneshot_send(tx, (), "remove_split_tunnel_app response");
                 if settings_changed {
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
-                    self.send_tunnel_command(TunnelCommand::AllowLan(allow_lan));
+                    self.on_split_tunnel_apps_changed();
                 }
             }
             Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
         }
     }
 
-    fn on_set_show_beta_releases(&mut self, tx: oneshot::Sender<()>, enabled: bool) {
-        let save_result = self.settings.set_show_beta_releases(enabled);
-        match save_result {
+    #[cfg(target_os = "linux")]
+    fn on_split_tunnel_apps_changed(&mut self) {
+        self.split_tunnel_apps
+            .set_apps(self.settings.split_tunnel.apps.clone());
+        self.event_listener
+            .notify_settings(self.settings.to_settings());
+    }
+
+    #[cfg(target_os = "linux")]
+    fn on_set_split_tunnel_mode(&mut self, tx: oneshot::Sender<()>, mode: SplitTunnelMode) {
+        match self.settings.set_split_tunnel_mode(mode) {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, (), "set_show_beta_releases response");
+                Self::oneshot_send(tx, (), "set_split_tunnel_mode response");
                 if settings_changed {
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
-                    let runtime = self.rpc_runtime.runtime();
-                    let mut handle = self.version_updater_handle.clone();
-                    runtime.block_on(async { handle.set_show_beta_releases(enabled).await });
+                    self.send_tunnel_command(TunnelCommand::SplitTunnelMode(mode));
                 }
             }
             Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
         }
     }
 
-    fn on_set_block_when_disconnected(
-        &mut self,
-        tx: oneshot::Sender<()>,
-        block_when_disconnected: bool,
-    ) {
-        let save_result = self
-            .settings
-            .set_block_when_disconnected(block_when_disconnected);
-        match save_result {
+    #[cfg(target_os = "linux")]
+    fn on_set_trusted_networks(&mut self, tx: oneshot::Sender<()>, rules: Vec<TrustedNetwork>) {
+        match self.settings.set_trusted_networks(rules) {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, (), "set_block_when_disconnected response");
+                Self::oneshot_send(tx, (), "set_trusted_networks response");
                 if settings_changed {
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
-                    self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
-                        block_when_disconnected,
-                    ));
+                    self.apply_trusted_networks();
                 }
             }
             Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
         }
     }
 
-    fn on_set_auto_connect(&mut self, tx: oneshot::Sender<()>, auto_connect: bool) {
-        let save_result = self.settings.set_auto_connect(auto_connect);
-        match save_result {
+    #[cfg(target_os = "linux")]
+    fn on_get_network_identity(&self, tx: oneshot::Sender<Option<NetworkIdentity>>) {
+        Self::oneshot_send(
+            tx,
+            self.network_identity.clone(),
+            "get_network_identity response",
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    fn on_set_firewall_exemptions(
+        &mut self,
+        tx: oneshot::Sender<()>,
+        exemptions: Vec<FirewallExemption>,
+    ) {
+        match self.settings.set_firewall_exemptions(exemptions.clone()) {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, (), "set auto-connect response");
+                Self::oneshot_send(tx, (), "set_firewall_exemptions response");
                 if settings_changed {
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
+                    self.send_tunnel_command(TunnelCommand::FirewallExemptions(exemptions));
                 }
             }
             Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
         }
     }
 
-    fn on_set_openvpn_mssfix(&mut self, tx: oneshot::Sender<()>, mssfix_arg: Option<u16>) {
-        let save_result = self.settings.set_openvpn_mssfix(mssfix_arg);
-        match save_result {
+    fn on_set_schedule(&mut self, tx: oneshot::Sender<()>, rules: Vec<ScheduleRule>) {
+        match self.settings.set_schedule(rules) {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, (), "set_openvpn_mssfix response");
+                Self::oneshot_send(tx, (), "set_schedule response");
                 if settings_changed {
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
-                    if let Some(TunnelType::OpenVpn) = self.get_connected_tunnel_type() {
-                        info!(
-                            "Initiating tunnel restart because the OpenVPN mssfix setting changed"
-                        );
-                        self.reconnect_tunnel();
-                    }
                 }
             }
             Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
         }
     }
 
-    fn on_set_bridge_settings(
+    fn on_import_settings(
         &mut self,
         tx: oneshot::Sender<Result<(), settings::Error>>,
-        new_settings: BridgeSettings,
+        mut new_settings: Settings,
     ) {
-        match self.settings.set_bridge_settings(new_settings) {
-            Ok(settings_changes) => {
-                if settings_changes {
+        // Settings are usually exported without the account token
+        if new_settings.get_account_token().is_none() {
+            new_settings.set_account_token(self.settings.get_account_token());
+        }
+
+        let old_settings = self.settings.to_settings();
+        let result = match self.settings.replace(new_settings) {
+            Ok(settings_changed) => {
+                if settings_changed {
+                    info!("Imported settings");
+                    self.apply_imported_settings(&old_settings);
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
-                    self.reconnect_tunnel();
-                };
-                Self::oneshot_send(tx, Ok(()), "set_bridge_settings");
+                }
+                Ok(())
             }
-
-            Err(e) => {
+            Err(error) => {
                 log::error!(
                     "{}",
-                    e.display_chain_with_msg("Failed to set new bridge settings")
+                    error.display_chain_with_msg("Failed to import settings")
                 );
-                Self::oneshot_send(tx, Err(e), "set_bridge_settings");
+                Err(error)
+            }
+        };
+        Self::oneshot_send(tx, result, "import_settings response");
+    }
+
+    /// Propagates the differences between `old_settings` and the current settings to the rest of
+    /// the daemon, without broadcasting the new settings.
+    fn apply_imported_settings(&mut self, old_settings: &Settings) {
+        let new_settings = self.settings.to_settings();
+
+        if new_settings.allow_lan != old_settings.allow_lan {
+            self.send_tunnel_command(TunnelCommand::AllowLan(new_settings.allow_lan));
+        }
+        if new_settings.block_when_disconnected != old_settings.block_when_disconnected {
+            self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
+                new_settings.block_when_disconnected,
+            ));
+        }
+        let dns_options = &new_settings.tunnel_options.dns_options;
+        if *dns_options != old_settings.tunnel_options.dns_options {
+            self.send_tunnel_command(TunnelCommand::CustomDns(dns_options.servers()));
+        }
+        if new_settings.firewall_exemptions != old_settings.firewall_exemptions {
+            self.send_tunnel_command(TunnelCommand::FirewallExemptions(
+                new_settings.firewall_exemptions.clone(),
+            ));
+        }
+        #[cfg(target_os = "linux")]
+        {
+            if new_settings.split_tunnel.apps != old_settings.split_tunnel.apps {
+                self.split_tunnel_apps
+                    .set_apps(new_settings.split_tunnel.apps.clone());
+            }
+            if new_settings.split_tunnel.mode != old_settings.split_tunnel.mode {
+                self.send_tunnel_command(TunnelCommand::SplitTunnelMode(
+                    new_settings.split_tunnel.mode,
+                ));
+            }
+        }
+        if new_settings.metrics != old_settings.metrics {
+            self.set_metrics_server(new_settings.metrics);
+        }
+        #[cfg(not(target_os = "android"))]
+        {
+            if new_settings.socks_proxy != old_settings.socks_proxy {
+                let listener = if new_settings.socks_proxy {
+                    talpid_core::socks_proxy::bind()
+                        .map_err(|error| {
+                            error!(
+                                "{}",
+                                error.display_chain_with_msg("Failed to start SOCKS5 proxy")
+                            )
+                        })
+                        .ok()
+                } else {
+                    None
+                };
+                self.send_tunnel_command(TunnelCommand::SocksProxy(listener));
+            }
+        }
+        if new_settings.show_beta_releases != old_settings.show_beta_releases {
+            let runtime = self.rpc_runtime.runtime();
+            let mut handle = self.version_updater_handle.clone();
+            let enabled = new_settings.show_beta_releases;
+            runtime.block_on(async { handle.set_show_beta_releases(enabled).await });
+        }
+        if new_settings.custom_lists != old_settings.custom_lists {
+            self.relay_selector
+                .set_custom_lists(new_settings.custom_lists.clone());
+        }
+
+        let account_token = new_settings.get_account_token();
+        let account_changed = account_token != old_settings.get_account_token();
+        if account_changed {
+            if let Some(token) = &account_token {
+                if let Err(e) = self.account_history.bump_history(token) {
+                    log::error!("Failed to bump account history: {}", e);
+                }
+            }
+            self.ensure_wireguard_keys_for_current_account();
+        }
+        let rotation_interval = new_settings.tunnel_options.wireguard.automatic_rotation;
+        if account_changed
+            || rotation_interval != old_settings.tunnel_options.wireguard.automatic_rotation
+        {
+            if let Some(token) = account_token.clone() {
+                self.wireguard_key_manager.set_rotation_interval(
+                    &mut self.account_history,
+                    token,
+                    rotation_interval
+                        .map(|hours| Duration::from_secs(60u64 * 60u64 * hours as u64)),
+                );
+            }
+        }
+
+        let relay_settings_changed =
+            new_settings.get_relay_settings() != old_settings.get_relay_settings();
+        if account_token.is_none() {
+            if account_changed {
+                info!("Disconnecting because account token was cleared");
+                self.set_target_state(TargetState::Unsecured);
+            }
+        } else if account_changed
+            || relay_settings_changed
+            || new_settings.bridge_settings != old_settings.bridge_settings
+            || new_settings.get_bridge_state() != old_settings.get_bridge_state()
+            || new_settings.tunnel_options.openvpn != old_settings.tunnel_options.openvpn
+            || new_settings.tunnel_options.wireguard.mtu
+                != old_settings.tunnel_options.wireguard.mtu
+            || new_settings.tunnel_options.generic != old_settings.tunnel_options.generic
+        {
+            info!("Initiating tunnel restart because imported settings changed");
+            self.reconnect_tunnel();
+        }
+        if relay_settings_changed {
+            self.measure_relay_latencies();
+        }
+        #[cfg(target_os = "linux")]
+        {
+            if new_settings.trusted_networks != old_settings.trusted_networks {
+                self.apply_trusted_networks();
+            }
+        }
+    }
+
+    fn on_update_relay_settings(
+        &mut self,
+        tx: oneshot::Sender<Result<(), settings::Error>>,
+        update: RelaySettingsUpdate,
+    ) {
+        let save_result = self.settings.update_relay_settings(update);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "update_relay_settings response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    info!("Initiating tunnel restart because the relay settings changed");
+                    self.reconnect_tunnel();
+                    self.measure_relay_latencies();
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "update_relay_settings response");
+            }
+        }
+    }
+
+    fn on_set_allow_lan(
+        &mut self,
+        tx: oneshot::Sender<Result<(), settings::Error>>,
+        allow_lan: bool,
+    ) {
+        let save_result = self.settings.set_allow_lan(allow_lan);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_allow_lan response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.send_tunnel_command(TunnelCommand::AllowLan(allow_lan));
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_allow_lan response");
+            }
+        }
+    }
+
+    fn on_set_show_beta_releases(&mut self, tx: oneshot::Sender<()>, enabled: bool) {
+        let save_result = self.settings.set_show_beta_releases(enabled);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, (), "set_show_beta_releases response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    let runtime = self.rpc_runtime.runtime();
+                    let mut handle = self.version_updater_handle.clone();
+                    runtime.block_on(async { handle.set_show_beta_releases(enabled).await });
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
+    fn on_set_tunnel_stats_events(&mut self, tx: oneshot::Sender<()>, enabled: bool) {
+        let save_result = self.settings.set_tunnel_stats_events(enabled);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, (), "set_tunnel_stats_events response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
+    fn on_set_socks_proxy(
+        &mut self,
+        tx: oneshot::Sender<Result<(), SocksProxyError>>,
+        enabled: bool,
+    ) {
+        // Bind before saving, so that a port that is in use is reported to the caller
+        #[cfg(not(target_os = "android"))]
+        let listener = if enabled && !self.settings.socks_proxy {
+            match talpid_core::socks_proxy::bind() {
+                Ok(listener) => Some(listener),
+                Err(error) => {
+                    error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to start SOCKS5 proxy")
+                    );
+                    Self::oneshot_send(
+                        tx,
+                        Err(SocksProxyError::Start(error)),
+                        "set_socks_proxy response",
+                    );
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        let save_result = self.settings.set_socks_proxy(enabled);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_socks_proxy response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    #[cfg(not(target_os = "android"))]
+                    self.send_tunnel_command(TunnelCommand::SocksProxy(listener));
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(
+                    tx,
+                    Err(SocksProxyError::SaveSettings(e)),
+                    "set_socks_proxy response",
+                );
+            }
+        }
+    }
+
+    fn on_set_log_level(&mut self, tx: oneshot::Sender<()>, log_level: log::LevelFilter) {
+        logging::set_log_level(log_level);
+        info!("Changed log level to {}", log_level);
+        Self::oneshot_send(tx, (), "set_log_level response");
+    }
+
+    fn on_set_metrics(&mut self, tx: oneshot::Sender<()>, enabled: bool) {
+        let save_result = self.settings.set_metrics(enabled);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, (), "set_metrics response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.set_metrics_server(enabled);
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
+    fn set_metrics_server(&mut self, enabled: bool) {
+        if !enabled {
+            self.metrics_server = None;
+        } else if self.metrics_server.is_none() {
+            match metrics::MetricsServer::start(self.tx.clone()) {
+                Ok(server) => self.metrics_server = Some(server),
+                Err(error) => error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to start metrics server")
+                ),
+            }
+        }
+    }
+
+    fn handle_get_metrics(&mut self, tx: mpsc::Sender<metrics::Metrics>) {
+        // The relay list has never been fetched if it was last updated at the epoch
+        let last_updated = self.relay_selector.last_updated();
+        let relay_list_age = if last_updated == UNIX_EPOCH {
+            None
+        } else {
+            Some(
+                SystemTime::now()
+                    .duration_since(last_updated)
+                    .unwrap_or_default(),
+            )
+        };
+        let wireguard_key_age = self
+            .settings
+            .get_account_token()
+            .and_then(|account| self.account_history.get(&account).ok()?)
+            .and_then(|account_entry| account_entry.wireguard)
+            .and_then(|wg| {
+                chrono::Utc::now()
+                    .signed_duration_since(wg.created)
+                    .to_std()
+                    .ok()
+            });
+        let mut metrics = metrics::Metrics::new(
+            &self.tunnel_state,
+            &self.metrics_counters,
+            relay_list_age,
+            wireguard_key_age,
+            self.app_version_info.supported,
+            self.app_version_info.suggested_upgrade.is_some(),
+        );
+
+        let (stats_tx, stats_rx) = oneshot::channel();
+        self.send_tunnel_command(TunnelCommand::GetTunnelStats(stats_tx));
+        self.spawn_future(async move {
+            if let Ok(stats) = stats_rx.compat().await {
+                metrics.tunnel_stats = stats.map(convert_tunnel_stats);
+            }
+            if tx.send(metrics).is_err() {
+                warn!("Unable to send metrics to the metrics server");
+            }
+        });
+    }
+
+    fn on_set_block_when_disconnected(
+        &mut self,
+        tx: oneshot::Sender<Result<(), settings::Error>>,
+        block_when_disconnected: bool,
+    ) {
+        let save_result = self
+            .settings
+            .set_block_when_disconnected(block_when_disconnected);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_block_when_disconnected response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
+                        block_when_disconnected,
+                    ));
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_block_when_disconnected response");
+            }
+        }
+    }
+
+    fn on_set_auto_connect(
+        &mut self,
+        tx: oneshot::Sender<Result<(), settings::Error>>,
+        auto_connect: bool,
+    ) {
+        let save_result = self.settings.set_auto_connect(auto_connect);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set auto-connect response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set auto-connect response");
+            }
+        }
+    }
+
+    fn on_set_openvpn_mssfix(&mut self, tx: oneshot::Sender<()>, mssfix_arg: Option<u16>) {
+        let save_result = self.settings.set_openvpn_mssfix(mssfix_arg);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, (), "set_openvpn_mssfix response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if let Some(TunnelType::OpenVpn) = self.get_connected_tunnel_type() {
+                        info!(
+                            "Initiating tunnel restart because the OpenVPN mssfix setting changed"
+                        );
+                        self.reconnect_tunnel();
+                    }
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
+    fn on_create_custom_list(
+        &mut self,
+        tx: oneshot::Sender<Result<custom_list::Id, custom_list::Error>>,
+        name: String,
+    ) {
+        let id = uuid::Uuid::new_v4().to_string();
+        let mut custom_lists = self.settings.custom_lists.clone();
+        let result = custom_lists.create(id.clone(), name).map(|()| id);
+        self.update_custom_lists(tx, custom_lists, result, None);
+    }
+
+    fn on_delete_custom_list(
+        &mut self,
+        tx: oneshot::Sender<Result<(), custom_list::Error>>,
+        name: String,
+    ) {
+        let mut custom_lists = self.settings.custom_lists.clone();
+        let result = custom_lists.delete(&name).and_then(|list| {
+            if self.settings.is_custom_list_in_use(&list.id) {
+                Err(custom_list::Error::ListInUse(name))
+            } else {
+                Ok(())
+            }
+        });
+        self.update_custom_lists(tx, custom_lists, result, None);
+    }
+
+    fn on_rename_custom_list(
+        &mut self,
+        tx: oneshot::Sender<Result<(), custom_list::Error>>,
+        name: String,
+        new_name: String,
+    ) {
+        let mut custom_lists = self.settings.custom_lists.clone();
+        let result = custom_lists.rename(&name, new_name);
+        self.update_custom_lists(tx, custom_lists, result, None);
+    }
+
+    fn on_add_custom_list_location(
+        &mut self,
+        tx: oneshot::Sender<Result<(), custom_list::Error>>,
+        name: String,
+        location: LocationConstraint,
+    ) {
+        let mut custom_lists = self.settings.custom_lists.clone();
+        let result = custom_lists.add_location(&name, location);
+        let changed_list = result.as_ref().ok().cloned();
+        self.update_custom_lists(tx, custom_lists, result.map(|_| ()), changed_list);
+    }
+
+    fn on_remove_custom_list_location(
+        &mut self,
+        tx: oneshot::Sender<Result<(), custom_list::Error>>,
+        name: String,
+        location: LocationConstraint,
+    ) {
+        let mut custom_lists = self.settings.custom_lists.clone();
+        let result = custom_lists.remove_location(&name, &location);
+        let changed_list = result.as_ref().ok().cloned();
+        self.update_custom_lists(tx, custom_lists, result.map(|_| ()), changed_list);
+    }
+
+    /// Saves the updated custom lists if the operation succeeded, and reconnects if the locations
+    /// of a list in use by the relay settings changed.
+    fn update_custom_lists<T>(
+        &mut self,
+        tx: oneshot::Sender<Result<T, custom_list::Error>>,
+        custom_lists: CustomListsSettings,
+        result: Result<T, custom_list::Error>,
+        changed_list: Option<custom_list::Id>,
+    ) {
+        if result.is_ok() {
+            match self.settings.set_custom_lists(custom_lists.clone()) {
+                Ok(settings_changed) => {
+                    if settings_changed {
+                        self.relay_selector.set_custom_lists(custom_lists);
+                        self.event_listener
+                            .notify_settings(self.settings.to_settings());
+                        let list_in_use = changed_list
+                            .map(|id| self.settings.is_custom_list_in_use(&id))
+                            .unwrap_or(false);
+                        if list_in_use {
+                            info!("Initiating tunnel restart because a custom list in use changed");
+                            self.reconnect_tunnel();
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                    return;
+                }
+            }
+        }
+        Self::oneshot_send(tx, result, "custom list response");
+    }
+
+    fn on_set_bridge_settings(
+        &mut self,
+        tx: oneshot::Sender<Result<(), settings::Error>>,
+        new_settings: BridgeSettings,
+    ) {
+        match self.settings.set_bridge_settings(new_settings) {
+            Ok(settings_changes) => {
+                if settings_changes {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.reconnect_tunnel();
+                };
+                Self::oneshot_send(tx, Ok(()), "set_bridge_settings");
+            }
+
+            Err(e) => {
+                log::error!(
+                    "{}",
+                    e.display_chain_with_msg("Failed to set new bridge settings")
+                );
+                Self::oneshot_send(tx, Err(e), "set_bridge_settings");
             }
         }
     }
@@ -1606,11 +2683,15 @@ where
     }
 
 
-    fn on_set_enable_ipv6(&mut self, tx: oneshot::Sender<()>, enable_ipv6: bool) {
+    fn on_set_enable_ipv6(
+        &mut self,
+        tx: oneshot::Sender<Result<(), settings::Error>>,
+        enable_ipv6: bool,
+    ) {
         let save_result = self.settings.set_enable_ipv6(enable_ipv6);
         match save_result {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, (), "set_enable_ipv6 response");
+                Self::oneshot_send(tx, Ok(()), "set_enable_ipv6 response");
                 if settings_changed {
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
@@ -1618,7 +2699,32 @@ where
                     self.reconnect_tunnel();
                 }
             }
-            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_enable_ipv6 response");
+            }
+        }
+    }
+
+    fn on_set_dns_options(
+        &mut self,
+        tx: oneshot::Sender<Result<(), settings::Error>>,
+        dns_options: DnsOptions,
+    ) {
+        let save_result = self.settings.set_dns_options(dns_options.clone());
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_dns_options response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.send_tunnel_command(TunnelCommand::CustomDns(dns_options.servers()));
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_dns_options response");
+            }
         }
     }
 
@@ -1701,10 +2807,7 @@ where
                 .map(|data| {
                     data.unwrap_or_else(|| {
                         log::error!("Account token set in settings but not in account history");
-                        account_history::AccountEntry {
-                            account: account_token.clone(),
-                            wireguard: None,
-                        }
+                        account_history::AccountEntry::new(account_token.clone())
                     })
                 })?;
 
@@ -1720,7 +2823,7 @@ where
             match gen_result {
                 Ok(new_data) => {
                     let public_key = new_data.get_public_key();
-                    account_entry.wireguard = Some(new_data);
+                    account_entry.set_wireguard_key(new_data);
                     self.account_history.insert(account_entry).map_err(|e| {
                         format!("Failed to add new wireguard key to account data: {}", e)
                     })?;
@@ -1813,6 +2916,409 @@ where
         });
     }
 
+    fn on_list_wireguard_keys(
+        &mut self,
+        tx: oneshot::Sender<Result<Vec<wireguard::AccountKey>, WireguardKeyError>>,
+    ) {
+        let account = match self.settings.get_account_token() {
+            Some(account) => account,
+            None => {
+                Self::oneshot_send(
+                    tx,
+                    Err(WireguardKeyError::NoAccount),
+                    "list_wireguard_keys response",
+                );
+                return;
+            }
+        };
+        let account_entry = match self.account_history.get(&account) {
+            Ok(entry) => {
+                entry.unwrap_or_else(|| account_history::AccountEntry::new(account.clone()))
+            }
+            Err(error) => {
+                Self::oneshot_send(
+                    tx,
+                    Err(WireguardKeyError::AccountHistory(error)),
+                    "list_wireguard_keys response",
+                );
+                return;
+            }
+        };
+
+        let stored_keys: Vec<_> = account_entry
+            .wireguard
+            .iter()
+            .map(|data| (data, true))
+            .chain(
+                account_entry
+                    .previous_wireguard
+                    .iter()
+                    .map(|data| (data, false)),
+            )
+            .map(|(data, active)| wireguard::AccountKey {
+                key: data.private_key.public_key(),
+                created: Some(data.created),
+                active,
+                registered: false,
+                stored: true,
+            })
+            .collect();
+
+        let list_request = self.wireguard_key_manager.list_keys(account);
+        self.rpc_handle.service().spawn(async move {
+            let result = list_request.await.map(|registered_keys| {
+                let mut keys = stored_keys;
+                for registered_key in registered_keys {
+                    match keys.iter_mut().find(|key| key.key == registered_key.key) {
+                        Some(key) => key.registered = true,
+                        None => keys.push(registered_key),
+                    }
+                }
+                keys
+            });
+            Self::oneshot_send(
+                tx,
+                result.map_err(WireguardKeyError::KeyManager),
+                "list_wireguard_keys response",
+            );
+        });
+    }
+
+    fn on_revoke_wireguard_key(
+        &mut self,
+        tx: oneshot::Sender<Result<(), WireguardKeyError>>,
+        key: talpid_types::net::wireguard::PublicKey,
+    ) {
+        let result = self.revoke_wireguard_key(key);
+        Self::oneshot_send(tx, result, "revoke_wireguard_key response");
+    }
+
+    fn revoke_wireguard_key(
+        &mut self,
+        key: talpid_types::net::wireguard::PublicKey,
+    ) -> Result<(), WireguardKeyError> {
+        let account = self
+            .settings
+            .get_account_token()
+            .ok_or(WireguardKeyError::NoAccount)?;
+        let mut account_entry = self
+            .account_history
+            .get(&account)
+            .map_err(WireguardKeyError::AccountHistory)?
+            .unwrap_or_else(|| account_history::AccountEntry::new(account.clone()));
+        if account_entry
+            .wireguard
+            .as_ref()
+            .map(|data| data.private_key.public_key() == key)
+            .unwrap_or(false)
+        {
+            return Err(WireguardKeyError::KeyInUse);
+        }
+
+        self.wireguard_key_manager
+            .remove_key(account, key.clone())
+            .map_err(WireguardKeyError::KeyManager)?;
+
+        if account_entry.find_previous_wireguard_key(&key).is_some() {
+            account_entry.forget_wireguard_key(&key);
+            self.account_history
+                .insert(account_entry)
+                .map_err(WireguardKeyError::AccountHistory)?;
+        }
+        log::info!("Revoked wireguard key {}", key);
+        Ok(())
+    }
+
+    fn on_restore_wireguard_key(
+        &mut self,
+        tx: oneshot::Sender<Result<wireguard::PublicKey, WireguardKeyError>>,
+        key: talpid_types::net::wireguard::PublicKey,
+    ) {
+        let result = self.restore_wireguard_key(key);
+        Self::oneshot_send(tx, result, "restore_wireguard_key response");
+    }
+
+    fn restore_wireguard_key(
+        &mut self,
+        key: talpid_types::net::wireguard::PublicKey,
+    ) -> Result<wireguard::PublicKey, WireguardKeyError> {
+        let account = self
+            .settings
+            .get_account_token()
+            .ok_or(WireguardKeyError::NoAccount)?;
+        let mut account_entry = self
+            .account_history
+            .get(&account)
+            .map_err(WireguardKeyError::AccountHistory)?
+            .unwrap_or_else(|| account_history::AccountEntry::new(account.clone()));
+        if let Some(data) = &account_entry.wireguard {
+            if data.private_key.public_key() == key {
+                return Ok(data.get_public_key());
+            }
+        }
+        let private_key = account_entry
+            .find_previous_wireguard_key(&key)
+            .ok_or(WireguardKeyError::UnknownKey)?
+            .private_key
+            .clone();
+
+        let data = self
+            .wireguard_key_manager
+            .restore_key(account.clone(), private_key)
+            .map_err(WireguardKeyError::KeyManager)?;
+        let public_key = data.get_public_key();
+        account_entry.set_wireguard_key(data);
+        self.account_history
+            .insert(account_entry)
+            .map_err(WireguardKeyError::AccountHistory)?;
+        log::info!("Restored wireguard key {}", key);
+
+        if let Some(TunnelType::Wireguard) = self.get_connected_tunnel_type() {
+            self.reconnect_tunnel();
+        }
+        self.event_listener
+            .notify_key_event(KeygenEvent::NewKey(public_key.clone()));
+        self.wireguard_key_manager
+            .reset_rotation(&mut self.account_history, account);
+        self.refresh_port_forwards();
+
+        Ok(public_key)
+    }
+
+    fn on_get_wireguard_config(
+        &mut self,
+        tx: oneshot::Sender<Result<WgQuickConfig, WireguardConfigError>>,
+        hostname: Option<String>,
+    ) {
+        let result = self.get_wireguard_config(hostname);
+        Self::oneshot_send(tx, result, "get_wireguard_config response");
+    }
+
+    fn get_wireguard_config(
+        &mut self,
+        hostname: Option<String>,
+    ) -> Result<WgQuickConfig, WireguardConfigError> {
+        let wg_data = self
+            .settings
+            .get_account_token()
+            .and_then(|account| self.account_history.get(&account).ok()?)
+            .and_then(|entry| entry.wireguard)
+            .ok_or(WireguardConfigError::NoKey)?;
+        let relay = match hostname {
+            Some(hostname) => self
+                .relay_selector
+                .get_relay_by_hostname(&hostname)
+                .ok_or(WireguardConfigError::UnknownRelay(hostname))?,
+            None => self
+                .last_generated_relay
+                .clone()
+                .ok_or(WireguardConfigError::NoRelay)?,
+        };
+        let endpoint_data = relay
+            .tunnels
+            .wireguard
+            .first()
+            .ok_or_else(|| WireguardConfigError::NotWireguardRelay(relay.hostname.clone()))?;
+        // Prefer the standard WireGuard port when the relay listens on it
+        let port = endpoint_data
+            .port_ranges
+            .iter()
+            .find(|(start, end)| (*start..=*end).contains(&WIREGUARD_DEFAULT_PORT))
+            .map(|_| WIREGUARD_DEFAULT_PORT)
+            .or_else(|| endpoint_data.port_ranges.first().map(|(start, _)| *start))
+            .ok_or_else(|| WireguardConfigError::NotWireguardRelay(relay.hostname.clone()))?;
+
+        Ok(WgQuickConfig {
+            private_key: wg_data.private_key,
+            addresses: vec![
+                wg_data.addresses.ipv4_address.into(),
+                wg_data.addresses.ipv6_address.into(),
+            ],
+            // The relays run a DNS resolver on the gateway
+            dns_servers: vec![
+                endpoint_data.ipv4_gateway.into(),
+                endpoint_data.ipv6_gateway.into(),
+            ],
+            mtu: self.settings.tunnel_options.wireguard.mtu,
+            peers: vec![WgQuickPeer {
+                public_key: endpoint_data.public_key.clone(),
+                preshared_key: None,
+                allowed_ips: vec![
+                    ipnetwork::Ipv4Network::new(Ipv4Addr::UNSPECIFIED, 0)
+                        .unwrap()
+                        .into(),
+                    ipnetwork::Ipv6Network::new(Ipv6Addr::UNSPECIFIED, 0)
+                        .unwrap()
+                        .into(),
+                ],
+                endpoint_host: relay.ipv4_addr_in.to_string(),
+                endpoint_port: port,
+                persistent_keepalive: None,
+            }],
+        })
+    }
+
+    fn get_account_and_wireguard_key(
+        &self,
+    ) -> Option<(AccountToken, talpid_types::net::wireguard::PublicKey)> {
+        let account = self.settings.get_account_token()?;
+        let wireguard_data = self.account_history.get(&account).ok()??.wireguard?;
+        Some((account, wireguard_data.private_key.public_key()))
+    }
+
+    /// Returns a future that fetches the ports forwarded to `key` and passes them on to the
+    /// daemon.
+    fn fetch_port_forwards(
+        &self,
+        account: AccountToken,
+        key: talpid_types::net::wireguard::PublicKey,
+    ) -> impl std::future::Future<Output = Result<Vec<PortForward>, mullvad_rpc::rest::Error>> {
+        let request = self.port_forward_proxy.list_port_forwards(account, &key);
+        let daemon_tx = self.tx.clone();
+        async move {
+            let port_forwards = request.await?;
+            let _ = daemon_tx.send(InternalDaemonEvent::PortForwards(
+                key,
+                port_forwards.clone(),
+            ));
+            Ok(port_forwards)
+        }
+    }
+
+    fn refresh_port_forwards(&mut self) {
+        match self.get_account_and_wireguard_key() {
+            Some((account, key)) => {
+                let fetch = self.fetch_port_forwards(account, key);
+                self.rpc_handle.service().spawn(async move {
+                    if let Err(error) = fetch.await {
+                        log::error!(
+                            "{}",
+                            error.display_chain_with_msg("Failed to fetch port forwards")
+                        );
+                    }
+                });
+            }
+            None => self.set_port_forwards(Vec::new()),
+        }
+    }
+
+    fn handle_port_forwards(
+        &mut self,
+        key: talpid_types::net::wireguard::PublicKey,
+        port_forwards: Vec<PortForward>,
+    ) {
+        match self.get_account_and_wireguard_key() {
+            Some((_, current_key)) if current_key == key => self.set_port_forwards(port_forwards),
+            _ => log::debug!("Dropping port forwards since the wireguard key has changed"),
+        }
+    }
+
+    /// Opens the forwarded ports in the firewall while connected.
+    fn set_port_forwards(&mut self, port_forwards: Vec<PortForward>) {
+        if self.port_forwards != port_forwards {
+            let ports = port_forwards
+                .iter()
+                .map(|port_forward| port_forward.port)
+                .collect();
+            self.port_forwards = port_forwards;
+            self.send_tunnel_command(TunnelCommand::ForwardedPorts(ports));
+        }
+    }
+
+    fn on_get_port_forwards(
+        &mut self,
+        tx: oneshot::Sender<Result<Vec<PortForward>, PortForwardError>>,
+    ) {
+        let (account, key) = match self.get_account_and_wireguard_key() {
+            Some(account_and_key) => account_and_key,
+            None => {
+                Self::oneshot_send(
+                    tx,
+                    Err(PortForwardError::NoKey),
+                    "get_port_forwards response",
+                );
+                return;
+            }
+        };
+
+        let fetch = self.fetch_port_forwards(account, key);
+        self.rpc_handle.service().spawn(async move {
+            let result = fetch.await.map_err(PortForwardError::RestError);
+            Self::oneshot_send(tx, result, "get_port_forwards response");
+        });
+    }
+
+    fn on_add_port_forward(
+        &mut self,
+        tx: oneshot::Sender<Result<PortForward, PortForwardError>>,
+        country: CountryCode,
+        city: CityCode,
+    ) {
+        let (account, key) = match self.get_account_and_wireguard_key() {
+            Some(account_and_key) => account_and_key,
+            None => {
+                Self::oneshot_send(
+                    tx,
+                    Err(PortForwardError::NoKey),
+                    "add_port_forward response",
+                );
+                return;
+            }
+        };
+
+        let request =
+            self.port_forward_proxy
+                .add_port_forward(account.clone(), &key, country, city);
+        let fetch = self.fetch_port_forwards(account, key);
+        self.rpc_handle.service().spawn(async move {
+            let result = request.await.map_err(PortForwardError::RestError);
+            if result.is_ok() {
+                if let Err(error) = fetch.await {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to fetch port forwards")
+                    );
+                }
+            }
+            Self::oneshot_send(tx, result, "add_port_forward response");
+        });
+    }
+
+    fn on_remove_port_forward(
+        &mut self,
+        tx: oneshot::Sender<Result<(), PortForwardError>>,
+        port: u16,
+    ) {
+        let (account, key) = match self.get_account_and_wireguard_key() {
+            Some(account_and_key) => account_and_key,
+            None => {
+                Self::oneshot_send(
+                    tx,
+                    Err(PortForwardError::NoKey),
+                    "remove_port_forward response",
+                );
+                return;
+            }
+        };
+
+        let request = self
+            .port_forward_proxy
+            .remove_port_forward(account.clone(), &key, port);
+        let fetch = self.fetch_port_forwards(account, key);
+        self.rpc_handle.service().spawn(async move {
+            let result = request.await.map_err(PortForwardError::RestError);
+            if result.is_ok() {
+                if let Err(error) = fetch.await {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to fetch port forwards")
+                    );
+                }
+            }
+            Self::oneshot_send(tx, result, "remove_port_forward response");
+        });
+    }
+
     fn on_get_settings(&self, tx: oneshot::Sender<Settings>) {
         Self::oneshot_send(tx, self.settings.to_settings(), "get_settings response");
     }
@@ -1998,3 +3504,11 @@ impl TunnelParametersGenerator for MullvadTunnelParametersGenerator {
         }
     }
 }
+
+fn convert_tunnel_stats(stats: talpid_core::tunnel::TunnelStats) -> TunnelStats {
+    TunnelStats {
+        tx_bytes: stats.tx_bytes,
+        rx_bytes: stats.rx_bytes,
+        last_handshake: stats.last_handshake.map(chrono::DateTime::from),
+    }
+}
@@ -4,7 +4,15 @@ use fern::{
     Output,
 };
 use log;
-use std::{fmt, io, path::PathBuf};
+use mullvad_types::states::TunnelState;
+use std::{
+    fmt, io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
 use talpid_core::logging::rotate_log;
 
 #[derive(err_derive::Error, Debug)]
@@ -63,22 +71,39 @@ const LINE_SEPARATOR: &str = "\r\n";
 
 const DATE_TIME_FORMAT_STR: &str = "[%Y-%m-%d %H:%M:%S%.3f]";
 
+/// The log level that `set_log_level` last set, stored as a `log::LevelFilter`.
+static LOG_LEVEL: AtomicUsize = AtomicUsize::new(log::LevelFilter::Info as usize);
+
+lazy_static::lazy_static! {
+    /// Name of the current tunnel state, included in JSON log records.
+    static ref TUNNEL_STATE: Mutex<&'static str> = Mutex::new("disconnected");
+}
+
+/// How log records are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per line, with the timestamp, level, target, message and tunnel state.
+    Json,
+}
+
 pub fn init_logger(
     log_level: log::LevelFilter,
     log_file: Option<&PathBuf>,
     output_timestamp: bool,
+    log_format: LogFormat,
 ) -> Result<(), Error> {
-    let mut top_dispatcher = fern::Dispatch::new().level(log_level);
-    for silenced_crate in SILENCED_CRATES {
-        top_dispatcher = top_dispatcher.level_for(*silenced_crate, log::LevelFilter::Warn);
-    }
-    for silenced_crate in SLIGHTLY_SILENCED_CRATES {
-        top_dispatcher = top_dispatcher.level_for(*silenced_crate, one_level_quieter(log_level));
-    }
+    // Records are filtered against the current level instead of fixed levels, so that the level
+    // can be changed after the logger is installed.
+    let mut top_dispatcher = fern::Dispatch::new().filter(|metadata| {
+        metadata.level() <= target_level(metadata.target(), current_log_level())
+    });
 
     let stdout_formatter = Formatter {
         output_timestamp,
         output_color: true,
+        log_format,
     };
     let stdout_dispatcher = fern::Dispatch::new()
         .format(move |out, message, record| stdout_formatter.output_msg(out, message, record))
@@ -90,6 +115,7 @@ pub fn init_logger(
         let file_formatter = Formatter {
             output_timestamp: true,
             output_color: false,
+            log_format,
         };
         let f = fern::log_file(log_file).map_err(|source| Error::WriteFile {
             path: log_file.display().to_string(),
@@ -109,9 +135,56 @@ pub fn init_logger(
         top_dispatcher = top_dispatcher.chain(logger);
     }
     top_dispatcher.apply().map_err(Error::SetLoggerError)?;
+    set_log_level(log_level);
     Ok(())
 }
 
+/// Changes the log level without reinstalling the logger. Crates in `SILENCED_CRATES` keep
+/// logging warnings and errors only, regardless of the level.
+pub fn set_log_level(log_level: log::LevelFilter) {
+    LOG_LEVEL.store(log_level as usize, Ordering::SeqCst);
+    log::set_max_level(log_level.max(log::LevelFilter::Warn));
+}
+
+fn current_log_level() -> log::LevelFilter {
+    use log::LevelFilter::*;
+    match LOG_LEVEL.load(Ordering::SeqCst) {
+        0 => Off,
+        1 => Error,
+        2 => Warn,
+        3 => Info,
+        4 => Debug,
+        _ => Trace,
+    }
+}
+
+/// Sets the tunnel state that is included in JSON log records.
+pub fn set_tunnel_state(tunnel_state: &TunnelState) {
+    *TUNNEL_STATE.lock().expect("Tunnel state lock poisoned") = tunnel_state.name();
+}
+
+/// Returns the level that records from `target` are logged at, given the overall log level.
+fn target_level(target: &str, log_level: log::LevelFilter) -> log::LevelFilter {
+    if SILENCED_CRATES
+        .iter()
+        .any(|silenced_crate| is_in_crate(target, silenced_crate))
+    {
+        log::LevelFilter::Warn
+    } else if SLIGHTLY_SILENCED_CRATES
+        .iter()
+        .any(|silenced_crate| is_in_crate(target, silenced_crate))
+    {
+        one_level_quieter(log_level)
+    } else {
+        log_level
+    }
+}
+
+fn is_in_crate(target: &str, crate_name: &str) -> bool {
+    target == crate_name
+        || (target.starts_with(crate_name) && target[crate_name.len()..].starts_with("::"))
+}
+
 fn one_level_quieter(level: log::LevelFilter) -> log::LevelFilter {
     use log::LevelFilter::*;
     match level {
@@ -124,10 +197,11 @@ fn one_level_quieter(level: log::LevelFilter) -> log::LevelFilter {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 struct Formatter {
     pub output_timestamp: bool,
     pub output_color: bool,
+    pub log_format: LogFormat,
 }
 
 impl Formatter {
@@ -153,6 +227,9 @@ impl Formatter {
         message: &fmt::Arguments<'_>,
         record: &log::Record<'_>,
     ) {
+        if self.log_format == LogFormat::Json {
+            return Self::output_json(out, message, record);
+        }
         let message = escape_newlines(format!("{}", message));
 
         out.finish(format_args!(
@@ -163,6 +240,22 @@ impl Formatter {
             message,
         ))
     }
+
+    fn output_json(
+        out: fern::FormatCallback<'_>,
+        message: &fmt::Arguments<'_>,
+        record: &log::Record<'_>,
+    ) {
+        let tunnel_state = *TUNNEL_STATE.lock().expect("Tunnel state lock poisoned");
+        let line = serde_json::json!({
+            "timestamp": chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+            "level": record.level().to_string(),
+            "target": record.target(),
+            "message": message.to_string(),
+            "tunnel_state": tunnel_state,
+        });
+        out.finish(format_args!("{}", line))
+    }
 }
 
 #[cfg(not(windows))]
@@ -174,3 +267,26 @@ fn escape_newlines(text: String) -> String {
 fn escape_newlines(text: String) -> String {
     text.replace("\n", LINE_SEPARATOR)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use log::LevelFilter;
+
+    #[test]
+    fn test_target_level() {
+        assert_eq!(
+            target_level("mullvad_daemon", LevelFilter::Trace),
+            LevelFilter::Trace
+        );
+        assert_eq!(
+            target_level("hyper::client", LevelFilter::Trace),
+            LevelFilter::Warn
+        );
+        assert_eq!(
+            target_level("hyper_rustls", LevelFilter::Trace),
+            LevelFilter::Trace
+        );
+        assert_eq!(target_level("nftnl", LevelFilter::Debug), LevelFilter::Info);
+    }
+}
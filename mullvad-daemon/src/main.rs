@@ -42,6 +42,7 @@ fn init_logging(config: &cli::Config) -> Result<Option<PathBuf>, String> {
         config.log_level,
         log_file.as_ref(),
         config.log_stdout_timestamps,
+        config.log_format,
     )
     .map_err(|e| e.display_chain_with_msg("Unable to initialize logger"))?;
     log_panics::init();
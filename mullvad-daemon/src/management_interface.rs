@@ -1,4 +1,7 @@
-use crate::{BoxFuture, DaemonCommand, DaemonCommandSender, EventListener};
+use crate::{
+    settings, BoxFuture, DaemonCommand, DaemonCommandSender, EventListener, PortForwardError,
+    SocksProxyError, WireguardConfigError, WireguardKeyError,
+};
 use jsonrpc_core::{
     futures::{future, sync, Future},
     Error, ErrorCode, MetaIoHandler, Metadata,
@@ -10,25 +13,46 @@ use mullvad_paths;
 use mullvad_rpc::{rest::Error as RestError, StatusCode};
 use mullvad_types::{
     account::{AccountData, AccountToken, VoucherSubmission},
-    location::GeoIpLocation,
-    relay_constraints::{BridgeSettings, BridgeState, RelaySettingsUpdate},
+    connection_history, custom_list,
+    location::{CityCode, CountryCode, GeoIpLocation},
+    port_forward::PortForward,
+    relay_constraints::{BridgeSettings, BridgeState, LocationConstraint, RelaySettingsUpdate},
     relay_list::RelayList,
-    settings::Settings,
-    states::{TargetState, TunnelState},
-    version, wireguard, DaemonEvent,
+    schedule::ScheduleRule,
+    settings::{self as settings_types, DnsOptions, Settings},
+    states::{TargetState, TunnelState, TunnelStats},
+    trusted_network::{TrustedNetwork, TrustedNetworkEvent},
+    version,
+    wg_quick::WgQuickConfig,
+    wireguard, DaemonEvent,
 };
 use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{hash_map::Entry, HashMap},
+    path::PathBuf,
     sync::Arc,
 };
 use talpid_ipc;
-use talpid_types::ErrorExt;
+use talpid_types::{
+    net::{
+        openvpn, wireguard::PublicKey as WireguardPublicKey, FirewallExemption, NetworkIdentity,
+        SplitTunnelMode,
+    },
+    ErrorExt,
+};
 use uuid;
 
 pub const INVALID_VOUCHER_CODE: i64 = -400;
 pub const VOUCHER_USED_ALREADY_CODE: i64 = -401;
 pub const INVALID_ACCOUNT_CODE: i64 = -200;
+pub const CUSTOM_LIST_ERROR_CODE: i64 = -600;
+pub const SETTING_LOCKED_CODE: i64 = -700;
+pub const PORT_FORWARD_ERROR_CODE: i64 = -800;
+pub const WIREGUARD_KEY_ERROR_CODE: i64 = -900;
+pub const WIREGUARD_CONFIG_ERROR_CODE: i64 = -1000;
+pub const SETTINGS_SAVE_ERROR_CODE: i64 = -1100;
+pub const SOCKS_PROXY_ERROR_CODE: i64 = -1200;
 
 
 build_rpc_trait! {
@@ -130,6 +154,32 @@ build_rpc_trait! {
         #[rpc(meta, name = "clear_account_history")]
         fn clear_account_history(&self, Self::Metadata) -> BoxFuture<(), Error>;
 
+        /// Returns past tunnel sessions, most recent first
+        #[rpc(meta, name = "get_connection_history")]
+        fn get_connection_history(&self, Self::Metadata) -> BoxFuture<Vec<connection_history::Session>, Error>;
+
+        /// Returns the traffic counters of the current tunnel, if there is one
+        #[rpc(meta, name = "get_tunnel_stats")]
+        fn get_tunnel_stats(&self, Self::Metadata) -> BoxFuture<Option<TunnelStats>, Error>;
+
+        /// Set whether to periodically broadcast the traffic counters of the tunnel
+        #[rpc(meta, name = "set_tunnel_stats_events")]
+        fn set_tunnel_stats_events(&self, Self::Metadata, bool) -> BoxFuture<(), Error>;
+
+        /// Set whether to run a SOCKS5 proxy on localhost that forwards connections through the
+        /// tunnel
+        #[rpc(meta, name = "set_socks_proxy")]
+        fn set_socks_proxy(&self, Self::Metadata, bool) -> BoxFuture<(), Error>;
+
+        /// Change the log level of the daemon until it is restarted. Takes one of "off", "error",
+        /// "warn", "info", "debug" and "trace"
+        #[rpc(meta, name = "set_log_level")]
+        fn set_log_level(&self, Self::Metadata, String) -> BoxFuture<(), Error>;
+
+        /// Set whether to serve metrics about the daemon in the Prometheus format on localhost
+        #[rpc(meta, name = "set_metrics")]
+        fn set_metrics(&self, Self::Metadata, bool) -> BoxFuture<(), Error>;
+
         /// Sets openvpn's mssfix parameter
         #[rpc(meta, name = "set_openvpn_mssfix")]
         fn set_openvpn_mssfix(&self, Self::Metadata, Option<u16>) -> BoxFuture<(), Error>;
@@ -146,6 +196,10 @@ build_rpc_trait! {
         #[rpc(meta, name = "set_enable_ipv6")]
         fn set_enable_ipv6(&self, Self::Metadata, bool) -> BoxFuture<(), Error>;
 
+        /// Set the DNS servers to use in the tunnel
+        #[rpc(meta, name = "set_dns_options")]
+        fn set_dns_options(&self, Self::Metadata, DnsOptions) -> BoxFuture<(), Error>;
+
         /// Set MTU for wireguard tunnels
         #[rpc(meta, name = "set_wireguard_mtu")]
         fn set_wireguard_mtu(&self, Self::Metadata, Option<u16>) -> BoxFuture<(), Error>;
@@ -156,7 +210,45 @@ build_rpc_trait! {
 
         /// Returns the current daemon settings
         #[rpc(meta, name = "get_settings")]
-        fn get_settings(&self, Self::Metadata) -> BoxFuture<Settings, Error>;
+        fn get_settings(&self, Self::Metadata) -> BoxFuture<SettingsResponse, Error>;
+
+        /// Returns the current daemon settings as JSON, optionally including the account token
+        #[rpc(meta, name = "export_settings")]
+        fn export_settings(&self, Self::Metadata, bool) -> BoxFuture<String, Error>;
+
+        /// Validates and applies settings previously returned by `export_settings`
+        #[rpc(meta, name = "import_settings")]
+        fn import_settings(&self, Self::Metadata, String) -> BoxFuture<(), Error>;
+
+        /// Creates an empty custom list with the given name and returns its ID
+        #[rpc(meta, name = "create_custom_list")]
+        fn create_custom_list(&self, Self::Metadata, String) -> BoxFuture<custom_list::Id, Error>;
+
+        /// Deletes the custom list with the given name
+        #[rpc(meta, name = "delete_custom_list")]
+        fn delete_custom_list(&self, Self::Metadata, String) -> BoxFuture<(), Error>;
+
+        /// Renames the custom list with the given name
+        #[rpc(meta, name = "rename_custom_list")]
+        fn rename_custom_list(&self, Self::Metadata, String, String) -> BoxFuture<(), Error>;
+
+        /// Adds a location to the custom list with the given name
+        #[rpc(meta, name = "add_custom_list_location")]
+        fn add_custom_list_location(
+            &self,
+            Self::Metadata,
+            String,
+            LocationConstraint
+        ) -> BoxFuture<(), Error>;
+
+        /// Removes a location from the custom list with the given name
+        #[rpc(meta, name = "remove_custom_list_location")]
+        fn remove_custom_list_location(
+            &self,
+            Self::Metadata,
+            String,
+            LocationConstraint
+        ) -> BoxFuture<(), Error>;
 
         /// Generates new wireguard key for current account
         #[rpc(meta, name = "generate_wireguard_key")]
@@ -170,6 +262,35 @@ build_rpc_trait! {
         #[rpc(meta, name = "verify_wireguard_key")]
         fn verify_wireguard_key(&self, Self::Metadata) -> BoxFuture<bool, Error>;
 
+        /// List the wireguard keys that are registered on the account or stored by the daemon
+        #[rpc(meta, name = "list_wireguard_keys")]
+        fn list_wireguard_keys(&self, Self::Metadata) -> BoxFuture<Vec<wireguard::AccountKey>, Error>;
+
+        /// Remove a wireguard key that is not in use from the account
+        #[rpc(meta, name = "revoke_wireguard_key")]
+        fn revoke_wireguard_key(&self, Self::Metadata, WireguardPublicKey) -> BoxFuture<(), Error>;
+
+        /// Start using a previously used wireguard key again
+        #[rpc(meta, name = "restore_wireguard_key")]
+        fn restore_wireguard_key(&self, Self::Metadata, WireguardPublicKey) -> BoxFuture<wireguard::PublicKey, Error>;
+
+        /// Get a wg-quick configuration for the current wireguard key and the given relay, or the
+        /// most recently selected relay
+        #[rpc(meta, name = "get_wireguard_config")]
+        fn get_wireguard_config(&self, Self::Metadata, Option<String>) -> BoxFuture<WgQuickConfig, Error>;
+
+        /// List the ports that are forwarded to the current wireguard key
+        #[rpc(meta, name = "get_port_forwards")]
+        fn get_port_forwards(&self, Self::Metadata) -> BoxFuture<Vec<PortForward>, Error>;
+
+        /// Forward a new port to the current wireguard key on the relays in a city
+        #[rpc(meta, name = "add_port_forward")]
+        fn add_port_forward(&self, Self::Metadata, CountryCode, CityCode) -> BoxFuture<PortForward, Error>;
+
+        /// Stop forwarding a port to the current wireguard key
+        #[rpc(meta, name = "remove_port_forward")]
+        fn remove_port_forward(&self, Self::Metadata, u16) -> BoxFuture<(), Error>;
+
         /// Retreive version of the app
         #[rpc(meta, name = "get_current_version")]
         fn get_current_version(&self, Self::Metadata) -> BoxFuture<String, Error>;
@@ -198,6 +319,34 @@ build_rpc_trait! {
         #[rpc(meta, name = "clear_split_tunnel_processes")]
         fn clear_split_tunnel_processes(&self, Self::Metadata) -> BoxFuture<(), Error>;
 
+        /// Exclude all processes started from an executable from the tunnel
+        #[rpc(meta, name = "add_split_tunnel_app")]
+        fn add_split_tunnel_app(&self, Self::Metadata, PathBuf) -> BoxFuture<(), Error>;
+
+        /// Stop excluding processes started from an executable from the tunnel
+        #[rpc(meta, name = "remove_split_tunnel_app")]
+        fn remove_split_tunnel_app(&self, Self::Metadata, PathBuf) -> BoxFuture<(), Error>;
+
+        /// Set whether split processes bypass the tunnel or are the only ones using it
+        #[rpc(meta, name = "set_split_tunnel_mode")]
+        fn set_split_tunnel_mode(&self, Self::Metadata, SplitTunnelMode) -> BoxFuture<(), Error>;
+
+        /// Set the networks on which the tunnel is disconnected automatically
+        #[rpc(meta, name = "set_trusted_networks")]
+        fn set_trusted_networks(&self, Self::Metadata, Vec<TrustedNetwork>) -> BoxFuture<(), Error>;
+
+        /// Get the identity of the network the host is connected to
+        #[rpc(meta, name = "get_network_identity")]
+        fn get_network_identity(&self, Self::Metadata) -> BoxFuture<Option<NetworkIdentity>, Error>;
+
+        /// Set the networks and ports that bypass the tunnel and the firewall
+        #[rpc(meta, name = "set_firewall_exemptions")]
+        fn set_firewall_exemptions(&self, Self::Metadata, Vec<FirewallExemption>) -> BoxFuture<(), Error>;
+
+        /// Set the rules that connect or disconnect the tunnel at given times
+        #[rpc(meta, name = "set_schedule")]
+        fn set_schedule(&self, Self::Metadata, Vec<ScheduleRule>) -> BoxFuture<(), Error>;
+
         #[pubsub(name = "daemon_event")] {
             /// Subscribes to events from the daemon.
             #[rpc(name = "daemon_event_subscribe")]
@@ -214,6 +363,15 @@ build_rpc_trait! {
     }
 }
 
+/// The response to `get_settings`. Adds the names of the settings that are locked by the policy,
+/// which are not serialized as part of [`Settings`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SettingsResponse {
+    #[serde(flatten)]
+    settings: Settings,
+    locked_fields: Vec<String>,
+}
+
 pub struct ManagementInterfaceServer {
     server: talpid_ipc::IpcServer,
     subscriptions: Arc<RwLock<HashMap<SubscriptionId, pubsub::Sink<DaemonEvent>>>>,
@@ -291,6 +449,15 @@ impl EventListener for ManagementInterfaceEventBroadcaster {
         log::debug!("Broadcasting new wireguard key event");
         self.notify(DaemonEvent::WireguardKey(key_event));
     }
+
+    fn notify_tunnel_stats(&self, stats: TunnelStats) {
+        self.notify(DaemonEvent::TunnelStats(stats));
+    }
+
+    fn notify_trusted_network_event(&self, event: TrustedNetworkEvent) {
+        log::debug!("Broadcasting trusted network event");
+        self.notify(DaemonEvent::TrustedNetwork(event));
+    }
 }
 
 impl ManagementInterfaceEventBroadcaster {
@@ -346,6 +513,119 @@ impl ManagementInterface {
             _ => Error::internal_error(),
         }
     }
+
+    /// Converts an error from updating the settings into a JSONRPC error.
+    fn map_settings_error(error: settings::Error) -> Error {
+        match error {
+            settings::Error::LockedByPolicy(_) => Error {
+                code: ErrorCode::from(SETTING_LOCKED_CODE),
+                message: error.to_string(),
+                data: None,
+            },
+            _ => Error {
+                code: ErrorCode::from(SETTINGS_SAVE_ERROR_CODE),
+                message: error.display_chain(),
+                data: None,
+            },
+        }
+    }
+
+    /// Converts an error from validating settings into a JSONRPC error.
+    fn invalid_settings_error(error: settings_types::Error) -> Error {
+        Error {
+            code: ErrorCode::InvalidParams,
+            message: error.display_chain(),
+            data: None,
+        }
+    }
+
+    /// Converts an error from a custom list operation into a JSONRPC error.
+    fn map_custom_list_error(error: custom_list::Error) -> Error {
+        Error {
+            code: ErrorCode::from(CUSTOM_LIST_ERROR_CODE),
+            message: error.to_string(),
+            data: None,
+        }
+    }
+
+    /// Converts an error from exporting a wireguard configuration into a JSONRPC error.
+    fn map_wireguard_config_error(error: WireguardConfigError) -> Error {
+        Error {
+            code: ErrorCode::from(WIREGUARD_CONFIG_ERROR_CODE),
+            message: error.to_string(),
+            data: None,
+        }
+    }
+
+    /// Converts an error from a port forwarding operation into a JSONRPC error.
+    fn map_port_forward_error(error: PortForwardError) -> Error {
+        log::error!("{}", error.display_chain());
+        match error {
+            PortForwardError::RestError(RestError::ApiError(status, message))
+                if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN =>
+            {
+                Error {
+                    code: ErrorCode::from(INVALID_ACCOUNT_CODE),
+                    message,
+                    data: None,
+                }
+            }
+            PortForwardError::RestError(RestError::ApiError(_, message)) => Error {
+                code: ErrorCode::from(PORT_FORWARD_ERROR_CODE),
+                message,
+                data: None,
+            },
+            PortForwardError::NoKey => Error {
+                code: ErrorCode::from(PORT_FORWARD_ERROR_CODE),
+                message: error.to_string(),
+                data: None,
+            },
+            _ => Error::internal_error(),
+        }
+    }
+
+    /// Converts an error from a wireguard key operation into a JSONRPC error.
+    fn map_wireguard_key_error(error: WireguardKeyError) -> Error {
+        log::error!("{}", error.display_chain());
+        match error {
+            WireguardKeyError::KeyManager(crate::wireguard::Error::RestError(
+                RestError::ApiError(status, message),
+            )) if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN => Error {
+                code: ErrorCode::from(INVALID_ACCOUNT_CODE),
+                message,
+                data: None,
+            },
+            WireguardKeyError::KeyManager(crate::wireguard::Error::RestError(
+                RestError::ApiError(_, message),
+            )) => Error {
+                code: ErrorCode::from(WIREGUARD_KEY_ERROR_CODE),
+                message,
+                data: None,
+            },
+            WireguardKeyError::NoAccount
+            | WireguardKeyError::KeyInUse
+            | WireguardKeyError::UnknownKey
+            | WireguardKeyError::KeyManager(crate::wireguard::Error::TooManyKeys) => Error {
+                code: ErrorCode::from(WIREGUARD_KEY_ERROR_CODE),
+                message: error.to_string(),
+                data: None,
+            },
+            _ => Error::internal_error(),
+        }
+    }
+
+    /// Converts an error from enabling or disabling the SOCKS5 proxy into a JSONRPC error.
+    fn map_socks_proxy_error(error: SocksProxyError) -> Error {
+        match error {
+            #[cfg(not(target_os = "android"))]
+            SocksProxyError::Start(_) => Error {
+                code: ErrorCode::from(SOCKS_PROXY_ERROR_CODE),
+                message: error.display_chain(),
+                data: None,
+            },
+            SocksProxyError::SaveSettings(error) => Self::map_settings_error(error),
+        }
+    }
 }
 
 impl ManagementInterfaceApi for ManagementInterface {
@@ -472,12 +752,16 @@ impl ManagementInterfaceApi for ManagementInterface {
         constraints_update: RelaySettingsUpdate,
     ) -> BoxFuture<(), Error> {
         log::debug!("update_relay_settings");
+        if let Err(error) = settings_types::validate_relay_settings_update(&constraints_update) {
+            return Box::new(future::err(Self::invalid_settings_error(error)));
+        }
         let (tx, rx) = sync::oneshot::channel();
 
         let message = DaemonCommand::UpdateRelaySettings(tx, constraints_update);
         let future = self
             .send_command_to_daemon(message)
-            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|settings_result| settings_result.map_err(Self::map_settings_error));
         Box::new(future)
     }
 
@@ -486,7 +770,8 @@ impl ManagementInterfaceApi for ManagementInterface {
         let (tx, rx) = sync::oneshot::channel();
         let future = self
             .send_command_to_daemon(DaemonCommand::SetAllowLan(tx, allow_lan))
-            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|settings_result| settings_result.map_err(Self::map_settings_error));
         Box::new(future)
     }
 
@@ -511,7 +796,8 @@ impl ManagementInterfaceApi for ManagementInterface {
                 tx,
                 block_when_disconnected,
             ))
-            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|settings_result| settings_result.map_err(Self::map_settings_error));
         Box::new(future)
     }
 
@@ -520,7 +806,8 @@ impl ManagementInterfaceApi for ManagementInterface {
         let (tx, rx) = sync::oneshot::channel();
         let future = self
             .send_command_to_daemon(DaemonCommand::SetAutoConnect(tx, auto_connect))
-            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|settings_result| settings_result.map_err(Self::map_settings_error));
         Box::new(future)
     }
 
@@ -615,8 +902,79 @@ impl ManagementInterfaceApi for ManagementInterface {
         Box::new(future)
     }
 
+    fn get_connection_history(
+        &self,
+        _: Self::Metadata,
+    ) -> BoxFuture<Vec<connection_history::Session>, Error> {
+        log::debug!("get_connection_history");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::GetConnectionHistory(tx))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
+    fn get_tunnel_stats(&self, _: Self::Metadata) -> BoxFuture<Option<TunnelStats>, Error> {
+        log::debug!("get_tunnel_stats");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::GetTunnelStats(tx))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
+    fn set_tunnel_stats_events(&self, _: Self::Metadata, enabled: bool) -> BoxFuture<(), Error> {
+        log::debug!("set_tunnel_stats_events({})", enabled);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetTunnelStatsEvents(tx, enabled))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
+    fn set_socks_proxy(&self, _: Self::Metadata, enabled: bool) -> BoxFuture<(), Error> {
+        log::debug!("set_socks_proxy({})", enabled);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetSocksProxy(tx, enabled))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_socks_proxy_error));
+        Box::new(future)
+    }
+
+    fn set_log_level(&self, _: Self::Metadata, log_level: String) -> BoxFuture<(), Error> {
+        log::debug!("set_log_level({})", log_level);
+        let log_level = match log_level.parse() {
+            Ok(log_level) => log_level,
+            Err(_) => {
+                return Box::new(future::err(Error {
+                    code: ErrorCode::InvalidParams,
+                    message: format!("Invalid log level: {}", log_level),
+                    data: None,
+                }))
+            }
+        };
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetLogLevel(tx, log_level))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
+    fn set_metrics(&self, _: Self::Metadata, enabled: bool) -> BoxFuture<(), Error> {
+        log::debug!("set_metrics({})", enabled);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetMetrics(tx, enabled))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
     fn set_openvpn_mssfix(&self, _: Self::Metadata, mssfix: Option<u16>) -> BoxFuture<(), Error> {
         log::debug!("set_openvpn_mssfix({:?})", mssfix);
+        if let Err(error) = settings_types::validate_openvpn_mssfix(mssfix) {
+            return Box::new(future::err(Self::invalid_settings_error(error)));
+        }
         let (tx, rx) = sync::oneshot::channel();
         let future = self
             .send_command_to_daemon(DaemonCommand::SetOpenVpnMssfix(tx, mssfix))
@@ -631,11 +989,20 @@ impl ManagementInterfaceApi for ManagementInterface {
         bridge_settings: BridgeSettings,
     ) -> BoxFuture<(), Error> {
         log::debug!("set_bridge_settings({:?})", bridge_settings);
+        if let BridgeSettings::Custom(proxy) = &bridge_settings {
+            if let Err(message) = openvpn::validate_proxy_settings(proxy) {
+                return Box::new(future::err(Error {
+                    code: ErrorCode::InvalidParams,
+                    message,
+                    data: None,
+                }));
+            }
+        }
         let (tx, rx) = sync::oneshot::channel();
         let future = self
             .send_command_to_daemon(DaemonCommand::SetBridgeSettings(tx, bridge_settings))
             .and_then(|_| rx.map_err(|_| Error::internal_error()))
-            .and_then(|settings_result| settings_result.map_err(|_| Error::internal_error()));
+            .and_then(|settings_result| settings_result.map_err(Self::map_settings_error));
 
         Box::new(future)
     }
@@ -650,7 +1017,7 @@ impl ManagementInterfaceApi for ManagementInterface {
         let future = self
             .send_command_to_daemon(DaemonCommand::SetBridgeState(tx, bridge_state))
             .and_then(|_| rx.map_err(|_| Error::internal_error()))
-            .and_then(|settings_result| settings_result.map_err(|_| Error::internal_error()));
+            .and_then(|settings_result| settings_result.map_err(Self::map_settings_error));
 
         Box::new(future)
     }
@@ -660,7 +1027,22 @@ impl ManagementInterfaceApi for ManagementInterface {
         let (tx, rx) = sync::oneshot::channel();
         let future = self
             .send_command_to_daemon(DaemonCommand::SetEnableIpv6(tx, enable_ipv6))
-            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|settings_result| settings_result.map_err(Self::map_settings_error));
+
+        Box::new(future)
+    }
+
+    fn set_dns_options(&self, _: Self::Metadata, dns_options: DnsOptions) -> BoxFuture<(), Error> {
+        log::debug!("set_dns_options({:?})", dns_options);
+        if let Err(error) = dns_options.validate() {
+            return Box::new(future::err(Self::invalid_settings_error(error)));
+        }
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetDnsOptions(tx, dns_options))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|settings_result| settings_result.map_err(Self::map_settings_error));
 
         Box::new(future)
     }
@@ -668,6 +1050,9 @@ impl ManagementInterfaceApi for ManagementInterface {
     /// Set MTU for wireguard tunnels
     fn set_wireguard_mtu(&self, _: Self::Metadata, mtu: Option<u16>) -> BoxFuture<(), Error> {
         log::debug!("set_wireguard_mtu({:?})", mtu);
+        if let Err(error) = settings_types::validate_wireguard_mtu(mtu) {
+            return Box::new(future::err(Self::invalid_settings_error(error)));
+        }
         let (tx, rx) = sync::oneshot::channel();
         let future = self
             .send_command_to_daemon(DaemonCommand::SetWireguardMtu(tx, mtu))
@@ -689,12 +1074,116 @@ impl ManagementInterfaceApi for ManagementInterface {
         Box::new(future)
     }
 
-    fn get_settings(&self, _: Self::Metadata) -> BoxFuture<Settings, Error> {
+    fn get_settings(&self, _: Self::Metadata) -> BoxFuture<SettingsResponse, Error> {
         log::debug!("get_settings");
         let (tx, rx) = sync::oneshot::channel();
         let future = self
             .send_command_to_daemon(DaemonCommand::GetSettings(tx))
-            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .map(|settings| SettingsResponse {
+                locked_fields: settings.locked_fields.clone(),
+                settings,
+            });
+        Box::new(future)
+    }
+
+    fn export_settings(
+        &self,
+        _: Self::Metadata,
+        include_account_token: bool,
+    ) -> BoxFuture<String, Error> {
+        log::debug!("export_settings({})", include_account_token);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::GetSettings(tx))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(move |settings| {
+                serde_json::to_string_pretty(&settings.export(include_account_token))
+                    .map_err(|_| Error::internal_error())
+            });
+        Box::new(future)
+    }
+
+    fn import_settings(&self, _: Self::Metadata, settings: String) -> BoxFuture<(), Error> {
+        log::debug!("import_settings");
+        let settings = match Settings::import_from_bytes(settings.as_bytes()) {
+            Ok(settings) => settings,
+            Err(error) => return Box::new(future::err(Self::invalid_settings_error(error))),
+        };
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::ImportSettings(tx, settings))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|settings_result| settings_result.map_err(Self::map_settings_error));
+        Box::new(future)
+    }
+
+    fn create_custom_list(
+        &self,
+        _: Self::Metadata,
+        name: String,
+    ) -> BoxFuture<custom_list::Id, Error> {
+        log::debug!("create_custom_list");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::CreateCustomList(tx, name))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_custom_list_error));
+        Box::new(future)
+    }
+
+    fn delete_custom_list(&self, _: Self::Metadata, name: String) -> BoxFuture<(), Error> {
+        log::debug!("delete_custom_list");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::DeleteCustomList(tx, name))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_custom_list_error));
+        Box::new(future)
+    }
+
+    fn rename_custom_list(
+        &self,
+        _: Self::Metadata,
+        name: String,
+        new_name: String,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("rename_custom_list");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::RenameCustomList(tx, name, new_name))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_custom_list_error));
+        Box::new(future)
+    }
+
+    fn add_custom_list_location(
+        &self,
+        _: Self::Metadata,
+        name: String,
+        location: LocationConstraint,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("add_custom_list_location");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::AddCustomListLocation(tx, name, location))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_custom_list_error));
+        Box::new(future)
+    }
+
+    fn remove_custom_list_location(
+        &self,
+        _: Self::Metadata,
+        name: String,
+        location: LocationConstraint,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("remove_custom_list_location");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::RemoveCustomListLocation(tx, name, location))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_custom_list_error));
         Box::new(future)
     }
 
@@ -731,6 +1220,96 @@ impl ManagementInterfaceApi for ManagementInterface {
         Box::new(future)
     }
 
+    fn list_wireguard_keys(
+        &self,
+        _: Self::Metadata,
+    ) -> BoxFuture<Vec<wireguard::AccountKey>, Error> {
+        log::debug!("list_wireguard_keys");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::ListWireguardKeys(tx))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_wireguard_key_error));
+        Box::new(future)
+    }
+
+    fn revoke_wireguard_key(
+        &self,
+        _: Self::Metadata,
+        key: WireguardPublicKey,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("revoke_wireguard_key({})", key);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::RevokeWireguardKey(tx, key))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_wireguard_key_error));
+        Box::new(future)
+    }
+
+    fn restore_wireguard_key(
+        &self,
+        _: Self::Metadata,
+        key: WireguardPublicKey,
+    ) -> BoxFuture<wireguard::PublicKey, Error> {
+        log::debug!("restore_wireguard_key({})", key);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::RestoreWireguardKey(tx, key))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_wireguard_key_error));
+        Box::new(future)
+    }
+
+    fn get_wireguard_config(
+        &self,
+        _: Self::Metadata,
+        hostname: Option<String>,
+    ) -> BoxFuture<WgQuickConfig, Error> {
+        log::debug!("get_wireguard_config({:?})", hostname);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::GetWireguardConfig(tx, hostname))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_wireguard_config_error));
+        Box::new(future)
+    }
+
+    fn get_port_forwards(&self, _: Self::Metadata) -> BoxFuture<Vec<PortForward>, Error> {
+        log::debug!("get_port_forwards");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::GetPortForwards(tx))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_port_forward_error));
+        Box::new(future)
+    }
+
+    fn add_port_forward(
+        &self,
+        _: Self::Metadata,
+        country: CountryCode,
+        city: CityCode,
+    ) -> BoxFuture<PortForward, Error> {
+        log::debug!("add_port_forward({}, {})", country, city);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::AddPortForward(tx, country, city))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_port_forward_error));
+        Box::new(future)
+    }
+
+    fn remove_port_forward(&self, _: Self::Metadata, port: u16) -> BoxFuture<(), Error> {
+        log::debug!("remove_port_forward({})", port);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::RemovePortForward(tx, port))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_port_forward_error));
+        Box::new(future)
+    }
+
     fn get_current_version(&self, _: Self::Metadata) -> BoxFuture<String, Error> {
         log::debug!("get_current_version");
         let (tx, rx) = sync::oneshot::channel();
@@ -812,6 +1391,131 @@ impl ManagementInterfaceApi for ManagementInterface {
         Box::new(future::ok(()))
     }
 
+    #[cfg(target_os = "linux")]
+    fn add_split_tunnel_app(&self, _: Self::Metadata, path: PathBuf) -> BoxFuture<(), Error> {
+        log::debug!("add_split_tunnel_app");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::AddSplitTunnelApp(tx, path))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+    #[cfg(not(target_os = "linux"))]
+    fn add_split_tunnel_app(&self, _: Self::Metadata, _: PathBuf) -> BoxFuture<(), Error> {
+        Box::new(future::ok(()))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn remove_split_tunnel_app(&self, _: Self::Metadata, path: PathBuf) -> BoxFuture<(), Error> {
+        log::debug!("remove_split_tunnel_app");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::RemoveSplitTunnelApp(tx, path))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+    #[cfg(not(target_os = "linux"))]
+    fn remove_split_tunnel_app(&self, _: Self::Metadata, _: PathBuf) -> BoxFuture<(), Error> {
+        Box::new(future::ok(()))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_split_tunnel_mode(
+        &self,
+        _: Self::Metadata,
+        mode: SplitTunnelMode,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_split_tunnel_mode({})", mode);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetSplitTunnelMode(tx, mode))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+    #[cfg(not(target_os = "linux"))]
+    fn set_split_tunnel_mode(&self, _: Self::Metadata, _: SplitTunnelMode) -> BoxFuture<(), Error> {
+        Box::new(future::ok(()))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_trusted_networks(
+        &self,
+        _: Self::Metadata,
+        rules: Vec<TrustedNetwork>,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_trusted_networks");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetTrustedNetworks(tx, rules))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+    #[cfg(not(target_os = "linux"))]
+    fn set_trusted_networks(
+        &self,
+        _: Self::Metadata,
+        _: Vec<TrustedNetwork>,
+    ) -> BoxFuture<(), Error> {
+        Box::new(future::ok(()))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn get_network_identity(&self, _: Self::Metadata) -> BoxFuture<Option<NetworkIdentity>, Error> {
+        log::debug!("get_network_identity");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::GetNetworkIdentity(tx))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+    #[cfg(not(target_os = "linux"))]
+    fn get_network_identity(&self, _: Self::Metadata) -> BoxFuture<Option<NetworkIdentity>, Error> {
+        Box::new(future::ok(None))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_firewall_exemptions(
+        &self,
+        _: Self::Metadata,
+        exemptions: Vec<FirewallExemption>,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_firewall_exemptions");
+        if let Err(error) = settings_types::validate_firewall_exemptions(&exemptions) {
+            return Box::new(future::err(Self::invalid_settings_error(error)));
+        }
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetFirewallExemptions(tx, exemptions))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+    #[cfg(not(target_os = "linux"))]
+    fn set_firewall_exemptions(
+        &self,
+        _: Self::Metadata,
+        exemptions: Vec<FirewallExemption>,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_firewall_exemptions");
+        match settings_types::validate_firewall_exemptions(&exemptions) {
+            Ok(()) => Box::new(future::ok(())),
+            Err(error) => Box::new(future::err(Self::invalid_settings_error(error))),
+        }
+    }
+
+    fn set_schedule(&self, _: Self::Metadata, rules: Vec<ScheduleRule>) -> BoxFuture<(), Error> {
+        log::debug!("set_schedule");
+        for relay_settings in rules.iter().filter_map(|rule| rule.relay_settings.as_ref()) {
+            if let Err(error) = settings_types::validate_relay_settings_update(relay_settings) {
+                return Box::new(future::err(Self::invalid_settings_error(error)));
+            }
+        }
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetSchedule(tx, rules))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
     fn clear_split_tunnel_processes(&self, _: Self::Metadata) -> BoxFuture<(), Error> {
         #[cfg(target_os = "linux")]
         {
@@ -0,0 +1,509 @@
+//! Exporter of daemon health metrics in the Prometheus text format, served over HTTP on localhost.
+
+use crate::{DaemonEventSender, InternalDaemonEvent};
+use mullvad_types::states::{TunnelState, TunnelStats};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+use talpid_core::mpsc::Sender;
+use talpid_types::{tunnel::ErrorStateCause, ErrorExt};
+
+/// Port on localhost that the metrics are served on.
+pub const METRICS_PORT: u16 = 9633;
+
+/// How often the server checks whether it has been stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Timeout for reading the request of a client.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest request that is read from a client.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+/// Maximum number of clients that are served at the same time. Connections beyond this are
+/// closed without a response.
+const MAX_CLIENTS: usize = 4;
+
+const TUNNEL_STATES: &[&str] = &[
+    "disconnected",
+    "connecting",
+    "connected",
+    "disconnecting",
+    "error",
+];
+
+/// Counters that are updated by the daemon as the tunnel changes state.
+#[derive(Debug, Default)]
+pub struct MetricsCounters {
+    connection_attempts: u64,
+    reconnects: u64,
+    errors: BTreeMap<&'static str, u64>,
+}
+
+impl MetricsCounters {
+    /// Counts the transition from `old_state` to `new_state`. Every transition to the connecting
+    /// state is a connection attempt, and those that are not preceded by the disconnected state
+    /// are reconnects.
+    pub fn record_transition(&mut self, old_state: &TunnelState, new_state: &TunnelState) {
+        match new_state {
+            TunnelState::Connecting { .. } => {
+                self.connection_attempts += 1;
+                if *old_state != TunnelState::Disconnected {
+                    self.reconnects += 1;
+                }
+            }
+            TunnelState::Error(error_state) => {
+                *self
+                    .errors
+                    .entry(cause_name(error_state.cause()))
+                    .or_insert(0) += 1;
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Values of all metrics at the time of a scrape.
+#[derive(Debug)]
+pub struct Metrics {
+    pub tunnel_state: &'static str,
+    pub connection_attempts: u64,
+    pub reconnects: u64,
+    pub errors: BTreeMap<&'static str, u64>,
+    pub relay_list_age: Option<Duration>,
+    pub wireguard_key_age: Option<Duration>,
+    pub tunnel_stats: Option<TunnelStats>,
+    pub version_supported: bool,
+    pub upgrade_available: bool,
+}
+
+impl Metrics {
+    pub fn new(
+        tunnel_state: &TunnelState,
+        counters: &MetricsCounters,
+        relay_list_age: Option<Duration>,
+        wireguard_key_age: Option<Duration>,
+        version_supported: bool,
+        upgrade_available: bool,
+    ) -> Self {
+        Metrics {
+            tunnel_state: tunnel_state.name(),
+            connection_attempts: counters.connection_attempts,
+            reconnects: counters.reconnects,
+            errors: counters.errors.clone(),
+            relay_list_age,
+            wireguard_key_age,
+            tunnel_stats: None,
+            version_supported,
+            upgrade_available,
+        }
+    }
+
+    /// Formats the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "mullvad_tunnel_state",
+            "gauge",
+            "Current tunnel state",
+        );
+        for state in TUNNEL_STATES {
+            let value = (*state == self.tunnel_state) as u8;
+            let _ = writeln!(out, "mullvad_tunnel_state{{state=\"{}\"}} {}", state, value);
+        }
+
+        header(
+            &mut out,
+            "mullvad_connection_attempts_total",
+            "counter",
+            "Number of times the tunnel started connecting",
+        );
+        let _ = writeln!(
+            out,
+            "mullvad_connection_attempts_total {}",
+            self.connection_attempts
+        );
+
+        header(
+            &mut out,
+            "mullvad_reconnects_total",
+            "counter",
+            "Number of connection attempts made without first disconnecting",
+        );
+        let _ = writeln!(out, "mullvad_reconnects_total {}", self.reconnects);
+
+        header(
+            &mut out,
+            "mullvad_tunnel_errors_total",
+            "counter",
+            "Number of times the tunnel entered the error state, by cause",
+        );
+        for (cause, count) in &self.errors {
+            let _ = writeln!(
+                out,
+                "mullvad_tunnel_errors_total{{cause=\"{}\"}} {}",
+                cause, count
+            );
+        }
+
+        if let Some(relay_list_age) = self.relay_list_age {
+            header(
+                &mut out,
+                "mullvad_relay_list_age_seconds",
+                "gauge",
+                "Time since the relay list was last updated",
+            );
+            let _ = writeln!(
+                out,
+                "mullvad_relay_list_age_seconds {}",
+                relay_list_age.as_secs()
+            );
+        }
+
+        if let Some(key_age) = self.wireguard_key_age {
+            header(
+                &mut out,
+                "mullvad_wireguard_key_age_seconds",
+                "gauge",
+                "Time since the WireGuard key of the account was created",
+            );
+            let _ = writeln!(
+                out,
+                "mullvad_wireguard_key_age_seconds {}",
+                key_age.as_secs()
+            );
+        }
+
+        if let Some(stats) = &self.tunnel_stats {
+            header(
+                &mut out,
+                "mullvad_tunnel_rx_bytes_total",
+                "counter",
+                "Bytes received through the current tunnel",
+            );
+            let _ = writeln!(out, "mullvad_tunnel_rx_bytes_total {}", stats.rx_bytes);
+            header(
+                &mut out,
+                "mullvad_tunnel_tx_bytes_total",
+                "counter",
+                "Bytes sent through the current tunnel",
+            );
+            let _ = writeln!(out, "mullvad_tunnel_tx_bytes_total {}", stats.tx_bytes);
+        }
+
+        header(
+            &mut out,
+            "mullvad_version_supported",
+            "gauge",
+            "Whether the running version is still supported",
+        );
+        let _ = writeln!(
+            out,
+            "mullvad_version_supported {}",
+            self.version_supported as u8
+        );
+        header(
+            &mut out,
+            "mullvad_version_upgrade_available",
+            "gauge",
+            "Whether an upgrade is suggested",
+        );
+        let _ = writeln!(
+            out,
+            "mullvad_version_upgrade_available {}",
+            self.upgrade_available as u8
+        );
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+}
+
+fn cause_name(cause: &ErrorStateCause) -> &'static str {
+    match cause {
+        ErrorStateCause::AuthFailed(_) => "auth_failed",
+        ErrorStateCause::Ipv6Unavailable => "ipv6_unavailable",
+        ErrorStateCause::SetFirewallPolicyError(_) => "set_firewall_policy_error",
+        ErrorStateCause::SetDnsError => "set_dns_error",
+        ErrorStateCause::StartTunnelError => "start_tunnel_error",
+        ErrorStateCause::TunnelParameterError(_) => "tunnel_parameter_error",
+        ErrorStateCause::IsOffline => "is_offline",
+        ErrorStateCause::TapAdapterProblem => "tap_adapter_problem",
+        #[cfg(target_os = "android")]
+        ErrorStateCause::VpnPermissionDenied => "vpn_permission_denied",
+    }
+}
+
+/// HTTP server that answers every request with the current metrics. Each client is served on its
+/// own thread, so that a slow client cannot delay stopping the server, but at most `MAX_CLIENTS`
+/// clients are served at the same time. Stops when dropped.
+pub struct MetricsServer {
+    closed: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    pub(crate) fn start(daemon_tx: DaemonEventSender) -> io::Result<Self> {
+        let listener =
+            TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), METRICS_PORT))?;
+        listener.set_nonblocking(true)?;
+        log::info!(
+            "Serving metrics on http://{}:{}/metrics",
+            Ipv4Addr::LOCALHOST,
+            METRICS_PORT
+        );
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let closed = closed.clone();
+            thread::spawn(move || {
+                if let Err(error) = serve(listener, daemon_tx, closed) {
+                    log::error!("{}", error.display_chain_with_msg("Metrics server stopped"));
+                }
+            })
+        };
+        Ok(MetricsServer {
+            closed,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Metrics server thread panicked");
+            }
+        }
+    }
+}
+
+fn serve(
+    listener: TcpListener,
+    daemon_tx: DaemonEventSender,
+    closed: Arc<AtomicBool>,
+) -> io::Result<()> {
+    let clients = Arc::new(AtomicUsize::new(0));
+    while !closed.load(Ordering::SeqCst) {
+        let client = match listener.accept() {
+            Ok((client, _)) => client,
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        let slot = match ClientSlot::acquire(&clients) {
+            Some(slot) => slot,
+            None => {
+                log::debug!("Too many metrics clients, closing connection");
+                continue;
+            }
+        };
+        let daemon_tx = daemon_tx.clone();
+        let closed = closed.clone();
+        thread::spawn(move || {
+            if let Err(error) = handle_request(client, &daemon_tx, &closed) {
+                log::debug!("Failed to serve metrics: {}", error);
+            }
+            drop(slot);
+        });
+    }
+    Ok(())
+}
+
+/// Counts a client as being served until dropped.
+struct ClientSlot(Arc<AtomicUsize>);
+
+impl ClientSlot {
+    /// Returns `None` if `MAX_CLIENTS` clients are already being served.
+    fn acquire(clients: &Arc<AtomicUsize>) -> Option<Self> {
+        if clients.fetch_add(1, Ordering::SeqCst) < MAX_CLIENTS {
+            Some(ClientSlot(clients.clone()))
+        } else {
+            clients.fetch_sub(1, Ordering::SeqCst);
+            None
+        }
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn handle_request(
+    mut client: TcpStream,
+    daemon_tx: &DaemonEventSender,
+    closed: &AtomicBool,
+) -> io::Result<()> {
+    client.set_nonblocking(false)?;
+    client.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let request = read_request(&mut client)?;
+
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    if path != "/metrics" {
+        return write_response(&mut client, "404 Not Found", "Not found\n");
+    }
+
+    match get_metrics(daemon_tx, closed) {
+        Some(metrics) => write_response(&mut client, "200 OK", &metrics.render()),
+        None => write_response(
+            &mut client,
+            "503 Service Unavailable",
+            "The daemon did not respond\n",
+        ),
+    }
+}
+
+/// Reads the request line and headers. The body, if any, is ignored.
+fn read_request(client: &mut TcpStream) -> io::Result<String> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let size = client.read(&mut buffer)?;
+        if size == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..size]);
+        if request.len() > MAX_REQUEST_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Request is too large",
+            ));
+        }
+    }
+    Ok(String::from_utf8_lossy(&request).into_owned())
+}
+
+/// Asks the daemon for the current metrics. Gives up if the server is stopped in the meantime.
+fn get_metrics(daemon_tx: &DaemonEventSender, closed: &AtomicBool) -> Option<Metrics> {
+    let (tx, rx) = mpsc::channel();
+    daemon_tx.send(InternalDaemonEvent::GetMetrics(tx)).ok()?;
+    loop {
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(metrics) => return Some(metrics),
+            Err(mpsc::RecvTimeoutError::Timeout) if !closed.load(Ordering::SeqCst) => (),
+            Err(_) => return None,
+        }
+    }
+}
+
+fn write_response(client: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    write!(
+        client,
+        "HTTP/1.1 {}\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use talpid_types::{
+        net::{Endpoint, TransportProtocol, TunnelEndpoint, TunnelType},
+        tunnel::ErrorState,
+    };
+
+    #[test]
+    fn test_render() {
+        let mut counters = MetricsCounters::default();
+        counters.connection_attempts = 3;
+        counters.reconnects = 2;
+        counters.errors.insert("is_offline", 1);
+        let mut metrics = Metrics::new(
+            &TunnelState::Disconnected,
+            &counters,
+            Some(Duration::from_secs(3600)),
+            None,
+            true,
+            false,
+        );
+        metrics.tunnel_stats = Some(TunnelStats {
+            tx_bytes: 100,
+            rx_bytes: 200,
+            last_handshake: None,
+        });
+
+        let output = metrics.render();
+        assert!(output.contains("mullvad_tunnel_state{state=\"disconnected\"} 1\n"));
+        assert!(output.contains("mullvad_tunnel_state{state=\"connected\"} 0\n"));
+        assert!(output.contains("mullvad_reconnects_total 2\n"));
+        assert!(output.contains("mullvad_tunnel_errors_total{cause=\"is_offline\"} 1\n"));
+        assert!(output.contains("mullvad_relay_list_age_seconds 3600\n"));
+        assert!(output.contains("mullvad_tunnel_rx_bytes_total 200\n"));
+        assert!(!output.contains("mullvad_wireguard_key_age_seconds"));
+
+        metrics.relay_list_age = None;
+        assert!(!metrics.render().contains("mullvad_relay_list_age_seconds"));
+    }
+
+    #[test]
+    fn test_record_transition() {
+        let endpoint = TunnelEndpoint {
+            endpoint: Endpoint {
+                address: "10.0.0.1:1194".parse().unwrap(),
+                protocol: TransportProtocol::Udp,
+            },
+            tunnel_type: TunnelType::OpenVpn,
+            proxy: None,
+            entry_endpoint: None,
+        };
+        let connecting = TunnelState::Connecting {
+            endpoint,
+            location: None,
+        };
+        let connected = TunnelState::Connected {
+            endpoint,
+            location: None,
+        };
+        let offline = TunnelState::Error(ErrorState::new(ErrorStateCause::IsOffline, None));
+
+        let mut counters = MetricsCounters::default();
+        counters.record_transition(&TunnelState::Disconnected, &connecting);
+        counters.record_transition(&connecting, &connected);
+        counters.record_transition(&connected, &connecting);
+        counters.record_transition(&connecting, &offline);
+        counters.record_transition(&offline, &offline);
+
+        assert_eq!(counters.connection_attempts, 2);
+        assert_eq!(counters.reconnects, 1);
+        assert_eq!(counters.errors.get("is_offline"), Some(&2));
+        assert_eq!(counters.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_client_slots() {
+        let clients = Arc::new(AtomicUsize::new(0));
+        let mut slots: Vec<_> = (0..MAX_CLIENTS)
+            .map(|_| ClientSlot::acquire(&clients).unwrap())
+            .collect();
+        assert!(ClientSlot::acquire(&clients).is_none());
+        assert_eq!(clients.load(Ordering::SeqCst), MAX_CLIENTS);
+
+        slots.pop();
+        assert!(ClientSlot::acquire(&clients).is_some());
+        drop(slots);
+        assert_eq!(clients.load(Ordering::SeqCst), 0);
+    }
+}
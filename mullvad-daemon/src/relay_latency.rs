@@ -0,0 +1,281 @@
+//! Measures and caches the round-trip time to relays, so that relays can be selected by latency.
+
+use log::{debug, trace};
+use mullvad_types::relay_list::Relay;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    net::Ipv4Addr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use talpid_types::ErrorExt;
+
+/// How long a measurement is used for relay selection before it has to be measured again.
+const MAX_MEASUREMENT_AGE: Duration = Duration::from_secs(10 * 60);
+/// How long to wait for a reply from a relay.
+const MEASUREMENT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Maximum number of relays that are measured at the same time.
+const MAX_CONCURRENT_MEASUREMENTS: usize = 16;
+/// Number of relays with the lowest latency that are considered when selecting by latency.
+const FASTEST_RELAY_COUNT: usize = 5;
+
+/// Measures the round-trip time to a single host.
+pub trait LatencyMeasurer: Send + Sync + 'static {
+    /// Returns the round-trip time to `addr`, or `None` if it could not be measured.
+    fn measure(&self, addr: Ipv4Addr) -> Option<Duration>;
+}
+
+/// Measures latency by sending an ICMP echo request over a raw socket and waiting for the reply.
+pub struct IcmpLatencyMeasurer;
+
+impl LatencyMeasurer for IcmpLatencyMeasurer {
+    fn measure(&self, addr: Ipv4Addr) -> Option<Duration> {
+        match talpid_core::ping_monitor::measure_latency(addr, MEASUREMENT_TIMEOUT) {
+            Ok(latency) => Some(latency),
+            Err(error) => {
+                trace!(
+                    "{}",
+                    error.display_chain_with_msg(&format!("Failed to measure latency to {}", addr))
+                );
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Measurement {
+    latency: Duration,
+    measured_at: Instant,
+}
+
+impl Measurement {
+    fn is_fresh(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.measured_at) < MAX_MEASUREMENT_AGE
+    }
+}
+
+/// Keeps track of the latest latency measurement for each relay, keyed by hostname.
+#[derive(Clone)]
+pub struct LatencyMonitor {
+    measurer: Arc<dyn LatencyMeasurer>,
+    measurements: Arc<Mutex<HashMap<String, Measurement>>>,
+    is_measuring: Arc<AtomicBool>,
+}
+
+impl LatencyMonitor {
+    pub fn new(measurer: Arc<dyn LatencyMeasurer>) -> Self {
+        LatencyMonitor {
+            measurer,
+            measurements: Arc::new(Mutex::new(HashMap::new())),
+            is_measuring: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Measures the latency to the given relays that lack a recent measurement. The measurements
+    /// are done on a background thread. Does nothing if measurements are already in progress.
+    pub fn measure(&self, relays: Vec<Relay>) {
+        if self.is_measuring.swap(true, Ordering::SeqCst) {
+            debug!("Relay latencies are already being measured");
+            return;
+        }
+
+        let now = Instant::now();
+        let stale_relays: Vec<Relay> = {
+            let measurements = self.measurements.lock();
+            relays
+                .into_iter()
+                .filter(|relay| {
+                    !measurements
+                        .get(&relay.hostname)
+                        .map(|measurement| measurement.is_fresh(now))
+                        .unwrap_or(false)
+                })
+                .collect()
+        };
+        if stale_relays.is_empty() {
+            self.is_measuring.store(false, Ordering::SeqCst);
+            return;
+        }
+
+        let monitor = self.clone();
+        thread::spawn(move || {
+            monitor.measure_relays(stale_relays);
+            monitor.is_measuring.store(false, Ordering::SeqCst);
+        });
+    }
+
+    fn measure_relays(&self, relays: Vec<Relay>) {
+        debug!("Measuring latency to {} relays", relays.len());
+        for chunk in relays.chunks(MAX_CONCURRENT_MEASUREMENTS) {
+            let handles: Vec<_> = chunk
+                .iter()
+                .map(|relay| {
+                    let measurer = self.measurer.clone();
+                    let hostname = relay.hostname.clone();
+                    let addr = relay.ipv4_addr_in;
+                    thread::spawn(move || (hostname, measurer.measure(addr)))
+                })
+                .collect();
+
+            for handle in handles {
+                let (hostname, latency) = match handle.join() {
+                    Ok(result) => result,
+                    Err(_) => continue,
+                };
+                let mut measurements = self.measurements.lock();
+                match latency {
+                    Some(latency) => {
+                        trace!("Latency to {}: {} ms", hostname, latency.as_millis());
+                        measurements.insert(
+                            hostname,
+                            Measurement {
+                                latency,
+                                measured_at: Instant::now(),
+                            },
+                        );
+                    }
+                    // Unreachable relays should not be preferred based on an old measurement
+                    None => {
+                        measurements.remove(&hostname);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Narrows down `relays` to the ones with the lowest recently measured latency, ordered by
+    /// latency. If none of the relays have been measured, all of them are returned unchanged.
+    pub fn fastest_relays(&self, relays: Vec<Relay>) -> Vec<Relay> {
+        let now = Instant::now();
+        let mut measured: Vec<(Duration, usize)> = {
+            let measurements = self.measurements.lock();
+            relays
+                .iter()
+                .enumerate()
+                .filter_map(|(index, relay)| {
+                    measurements
+                        .get(&relay.hostname)
+                        .filter(|measurement| measurement.is_fresh(now))
+                        .map(|measurement| (measurement.latency, index))
+                })
+                .collect()
+        };
+        if measured.is_empty() {
+            debug!("No recent latency measurements, ignoring latency");
+            return relays;
+        }
+
+        measured.sort_by_key(|(latency, _)| *latency);
+        measured.truncate(FASTEST_RELAY_COUNT);
+        measured
+            .into_iter()
+            .map(|(_, index)| relays[index].clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct MockMeasurer {
+        latencies: HashMap<Ipv4Addr, Duration>,
+    }
+
+    impl LatencyMeasurer for MockMeasurer {
+        fn measure(&self, addr: Ipv4Addr) -> Option<Duration> {
+            self.latencies.get(&addr).cloned()
+        }
+    }
+
+    fn relay(index: u8) -> Relay {
+        Relay {
+            hostname: format!("se{}-wireguard", index),
+            ipv4_addr_in: Ipv4Addr::new(10, 0, 0, index),
+            ipv6_addr_in: None,
+            include_in_country: true,
+            active: true,
+            owned: true,
+            provider: "31173".to_owned(),
+            weight: 1,
+            tunnels: Default::default(),
+            bridges: Default::default(),
+            location: None,
+        }
+    }
+
+    fn monitor_with_latencies(latencies: &[(u8, u64)]) -> LatencyMonitor {
+        let latencies = latencies
+            .iter()
+            .map(|(index, millis)| {
+                (
+                    Ipv4Addr::new(10, 0, 0, *index),
+                    Duration::from_millis(*millis),
+                )
+            })
+            .collect();
+        LatencyMonitor::new(Arc::new(MockMeasurer { latencies }))
+    }
+
+    #[test]
+    fn test_fastest_relays() {
+        let relays: Vec<Relay> = (1..=8).map(relay).collect();
+        // Relay 8 does not reply
+        let monitor = monitor_with_latencies(&[
+            (1, 70),
+            (2, 20),
+            (3, 90),
+            (4, 10),
+            (5, 50),
+            (6, 30),
+            (7, 40),
+        ]);
+
+        let hostnames = |relays: Vec<Relay>| -> Vec<String> {
+            relays.into_iter().map(|relay| relay.hostname).collect()
+        };
+
+        // Without any measurements, no relays are filtered out
+        assert_eq!(
+            hostnames(monitor.fastest_relays(relays.clone())),
+            hostnames(relays.clone())
+        );
+
+        monitor.measure_relays(relays.clone());
+        assert_eq!(
+            hostnames(monitor.fastest_relays(relays)),
+            vec![
+                "se4-wireguard",
+                "se2-wireguard",
+                "se6-wireguard",
+                "se7-wireguard",
+                "se5-wireguard",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stale_measurements_are_ignored() {
+        let relays: Vec<Relay> = (1..=2).map(relay).collect();
+        let monitor = monitor_with_latencies(&[(1, 10), (2, 20)]);
+        monitor.measure_relays(relays.clone());
+
+        let stale_time = Instant::now() - (MAX_MEASUREMENT_AGE + Duration::from_secs(1));
+        monitor
+            .measurements
+            .lock()
+            .get_mut("se1-wireguard")
+            .unwrap()
+            .measured_at = stale_time;
+
+        let fastest = monitor.fastest_relays(relays);
+        assert_eq!(fastest.len(), 1);
+        assert_eq!(fastest[0].hostname, "se2-wireguard");
+    }
+}
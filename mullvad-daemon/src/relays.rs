@@ -1,6 +1,7 @@
 //! When changing relay selection, please verify if `docs/relay-selector.md` needs to be
 //! updated as well.
 
+use crate::relay_latency::{IcmpLatencyMeasurer, LatencyMonitor};
 use chrono::{DateTime, Local};
 use futures::{
     channel::mpsc,
@@ -10,11 +11,13 @@ use futures::{
 use log::{debug, error, info, warn};
 use mullvad_rpc::{rest::MullvadRestHandle, RelayListProxy};
 use mullvad_types::{
+    custom_list::CustomListsSettings,
     endpoint::MullvadEndpoint,
     location::Location,
     relay_constraints::{
         BridgeState, Constraint, InternalBridgeConstraints, LocationConstraint, Match,
-        OpenVpnConstraints, RelayConstraints, WireguardConstraints,
+        OpenVpnConstraints, Ownership, Providers, RelayConstraints, SelectionMode,
+        WireguardConstraints,
     },
     relay_list::{OpenVpnEndpointData, Relay, RelayList, RelayTunnels, WireguardEndpointData},
 };
@@ -30,13 +33,21 @@ use std::{
 };
 use talpid_core::future_retry::{retry_future_with_backoff, ExponentialBackoff, Jittered};
 use talpid_types::{
-    net::{all_of_the_internet, openvpn::ProxySettings, wireguard, TransportProtocol, TunnelType},
+    net::{
+        all_of_the_internet,
+        openvpn::{self, ProxySettings},
+        wireguard, TransportProtocol, TunnelType,
+    },
     ErrorExt,
 };
 use tokio02::fs::File;
 
 const DATE_TIME_FORMAT_STR: &str = "%Y-%m-%d %H:%M:%S%.3f";
 const RELAYS_FILENAME: &str = "relays.json";
+/// Ports on which the relays accept WireGuard traffic tunneled over TCP. The relay list does not
+/// include them, so they have to match the ports that the UDP-over-TCP service listens on on
+/// every WireGuard relay.
+const UDP_OVER_TCP_PORTS: &[u16] = &[80, 5001];
 /// How often the updater should wake up to check the cache of the in-memory cache of relays.
 /// This check is very cheap. The only reason to not have it very often is because if downloading
 /// constantly fails it will try very often and fill the logs etc.
@@ -60,6 +71,9 @@ pub enum Error {
     #[error(display = "No relays matching current constraints")]
     NoRelay,
 
+    #[error(display = "Multihop is only supported by WireGuard")]
+    MultihopRequiresWireguard,
+
     #[error(display = "Failure in serialization of the relay list")]
     Serialize(#[error(source)] serde_json::Error),
 
@@ -146,6 +160,8 @@ pub struct RelaySelector {
     parsed_relays: Arc<Mutex<ParsedRelays>>,
     rng: ThreadRng,
     updater: RelayListUpdaterHandle,
+    latency_monitor: LatencyMonitor,
+    custom_lists: CustomListsSettings,
 }
 
 impl RelaySelector {
@@ -187,6 +203,8 @@ impl RelaySelector {
             parsed_relays,
             rng: rand::thread_rng(),
             updater,
+            latency_monitor: LatencyMonitor::new(Arc::new(IcmpLatencyMeasurer)),
+            custom_lists: CustomListsSettings::default(),
         }
     }
 
@@ -201,40 +219,108 @@ impl RelaySelector {
         }
     }
 
+    /// Sets the custom lists that location constraints may refer to.
+    pub fn set_custom_lists(&mut self, custom_lists: CustomListsSettings) {
+        self.custom_lists = custom_lists;
+    }
+
+    /// Returns when the relay list was last updated.
+    pub fn last_updated(&self) -> SystemTime {
+        self.parsed_relays.lock().last_updated()
+    }
+
     /// Returns all countries and cities. The cities in the object returned does not have any
     /// relays in them.
     pub fn get_locations(&mut self) -> RelayList {
         self.parsed_relays.lock().locations().clone()
     }
 
+    /// Returns the relay with the given hostname, if there is one.
+    pub fn get_relay_by_hostname(&self, hostname: &str) -> Option<Relay> {
+        self.parsed_relays
+            .lock()
+            .relays()
+            .iter()
+            .find(|relay| relay.hostname == hostname)
+            .cloned()
+    }
+
+    /// Measures the latency to the relays matching the given constraints in the background, for
+    /// use when relays are selected by latency. Traffic to the relays must not be blocked by the
+    /// firewall when this is called.
+    pub fn measure_latencies(&self, constraints: &RelayConstraints) {
+        let relays: Vec<Relay> = {
+            let parsed_relays = self.parsed_relays.lock();
+            let active_relays = parsed_relays.relays().iter().filter(|relay| relay.active);
+            match constraints.entry_location {
+                // When using multihop, the latency to the entry relay is what matters
+                Some(ref entry_location) => active_relays
+                    .filter(|relay| {
+                        !relay.tunnels.wireguard.is_empty()
+                            && self.relay_matches_location(relay, entry_location)
+                            && Self::relay_matches_provider_and_ownership(
+                                relay,
+                                &constraints.providers,
+                                &constraints.ownership,
+                            )
+                    })
+                    .cloned()
+                    .collect(),
+                None => active_relays
+                    .filter_map(|relay| self.matching_relay(relay, constraints))
+                    .collect(),
+            }
+        };
+        self.latency_monitor.measure(relays);
+    }
+
     /// Returns a random relay and relay endpoint matching the given constraints and with
-    /// preferences applied.
+    /// preferences applied. If multihop is enabled, the entry relay is returned as well.
     pub fn get_tunnel_endpoint(
         &mut self,
         relay_constraints: &RelayConstraints,
         bridge_state: &BridgeState,
         retry_attempt: u32,
         wg_key_exists: bool,
-    ) -> Result<(Relay, MullvadEndpoint), Error> {
+    ) -> Result<(Relay, Option<Relay>, MullvadEndpoint), Error> {
+        // Multihop is only supported by WireGuard
+        if relay_constraints.entry_location.is_some()
+            && relay_constraints.tunnel_protocol == Constraint::Only(TunnelType::OpenVpn)
+        {
+            warn!("An entry location is set, but multihop is not supported by OpenVPN");
+            return Err(Error::MultihopRequiresWireguard);
+        }
+        let multihop_constraints;
+        let relay_constraints = if relay_constraints.entry_location.is_some()
+            && relay_constraints.tunnel_protocol.is_any()
+        {
+            multihop_constraints = RelayConstraints {
+                tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+                ..relay_constraints.clone()
+            };
+            &multihop_constraints
+        } else {
+            relay_constraints
+        };
+
         let preferred_constraints = self.preferred_constraints(
             relay_constraints,
             bridge_state,
             retry_attempt,
             wg_key_exists,
         );
-        if let Some((relay, endpoint)) = self.get_tunnel_endpoint_internal(&preferred_constraints) {
+        if let Some(result) = self.get_tunnel_endpoint_internal(&preferred_constraints) {
             debug!(
                 "Relay matched on highest preference for retry attempt {}",
                 retry_attempt
             );
-            Ok((relay, endpoint))
-        } else if let Some((relay, endpoint)) = self.get_tunnel_endpoint_internal(relay_constraints)
-        {
+            Ok(result)
+        } else if let Some(result) = self.get_tunnel_endpoint_internal(relay_constraints) {
             debug!(
                 "Relay matched on second preference for retry attempt {}",
                 retry_attempt
             );
-            Ok((relay, endpoint))
+            Ok(result)
         } else {
             warn!("No relays matching {}", relay_constraints);
             Err(Error::NoRelay)
@@ -264,6 +350,10 @@ impl RelaySelector {
             location: original_constraints.location.clone(),
             tunnel_protocol: original_constraints.tunnel_protocol.clone(),
             wireguard_constraints: original_constraints.wireguard_constraints,
+            entry_location: original_constraints.entry_location.clone(),
+            providers: original_constraints.providers.clone(),
+            ownership: original_constraints.ownership,
+            selection_mode: original_constraints.selection_mode,
             ..Default::default()
         };
         // Highest priority preference. Where we prefer OpenVPN using UDP. But without changing
@@ -284,8 +374,19 @@ impl RelaySelector {
                         original_constraints.openvpn_constraints;
                 }
 
-                if relay_constraints.wireguard_constraints.port.is_any() {
-                    relay_constraints.wireguard_constraints.port = preferred_port;
+                let wireguard_constraints = &mut relay_constraints.wireguard_constraints;
+                if wireguard_constraints.protocol.is_any() {
+                    if wireguard_constraints.port.is_any() {
+                        wireguard_constraints.port = preferred_port;
+                    }
+                    if preferred_tunnel == TunnelType::Wireguard {
+                        wireguard_constraints.protocol = Constraint::Only(preferred_protocol);
+                    }
+                } else if wireguard_constraints.protocol == Constraint::Only(TransportProtocol::Udp)
+                    && wireguard_constraints.port.is_any()
+                    && preferred_protocol == TransportProtocol::Udp
+                {
+                    wireguard_constraints.port = preferred_port;
                 }
 
                 relay_constraints.tunnel_protocol = Constraint::Only(preferred_tunnel);
@@ -308,10 +409,17 @@ impl RelaySelector {
                 relay_constraints.wireguard_constraints =
                     original_constraints.wireguard_constraints;
                 // This ensures that if after the first 2 failed attempts the daemon does not
-                // connect, then afterwards 2 of each 4 successive attempts will try to connect on
-                // port 53.
-                if retry_attempt % 4 > 1 && relay_constraints.wireguard_constraints.port.is_any() {
-                    relay_constraints.wireguard_constraints.port = Constraint::Only(53);
+                // connect, then afterwards 2 of each 4 successive attempts will try something
+                // else: first port 53, then tunneling the traffic over TCP unless a protocol has
+                // been explicitly selected.
+                let wireguard_constraints = &mut relay_constraints.wireguard_constraints;
+                if cfg!(not(target_os = "android"))
+                    && wireguard_constraints.protocol.is_any()
+                    && retry_attempt % 4 == 3
+                {
+                    wireguard_constraints.protocol = Constraint::Only(TransportProtocol::Tcp);
+                } else if retry_attempt % 4 > 1 && wireguard_constraints.port.is_any() {
+                    wireguard_constraints.port = Constraint::Only(53);
                 }
             }
         }
@@ -358,7 +466,7 @@ impl RelaySelector {
             .relays()
             .iter()
             .filter(|relay| relay.active)
-            .filter_map(|relay| Self::matching_bridge_relay(relay, constraints))
+            .filter_map(|relay| self.matching_bridge_relay(relay, constraints))
             .collect();
 
         if matching_relays.is_empty() {
@@ -388,7 +496,7 @@ impl RelaySelector {
                 self.parsed_relays.lock().relays().iter().any(|relay| {
                     relay.active
                         && !relay.tunnels.wireguard.is_empty()
-                        && Self::relay_matches_location(relay, &location_constraint)
+                        && self.relay_matches_location(relay, &location_constraint)
                 });
             // If location does not support WireGuard, defer to preferred OpenVPN tunnel
             // constraints
@@ -397,28 +505,7 @@ impl RelaySelector {
                     Self::preferred_openvpn_constraints(retry_attempt);
                 return (preferred_port, preferred_protocol, TunnelType::OpenVpn);
             }
-
-
-            // Try out WireGuard in the first two connection attempts, first with any port,
-            // afterwards on port 53. Afterwards, connect through OpenVPN alternating between UDP
-            // on any port twice and TCP on port 443 once.
-            match retry_attempt {
-                0 if location_supports_wireguard => (
-                    Constraint::Any,
-                    TransportProtocol::Udp,
-                    TunnelType::Wireguard,
-                ),
-                1 => (
-                    Constraint::Only(53),
-                    TransportProtocol::Udp,
-                    TunnelType::Wireguard,
-                ),
-                _ => {
-                    let (preferred_port, preferred_protocol) =
-                        Self::preferred_openvpn_constraints(retry_attempt - 2);
-                    (preferred_port, preferred_protocol, TunnelType::OpenVpn)
-                }
-            }
+            Self::preferred_constraints_with_wireguard(retry_attempt)
         }
 
         #[cfg(target_os = "windows")]
@@ -431,6 +518,40 @@ impl RelaySelector {
         }
     }
 
+    /// Returns preferred constraints when both WireGuard and OpenVPN can be used.
+    #[cfg(not(target_os = "windows"))]
+    fn preferred_constraints_with_wireguard(
+        retry_attempt: u32,
+    ) -> (Constraint<u16>, TransportProtocol, TunnelType) {
+        // Try out WireGuard in the first three connection attempts, first with any port,
+        // afterwards on port 53 and then tunneled over TCP. Afterwards, connect through
+        // OpenVPN alternating between UDP on any port twice and TCP on port 443 once.
+        match retry_attempt {
+            0 => (
+                Constraint::Any,
+                TransportProtocol::Udp,
+                TunnelType::Wireguard,
+            ),
+            1 => (
+                Constraint::Only(53),
+                TransportProtocol::Udp,
+                TunnelType::Wireguard,
+            ),
+            // The UDP-over-TCP socket can't be excluded from the tunnel on Android yet
+            2 if cfg!(not(target_os = "android")) => (
+                Constraint::Any,
+                TransportProtocol::Tcp,
+                TunnelType::Wireguard,
+            ),
+            _ => {
+                let wireguard_attempts = if cfg!(target_os = "android") { 2 } else { 3 };
+                let (preferred_port, preferred_protocol) =
+                    Self::preferred_openvpn_constraints(retry_attempt - wireguard_attempts);
+                (preferred_port, preferred_protocol, TunnelType::OpenVpn)
+            }
+        }
+    }
+
     fn preferred_openvpn_constraints(retry_attempt: u32) -> (Constraint<u16>, TransportProtocol) {
         // Prefer UDP by default. But if that has failed a couple of times, then try TCP port
         // 443, which works for many with UDP problems. After that, just alternate
@@ -448,31 +569,125 @@ impl RelaySelector {
     fn get_tunnel_endpoint_internal(
         &mut self,
         constraints: &RelayConstraints,
-    ) -> Option<(Relay, MullvadEndpoint)> {
+    ) -> Option<(Relay, Option<Relay>, MullvadEndpoint)> {
         let matching_relays: Vec<Relay> = self
             .parsed_relays
             .lock()
             .relays()
             .iter()
             .filter(|relay| relay.active)
-            .filter_map(|relay| Self::matching_relay(relay, constraints))
+            .filter_map(|relay| self.matching_relay(relay, constraints))
             .collect();
+        let matching_relays = if constraints.selection_mode == SelectionMode::Latency
+            && constraints.entry_location.is_none()
+        {
+            self.latency_monitor.fastest_relays(matching_relays)
+        } else {
+            matching_relays
+        };
 
-        self.pick_random_relay(&matching_relays)
-            .and_then(|selected_relay| {
-                info!(
-                    "Selected relay {} at {}",
-                    selected_relay.hostname, selected_relay.ipv4_addr_in
-                );
-                self.get_random_tunnel(&selected_relay, &constraints)
-                    .map(|endpoint| (selected_relay.clone(), endpoint))
+        let selected_relay = self.pick_random_relay(&matching_relays)?;
+        info!(
+            "Selected relay {} at {}",
+            selected_relay.hostname, selected_relay.ipv4_addr_in
+        );
+        let endpoint = self.get_random_tunnel(&selected_relay, &constraints)?;
+
+        match (&constraints.entry_location, endpoint) {
+            (
+                Some(entry_location),
+                MullvadEndpoint::Wireguard {
+                    peer,
+                    ipv4_gateway,
+                    ipv6_gateway,
+                    udp_over_tcp_endpoint,
+                    ..
+                },
+            ) => {
+                if udp_over_tcp_endpoint.is_some() {
+                    warn!("UDP-over-TCP is not supported with multihop, connecting over UDP");
+                }
+                let (entry_relay, entry_endpoint) =
+                    self.get_entry_endpoint(&selected_relay, &peer, entry_location, constraints)?;
+                Some((
+                    selected_relay.clone(),
+                    Some(entry_relay),
+                    MullvadEndpoint::Wireguard {
+                        peer,
+                        ipv4_gateway,
+                        ipv6_gateway,
+                        entry_endpoint: Some(entry_endpoint),
+                        udp_over_tcp_endpoint: None,
+                    },
+                ))
+            }
+            // Never silently fall back to a single hop when an entry location is set
+            (Some(_), _) => None,
+            (None, endpoint) => Some((selected_relay.clone(), None, endpoint)),
+        }
+    }
+
+    /// Picks a random relay to enter through when using multihop. The entry relay must be
+    /// distinct from the exit relay, and forwards the traffic to the exit relay's multihop port.
+    fn get_entry_endpoint(
+        &mut self,
+        exit_relay: &Relay,
+        exit_peer: &wireguard::PeerConfig,
+        entry_location: &Constraint<LocationConstraint>,
+        constraints: &RelayConstraints,
+    ) -> Option<(Relay, SocketAddr)> {
+        let multihop_port = exit_relay
+            .tunnels
+            .wireguard
+            .iter()
+            .find(|data| data.public_key == exit_peer.public_key)
+            .and_then(|data| data.multihop_port)?;
+
+        let matching_entry_relays: Vec<Relay> = self
+            .parsed_relays
+            .lock()
+            .relays()
+            .iter()
+            .filter(|relay| {
+                relay.active
+                    && relay.hostname != exit_relay.hostname
+                    && !relay.tunnels.wireguard.is_empty()
+                    && self.relay_matches_location(relay, entry_location)
+                    && Self::relay_matches_provider_and_ownership(
+                        relay,
+                        &constraints.providers,
+                        &constraints.ownership,
+                    )
             })
+            .cloned()
+            .collect();
+        let matching_entry_relays = match constraints.selection_mode {
+            SelectionMode::Latency => self.latency_monitor.fastest_relays(matching_entry_relays),
+            SelectionMode::Weighted => matching_entry_relays,
+        };
+
+        let entry_relay = self.pick_random_relay(&matching_entry_relays)?;
+        info!(
+            "Selected entry relay {} at {}",
+            entry_relay.hostname, entry_relay.ipv4_addr_in
+        );
+        Some((
+            entry_relay.clone(),
+            SocketAddr::new(entry_relay.ipv4_addr_in.into(), multihop_port),
+        ))
     }
 
     /// Takes a `Relay` and a corresponding `RelayConstraints` and returns a new `Relay` if the
     /// given relay matches the constraints.
-    fn matching_relay(relay: &Relay, constraints: &RelayConstraints) -> Option<Relay> {
-        if !Self::relay_matches_location(relay, &constraints.location) {
+    fn matching_relay(&self, relay: &Relay, constraints: &RelayConstraints) -> Option<Relay> {
+        if !self.relay_matches_location(relay, &constraints.location) {
+            return None;
+        }
+        if !Self::relay_matches_provider_and_ownership(
+            relay,
+            &constraints.providers,
+            &constraints.ownership,
+        ) {
             return None;
         }
 
@@ -501,6 +716,13 @@ impl RelaySelector {
                     ),
                     openvpn: vec![],
                 };
+                // Exit relays must be reachable through an entry relay when using multihop
+                if constraints.entry_location.is_some() {
+                    relay
+                        .tunnels
+                        .wireguard
+                        .retain(|data| data.multihop_port.is_some());
+                }
                 relay
             }
 
@@ -533,44 +755,94 @@ impl RelaySelector {
         }
     }
 
-    fn relay_matches_location(relay: &Relay, location: &Constraint<LocationConstraint>) -> bool {
+    fn relay_matches_location(
+        &self,
+        relay: &Relay,
+        location: &Constraint<LocationConstraint>,
+    ) -> bool {
         match location {
             Constraint::Any => true,
-            Constraint::Only(LocationConstraint::Country(ref country)) => {
+            Constraint::Only(location) => {
+                Self::relay_matches_location_constraint(relay, location, &self.custom_lists)
+            }
+        }
+    }
+
+    fn relay_matches_location_constraint(
+        relay: &Relay,
+        location: &LocationConstraint,
+        custom_lists: &CustomListsSettings,
+    ) -> bool {
+        match location {
+            LocationConstraint::Country(ref country) => {
                 relay
                     .location
                     .as_ref()
                     .map_or(false, |loc| loc.country_code == *country)
                     && relay.include_in_country
             }
-            Constraint::Only(LocationConstraint::City(ref country, ref city)) => {
+            LocationConstraint::City(ref country, ref city) => {
                 relay.location.as_ref().map_or(false, |loc| {
                     loc.country_code == *country && loc.city_code == *city
                 })
             }
-            Constraint::Only(LocationConstraint::Hostname(ref country, ref city, ref hostname)) => {
+            LocationConstraint::Hostname(ref country, ref city, ref hostname) => {
                 relay.location.as_ref().map_or(false, |loc| {
                     loc.country_code == *country
                         && loc.city_code == *city
                         && relay.hostname == *hostname
                 })
             }
+            LocationConstraint::CustomList(ref id) => custom_lists.get(id).map_or(false, |list| {
+                // Custom lists can't be nested, so don't resolve lists within the list
+                list.locations.iter().any(|location| {
+                    Self::relay_matches_location_constraint(
+                        relay,
+                        location,
+                        &CustomListsSettings::default(),
+                    )
+                })
+            }),
         }
     }
 
+    fn relay_matches_provider_and_ownership(
+        relay: &Relay,
+        providers: &Constraint<Providers>,
+        ownership: &Constraint<Ownership>,
+    ) -> bool {
+        let provider_matches = match providers {
+            Constraint::Any => true,
+            Constraint::Only(ref providers) => providers.matches(relay),
+        };
+        let ownership_matches = match ownership {
+            Constraint::Any => true,
+            Constraint::Only(ref ownership) => ownership.matches(relay),
+        };
+        provider_matches && ownership_matches
+    }
+
     fn matching_bridge_relay(
+        &self,
         relay: &Relay,
         constraints: &InternalBridgeConstraints,
     ) -> Option<Relay> {
-        if !Self::relay_matches_location(relay, &constraints.location) {
+        if !self.relay_matches_location(relay, &constraints.location) {
+            return None;
+        }
+        if !Self::relay_matches_provider_and_ownership(
+            relay,
+            &constraints.providers,
+            &constraints.ownership,
+        ) {
             return None;
         }
 
         let mut filtered_relay = relay.clone();
-        filtered_relay
-            .bridges
-            .shadowsocks
-            .retain(|bridge| constraints.transport_protocol.matches(&bridge.protocol));
+        filtered_relay.bridges.shadowsocks.retain(|bridge| {
+            constraints.transport_protocol.matches(&bridge.protocol)
+                && openvpn::SHADOWSOCKS_CIPHERS.contains(&bridge.cipher.as_str())
+        });
         if filtered_relay.bridges.shadowsocks.is_empty() {
             return None;
         }
@@ -699,16 +971,38 @@ impl RelaySelector {
         data: WireguardEndpointData,
         constraints: WireguardConstraints,
     ) -> Option<MullvadEndpoint> {
-        let port = self.get_port_for_wireguard_relay(&data, constraints)?;
+        let (port, udp_over_tcp_endpoint) =
+            if constraints.protocol == Constraint::Only(TransportProtocol::Tcp) {
+                // The port constraint applies to the TCP endpoint, so any port can be used for
+                // the WireGuard traffic itself.
+                let tcp_port = match constraints.port {
+                    Constraint::Only(port) => port,
+                    Constraint::Any => *UDP_OVER_TCP_PORTS.choose(&mut self.rng)?,
+                };
+                let port = self.get_port_for_wireguard_relay(
+                    &data,
+                    WireguardConstraints {
+                        port: Constraint::Any,
+                        ..constraints
+                    },
+                )?;
+                (port, Some(SocketAddr::new(host, tcp_port)))
+            } else {
+                (self.get_port_for_wireguard_relay(&data, constraints)?, None)
+            };
         let peer_config = wireguard::PeerConfig {
             public_key: data.public_key,
             endpoint: SocketAddr::new(host, port),
             allowed_ips: all_of_the_internet(),
+            preshared_key: None,
+            persistent_keepalive: None,
         };
         Some(MullvadEndpoint::Wireguard {
             peer: peer_config,
             ipv4_gateway: data.ipv4_gateway,
             ipv6_gateway: data.ipv6_gateway,
+            entry_endpoint: None,
+            udp_over_tcp_endpoint,
         })
     }
 
@@ -945,3 +1239,333 @@ impl RelayListUpdater {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mullvad_types::relay_list::{
+        RelayBridges, RelayListCity, RelayListCountry, ShadowsocksEndpointData,
+    };
+    use std::net::Ipv4Addr;
+
+    fn relay(index: u8, provider: &str, owned: bool) -> Relay {
+        Relay {
+            hostname: format!("se{}", index),
+            ipv4_addr_in: Ipv4Addr::new(10, 0, 0, index),
+            ipv6_addr_in: None,
+            include_in_country: true,
+            active: true,
+            owned,
+            provider: provider.to_owned(),
+            weight: 1,
+            tunnels: RelayTunnels {
+                openvpn: vec![OpenVpnEndpointData {
+                    port: 1194,
+                    protocol: TransportProtocol::Udp,
+                }],
+                wireguard: vec![WireguardEndpointData {
+                    port_ranges: vec![(53, 53), (4000, 33433)],
+                    ipv4_gateway: Ipv4Addr::new(10, 64, 0, 1),
+                    ipv6_gateway: "fc00:bbbb:bbbb:bb01::1".parse().unwrap(),
+                    public_key: wireguard::PublicKey::from([index; 32]),
+                    multihop_port: None,
+                }],
+            },
+            bridges: RelayBridges {
+                shadowsocks: vec![ShadowsocksEndpointData {
+                    port: 443,
+                    cipher: "aes-256-gcm".to_owned(),
+                    password: "mullvad".to_owned(),
+                    protocol: TransportProtocol::Tcp,
+                }],
+            },
+            location: None,
+        }
+    }
+
+    fn selector_with_relays(relays: Vec<Relay>) -> RelaySelector {
+        let relay_list = RelayList {
+            countries: vec![RelayListCountry {
+                name: "Sweden".to_owned(),
+                code: "se".to_owned(),
+                cities: vec![RelayListCity {
+                    name: "Gothenburg".to_owned(),
+                    code: "got".to_owned(),
+                    latitude: 57.70887,
+                    longitude: 11.97456,
+                    relays,
+                }],
+            }],
+        };
+        RelaySelector {
+            parsed_relays: Arc::new(Mutex::new(ParsedRelays::from_relay_list(
+                relay_list,
+                SystemTime::now(),
+            ))),
+            rng: rand::thread_rng(),
+            updater: RelayListUpdaterHandle {
+                tx: mpsc::channel(1).0,
+            },
+            latency_monitor: LatencyMonitor::new(Arc::new(IcmpLatencyMeasurer)),
+            custom_lists: CustomListsSettings::default(),
+        }
+    }
+
+    fn multihop_relay(index: u8, multihop_port: Option<u16>) -> Relay {
+        let mut relay = relay(index, "31173", true);
+        relay.tunnels.wireguard[0].multihop_port = multihop_port;
+        relay
+    }
+
+    fn hostname_constraint(hostname: &str) -> Constraint<LocationConstraint> {
+        Constraint::Only(LocationConstraint::Hostname(
+            "se".to_owned(),
+            "got".to_owned(),
+            hostname.to_owned(),
+        ))
+    }
+
+    fn providers(providers: &[&str]) -> Constraint<Providers> {
+        Constraint::Only(
+            Providers::new(providers.iter().map(|provider| provider.to_string())).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_preferred_openvpn_constraints() {
+        let attempts: Vec<_> = (0..6)
+            .map(RelaySelector::preferred_openvpn_constraints)
+            .collect();
+        assert_eq!(
+            attempts,
+            vec![
+                (Constraint::Any, TransportProtocol::Udp),
+                (Constraint::Any, TransportProtocol::Udp),
+                (Constraint::Only(443), TransportProtocol::Tcp),
+                (Constraint::Only(443), TransportProtocol::Tcp),
+                (Constraint::Any, TransportProtocol::Udp),
+                (Constraint::Any, TransportProtocol::Tcp),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(not(any(target_os = "windows", target_os = "android")))]
+    fn test_preferred_constraints_with_wireguard() {
+        let attempts: Vec<_> = (0..5)
+            .map(RelaySelector::preferred_constraints_with_wireguard)
+            .collect();
+        assert_eq!(
+            attempts,
+            vec![
+                (
+                    Constraint::Any,
+                    TransportProtocol::Udp,
+                    TunnelType::Wireguard
+                ),
+                (
+                    Constraint::Only(53),
+                    TransportProtocol::Udp,
+                    TunnelType::Wireguard
+                ),
+                (
+                    Constraint::Any,
+                    TransportProtocol::Tcp,
+                    TunnelType::Wireguard
+                ),
+                (Constraint::Any, TransportProtocol::Udp, TunnelType::OpenVpn),
+                (Constraint::Any, TransportProtocol::Udp, TunnelType::OpenVpn),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "android")]
+    fn test_android_skips_udp_over_tcp() {
+        assert_eq!(
+            RelaySelector::preferred_constraints_with_wireguard(2),
+            (Constraint::Any, TransportProtocol::Udp, TunnelType::OpenVpn)
+        );
+    }
+
+    #[test]
+    fn test_tunnel_relay_provider_and_ownership() {
+        let mut selector = selector_with_relays(vec![
+            relay(1, "31173", true),
+            relay(2, "M247", false),
+            relay(3, "31173", false),
+        ]);
+
+        let constraints = RelayConstraints {
+            providers: providers(&["31173"]),
+            ownership: Constraint::Only(Ownership::Rented),
+            ..RelayConstraints::default()
+        };
+        for _ in 0..10 {
+            let (relay, _, _) = selector
+                .get_tunnel_endpoint(&constraints, &BridgeState::Auto, 0, true)
+                .unwrap();
+            assert_eq!(relay.hostname, "se3");
+        }
+
+        let constraints = RelayConstraints {
+            providers: providers(&["M247"]),
+            ownership: Constraint::Only(Ownership::MullvadOwned),
+            ..RelayConstraints::default()
+        };
+        match selector.get_tunnel_endpoint(&constraints, &BridgeState::Auto, 0, true) {
+            Err(Error::NoRelay) => (),
+            result => panic!("Expected no matching relay, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_bridge_provider_and_ownership() {
+        let mut selector = selector_with_relays(vec![
+            relay(1, "31173", true),
+            relay(2, "M247", false),
+            relay(3, "31173", false),
+        ]);
+        let location = selector
+            .get_relay_by_hostname("se1")
+            .and_then(|relay| relay.location)
+            .unwrap();
+
+        let constraints = InternalBridgeConstraints {
+            location: Constraint::Any,
+            providers: providers(&["31173"]),
+            ownership: Constraint::Only(Ownership::Rented),
+            transport_protocol: Constraint::Only(TransportProtocol::Tcp),
+        };
+        for _ in 0..10 {
+            let (_, relay) = selector
+                .get_proxy_settings(&constraints, &location)
+                .unwrap();
+            assert_eq!(relay.hostname, "se3");
+        }
+
+        let constraints = InternalBridgeConstraints {
+            providers: providers(&["M247"]),
+            ownership: Constraint::Only(Ownership::MullvadOwned),
+            ..constraints
+        };
+        assert!(selector
+            .get_proxy_settings(&constraints, &location)
+            .is_none());
+    }
+
+    #[test]
+    fn test_multihop_entry_differs_from_exit() {
+        let mut selector = selector_with_relays(vec![
+            multihop_relay(1, Some(3001)),
+            multihop_relay(2, Some(3002)),
+        ]);
+
+        let constraints = RelayConstraints {
+            location: hostname_constraint("se1"),
+            entry_location: Some(Constraint::Any),
+            ..RelayConstraints::default()
+        };
+        for _ in 0..10 {
+            let (exit_relay, entry_relay, _) = selector
+                .get_tunnel_endpoint(&constraints, &BridgeState::Auto, 0, true)
+                .unwrap();
+            assert_eq!(exit_relay.hostname, "se1");
+            assert_eq!(entry_relay.unwrap().hostname, "se2");
+        }
+
+        // The only entry candidate is the exit relay itself
+        let constraints = RelayConstraints {
+            entry_location: Some(hostname_constraint("se1")),
+            ..constraints
+        };
+        match selector.get_tunnel_endpoint(&constraints, &BridgeState::Auto, 0, true) {
+            Err(Error::NoRelay) => (),
+            result => panic!("Expected no matching relay, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_multihop_uses_exit_multihop_port() {
+        let mut selector = selector_with_relays(vec![
+            multihop_relay(1, Some(3001)),
+            multihop_relay(2, Some(3002)),
+        ]);
+
+        let constraints = RelayConstraints {
+            location: hostname_constraint("se1"),
+            entry_location: Some(hostname_constraint("se2")),
+            ..RelayConstraints::default()
+        };
+        let (_, _, endpoint) = selector
+            .get_tunnel_endpoint(&constraints, &BridgeState::Auto, 0, true)
+            .unwrap();
+        match endpoint {
+            MullvadEndpoint::Wireguard {
+                peer,
+                entry_endpoint,
+                ..
+            } => {
+                assert_eq!(peer.endpoint.ip(), IpAddr::from(Ipv4Addr::new(10, 0, 0, 1)));
+                assert_eq!(
+                    entry_endpoint,
+                    Some(SocketAddr::new(Ipv4Addr::new(10, 0, 0, 2).into(), 3001))
+                );
+            }
+            endpoint => panic!("Expected a WireGuard endpoint, got {:?}", endpoint),
+        }
+    }
+
+    #[test]
+    fn test_multihop_requires_exit_multihop_port() {
+        let mut selector =
+            selector_with_relays(vec![multihop_relay(1, None), multihop_relay(2, Some(3002))]);
+
+        // Only relays with a multihop port can be used as exit relays
+        let constraints = RelayConstraints {
+            entry_location: Some(Constraint::Any),
+            ..RelayConstraints::default()
+        };
+        for _ in 0..10 {
+            let (exit_relay, entry_relay, _) = selector
+                .get_tunnel_endpoint(&constraints, &BridgeState::Auto, 0, true)
+                .unwrap();
+            assert_eq!(exit_relay.hostname, "se2");
+            assert_eq!(entry_relay.unwrap().hostname, "se1");
+        }
+
+        let constraints = RelayConstraints {
+            location: hostname_constraint("se1"),
+            ..constraints
+        };
+        match selector.get_tunnel_endpoint(&constraints, &BridgeState::Auto, 0, true) {
+            Err(Error::NoRelay) => (),
+            result => panic!("Expected no matching relay, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_multihop_requires_wireguard() {
+        let mut selector = selector_with_relays(vec![
+            multihop_relay(1, Some(3001)),
+            multihop_relay(2, Some(3002)),
+        ]);
+
+        let constraints = RelayConstraints {
+            tunnel_protocol: Constraint::Only(TunnelType::OpenVpn),
+            entry_location: Some(Constraint::Any),
+            ..RelayConstraints::default()
+        };
+        for retry_attempt in 0..5 {
+            match selector.get_tunnel_endpoint(
+                &constraints,
+                &BridgeState::Auto,
+                retry_attempt,
+                true,
+            ) {
+                Err(Error::MultihopRequiresWireguard) => (),
+                result => panic!("Expected multihop to be rejected, got {:?}", result),
+            }
+        }
+    }
+}
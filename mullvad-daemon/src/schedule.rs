@@ -0,0 +1,90 @@
+use chrono::NaiveDateTime;
+use mullvad_types::schedule::{self, ScheduleRule};
+
+/// Source of the local time that schedule rules are matched against.
+pub trait Clock: Send {
+    fn now(&self) -> NaiveDateTime;
+}
+
+/// Reads the local time of the system.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        chrono::Local::now().naive_local()
+    }
+}
+
+/// Keeps track of which schedule rules have already been handled.
+pub struct Scheduler {
+    clock: Box<dyn Clock>,
+    last_check: NaiveDateTime,
+}
+
+impl Scheduler {
+    /// Creates a scheduler that only considers rules that are due after this point in time.
+    pub fn new(clock: Box<dyn Clock>) -> Self {
+        let last_check = clock.now();
+        Scheduler { clock, last_check }
+    }
+
+    /// Returns the rule that became due most recently since the last call, if any. If the clock
+    /// was turned back, nothing is returned until the next call.
+    pub fn poll<'a>(&mut self, rules: &'a [ScheduleRule]) -> Option<&'a ScheduleRule> {
+        let now = self.clock.now();
+        let last_check = std::mem::replace(&mut self.last_check, now);
+        if now <= last_check {
+            return None;
+        }
+        schedule::latest_due_rule(rules, last_check, now)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{Duration, NaiveDate};
+    use mullvad_types::schedule::ScheduleAction;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone)]
+    struct MockClock(Arc<Mutex<NaiveDateTime>>);
+
+    impl MockClock {
+        fn advance(&self, duration: Duration) {
+            *self.0.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> NaiveDateTime {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_scheduler_fires_rules_once() {
+        let rules = vec![ScheduleRule {
+            when: "30 7 * * *".parse().unwrap(),
+            action: ScheduleAction::Connect,
+            relay_settings: None,
+        }];
+        let clock = MockClock(Arc::new(Mutex::new(
+            NaiveDate::from_ymd(2020, 6, 1).and_hms(7, 29, 45),
+        )));
+        let mut scheduler = Scheduler::new(Box::new(clock.clone()));
+
+        clock.advance(Duration::seconds(10));
+        assert_eq!(scheduler.poll(&rules), None);
+        clock.advance(Duration::seconds(10));
+        assert_eq!(scheduler.poll(&rules), Some(&rules[0]));
+        clock.advance(Duration::seconds(10));
+        assert_eq!(scheduler.poll(&rules), None);
+
+        // Turning the clock back does not trigger the rule again
+        clock.advance(Duration::minutes(-5));
+        assert_eq!(scheduler.poll(&rules), None);
+        clock.advance(Duration::seconds(10));
+        assert_eq!(scheduler.poll(&rules), None);
+    }
+}
@@ -1,7 +1,11 @@
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+#[cfg(target_os = "linux")]
+use mullvad_types::trusted_network::TrustedNetwork;
 use mullvad_types::{
+    custom_list::CustomListsSettings,
     relay_constraints::{BridgeSettings, BridgeState, RelaySettingsUpdate},
-    settings::Settings,
+    schedule::ScheduleRule,
+    settings::{DnsOptions, Settings, SettingsPolicy},
 };
 use std::{
     fs::{self, File},
@@ -9,13 +13,20 @@ use std::{
     ops::Deref,
     path::{Path, PathBuf},
 };
-use talpid_types::ErrorExt;
+#[cfg(target_os = "linux")]
+use talpid_types::net::{FirewallExemption, SplitTunnelMode};
+use talpid_types::{
+    net::openvpn::{ProxySettings, SHADOWSOCKS_CIPHERS},
+    ErrorExt,
+};
 
 #[cfg(windows)]
 use talpid_core::logging::windows::log_sink;
 
 
 static SETTINGS_FILE: &str = "settings.json";
+/// Settings pinned by the system administrator. Only used if owned and only writable by root.
+static POLICY_FILE: &str = "policy.json";
 
 
 #[derive(err_derive::Error, Debug)]
@@ -29,6 +40,9 @@ pub enum Error {
 
     #[error(display = "Unable to write settings to {}", _0)]
     WriteError(String, #[error(source)] io::Error),
+
+    #[error(display = "The setting \"{}\" is locked by the system policy", _0)]
+    LockedByPolicy(&'static str),
 }
 
 #[derive(err_derive::Error, Debug)]
@@ -47,26 +61,55 @@ enum LoadSettingsError {
     WinMigrationError(ffi::WinUtilMigrationStatus),
 }
 
+#[derive(err_derive::Error, Debug)]
+enum LoadPolicyError {
+    #[error(display = "Unable to read policy file")]
+    Read(#[error(source)] io::Error),
+
+    #[error(display = "Unable to parse policy file")]
+    ParseError(#[error(source)] serde_json::Error),
+
+    #[cfg(unix)]
+    #[error(display = "The policy file must be owned by root and only writable by its owner")]
+    InsecurePermissions,
+}
+
 
 #[derive(Debug)]
 pub struct SettingsPersister {
+    /// The settings in effect, where the values pinned by the policy override the user's.
     settings: Settings,
+    /// The settings as saved to disk. Settings locked by the policy keep the user's own values.
+    user_settings: Settings,
+    policy: SettingsPolicy,
     path: PathBuf,
 }
 
 impl SettingsPersister {
     /// Loads user settings from file. If no file is present it returns the defaults.
+    /// Settings locked by the policy file are overridden by the values it pins.
     pub fn load(settings_dir: &Path) -> Self {
         let path = settings_dir.join(SETTINGS_FILE);
-        let (mut settings, mut should_save) = Self::load_settings(&path);
+        let (mut user_settings, mut should_save) = Self::load_settings(&path);
 
         // Force IPv6 to be enabled on Android
         if cfg!(target_os = "android") {
             should_save |=
-                Self::update_field(&mut settings.tunnel_options.generic.enable_ipv6, true);
+                Self::update_field(&mut user_settings.tunnel_options.generic.enable_ipv6, true);
         }
 
-        let mut persister = SettingsPersister { settings, path };
+        should_save |= Self::remove_unsupported_bridge(&mut user_settings);
+
+        let policy = Self::load_policy(&settings_dir.join(POLICY_FILE));
+        let mut settings = user_settings.clone();
+        policy.apply(&mut settings);
+
+        let mut persister = SettingsPersister {
+            settings,
+            user_settings,
+            policy,
+            path,
+        };
 
         if should_save {
             if let Err(error) = persister.save() {
@@ -117,6 +160,52 @@ impl SettingsPersister {
             .map_err(LoadSettingsError::ParseError)
     }
 
+    fn load_policy(path: &Path) -> SettingsPolicy {
+        match Self::load_policy_from_file(path) {
+            Ok(Some(policy)) => {
+                info!(
+                    "Loaded settings policy from {}. Locked settings: {}",
+                    path.display(),
+                    policy.locked_fields().join(", ")
+                );
+                policy
+            }
+            Ok(None) => SettingsPolicy::default(),
+            Err(error) => {
+                error!(
+                    "{}",
+                    error.display_chain_with_msg(&format!(
+                        "Ignoring settings policy in {}",
+                        path.display()
+                    ))
+                );
+                SettingsPolicy::default()
+            }
+        }
+    }
+
+    fn load_policy_from_file(path: &Path) -> Result<Option<SettingsPolicy>, LoadPolicyError> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(LoadPolicyError::Read(error)),
+        };
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let metadata = file.metadata().map_err(LoadPolicyError::Read)?;
+            if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+                return Err(LoadPolicyError::InsecurePermissions);
+            }
+        }
+
+        serde_json::from_reader(io::BufReader::new(file))
+            .map(Some)
+            .map_err(LoadPolicyError::ParseError)
+    }
+
     #[cfg(windows)]
     fn try_load_settings_after_windows_update(
         path: &Path,
@@ -135,21 +224,30 @@ impl SettingsPersister {
         }
     }
 
-    /// Serializes the settings and saves them to the file it was loaded from.
+    /// Serializes the settings and saves them to the file it was loaded from. The user's own
+    /// values are saved for the settings that are locked by the policy.
     fn save(&mut self) -> Result<(), Error> {
+        let mut user_settings = self.settings.clone();
+        self.policy.restore(&self.user_settings, &mut user_settings);
+
         debug!("Writing settings to {}", self.path.display());
         let mut file = File::create(&self.path)
             .map_err(|e| Error::WriteError(self.path.display().to_string(), e))?;
 
-        serde_json::to_writer_pretty(&mut file, &self.settings).map_err(Error::SerializeError)?;
+        serde_json::to_writer_pretty(&mut file, &user_settings).map_err(Error::SerializeError)?;
         file.sync_all()
-            .map_err(|e| Error::WriteError(self.path.display().to_string(), e))
+            .map_err(|e| Error::WriteError(self.path.display().to_string(), e))?;
+
+        self.user_settings = user_settings;
+        Ok(())
     }
 
     /// Resets default settings
     #[cfg(not(target_os = "android"))]
     pub fn reset(&mut self) -> Result<(), Error> {
+        self.user_settings = Settings::default();
         self.settings = Settings::default();
+        self.policy.apply(&mut self.settings);
         self.save().or_else(|e| {
             log::error!(
                 "{}",
@@ -165,6 +263,23 @@ impl SettingsPersister {
         self.settings.clone()
     }
 
+    /// Replaces all settings at once. Fails if this would change a setting that is locked by the
+    /// policy. The previous settings are kept if the new ones cannot be saved.
+    pub fn replace(&mut self, mut settings: Settings) -> Result<bool, Error> {
+        if let Some(field) = self.policy.check(&settings) {
+            return Err(Error::LockedByPolicy(field));
+        }
+        self.policy.apply(&mut settings);
+        if self.settings == settings {
+            return Ok(false);
+        }
+        let old_settings = std::mem::replace(&mut self.settings, settings);
+        self.save().map(|_| true).map_err(|error| {
+            self.settings = old_settings;
+            error
+        })
+    }
+
     /// Changes account number to the one given. Also saves the new settings to disk.
     /// The boolean in the Result indicates if the account token changed or not
     pub fn set_account_token(&mut self, account_token: Option<String>) -> Result<bool, Error> {
@@ -173,29 +288,27 @@ impl SettingsPersister {
     }
 
     pub fn update_relay_settings(&mut self, update: RelaySettingsUpdate) -> Result<bool, Error> {
-        let should_save = self.settings.update_relay_settings(update);
-        self.update(should_save)
+        self.update_locked(|settings| settings.update_relay_settings(update))
     }
 
     pub fn set_allow_lan(&mut self, allow_lan: bool) -> Result<bool, Error> {
-        let should_save = Self::update_field(&mut self.settings.allow_lan, allow_lan);
-        self.update(should_save)
+        self.update_locked(|settings| Self::update_field(&mut settings.allow_lan, allow_lan))
     }
 
     pub fn set_block_when_disconnected(
         &mut self,
         block_when_disconnected: bool,
     ) -> Result<bool, Error> {
-        let should_save = Self::update_field(
-            &mut self.settings.block_when_disconnected,
-            block_when_disconnected,
-        );
-        self.update(should_save)
+        self.update_locked(|settings| {
+            Self::update_field(
+                &mut settings.block_when_disconnected,
+                block_when_disconnected,
+            )
+        })
     }
 
     pub fn set_auto_connect(&mut self, auto_connect: bool) -> Result<bool, Error> {
-        let should_save = Self::update_field(&mut self.settings.auto_connect, auto_connect);
-        self.update(should_save)
+        self.update_locked(|settings| Self::update_field(&mut settings.auto_connect, auto_connect))
     }
 
     pub fn set_openvpn_mssfix(&mut self, openvpn_mssfix: Option<u16>) -> Result<bool, Error> {
@@ -207,11 +320,12 @@ impl SettingsPersister {
     }
 
     pub fn set_enable_ipv6(&mut self, enable_ipv6: bool) -> Result<bool, Error> {
-        let should_save = Self::update_field(
-            &mut self.settings.tunnel_options.generic.enable_ipv6,
-            enable_ipv6,
-        );
-        self.update(should_save)
+        self.update_locked(|settings| {
+            Self::update_field(
+                &mut settings.tunnel_options.generic.enable_ipv6,
+                enable_ipv6,
+            )
+        })
     }
 
     pub fn set_wireguard_mtu(&mut self, mtu: Option<u16>) -> Result<bool, Error> {
@@ -236,16 +350,104 @@ impl SettingsPersister {
         self.update(should_save)
     }
 
+    pub fn set_dns_options(&mut self, dns_options: DnsOptions) -> Result<bool, Error> {
+        self.update_locked(|settings| {
+            Self::update_field(&mut settings.tunnel_options.dns_options, dns_options)
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn add_split_tunnel_app(&mut self, path: PathBuf) -> Result<bool, Error> {
+        let should_save = !self.settings.split_tunnel.apps.contains(&path);
+        if should_save {
+            self.settings.split_tunnel.apps.push(path);
+        }
+        self.update(should_save)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn remove_split_tunnel_app(&mut self, path: &Path) -> Result<bool, Error> {
+        let apps = &mut self.settings.split_tunnel.apps;
+        let old_len = apps.len();
+        apps.retain(|app| app != path);
+        let should_save = apps.len() != old_len;
+        self.update(should_save)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn set_split_tunnel_mode(&mut self, mode: SplitTunnelMode) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.split_tunnel.mode, mode);
+        self.update(should_save)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn set_trusted_networks(&mut self, rules: Vec<TrustedNetwork>) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.trusted_networks, rules);
+        self.update(should_save)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn set_firewall_exemptions(
+        &mut self,
+        exemptions: Vec<FirewallExemption>,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.firewall_exemptions, exemptions);
+        self.update(should_save)
+    }
+
+    pub fn set_schedule(&mut self, rules: Vec<ScheduleRule>) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.schedule, rules);
+        self.update(should_save)
+    }
+
+    pub fn set_tunnel_stats_events(&mut self, tunnel_stats_events: bool) -> Result<bool, Error> {
+        let should_save =
+            Self::update_field(&mut self.settings.tunnel_stats_events, tunnel_stats_events);
+        self.update(should_save)
+    }
+
+    pub fn set_socks_proxy(&mut self, socks_proxy: bool) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.socks_proxy, socks_proxy);
+        self.update(should_save)
+    }
+
+    pub fn set_metrics(&mut self, metrics: bool) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.metrics, metrics);
+        self.update(should_save)
+    }
+
     pub fn set_bridge_settings(&mut self, bridge_settings: BridgeSettings) -> Result<bool, Error> {
         let should_save = Self::update_field(&mut self.settings.bridge_settings, bridge_settings);
         self.update(should_save)
     }
 
     pub fn set_bridge_state(&mut self, bridge_state: BridgeState) -> Result<bool, Error> {
-        let should_save = self.settings.set_bridge_state(bridge_state);
+        self.update_locked(|settings| settings.set_bridge_state(bridge_state))
+    }
+
+    pub fn set_custom_lists(&mut self, custom_lists: CustomListsSettings) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.custom_lists, custom_lists);
         self.update(should_save)
     }
 
+    /// Resets custom Shadowsocks bridges whose cipher is no longer supported, since connecting
+    /// through them would always fail.
+    fn remove_unsupported_bridge(settings: &mut Settings) -> bool {
+        match &settings.bridge_settings {
+            BridgeSettings::Custom(ProxySettings::Shadowsocks(shadowsocks))
+                if !SHADOWSOCKS_CIPHERS.contains(&shadowsocks.cipher.as_str()) =>
+            {
+                warn!(
+                    "Removing custom bridge {} since the cipher {} is not supported",
+                    shadowsocks.peer, shadowsocks.cipher
+                );
+                settings.bridge_settings = Settings::default().bridge_settings;
+                true
+            }
+            _ => false,
+        }
+    }
+
     fn update_field<T: Eq>(field: &mut T, new_value: T) -> bool {
         if *field != new_value {
             *field = new_value;
@@ -262,6 +464,23 @@ impl SettingsPersister {
             Ok(false)
         }
     }
+
+    /// Applies `update_fn` to a copy of the settings, and only keeps the result if it does not
+    /// change any setting that is locked by the policy.
+    fn update_locked(
+        &mut self,
+        update_fn: impl FnOnce(&mut Settings) -> bool,
+    ) -> Result<bool, Error> {
+        let mut settings = self.settings.clone();
+        if !update_fn(&mut settings) {
+            return Ok(false);
+        }
+        if let Some(field) = self.policy.check(&settings) {
+            return Err(Error::LockedByPolicy(field));
+        }
+        self.settings = settings;
+        self.update(true)
+    }
 }
 
 impl Deref for SettingsPersister {
@@ -153,6 +153,71 @@ impl KeyManager {
     }
 
 
+    /// Lists the keys that are registered on an account.
+    pub fn list_keys(
+        &self,
+        account: AccountToken,
+    ) -> impl Future<Output = Result<Vec<AccountKey>>> {
+        let mut rpc = mullvad_rpc::WireguardKeyProxy::new(self.http_handle.clone());
+        async move {
+            rpc.list_wireguard_keys(account)
+                .await
+                .map_err(Self::map_rpc_error)
+        }
+    }
+
+    /// Removes a key from an account synchronously. Removing a key that is not registered is
+    /// not an error.
+    pub fn remove_key(
+        &self,
+        account: AccountToken,
+        key: talpid_types::net::wireguard::PublicKey,
+    ) -> Result<()> {
+        let mut rpc = mullvad_rpc::WireguardKeyProxy::new(self.http_handle.clone());
+        self.http_handle.service().block_on(async move {
+            match rpc.remove_wireguard_key(account, &key).await {
+                Ok(()) => Ok(()),
+                Err(mullvad_rpc::rest::Error::ApiError(status, _code))
+                    if status == mullvad_rpc::StatusCode::NOT_FOUND =>
+                {
+                    Ok(())
+                }
+                Err(err) => Err(Self::map_rpc_error(err)),
+            }
+        })
+    }
+
+    /// Makes a previously used key usable again synchronously, registering it on the account
+    /// if it has been removed. The returned data counts as newly created, so that automatic
+    /// rotation does not immediately replace it.
+    pub fn restore_key(
+        &mut self,
+        account: AccountToken,
+        private_key: PrivateKey,
+    ) -> Result<WireguardData> {
+        self.reset();
+
+        let mut rpc = mullvad_rpc::WireguardKeyProxy::new(self.http_handle.clone());
+        let public_key = private_key.public_key();
+        let addresses = self.http_handle.service().block_on(async move {
+            let registered_addresses = rpc.get_wireguard_key(account.clone(), &public_key).await;
+            match registered_addresses {
+                Err(mullvad_rpc::rest::Error::ApiError(status, _code))
+                    if status == mullvad_rpc::StatusCode::NOT_FOUND =>
+                {
+                    rpc.push_wg_key(account, public_key, None).await
+                }
+                result => result,
+            }
+        });
+
+        Ok(WireguardData {
+            private_key,
+            addresses: addresses.map_err(Self::map_rpc_error)?,
+            created: Utc::now(),
+        })
+    }
+
     /// Generate a new private key asynchronously. The new keys will be sent to the daemon channel.
     pub fn generate_key_async(&mut self, account: AccountToken, timeout: Option<Duration>) {
         self.reset();
@@ -5,16 +5,24 @@ use jsonrpc_client_core::{Client, ClientHandle, Future};
 use jsonrpc_client_ipc::IpcTransport;
 use mullvad_types::{
     account::{AccountData, AccountToken, VoucherSubmission},
-    location::GeoIpLocation,
-    relay_constraints::{BridgeSettings, BridgeState, RelaySettings, RelaySettingsUpdate},
+    connection_history, custom_list,
+    location::{CityCode, CountryCode, GeoIpLocation},
+    port_forward::PortForward,
+    relay_constraints::{
+        BridgeSettings, BridgeState, LocationConstraint, RelaySettings, RelaySettingsUpdate,
+    },
     relay_list::RelayList,
-    settings::{Settings, TunnelOptions},
-    states::TunnelState,
+    schedule::ScheduleRule,
+    settings::{DnsOptions, Settings, TunnelOptions},
+    states::{TunnelState, TunnelStats},
+    trusted_network::TrustedNetwork,
     version::AppVersionInfo,
+    wg_quick::WgQuickConfig,
     wireguard, DaemonEvent,
 };
 use serde::{Deserialize, Serialize};
 use std::{io, path::Path, thread};
+use talpid_types::net::{FirewallExemption, NetworkIdentity, SplitTunnelMode};
 
 static NO_ARGS: [u8; 0] = [];
 
@@ -187,6 +195,44 @@ impl DaemonRpcClient {
         self.call("verify_wireguard_key", &NO_ARGS)
     }
 
+    pub fn list_wireguard_keys(&mut self) -> Result<Vec<wireguard::AccountKey>> {
+        self.call("list_wireguard_keys", &NO_ARGS)
+    }
+
+    pub fn revoke_wireguard_key(
+        &mut self,
+        key: talpid_types::net::wireguard::PublicKey,
+    ) -> Result<()> {
+        self.call("revoke_wireguard_key", &[key])
+    }
+
+    pub fn restore_wireguard_key(
+        &mut self,
+        key: talpid_types::net::wireguard::PublicKey,
+    ) -> Result<wireguard::PublicKey> {
+        self.call("restore_wireguard_key", &[key])
+    }
+
+    pub fn get_wireguard_config(&mut self, hostname: Option<String>) -> Result<WgQuickConfig> {
+        self.call("get_wireguard_config", &[hostname])
+    }
+
+    pub fn get_port_forwards(&mut self) -> Result<Vec<PortForward>> {
+        self.call("get_port_forwards", &NO_ARGS)
+    }
+
+    pub fn add_port_forward(
+        &mut self,
+        country: CountryCode,
+        city: CityCode,
+    ) -> Result<PortForward> {
+        self.call("add_port_forward", &[country, city])
+    }
+
+    pub fn remove_port_forward(&mut self, port: u16) -> Result<()> {
+        self.call("remove_port_forward", &[port])
+    }
+
     pub fn get_version_info(&mut self) -> Result<AppVersionInfo> {
         self.call("get_version_info", &NO_ARGS)
     }
@@ -199,6 +245,34 @@ impl DaemonRpcClient {
         self.call("clear_account_history", &NO_ARGS)
     }
 
+    pub fn get_connection_history(&mut self) -> Result<Vec<connection_history::Session>> {
+        self.call("get_connection_history", &NO_ARGS)
+    }
+
+    pub fn get_tunnel_stats(&mut self) -> Result<Option<TunnelStats>> {
+        self.call("get_tunnel_stats", &NO_ARGS)
+    }
+
+    pub fn set_tunnel_stats_events(&mut self, enabled: bool) -> Result<()> {
+        self.call("set_tunnel_stats_events", &[enabled])
+    }
+
+    pub fn set_socks_proxy(&mut self, enabled: bool) -> Result<()> {
+        self.call("set_socks_proxy", &[enabled])
+    }
+
+    pub fn set_log_level(&mut self, log_level: &str) -> Result<()> {
+        self.call("set_log_level", &[log_level])
+    }
+
+    pub fn set_metrics(&mut self, enabled: bool) -> Result<()> {
+        self.call("set_metrics", &[enabled])
+    }
+
+    pub fn set_dns_options(&mut self, dns_options: DnsOptions) -> Result<()> {
+        self.call("set_dns_options", &[dns_options])
+    }
+
     pub fn set_enable_ipv6(&mut self, enabled: bool) -> Result<()> {
         self.call("set_enable_ipv6", &[enabled])
     }
@@ -239,6 +313,34 @@ impl DaemonRpcClient {
         self.call("update_relay_settings", &[update])
     }
 
+    pub fn create_custom_list(&mut self, name: String) -> Result<custom_list::Id> {
+        self.call("create_custom_list", &[name])
+    }
+
+    pub fn delete_custom_list(&mut self, name: String) -> Result<()> {
+        self.call("delete_custom_list", &[name])
+    }
+
+    pub fn rename_custom_list(&mut self, name: String, new_name: String) -> Result<()> {
+        self.call("rename_custom_list", &[name, new_name])
+    }
+
+    pub fn add_custom_list_location(
+        &mut self,
+        name: String,
+        location: LocationConstraint,
+    ) -> Result<()> {
+        self.call("add_custom_list_location", &(name, location))
+    }
+
+    pub fn remove_custom_list_location(
+        &mut self,
+        name: String,
+        location: LocationConstraint,
+    ) -> Result<()> {
+        self.call("remove_custom_list_location", &(name, location))
+    }
+
     pub fn get_split_tunnel_processes(&mut self) -> Result<Vec<i32>> {
         self.call("get_split_tunnel_processes", &NO_ARGS)
     }
@@ -255,6 +357,41 @@ impl DaemonRpcClient {
         self.call("clear_split_tunnel_processes", &NO_ARGS)
     }
 
+    pub fn add_split_tunnel_app(&mut self, path: &Path) -> Result<()> {
+        self.call("add_split_tunnel_app", &[path])
+    }
+
+    pub fn remove_split_tunnel_app(&mut self, path: &Path) -> Result<()> {
+        self.call("remove_split_tunnel_app", &[path])
+    }
+
+    pub fn export_settings(&mut self, include_account_token: bool) -> Result<String> {
+        self.call("export_settings", &[include_account_token])
+    }
+
+    pub fn import_settings(&mut self, settings: String) -> Result<()> {
+        self.call("import_settings", &[settings])
+    }
+
+    pub fn set_split_tunnel_mode(&mut self, mode: SplitTunnelMode) -> Result<()> {
+        self.call("set_split_tunnel_mode", &[mode])
+    }
+
+    pub fn set_trusted_networks(&mut self, rules: Vec<TrustedNetwork>) -> Result<()> {
+        self.call("set_trusted_networks", &[rules])
+    }
+
+    pub fn get_network_identity(&mut self) -> Result<Option<NetworkIdentity>> {
+        self.call("get_network_identity", &NO_ARGS)
+    }
+
+    pub fn set_firewall_exemptions(&mut self, exemptions: Vec<FirewallExemption>) -> Result<()> {
+        self.call("set_firewall_exemptions", &[exemptions])
+    }
+
+    pub fn set_schedule(&mut self, rules: Vec<ScheduleRule>) -> Result<()> {
+        self.call("set_schedule", &[rules])
+    }
 
     pub fn call<A, O>(&mut self, method: &'static str, args: &A) -> Result<O>
     where
@@ -24,6 +24,9 @@ pub enum Error {
 
     #[error(display = "Error performing RPC with the remote API")]
     RpcError(#[error(source)] mullvad_rpc::rest::Error),
+
+    #[error(display = "Failed to update settings")]
+    UpdateSettings,
 }
 
 type Result<T> = std::result::Result<T, Error>;
@@ -187,7 +190,9 @@ impl DaemonInterface {
 
         self.send_command(DaemonCommand::SetAllowLan(tx, allow_lan))?;
 
-        rx.wait().map_err(|_| Error::NoResponse)
+        rx.wait()
+            .map_err(|_| Error::NoResponse)?
+            .map_err(|_| Error::UpdateSettings)
     }
 
     pub fn set_auto_connect(&self, auto_connect: bool) -> Result<()> {
@@ -195,7 +200,9 @@ impl DaemonInterface {
 
         self.send_command(DaemonCommand::SetAutoConnect(tx, auto_connect))?;
 
-        rx.wait().map_err(|_| Error::NoResponse)
+        rx.wait()
+            .map_err(|_| Error::NoResponse)?
+            .map_err(|_| Error::UpdateSettings)
     }
 
     pub fn set_wireguard_mtu(&self, wireguard_mtu: Option<u16>) -> Result<()> {
@@ -226,7 +233,9 @@ impl DaemonInterface {
 
         self.send_command(DaemonCommand::UpdateRelaySettings(tx, update))?;
 
-        rx.wait().map_err(|_| Error::NoResponse)
+        rx.wait()
+            .map_err(|_| Error::NoResponse)?
+            .map_err(|_| Error::UpdateSettings)
     }
 
     fn send_command(&self, command: DaemonCommand) -> Result<()> {
@@ -7,7 +7,11 @@ use jnix::{
 };
 use mullvad_daemon::EventListener;
 use mullvad_types::{
-    relay_list::RelayList, settings::Settings, states::TunnelState, version::AppVersionInfo,
+    relay_list::RelayList,
+    settings::Settings,
+    states::{TunnelState, TunnelStats},
+    trusted_network::TrustedNetworkEvent,
+    version::AppVersionInfo,
     wireguard::KeygenEvent,
 };
 use std::{sync::mpsc, thread};
@@ -63,6 +67,12 @@ impl EventListener for JniEventListener {
     fn notify_app_version(&self, app_version_info: AppVersionInfo) {
         let _ = self.0.send(Event::AppVersionInfo(app_version_info));
     }
+
+    /// Tunnel statistics are not forwarded, since the Android app has no view that displays
+    /// them and the `MullvadDaemon` Java class has no handler for them.
+    fn notify_tunnel_stats(&self, _: TunnelStats) {}
+
+    fn notify_trusted_network_event(&self, _: TrustedNetworkEvent) {}
 }
 
 struct JniEventHandler<'env> {
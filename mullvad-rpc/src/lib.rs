@@ -25,6 +25,9 @@ use crate::cached_dns_resolver::CachedDnsResolver;
 mod https_client_with_sni;
 use crate::https_client_with_sni::HttpsConnectorWithSni;
 
+mod port_forward;
+pub use port_forward::{PortForwardProxy, PORT_LIMIT_REACHED};
+
 mod relay_list;
 pub use hyper::StatusCode;
 pub use relay_list::RelayListProxy;
@@ -405,6 +408,43 @@ impl WireguardKeyProxy {
         rest::deserialize_body(response).await
     }
 
+    /// Lists all WireGuard keys that are registered on the account.
+    pub async fn list_wireguard_keys(
+        &mut self,
+        account_token: AccountToken,
+    ) -> Result<Vec<mullvad_types::wireguard::AccountKey>, rest::Error> {
+        #[derive(serde::Deserialize)]
+        struct RegisteredKey {
+            pubkey: wireguard::PublicKey,
+            #[serde(default)]
+            created: Option<DateTime<Utc>>,
+        }
+
+        let service = self.handle.service.clone();
+
+        let response = rest::send_request(
+            &self.handle.factory,
+            service,
+            &"/v1/wireguard-keys",
+            Method::GET,
+            Some(account_token),
+            StatusCode::OK,
+        )
+        .await?;
+
+        let keys: Vec<RegisteredKey> = rest::deserialize_body(response).await?;
+        Ok(keys
+            .into_iter()
+            .map(|key| mullvad_types::wireguard::AccountKey {
+                key: key.pubkey,
+                created: key.created,
+                active: false,
+                registered: true,
+                stored: false,
+            })
+            .collect())
+    }
+
     pub async fn remove_wireguard_key(
         &mut self,
         account_token: AccountToken,
@@ -0,0 +1,231 @@
+use crate::rest;
+use hyper::{Method, StatusCode};
+use mullvad_types::{
+    account::AccountToken,
+    location::{CityCode, CountryCode},
+    port_forward::PortForward,
+};
+use std::future::Future;
+use talpid_types::net::wireguard;
+
+/// Error code for when no more ports can be forwarded for an account.
+pub const PORT_LIMIT_REACHED: &str = "PORT_LIMIT_REACHED";
+
+/// Manages the ports that are forwarded to a WireGuard key.
+#[derive(Clone)]
+pub struct PortForwardProxy {
+    handle: rest::MullvadRestHandle,
+}
+
+impl PortForwardProxy {
+    pub fn new(handle: rest::MullvadRestHandle) -> Self {
+        Self { handle }
+    }
+
+    /// Lists the ports that are forwarded to `key`.
+    pub fn list_port_forwards(
+        &self,
+        account_token: AccountToken,
+        key: &wireguard::PublicKey,
+    ) -> impl Future<Output = Result<Vec<PortForward>, rest::Error>> + 'static {
+        let response = rest::send_request(
+            &self.handle.factory,
+            self.handle.service.clone(),
+            &Self::ports_path(key),
+            Method::GET,
+            Some(account_token),
+            StatusCode::OK,
+        );
+        async move { rest::deserialize_body(response.await?).await }
+    }
+
+    /// Forwards a new port to `key` on the relays in the given city.
+    pub fn add_port_forward(
+        &self,
+        account_token: AccountToken,
+        key: &wireguard::PublicKey,
+        country_code: CountryCode,
+        city_code: CityCode,
+    ) -> impl Future<Output = Result<PortForward, rest::Error>> + 'static {
+        #[derive(serde::Serialize)]
+        struct AddRequest {
+            country_code: CountryCode,
+            city_code: CityCode,
+        }
+
+        let body = AddRequest {
+            country_code,
+            city_code,
+        };
+        let response = rest::post_request_with_json(
+            &self.handle.factory,
+            self.handle.service.clone(),
+            &Self::ports_path(key),
+            &body,
+            Some(account_token),
+            StatusCode::CREATED,
+        );
+        async move { rest::deserialize_body(response.await?).await }
+    }
+
+    /// Stops forwarding `port` to `key`.
+    pub fn remove_port_forward(
+        &self,
+        account_token: AccountToken,
+        key: &wireguard::PublicKey,
+        port: u16,
+    ) -> impl Future<Output = Result<(), rest::Error>> + 'static {
+        let response = rest::send_request(
+            &self.handle.factory,
+            self.handle.service.clone(),
+            &format!("{}/{}", Self::ports_path(key), port),
+            Method::DELETE,
+            Some(account_token),
+            StatusCode::NO_CONTENT,
+        );
+        async move {
+            response.await?;
+            Ok(())
+        }
+    }
+
+    fn ports_path(key: &wireguard::PublicKey) -> String {
+        format!(
+            "/v1/wireguard-keys/{}/ports",
+            urlencoding::encode(&key.to_base64())
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{event_loop, rest::RequestService};
+    use hyper::{
+        client::HttpConnector,
+        header,
+        service::{make_service_fn, service_fn},
+        Body, Request, Response, Server,
+    };
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    const ACCOUNT_TOKEN: &str = "1234123412341234";
+    const PORT_FORWARD: &str = r#"{"port": 51234, "country_code": "se", "city_code": "got"}"#;
+
+    /// Answers like the API would, and records the method and path of every request.
+    async fn mock_api(
+        request: Request<Body>,
+        requests: Arc<Mutex<Vec<(Method, String)>>>,
+    ) -> Result<Response<Body>, Infallible> {
+        let method = request.method().clone();
+        let path = request.uri().path().to_owned();
+        let authorized = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .map(|auth| auth.as_bytes() == format!("Token {}", ACCOUNT_TOKEN).as_bytes())
+            .unwrap_or(false);
+        requests.lock().unwrap().push((method.clone(), path));
+
+        let (status, body) = match method {
+            _ if !authorized => (
+                StatusCode::UNAUTHORIZED,
+                r#"{"code": "INVALID_AUTH"}"#.to_owned(),
+            ),
+            Method::GET => (StatusCode::OK, format!("[{}]", PORT_FORWARD)),
+            Method::POST => (StatusCode::CREATED, PORT_FORWARD.to_owned()),
+            Method::DELETE => (StatusCode::NO_CONTENT, String::new()),
+            _ => (StatusCode::METHOD_NOT_ALLOWED, String::new()),
+        };
+        Ok(Response::builder()
+            .status(status)
+            .body(Body::from(body))
+            .unwrap())
+    }
+
+    /// Returns a handle for the API that is served at `address` over plain HTTP.
+    fn mock_rest_handle(address: SocketAddr) -> rest::MullvadRestHandle {
+        let mut connector = HttpConnector::new();
+        connector.enforce_http(false);
+        let service = RequestService::new(connector, tokio::runtime::Handle::current());
+        let handle = service.handle();
+        tokio::spawn(service.into_future());
+
+        let factory = rest::RequestFactory::new(address.to_string(), None, Some("app".to_owned()));
+        rest::MullvadRestHandle {
+            service: handle,
+            factory,
+        }
+    }
+
+    #[test]
+    fn test_port_forward_requests() {
+        let mut runtime = event_loop::create_runtime().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let key = wireguard::PublicKey::from([1u8; 32]);
+        let key_path = format!(
+            "/app/v1/wireguard-keys/{}/ports",
+            urlencoding::encode(&key.to_base64())
+        );
+
+        let server_requests = requests.clone();
+        runtime.block_on(async move {
+            let make_service = make_service_fn(move |_| {
+                let requests = server_requests.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| {
+                        mock_api(request, requests.clone())
+                    }))
+                }
+            });
+            let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+            let proxy = PortForwardProxy::new(mock_rest_handle(server.local_addr()));
+            tokio::spawn(server);
+
+            let expected = PortForward {
+                port: 51234,
+                country_code: "se".to_owned(),
+                city_code: "got".to_owned(),
+            };
+            let account = ACCOUNT_TOKEN.to_owned();
+            assert_eq!(
+                proxy
+                    .list_port_forwards(account.clone(), &key)
+                    .await
+                    .unwrap(),
+                vec![expected.clone()]
+            );
+            assert_eq!(
+                proxy
+                    .add_port_forward(account.clone(), &key, "se".to_owned(), "got".to_owned())
+                    .await
+                    .unwrap(),
+                expected
+            );
+            proxy
+                .remove_port_forward(account, &key, 51234)
+                .await
+                .unwrap();
+
+            match proxy.list_port_forwards("invalid".to_owned(), &key).await {
+                Err(rest::Error::ApiError(StatusCode::UNAUTHORIZED, code)) => {
+                    assert_eq!(code, "INVALID_AUTH")
+                }
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        });
+
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                (Method::GET, key_path.clone()),
+                (Method::POST, key_path.clone()),
+                (Method::DELETE, format!("{}/51234", key_path)),
+                (Method::GET, key_path),
+            ]
+        );
+    }
+}
@@ -148,13 +148,16 @@ impl ServerRelayList {
             relays,
         } = wireguard;
 
-        let wireguard_endpoint_data =
-            |public_key: wireguard::PublicKey| relay_list::WireguardEndpointData {
+        let wireguard_endpoint_data = |public_key: wireguard::PublicKey,
+                                       multihop_port: Option<u16>| {
+            relay_list::WireguardEndpointData {
                 port_ranges: port_ranges.clone(),
                 ipv4_gateway,
                 ipv6_gateway,
                 public_key,
-            };
+                multihop_port,
+            }
+        };
 
         for mut wireguard_relay in relays {
             wireguard_relay.relay.to_lower();
@@ -180,15 +183,17 @@ impl ServerRelayList {
                             .iter_mut()
                             .find(|r| r.hostname == wireguard_relay.relay.hostname)
                         {
-                            Some(relay) => relay
-                                .tunnels
-                                .wireguard
-                                .push(wireguard_endpoint_data(wireguard_relay.public_key)),
+                            Some(relay) => relay.tunnels.wireguard.push(wireguard_endpoint_data(
+                                wireguard_relay.public_key,
+                                wireguard_relay.multihop_port,
+                            )),
                             None => {
                                 let mut relay = relay(wireguard_relay.relay, location);
                                 relay.ipv6_addr_in = Some(wireguard_relay.ipv6_addr_in);
-                                relay.tunnels.wireguard =
-                                    vec![wireguard_endpoint_data(wireguard_relay.public_key)];
+                                relay.tunnels.wireguard = vec![wireguard_endpoint_data(
+                                    wireguard_relay.public_key,
+                                    wireguard_relay.multihop_port,
+                                )];
                                 city.relays.push(relay);
                             }
                         };
@@ -338,6 +343,8 @@ struct WireGuardRelay {
     relay: Relay,
     ipv6_addr_in: Ipv6Addr,
     public_key: wireguard::PublicKey,
+    #[serde(default)]
+    multihop_port: Option<u16>,
 }
 
 #[derive(Debug, serde::Deserialize)]
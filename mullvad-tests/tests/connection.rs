@@ -214,6 +214,7 @@ fn get_default_endpoint() -> TunnelEndpoint {
         },
         tunnel_type: TunnelType::OpenVpn,
         proxy: None,
+        entry_endpoint: None,
     }
 }
 
@@ -228,6 +229,7 @@ fn get_default_location() -> Option<GeoIpLocation> {
         mullvad_exit_ip: true,
         hostname: Some("fakehost".to_string()),
         bridge_hostname: None,
+        entry_hostname: None,
     })
 }
 
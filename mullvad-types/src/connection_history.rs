@@ -0,0 +1,48 @@
+use chrono::{offset::Utc, DateTime};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use talpid_types::{net::TunnelType, tunnel::ErrorStateCause};
+
+/// A period during which the tunnel was connected.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Hostname of the exit relay, or `None` when connected to a custom tunnel endpoint.
+    pub hostname: Option<String>,
+    pub tunnel_type: TunnelType,
+    pub start: DateTime<Utc>,
+    /// When the tunnel went down. `None` while the session is ongoing, or if the daemon stopped
+    /// before the session ended.
+    pub end: Option<DateTime<Utc>>,
+    /// Traffic transferred through the tunnel, if the tunnel reported any traffic counters.
+    pub traffic: Option<TrafficStats>,
+    pub disconnect_reason: Option<DisconnectReason>,
+}
+
+/// Number of bytes transferred through a tunnel.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrafficStats {
+    pub tx_bytes: u64,
+    pub rx_bytes: u64,
+}
+
+/// Why a [`Session`] ended.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason {
+    /// The tunnel was disconnected on request.
+    Disconnected,
+    /// The tunnel was disconnected in order to connect again.
+    Reconnect,
+    /// The tunnel was disconnected because the daemon entered the error state.
+    Error(ErrorStateCause),
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisconnectReason::Disconnected => write!(f, "disconnected"),
+            DisconnectReason::Reconnect => write!(f, "reconnected"),
+            DisconnectReason::Error(cause) => write!(f, "error: {}", cause),
+        }
+    }
+}
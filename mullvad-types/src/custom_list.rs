@@ -0,0 +1,213 @@
+use crate::relay_constraints::LocationConstraint;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Identifies a custom list. Unlike the name, the ID never changes.
+pub type Id = String;
+
+#[derive(err_derive::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    #[error(display = "A custom list named \"{}\" already exists", _0)]
+    ListExists(String),
+
+    #[error(display = "There is no custom list named \"{}\"", _0)]
+    ListNotFound(String),
+
+    #[error(display = "Custom list names cannot be empty")]
+    EmptyName,
+
+    #[error(display = "Custom lists cannot contain other custom lists")]
+    NestedList,
+
+    #[error(display = "The custom list already contains {}", _0)]
+    LocationExists(LocationConstraint),
+
+    #[error(display = "The custom list does not contain {}", _0)]
+    LocationNotFound(LocationConstraint),
+
+    #[error(display = "The custom list \"{}\" is used by the relay settings", _0)]
+    ListInUse(String),
+}
+
+/// A named group of locations. Used as a [`LocationConstraint`], it matches relays that match
+/// any of its locations.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct CustomList {
+    pub id: Id,
+    pub name: String,
+    pub locations: Vec<LocationConstraint>,
+}
+
+impl fmt::Display for CustomList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// All custom lists defined by the user.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CustomListsSettings {
+    custom_lists: Vec<CustomList>,
+}
+
+impl CustomListsSettings {
+    pub fn lists(&self) -> &[CustomList] {
+        &self.custom_lists
+    }
+
+    pub fn get(&self, id: &str) -> Option<&CustomList> {
+        self.custom_lists.iter().find(|list| list.id == id)
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<&CustomList> {
+        self.custom_lists.iter().find(|list| list.name == name)
+    }
+
+    fn find_by_name_mut(&mut self, name: &str) -> Result<&mut CustomList, Error> {
+        self.custom_lists
+            .iter_mut()
+            .find(|list| list.name == name)
+            .ok_or_else(|| Error::ListNotFound(name.to_owned()))
+    }
+
+    fn ensure_name_is_available(&self, name: &str) -> Result<(), Error> {
+        if name.is_empty() {
+            return Err(Error::EmptyName);
+        }
+        if self.find_by_name(name).is_some() {
+            return Err(Error::ListExists(name.to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Adds a new, empty list.
+    pub fn create(&mut self, id: Id, name: String) -> Result<(), Error> {
+        self.ensure_name_is_available(&name)?;
+        self.custom_lists.push(CustomList {
+            id,
+            name,
+            locations: vec![],
+        });
+        Ok(())
+    }
+
+    /// Removes a list and returns it.
+    pub fn delete(&mut self, name: &str) -> Result<CustomList, Error> {
+        let index = self
+            .custom_lists
+            .iter()
+            .position(|list| list.name == name)
+            .ok_or_else(|| Error::ListNotFound(name.to_owned()))?;
+        Ok(self.custom_lists.remove(index))
+    }
+
+    pub fn rename(&mut self, name: &str, new_name: String) -> Result<(), Error> {
+        if name == new_name {
+            return self.find_by_name_mut(name).map(|_| ());
+        }
+        self.ensure_name_is_available(&new_name)?;
+        self.find_by_name_mut(name)?.name = new_name;
+        Ok(())
+    }
+
+    /// Adds a location to a list and returns the ID of the list.
+    pub fn add_location(&mut self, name: &str, location: LocationConstraint) -> Result<Id, Error> {
+        if let LocationConstraint::CustomList(_) = location {
+            return Err(Error::NestedList);
+        }
+        let list = self.find_by_name_mut(name)?;
+        if list.locations.contains(&location) {
+            return Err(Error::LocationExists(location));
+        }
+        list.locations.push(location);
+        Ok(list.id.clone())
+    }
+
+    /// Removes a location from a list and returns the ID of the list.
+    pub fn remove_location(
+        &mut self,
+        name: &str,
+        location: &LocationConstraint,
+    ) -> Result<Id, Error> {
+        let list = self.find_by_name_mut(name)?;
+        let index = list
+            .locations
+            .iter()
+            .position(|list_location| list_location == location)
+            .ok_or_else(|| Error::LocationNotFound(location.clone()))?;
+        list.locations.remove(index);
+        Ok(list.id.clone())
+    }
+
+    /// Checks that the lists could have been created by the operations above. This is used when
+    /// the lists are read from an external source.
+    pub fn validate(&self) -> Result<(), Error> {
+        for (index, list) in self.custom_lists.iter().enumerate() {
+            let others = &self.custom_lists[..index];
+            if list.name.is_empty() {
+                return Err(Error::EmptyName);
+            }
+            if others
+                .iter()
+                .any(|other| other.name == list.name || other.id == list.id)
+            {
+                return Err(Error::ListExists(list.name.clone()));
+            }
+            for (location_index, location) in list.locations.iter().enumerate() {
+                if let LocationConstraint::CustomList(_) = location {
+                    return Err(Error::NestedList);
+                }
+                if list.locations[..location_index].contains(location) {
+                    return Err(Error::LocationExists(location.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_custom_list_operations() {
+        let mut lists = CustomListsSettings::default();
+        lists
+            .create("1".to_owned(), "favourites".to_owned())
+            .unwrap();
+        assert_eq!(
+            lists.create("2".to_owned(), "favourites".to_owned()),
+            Err(Error::ListExists("favourites".to_owned()))
+        );
+
+        let sweden = LocationConstraint::Country("se".to_owned());
+        assert_eq!(
+            lists.add_location("favourites", sweden.clone()),
+            Ok("1".to_owned())
+        );
+        assert_eq!(
+            lists.add_location("favourites", sweden.clone()),
+            Err(Error::LocationExists(sweden.clone()))
+        );
+        assert_eq!(
+            lists.add_location("favourites", LocationConstraint::CustomList("1".to_owned())),
+            Err(Error::NestedList)
+        );
+
+        lists.rename("favourites", "best".to_owned()).unwrap();
+        assert!(lists.find_by_name("favourites").is_none());
+        assert_eq!(lists.get("1").unwrap().name, "best");
+        assert_eq!(lists.get("1").unwrap().locations, vec![sweden.clone()]);
+
+        assert_eq!(lists.remove_location("best", &sweden), Ok("1".to_owned()));
+        assert_eq!(
+            lists.remove_location("best", &sweden),
+            Err(Error::LocationNotFound(sweden))
+        );
+
+        assert_eq!(lists.delete("best").unwrap().id, "1");
+        assert!(lists.lists().is_empty());
+    }
+}
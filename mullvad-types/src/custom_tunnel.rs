@@ -16,6 +16,11 @@ pub enum Error {
 
     #[error(display = "Host has no IPv4 address: {}", _0)]
     HostHasNoIpv4(String),
+
+    #[error(
+        display = "Custom WireGuard relays with more than one peer are not supported on Windows"
+    )]
+    MultiplePeersNotSupported,
 }
 
 
@@ -41,11 +46,25 @@ impl CustomTunnelEndpoint {
         }
     }
 
+    /// Checks that the endpoint can be used on this platform. The firewall on Windows only allows
+    /// traffic to a single relay, so WireGuard endpoints with additional peers are rejected there.
+    pub fn validate(&self) -> Result<(), Error> {
+        match &self.config {
+            ConnectionConfig::Wireguard(connection)
+                if cfg!(windows) && !connection.additional_peers.is_empty() =>
+            {
+                Err(Error::MultiplePeersNotSupported)
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn to_tunnel_parameters(
         &self,
         tunnel_options: TunnelOptions,
         proxy: Option<openvpn::ProxySettings>,
     ) -> Result<TunnelParameters, Error> {
+        self.validate()?;
         let ip = resolve_to_ip(&self.host)?;
         let mut config = self.config.clone();
         config.set_ip(ip);
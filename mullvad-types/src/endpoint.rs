@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::{
     fmt,
-    net::{Ipv4Addr, Ipv6Addr},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
 };
 use talpid_types::net::{wireguard, Endpoint, TransportProtocol};
 
@@ -16,6 +16,10 @@ pub enum MullvadEndpoint {
         peer: wireguard::PeerConfig,
         ipv4_gateway: Ipv4Addr,
         ipv6_gateway: Ipv6Addr,
+        /// Address of the entry relay that forwards traffic to `peer`, when using multihop.
+        entry_endpoint: Option<SocketAddr>,
+        /// TCP endpoint on the relay that traffic is tunneled through, when using UDP-over-TCP.
+        udp_over_tcp_endpoint: Option<SocketAddr>,
     },
 }
 
@@ -24,11 +28,18 @@ impl MullvadEndpoint {
     pub fn to_endpoint(&self) -> Endpoint {
         match self {
             MullvadEndpoint::OpenVpn(endpoint) => *endpoint,
-            MullvadEndpoint::Wireguard { peer, .. } => Endpoint::new(
-                peer.endpoint.ip(),
-                peer.endpoint.port(),
-                TransportProtocol::Udp,
-            ),
+            MullvadEndpoint::Wireguard {
+                udp_over_tcp_endpoint: Some(address),
+                ..
+            } => Endpoint::new(address.ip(), address.port(), TransportProtocol::Tcp),
+            MullvadEndpoint::Wireguard {
+                peer,
+                entry_endpoint,
+                ..
+            } => {
+                let address = entry_endpoint.unwrap_or(peer.endpoint);
+                Endpoint::new(address.ip(), address.port(), TransportProtocol::Udp)
+            }
         }
     }
 }
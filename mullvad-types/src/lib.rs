@@ -2,13 +2,20 @@
 
 pub mod account;
 pub mod auth_failed;
+pub mod connection_history;
+pub mod custom_list;
 pub mod endpoint;
 pub mod location;
+pub mod ovpn;
+pub mod port_forward;
 pub mod relay_constraints;
 pub mod relay_list;
+pub mod schedule;
 pub mod settings;
 pub mod states;
+pub mod trusted_network;
 pub mod version;
+pub mod wg_quick;
 pub mod wireguard;
 
 mod custom_tunnel;
@@ -32,4 +39,10 @@ pub enum DaemonEvent {
 
     /// Key event
     WireguardKey(wireguard::KeygenEvent),
+
+    /// Periodic traffic counters of the current tunnel. Only sent if enabled in the settings.
+    TunnelStats(states::TunnelStats),
+
+    /// The daemon connected or disconnected because the network changed.
+    TrustedNetwork(trusted_network::TrustedNetworkEvent),
 }
@@ -0,0 +1,492 @@
+use crate::CustomTunnelEndpoint;
+use std::{net::Ipv4Addr, str::FromStr};
+use talpid_types::net::{openvpn, Endpoint, TransportProtocol};
+
+const DEFAULT_PORT: u16 = 1194;
+
+/// Directives that only affect how the client behaves, and which the daemon decides itself.
+const IGNORED_DIRECTIVES: &[&str] = &[
+    "client",
+    "tls-client",
+    "pull",
+    "nobind",
+    "persist-key",
+    "persist-tun",
+    "resolv-retry",
+    "verb",
+    "mute",
+    "mute-replay-warnings",
+    "explicit-exit-notify",
+    "remote-random",
+    "reneg-sec",
+    "route-delay",
+    "fast-io",
+    "sndbuf",
+    "rcvbuf",
+    "ping",
+    "ping-restart",
+    "ping-exit",
+    "connect-retry",
+    "connect-retry-max",
+    "auth-nocache",
+    "tls-version-min",
+    "redirect-gateway",
+];
+
+/// Directives that run programs, load code or write to files outside of the daemon's control.
+const UNSAFE_DIRECTIVES: &[&str] = &[
+    "up",
+    "down",
+    "up-restart",
+    "route-up",
+    "route-pre-down",
+    "ipchange",
+    "tls-verify",
+    "auth-user-pass-verify",
+    "client-connect",
+    "client-disconnect",
+    "learn-address",
+    "script-security",
+    "plugin",
+    "management",
+    "config",
+    "cd",
+    "chroot",
+    "daemon",
+    "log",
+    "log-append",
+    "status",
+    "writepid",
+    "iproute",
+    "tmp-dir",
+    "askpass",
+    "setenv",
+    "dev-node",
+];
+
+#[derive(err_derive::Error, Debug)]
+pub enum Error {
+    #[error(display = "Line {}: Unknown inline block <{}>", _0, _1)]
+    UnknownBlock(usize, String),
+
+    #[error(display = "Line {}: Inline block <{}> is never closed", _0, _1)]
+    UnterminatedBlock(usize, String),
+
+    #[error(
+        display = "Line {}: {} is not allowed since it runs external code",
+        _0,
+        _1
+    )]
+    UnsafeDirective(usize, String),
+
+    #[error(display = "Line {}: Unsupported directive {}", _0, _1)]
+    UnsupportedDirective(usize, String),
+
+    #[error(
+        display = "Line {}: {} refers to a file, only inline blocks are supported",
+        _0,
+        _1
+    )]
+    ExternalFile(usize, String),
+
+    #[error(display = "Line {}: Invalid value for {}", _0, _1)]
+    InvalidValue(usize, String),
+
+    #[error(display = "Both tls-auth and tls-crypt keys are given")]
+    MultipleControlChannelKeys,
+
+    #[error(display = "The configuration has no {}", _0)]
+    MissingValue(&'static str),
+}
+
+/// An OpenVPN client configuration in the format of `.ovpn` files. Certificates and keys must
+/// be inline blocks.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OvpnConfig {
+    /// Host name or IP address of the server. Only the first `remote` is used.
+    pub remote_host: String,
+    pub remote_port: u16,
+    pub protocol: TransportProtocol,
+    pub custom_config: openvpn::CustomConfig,
+}
+
+impl OvpnConfig {
+    /// Converts the configuration into a custom relay. The username and password are only used
+    /// if the configuration contains `auth-user-pass`.
+    pub fn to_custom_tunnel_endpoint(
+        &self,
+        username: String,
+        password: String,
+    ) -> CustomTunnelEndpoint {
+        let mut config = openvpn::ConnectionConfig::new(
+            Endpoint::new(Ipv4Addr::UNSPECIFIED, self.remote_port, self.protocol),
+            username,
+            password,
+        );
+        config.custom_config = Some(self.custom_config.clone());
+        CustomTunnelEndpoint::new(
+            self.remote_host.clone(),
+            crate::ConnectionConfig::OpenVpn(config),
+        )
+    }
+}
+
+impl FromStr for OvpnConfig {
+    type Err = Error;
+
+    fn from_str(config: &str) -> Result<Self, Self::Err> {
+        let mut remote = None;
+        let mut default_port = DEFAULT_PORT;
+        let mut default_protocol = TransportProtocol::Udp;
+        let mut ca = None;
+        let mut cert = None;
+        let mut key = None;
+        let mut tls_auth = None;
+        let mut tls_crypt = None;
+        let mut key_direction = None;
+        let mut cipher = None;
+        let mut data_ciphers = None;
+        let mut auth = None;
+        let mut verify_x509_name = None;
+        let mut auth_user_pass = false;
+
+        let mut lines = config.lines().enumerate();
+        while let Some((index, line)) = lines.next() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('<') && line.ends_with('>') {
+                let tag = &line[1..line.len() - 1];
+                let end_tag = format!("</{}>", tag);
+                let mut contents = String::new();
+                loop {
+                    match lines.next() {
+                        Some((_, line)) if line.trim() == end_tag => break,
+                        Some((_, line)) => {
+                            contents.push_str(line.trim());
+                            contents.push('\n');
+                        }
+                        None => return Err(Error::UnterminatedBlock(line_number, tag.to_owned())),
+                    }
+                }
+                match tag {
+                    "ca" => ca = Some(contents),
+                    "cert" => cert = Some(contents),
+                    "key" => key = Some(contents),
+                    "tls-auth" => tls_auth = Some(contents),
+                    "tls-crypt" => tls_crypt = Some(contents),
+                    _ => return Err(Error::UnknownBlock(line_number, tag.to_owned())),
+                }
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let directive = words.next().unwrap_or("");
+            let args: Vec<&str> = words.collect();
+            let invalid_value = || Error::InvalidValue(line_number, directive.to_owned());
+
+            match directive {
+                "remote" => {
+                    if remote.is_some() {
+                        log::warn!("Ignoring additional remote on line {}", line_number);
+                        continue;
+                    }
+                    let host = args.get(0).ok_or_else(invalid_value)?;
+                    let port = match args.get(1) {
+                        Some(port) => Some(port.parse().map_err(|_| invalid_value())?),
+                        None => None,
+                    };
+                    let protocol = match args.get(2) {
+                        Some(protocol) => Some(parse_protocol(protocol).ok_or_else(invalid_value)?),
+                        None => None,
+                    };
+                    remote = Some((host.to_string(), port, protocol));
+                }
+                "port" => {
+                    default_port = args
+                        .get(0)
+                        .and_then(|port| port.parse().ok())
+                        .ok_or_else(invalid_value)?
+                }
+                "proto" => {
+                    default_protocol = args
+                        .get(0)
+                        .and_then(|protocol| parse_protocol(protocol))
+                        .ok_or_else(invalid_value)?
+                }
+                "dev" | "dev-type" => {
+                    if !args
+                        .get(0)
+                        .map(|dev| dev.starts_with("tun"))
+                        .unwrap_or(false)
+                    {
+                        return Err(invalid_value());
+                    }
+                }
+                "remote-cert-tls" => {
+                    if args.get(0) != Some(&"server") {
+                        return Err(invalid_value());
+                    }
+                }
+                "ca" | "cert" | "key" | "tls-auth" | "tls-crypt" => {
+                    if args.get(0) != Some(&"[inline]") {
+                        return Err(Error::ExternalFile(line_number, directive.to_owned()));
+                    }
+                    if directive == "tls-auth" {
+                        if let Some(direction) = args.get(1) {
+                            key_direction =
+                                Some(parse_key_direction(direction).ok_or_else(invalid_value)?);
+                        }
+                    }
+                }
+                "key-direction" => {
+                    key_direction = Some(
+                        args.get(0)
+                            .and_then(|direction| parse_key_direction(direction))
+                            .ok_or_else(invalid_value)?,
+                    )
+                }
+                "cipher" | "data-ciphers-fallback" => {
+                    cipher = Some(args.get(0).ok_or_else(invalid_value)?.to_string())
+                }
+                "data-ciphers" | "ncp-ciphers" => {
+                    data_ciphers = Some(args.get(0).ok_or_else(invalid_value)?.to_string())
+                }
+                "verify-x509-name" => {
+                    verify_x509_name = Some(
+                        parse_verify_x509_name(line[directive.len()..].trim())
+                            .ok_or_else(invalid_value)?,
+                    )
+                }
+                "compress" | "comp-lzo" => {
+                    // The server pushes the compression it uses to the client
+                    log::debug!("Ignoring {} on line {}", directive, line_number);
+                }
+                "auth" => auth = Some(args.get(0).ok_or_else(invalid_value)?.to_string()),
+                "auth-user-pass" => {
+                    // Credentials are given separately instead of being read from a file
+                    if !args.is_empty() {
+                        return Err(Error::ExternalFile(line_number, directive.to_owned()));
+                    }
+                    auth_user_pass = true;
+                }
+                directive if IGNORED_DIRECTIVES.contains(&directive) => {
+                    log::debug!("Ignoring {} on line {}", directive, line_number);
+                }
+                directive if UNSAFE_DIRECTIVES.contains(&directive) => {
+                    return Err(Error::UnsafeDirective(line_number, directive.to_owned()));
+                }
+                directive => {
+                    return Err(Error::UnsupportedDirective(
+                        line_number,
+                        directive.to_owned(),
+                    ))
+                }
+            }
+        }
+
+        let (remote_host, remote_port, protocol) = remote.ok_or(Error::MissingValue("remote"))?;
+        let tls_auth = match (tls_auth, tls_crypt) {
+            (Some(_), Some(_)) => return Err(Error::MultipleControlChannelKeys),
+            (Some(key), None) => Some(openvpn::TlsAuth::Auth { key, key_direction }),
+            (None, Some(key)) => Some(openvpn::TlsAuth::Crypt { key }),
+            (None, None) => None,
+        };
+        match (&cert, &key) {
+            (Some(_), None) => return Err(Error::MissingValue("<key> block")),
+            (None, Some(_)) => return Err(Error::MissingValue("<cert> block")),
+            (None, None) if !auth_user_pass => {
+                return Err(Error::MissingValue(
+                    "client certificate or auth-user-pass directive",
+                ))
+            }
+            _ => (),
+        }
+
+        Ok(OvpnConfig {
+            remote_host,
+            remote_port: remote_port.unwrap_or(default_port),
+            protocol: protocol.unwrap_or(default_protocol),
+            custom_config: openvpn::CustomConfig {
+                ca: ca.ok_or(Error::MissingValue("<ca> block"))?,
+                cert,
+                key,
+                tls_auth,
+                cipher,
+                data_ciphers,
+                auth,
+                auth_user_pass,
+                verify_x509_name,
+            },
+        })
+    }
+}
+
+fn parse_protocol(protocol: &str) -> Option<TransportProtocol> {
+    match protocol {
+        "udp" | "udp4" | "udp6" => Some(TransportProtocol::Udp),
+        "tcp" | "tcp4" | "tcp6" | "tcp-client" | "tcp4-client" | "tcp6-client" => {
+            Some(TransportProtocol::Tcp)
+        }
+        _ => None,
+    }
+}
+
+/// Parses the arguments of `verify-x509-name`. The name is quoted if it contains spaces, which
+/// distinguished names usually do.
+fn parse_verify_x509_name(args: &str) -> Option<openvpn::VerifyX509Name> {
+    let (name, rest) = match args.chars().next()? {
+        quote @ '\'' | quote @ '"' => {
+            let end = args[1..].find(quote)? + 1;
+            (&args[1..end], &args[end + 1..])
+        }
+        _ => {
+            let end = args.find(char::is_whitespace).unwrap_or_else(|| args.len());
+            (&args[..end], &args[end..])
+        }
+    };
+    let mut rest = rest.split_whitespace();
+    let name_type = match rest.next() {
+        Some(name_type @ "subject")
+        | Some(name_type @ "name")
+        | Some(name_type @ "name-prefix") => Some(name_type.to_owned()),
+        Some(_) => return None,
+        None => None,
+    };
+    if name.is_empty() || rest.next().is_some() {
+        return None;
+    }
+    Some(openvpn::VerifyX509Name {
+        name: name.to_owned(),
+        name_type,
+    })
+}
+
+fn parse_key_direction(direction: &str) -> Option<u8> {
+    match direction {
+        "0" => Some(0),
+        "1" => Some(1),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = "\
+client
+dev tun
+proto tcp
+remote vpn.example.com 443
+resolv-retry infinite
+nobind
+persist-key
+persist-tun
+remote-cert-tls server
+cipher AES-256-GCM
+auth SHA512
+auth-user-pass
+key-direction 1
+verb 3
+<ca>
+-----BEGIN CERTIFICATE-----
+MIIB
+-----END CERTIFICATE-----
+</ca>
+<tls-auth>
+-----BEGIN OpenVPN Static key V1-----
+0123
+-----END OpenVPN Static key V1-----
+</tls-auth>
+";
+
+    #[test]
+    fn test_parse() {
+        let config: OvpnConfig = CONFIG.parse().unwrap();
+        assert_eq!(config.remote_host, "vpn.example.com");
+        assert_eq!(config.remote_port, 443);
+        assert_eq!(config.protocol, TransportProtocol::Tcp);
+        assert!(config.custom_config.auth_user_pass);
+        assert_eq!(
+            config.custom_config.ca,
+            "-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n"
+        );
+        assert_eq!(config.custom_config.cipher.as_deref(), Some("AES-256-GCM"));
+        match config.custom_config.tls_auth {
+            Some(openvpn::TlsAuth::Auth { key_direction, .. }) => {
+                assert_eq!(key_direction, Some(1))
+            }
+            tls_auth => panic!("Unexpected tls-auth: {:?}", tls_auth),
+        }
+    }
+
+    #[test]
+    fn test_rejected_directives() {
+        let script = CONFIG.replace("verb 3", "up /etc/openvpn/update-resolv-conf");
+        match script.parse::<OvpnConfig>() {
+            Err(Error::UnsafeDirective(14, directive)) => assert_eq!(directive, "up"),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        let file = CONFIG.replace("auth-user-pass", "auth-user-pass /root/credentials");
+        match file.parse::<OvpnConfig>() {
+            Err(Error::ExternalFile(12, directive)) => assert_eq!(directive, "auth-user-pass"),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        let no_auth = CONFIG.replace("auth-user-pass\n", "");
+        match no_auth.parse::<OvpnConfig>() {
+            Err(Error::MissingValue(_)) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_parse_newer_directives() {
+        let newer = CONFIG.replace(
+            "cipher AES-256-GCM",
+            "data-ciphers AES-256-GCM:CHACHA20-POLY1305\n\
+             data-ciphers-fallback AES-256-CBC\n\
+             compress\n\
+             verify-x509-name 'C=SE, CN=vpn.example.com' subject",
+        );
+        let config: OvpnConfig = newer.parse().unwrap();
+        assert_eq!(config.custom_config.cipher.as_deref(), Some("AES-256-CBC"));
+        assert_eq!(
+            config.custom_config.data_ciphers.as_deref(),
+            Some("AES-256-GCM:CHACHA20-POLY1305")
+        );
+        assert_eq!(
+            config.custom_config.verify_x509_name,
+            Some(openvpn::VerifyX509Name {
+                name: "C=SE, CN=vpn.example.com".to_owned(),
+                name_type: Some("subject".to_owned()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_verify_x509_name() {
+        assert_eq!(
+            parse_verify_x509_name("vpn.example.com name"),
+            Some(openvpn::VerifyX509Name {
+                name: "vpn.example.com".to_owned(),
+                name_type: Some("name".to_owned()),
+            })
+        );
+        assert_eq!(
+            parse_verify_x509_name("\"CN=vpn\""),
+            Some(openvpn::VerifyX509Name {
+                name: "CN=vpn".to_owned(),
+                name_type: None,
+            })
+        );
+        assert_eq!(parse_verify_x509_name("vpn.example.com other"), None);
+        assert_eq!(parse_verify_x509_name("'CN=vpn"), None);
+        assert_eq!(parse_verify_x509_name(""), None);
+    }
+}
@@ -0,0 +1,21 @@
+use crate::location::{CityCode, CountryCode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A port that is forwarded to the WireGuard key it is bound to, on every relay in a city.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub struct PortForward {
+    pub port: u16,
+    pub country_code: CountryCode,
+    pub city_code: CityCode,
+}
+
+impl fmt::Display for PortForward {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} in {}, {}",
+            self.port, self.city_code, self.country_code
+        )
+    }
+}
@@ -2,14 +2,15 @@
 //! updated as well.
 
 use crate::{
+    custom_list,
     location::{CityCode, CountryCode, Hostname},
-    relay_list::{OpenVpnEndpointData, WireguardEndpointData},
+    relay_list::{OpenVpnEndpointData, Relay, WireguardEndpointData},
     CustomTunnelEndpoint,
 };
 #[cfg(target_os = "android")]
 use jnix::{FromJava, IntoJava};
-use serde::{Deserialize, Serialize};
-use std::fmt;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{collections::BTreeSet, fmt};
 use talpid_types::net::{openvpn::ProxySettings, TransportProtocol, TunnelType};
 
 
@@ -143,6 +144,20 @@ pub struct RelayConstraints {
     pub wireguard_constraints: WireguardConstraints,
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub openvpn_constraints: OpenVpnConstraints,
+    /// Location of the entry relay when using WireGuard multihop. Multihop is disabled when this
+    /// is `None`.
+    #[serde(default)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub entry_location: Option<Constraint<LocationConstraint>>,
+    #[serde(default)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub providers: Constraint<Providers>,
+    #[serde(default)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub ownership: Constraint<Ownership>,
+    #[serde(default)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub selection_mode: SelectionMode,
 }
 
 #[cfg(target_os = "android")]
@@ -153,6 +168,10 @@ impl Default for RelayConstraints {
             tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
             wireguard_constraints: WireguardConstraints::default(),
             openvpn_constraints: OpenVpnConstraints::default(),
+            entry_location: None,
+            providers: Constraint::Any,
+            ownership: Constraint::Any,
+            selection_mode: SelectionMode::default(),
         }
     }
 }
@@ -170,6 +189,12 @@ impl RelayConstraints {
             openvpn_constraints: update
                 .openvpn_constraints
                 .unwrap_or_else(|| self.openvpn_constraints.clone()),
+            entry_location: update
+                .entry_location
+                .unwrap_or_else(|| self.entry_location.clone()),
+            providers: update.providers.unwrap_or_else(|| self.providers.clone()),
+            ownership: update.ownership.unwrap_or(self.ownership),
+            selection_mode: update.selection_mode.unwrap_or(self.selection_mode),
         }
     }
 }
@@ -196,12 +221,137 @@ impl fmt::Display for RelayConstraints {
         }
         write!(f, " in ")?;
         match self.location {
-            Constraint::Any => write!(f, "any location"),
-            Constraint::Only(ref location_constraint) => location_constraint.fmt(f),
+            Constraint::Any => write!(f, "any location")?,
+            Constraint::Only(ref location_constraint) => location_constraint.fmt(f)?,
+        }
+        if let Some(ref entry_location) = self.entry_location {
+            write!(f, " via entry relay in ")?;
+            match entry_location {
+                Constraint::Any => write!(f, "any location")?,
+                Constraint::Only(ref location_constraint) => location_constraint.fmt(f)?,
+            }
+        }
+        if let Constraint::Only(ref providers) = self.providers {
+            write!(f, " hosted by {}", providers)?;
+        }
+        if let Constraint::Only(ref ownership) = self.ownership {
+            write!(f, " on {}", ownership)?;
         }
+        if self.selection_mode != SelectionMode::default() {
+            write!(f, ", selected by {}", self.selection_mode)?;
+        }
+        Ok(())
     }
 }
 
+/// Name of a company hosting relays, as listed in the relay list.
+pub type Provider = String;
+
+/// Limits the set of [`crate::relay_list::Relay`]s to those hosted by any of the given providers.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct Providers {
+    providers: BTreeSet<Provider>,
+}
+
+/// Returned if the iterator passed to [`Providers::new`] is empty.
+#[derive(err_derive::Error, Debug)]
+#[error(display = "No providers were specified")]
+pub struct NoProviders;
+
+impl Providers {
+    pub fn new(providers: impl Iterator<Item = Provider>) -> Result<Providers, NoProviders> {
+        let providers: BTreeSet<Provider> = providers.collect();
+        if providers.is_empty() {
+            return Err(NoProviders);
+        }
+        Ok(Providers { providers })
+    }
+
+    pub fn providers(&self) -> &BTreeSet<Provider> {
+        &self.providers
+    }
+}
+
+impl<'de> Deserialize<'de> for Providers {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // Goes through `Providers::new` so that an empty set is rejected
+        #[derive(Deserialize)]
+        struct RawProviders {
+            providers: BTreeSet<Provider>,
+        }
+        let raw = RawProviders::deserialize(deserializer)?;
+        Providers::new(raw.providers.into_iter()).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Match<Relay> for Providers {
+    fn matches(&self, relay: &Relay) -> bool {
+        self.providers.contains(&relay.provider)
+    }
+}
+
+impl fmt::Display for Providers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let providers: Vec<&str> = self.providers.iter().map(String::as_str).collect();
+        write!(f, "{}", providers.join(", "))
+    }
+}
+
+/// Limits the set of [`crate::relay_list::Relay`]s based on who owns the servers.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ownership {
+    /// Servers owned by Mullvad.
+    MullvadOwned,
+    /// Servers rented from the hosting provider.
+    Rented,
+}
+
+impl Match<Relay> for Ownership {
+    fn matches(&self, relay: &Relay) -> bool {
+        match self {
+            Ownership::MullvadOwned => relay.owned,
+            Ownership::Rented => !relay.owned,
+        }
+    }
+}
+
+impl fmt::Display for Ownership {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Ownership::MullvadOwned => write!(f, "Mullvad-owned servers"),
+            Ownership::Rented => write!(f, "rented servers"),
+        }
+    }
+}
+
+/// Determines how a `RelaySelector` picks a relay among the relays matching the constraints.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionMode {
+    /// Relays are picked at random, proportionally to their weight.
+    Weighted,
+    /// Relays with the lowest measured round-trip time are preferred.
+    Latency,
+}
+
+impl Default for SelectionMode {
+    fn default() -> Self {
+        SelectionMode::Weighted
+    }
+}
+
+impl fmt::Display for SelectionMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            SelectionMode::Weighted => write!(f, "weight"),
+            SelectionMode::Latency => write!(f, "latency"),
+        }
+    }
+}
 
 /// Limits the set of [`crate::relay_list::Relay`]s used by a `RelaySelector` based on
 /// location.
@@ -216,6 +366,8 @@ pub enum LocationConstraint {
     City(CountryCode, CityCode),
     /// An single hostname in a given city.
     Hostname(CountryCode, CityCode, Hostname),
+    /// Any location in the custom list with the given ID.
+    CustomList(custom_list::Id),
 }
 
 impl fmt::Display for LocationConstraint {
@@ -226,6 +378,7 @@ impl fmt::Display for LocationConstraint {
             LocationConstraint::Hostname(country, city, hostname) => {
                 write!(f, "city {}, {}, hostname {}", city, country, hostname)
             }
+            LocationConstraint::CustomList(id) => write!(f, "custom list {}", id),
         }
     }
 }
@@ -303,19 +456,32 @@ impl Match<OpenVpnEndpointData> for OpenVpnConstraints {
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
 pub struct WireguardConstraints {
     pub port: Constraint<u16>,
+    /// Selecting TCP tunnels the WireGuard traffic over TCP to the relay, in which case `port` is
+    /// the TCP port on the relay.
+    #[serde(default)]
+    pub protocol: Constraint<TransportProtocol>,
 }
 
 impl fmt::Display for WireguardConstraints {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self.port {
-            Constraint::Any => write!(f, "any port"),
-            Constraint::Only(port) => write!(f, "port {}", port),
+            Constraint::Any => write!(f, "any port")?,
+            Constraint::Only(port) => write!(f, "port {}", port)?,
+        }
+        match self.protocol {
+            Constraint::Any => Ok(()),
+            Constraint::Only(TransportProtocol::Udp) => write!(f, " over UDP"),
+            Constraint::Only(TransportProtocol::Tcp) => write!(f, " using UDP-over-TCP"),
         }
     }
 }
 
 impl Match<WireguardEndpointData> for WireguardConstraints {
     fn matches(&self, endpoint: &WireguardEndpointData) -> bool {
+        // The TCP port is not related to the WireGuard ports of the relay
+        if self.protocol == Constraint::Only(TransportProtocol::Tcp) {
+            return true;
+        }
         match self.port {
             Constraint::Any => true,
             Constraint::Only(port) => endpoint
@@ -380,11 +546,13 @@ impl fmt::Display for BridgeState {
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 pub struct InternalBridgeConstraints {
     pub location: Constraint<LocationConstraint>,
+    pub providers: Constraint<Providers>,
+    pub ownership: Constraint<Ownership>,
     pub transport_protocol: Constraint<TransportProtocol>,
 }
 
 /// Used to update the [`RelaySettings`] used in `mullvad-daemon`.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[cfg_attr(target_os = "android", derive(FromJava))]
 #[cfg_attr(target_os = "android", jnix(package = "net.mullvad.mullvadvpn.model"))]
 #[serde(rename_all = "snake_case")]
@@ -420,7 +588,7 @@ impl RelaySettingsUpdate {
 }
 
 /// Used in [`RelaySettings`] to change relay constraints in the daemon.
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
 #[cfg_attr(target_os = "android", derive(FromJava))]
 #[cfg_attr(target_os = "android", jnix(package = "net.mullvad.mullvadvpn.model"))]
 #[serde(default)]
@@ -432,4 +600,98 @@ pub struct RelayConstraintsUpdate {
     pub wireguard_constraints: Option<WireguardConstraints>,
     #[cfg_attr(target_os = "android", jnix(default))]
     pub openvpn_constraints: Option<OpenVpnConstraints>,
+    /// `Some(None)` disables multihop.
+    #[serde(
+        deserialize_with = "deserialize_double_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[cfg_attr(target_os = "android", jnix(default))]
+    pub entry_location: Option<Option<Constraint<LocationConstraint>>>,
+    #[cfg_attr(target_os = "android", jnix(default))]
+    pub providers: Option<Constraint<Providers>>,
+    #[cfg_attr(target_os = "android", jnix(default))]
+    pub ownership: Option<Constraint<Ownership>>,
+    #[cfg_attr(target_os = "android", jnix(default))]
+    pub selection_mode: Option<SelectionMode>,
+}
+
+/// Deserializes a value that is present into `Some`, so that an explicit `null` can be told apart
+/// from a missing field.
+fn deserialize_double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_entry_location_update() {
+        let constraints = RelayConstraints {
+            entry_location: Some(Constraint::Only(LocationConstraint::Country(
+                "se".to_owned(),
+            ))),
+            ..Default::default()
+        };
+
+        let update: RelayConstraintsUpdate = serde_json::from_str("{}").unwrap();
+        assert_eq!(
+            constraints.merge(update).entry_location,
+            constraints.entry_location
+        );
+
+        let update: RelayConstraintsUpdate =
+            serde_json::from_str(r#"{ "entry_location": null }"#).unwrap();
+        assert_eq!(constraints.merge(update).entry_location, None);
+
+        let update: RelayConstraintsUpdate =
+            serde_json::from_str(r#"{ "entry_location": "any" }"#).unwrap();
+        assert_eq!(
+            constraints.merge(update).entry_location,
+            Some(Constraint::Any)
+        );
+    }
+
+    #[test]
+    fn test_provider_and_ownership_match() {
+        let relay: Relay = serde_json::from_str(
+            r#"{
+                "hostname": "se1-wireguard",
+                "ipv4_addr_in": "185.213.154.68",
+                "ipv6_addr_in": null,
+                "include_in_country": true,
+                "active": true,
+                "owned": true,
+                "provider": "31173",
+                "weight": 1,
+                "location": null
+            }"#,
+        )
+        .unwrap();
+
+        assert!(Ownership::MullvadOwned.matches(&relay));
+        assert!(!Ownership::Rented.matches(&relay));
+
+        let providers =
+            Providers::new(vec!["M247".to_owned(), "31173".to_owned()].into_iter()).unwrap();
+        assert!(providers.matches(&relay));
+        let providers = Providers::new(vec!["M247".to_owned()].into_iter()).unwrap();
+        assert!(!providers.matches(&relay));
+
+        assert!(Providers::new(Vec::new().into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_providers() {
+        let providers: Providers = serde_json::from_str(r#"{"providers": ["M247"]}"#).unwrap();
+        assert_eq!(
+            providers,
+            Providers::new(vec!["M247".to_owned()].into_iter()).unwrap()
+        );
+        assert!(serde_json::from_str::<Providers>(r#"{"providers": []}"#).is_err());
+    }
 }
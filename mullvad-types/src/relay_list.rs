@@ -139,6 +139,9 @@ pub struct WireguardEndpointData {
     pub ipv6_gateway: Ipv6Addr,
     /// The peer's public key
     pub public_key: wireguard::PublicKey,
+    /// Port on other relays that forwards traffic to this relay. Used for multihop.
+    #[serde(default)]
+    pub multihop_port: Option<u16>,
 }
 
 impl fmt::Display for WireguardEndpointData {
@@ -0,0 +1,277 @@
+use crate::relay_constraints::{
+    Constraint, LocationConstraint, RelayConstraintsUpdate, RelaySettingsUpdate,
+};
+use chrono::{Datelike, Duration, NaiveDateTime, Timelike};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+/// Longest time that missed rules are looked for, e.g. after the computer has been suspended.
+const MAX_CATCH_UP_MINUTES: i64 = 24 * 60;
+
+#[derive(err_derive::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    #[error(display = "Expected 5 fields in the schedule expression, found {}", _0)]
+    FieldCount(usize),
+
+    #[error(display = "Invalid value \"{}\" in the {} field", _1, _0)]
+    InvalidField(&'static str, String),
+}
+
+/// A cron-like expression with the fields minute, hour, day of month, month and day of week.
+/// Each field is `*`, a value, a range `a-b`, a step `*/n` or `a-b/n`, or a comma separated list
+/// of these. Days of the week are 0-7, where both 0 and 7 are Sunday. Like in cron, if both the
+/// day of month and the day of week are restricted, either of them has to match.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CronExpression {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    day_of_month_restricted: bool,
+    day_of_week_restricted: bool,
+}
+
+impl CronExpression {
+    /// Returns whether the expression matches the minute of `time`.
+    pub fn matches(&self, time: &NaiveDateTime) -> bool {
+        let is_set = |field: u64, value: u32| field & (1 << value) != 0;
+
+        let day_of_month = is_set(self.days_of_month, time.day());
+        let day_of_week = is_set(self.days_of_week, time.weekday().num_days_from_sunday());
+        let day = if self.day_of_month_restricted && self.day_of_week_restricted {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        };
+
+        day && is_set(self.minutes, time.minute())
+            && is_set(self.hours, time.hour())
+            && is_set(self.months, time.month())
+    }
+}
+
+impl FromStr for CronExpression {
+    type Err = Error;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(Error::FieldCount(fields.len()));
+        }
+
+        let mut days_of_week = parse_field("day of week", fields[4], 0, 7)?;
+        // Sunday can be given as both 0 and 7
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week |= 1;
+        }
+
+        Ok(CronExpression {
+            expression: fields.join(" "),
+            minutes: parse_field("minute", fields[0], 0, 59)?,
+            hours: parse_field("hour", fields[1], 0, 23)?,
+            days_of_month: parse_field("day of month", fields[2], 1, 31)?,
+            months: parse_field("month", fields[3], 1, 12)?,
+            days_of_week,
+            day_of_month_restricted: fields[2] != "*",
+            day_of_week_restricted: fields[4] != "*",
+        })
+    }
+}
+
+/// Parses a field into a bit set where bit `n` is set if the value `n` matches.
+fn parse_field(name: &'static str, field: &str, min: u32, max: u32) -> Result<u64, Error> {
+    let invalid = || Error::InvalidField(name, field.to_owned());
+    let parse_value = |value: &str| {
+        value
+            .parse::<u32>()
+            .ok()
+            .filter(|value| (min..=max).contains(value))
+            .ok_or_else(invalid)
+    };
+
+    let mut bits = 0;
+    for part in field.split(',') {
+        let mut range_and_step = part.splitn(2, '/');
+        let range = range_and_step.next().unwrap_or("");
+        let step = match range_and_step.next() {
+            Some(step) => step
+                .parse::<u32>()
+                .ok()
+                .filter(|step| *step > 0)
+                .ok_or_else(invalid)?,
+            None => 1,
+        };
+
+        let mut bounds = range.splitn(2, '-');
+        let (start, end) = match (bounds.next().unwrap_or(""), bounds.next()) {
+            ("*", None) => (min, max),
+            (start, Some(end)) => (parse_value(start)?, parse_value(end)?),
+            // A single value with a step means every step from that value, like in cron
+            (start, None) if part.contains('/') => (parse_value(start)?, max),
+            (value, None) => {
+                let value = parse_value(value)?;
+                (value, value)
+            }
+        };
+        if start > end {
+            return Err(invalid());
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl fmt::Display for CronExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.expression.fmt(f)
+    }
+}
+
+impl Serialize for CronExpression {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.expression)
+    }
+}
+
+impl<'de> Deserialize<'de> for CronExpression {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let expression = String::deserialize(deserializer)?;
+        expression.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleAction {
+    Connect,
+    Disconnect,
+}
+
+impl fmt::Display for ScheduleAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleAction::Connect => "connect".fmt(f),
+            ScheduleAction::Disconnect => "disconnect".fmt(f),
+        }
+    }
+}
+
+/// Connects or disconnects the tunnel at the times matched by `when`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ScheduleRule {
+    pub when: CronExpression,
+    pub action: ScheduleAction,
+    /// Relay settings to apply before connecting. Only used when connecting.
+    #[serde(default)]
+    pub relay_settings: Option<RelaySettingsUpdate>,
+}
+
+impl fmt::Display for ScheduleRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\": {}", self.when, self.action)?;
+        match &self.relay_settings {
+            Some(RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+                location: Some(Constraint::Only(location)),
+                ..
+            })) => write!(f, " to {}", location),
+            Some(_) => write!(f, " with custom relay settings"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl ScheduleRule {
+    /// Returns a rule that connects to `location`.
+    pub fn connect_to(when: CronExpression, location: Constraint<LocationConstraint>) -> Self {
+        ScheduleRule {
+            when,
+            action: ScheduleAction::Connect,
+            relay_settings: Some(RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+                location: Some(location),
+                ..RelayConstraintsUpdate::default()
+            })),
+        }
+    }
+}
+
+/// Returns the rule that was due most recently in the interval `(since, until]`, or `None` if no
+/// rule was due. If several rules are due at the same minute, the last one wins. Only the last
+/// day before `until` is searched.
+pub fn latest_due_rule(
+    rules: &[ScheduleRule],
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+) -> Option<&ScheduleRule> {
+    let earliest = std::cmp::max(since, until - Duration::minutes(MAX_CATCH_UP_MINUTES));
+    let mut minute = until.with_second(0)?.with_nanosecond(0)?;
+    while minute > earliest {
+        if let Some(rule) = rules.iter().rev().find(|rule| rule.when.matches(&minute)) {
+            return Some(rule);
+        }
+        minute -= Duration::minutes(1);
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_cron_expression() {
+        let workdays: CronExpression = "0 9 * * 1-5".parse().unwrap();
+        // 2020-06-01 is a Monday
+        let monday = NaiveDate::from_ymd(2020, 6, 1).and_hms(9, 0, 0);
+        let sunday = NaiveDate::from_ymd(2020, 6, 7).and_hms(9, 0, 0);
+        assert!(workdays.matches(&monday));
+        assert!(!workdays.matches(&(monday + Duration::minutes(1))));
+        assert!(!workdays.matches(&sunday));
+
+        let every_quarter: CronExpression = "*/15 0-6,22-23 * * 0,7".parse().unwrap();
+        assert!(every_quarter.matches(&sunday.with_hour(22).unwrap()));
+        assert!(every_quarter.matches(&sunday.with_hour(3).unwrap().with_minute(45).unwrap()));
+        assert!(!every_quarter.matches(&sunday.with_hour(3).unwrap().with_minute(40).unwrap()));
+
+        assert_eq!(
+            "0 9 * *".parse::<CronExpression>(),
+            Err(Error::FieldCount(4))
+        );
+        assert!("60 9 * * *".parse::<CronExpression>().is_err());
+        assert!("0 9 * * 5-1".parse::<CronExpression>().is_err());
+        assert!("*/0 9 * * *".parse::<CronExpression>().is_err());
+    }
+
+    #[test]
+    fn test_latest_due_rule() {
+        let rule = |when: &str, action| ScheduleRule {
+            when: when.parse().unwrap(),
+            action,
+            relay_settings: None,
+        };
+        let rules = vec![
+            rule("0 9 * * *", ScheduleAction::Connect),
+            rule("0 22 * * *", ScheduleAction::Disconnect),
+        ];
+        let morning = NaiveDate::from_ymd(2020, 6, 1).and_hms(8, 59, 30);
+
+        assert_eq!(
+            latest_due_rule(&rules, morning, morning + Duration::seconds(20)),
+            None
+        );
+        assert_eq!(
+            latest_due_rule(&rules, morning, morning + Duration::seconds(40)),
+            Some(&rules[0])
+        );
+        // Only the most recent rule is returned after a long pause
+        assert_eq!(
+            latest_due_rule(&rules, morning, morning + Duration::hours(14)),
+            Some(&rules[1])
+        );
+    }
+}
@@ -68,9 +68,18 @@ impl super::SettingsMigration for Migration {
                 bridge_state: old.bridge_state,
                 allow_lan: old.allow_lan,
                 block_when_disconnected: old.block_when_disconnected,
+                firewall_exemptions: Vec::new(),
                 auto_connect: old.auto_connect,
                 tunnel_options: old.tunnel_options,
+                custom_lists: Default::default(),
+                split_tunnel: Default::default(),
+                trusted_networks: Vec::new(),
+                schedule: Vec::new(),
                 show_beta_releases: false,
+                tunnel_stats_events: false,
+                socks_proxy: false,
+                metrics: false,
+                locked_fields: Vec::new(),
                 settings_version: super::SettingsVersion::V2,
             }),
             VersionedSettings::V2(new) => VersionedSettings::V2(new),
@@ -1,15 +1,30 @@
-use crate::relay_constraints::{
-    BridgeConstraints, BridgeSettings, BridgeState, Constraint, LocationConstraint,
-    RelayConstraints, RelaySettings, RelaySettingsUpdate,
+use crate::{
+    custom_list::{self, CustomListsSettings},
+    relay_constraints::{
+        BridgeConstraints, BridgeSettings, BridgeState, Constraint, LocationConstraint,
+        RelayConstraints, RelaySettings, RelaySettingsUpdate,
+    },
+    schedule::ScheduleRule,
+    trusted_network::TrustedNetwork,
 };
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
 use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use serde_json;
-use talpid_types::net::{openvpn, wireguard, GenericTunnelOptions};
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+};
+use talpid_types::net::{
+    openvpn, wireguard, FirewallExemption, GenericTunnelOptions, SplitTunnelMode,
+};
 
 mod migrations;
+mod policy;
+
+pub use policy::SettingsPolicy;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -21,8 +36,79 @@ pub enum Error {
 
     #[error(display = "Unable to read any version of the settings")]
     NoMatchingVersion,
+
+    #[error(display = "Invalid custom lists")]
+    InvalidCustomLists(#[error(source)] custom_list::Error),
+
+    #[error(display = "The relay settings use a custom list that does not exist")]
+    UnknownCustomList,
+
+    #[error(display = "Invalid {}: {}", _0, _1)]
+    InvalidValue(&'static str, String),
+}
+
+/// Range of valid OpenVPN mssfix values. Matches the range allowed by the GUI.
+pub const OPENVPN_MSSFIX_RANGE: std::ops::RangeInclusive<u16> = 1000..=1450;
+/// Range of valid WireGuard MTU values. Matches the range allowed by the GUI.
+pub const WIREGUARD_MTU_RANGE: std::ops::RangeInclusive<u16> = 1280..=1420;
+
+/// Checks that `mssfix` is unset or within [`OPENVPN_MSSFIX_RANGE`].
+pub fn validate_openvpn_mssfix(mssfix: Option<u16>) -> Result<()> {
+    match mssfix {
+        Some(mssfix) if !OPENVPN_MSSFIX_RANGE.contains(&mssfix) => Err(Error::InvalidValue(
+            "OpenVPN mssfix",
+            format!("{} is not in the range {:?}", mssfix, OPENVPN_MSSFIX_RANGE),
+        )),
+        _ => Ok(()),
+    }
 }
 
+/// Checks that `mtu` is unset or within [`WIREGUARD_MTU_RANGE`].
+pub fn validate_wireguard_mtu(mtu: Option<u16>) -> Result<()> {
+    match mtu {
+        Some(mtu) if !WIREGUARD_MTU_RANGE.contains(&mtu) => Err(Error::InvalidValue(
+            "WireGuard MTU",
+            format!("{} is not in the range {:?}", mtu, WIREGUARD_MTU_RANGE),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that a custom relay in `update` can be used on this platform.
+pub fn validate_relay_settings_update(update: &RelaySettingsUpdate) -> Result<()> {
+    match update {
+        RelaySettingsUpdate::CustomTunnelEndpoint(endpoint) => endpoint
+            .validate()
+            .map_err(|error| Error::InvalidValue("custom relay", error.to_string())),
+        RelaySettingsUpdate::Normal(_) => Ok(()),
+    }
+}
+
+/// Checks that the firewall exemptions can be enforced. They are only supported on Linux, and
+/// ports can only be matched together with a protocol.
+pub fn validate_firewall_exemptions(exemptions: &[FirewallExemption]) -> Result<()> {
+    if !cfg!(target_os = "linux") && !exemptions.is_empty() {
+        return Err(Error::InvalidValue(
+            "firewall exemption",
+            "firewall exemptions are only supported on Linux".to_owned(),
+        ));
+    }
+    for exemption in exemptions {
+        if exemption.ports.is_some() && exemption.protocol.is_none() {
+            return Err(Error::InvalidValue(
+                "firewall exemption",
+                format!("{} has ports but no protocol", exemption),
+            ));
+        }
+        if exemption.ports.map(|ports| ports.start > ports.end) == Some(true) {
+            return Err(Error::InvalidValue(
+                "firewall exemption",
+                format!("{} has an empty port range", exemption),
+            ));
+        }
+    }
+    Ok(())
+}
 
 /// Mullvad daemon settings.
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -42,14 +128,46 @@ pub struct Settings {
     /// the firewall to not allow any traffic in or out.
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub block_when_disconnected: bool,
+    /// Traffic that bypasses the tunnel and is always allowed by the firewall, also when
+    /// blocking. Only supported on Linux.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub firewall_exemptions: Vec<FirewallExemption>,
     /// If the daemon should connect the VPN tunnel directly on start or not.
     pub auto_connect: bool,
     /// Options that should be applied to tunnels of a specific type regardless of where the relays
     /// might be located.
     pub tunnel_options: TunnelOptions,
+    /// Named groups of locations that can be used as location constraints.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub custom_lists: CustomListsSettings,
+    /// Applications to split from the tunnel. Only used on Linux.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub split_tunnel: SplitTunnelSettings,
+    /// Networks on which the tunnel is disconnected automatically. On all other networks it is
+    /// connected automatically. Not used if empty. Only used on Linux.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub trusted_networks: Vec<TrustedNetwork>,
+    /// Rules that connect or disconnect the tunnel at given times.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub schedule: Vec<ScheduleRule>,
     /// Whether to notify users of beta updates.
     #[serde(deserialize_with = "deserialize_show_beta_releases")]
     pub show_beta_releases: bool,
+    /// Whether to periodically broadcast the traffic counters of the tunnel while connected.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub tunnel_stats_events: bool,
+    /// Whether to run a SOCKS5 proxy on localhost that forwards connections through the tunnel.
+    /// Not used on Android.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub socks_proxy: bool,
+    /// Whether to serve metrics about the daemon in the Prometheus format on localhost.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub metrics: bool,
+    /// Settings that are locked by the system policy file and cannot be changed. Not saved, since
+    /// it is derived from the policy.
+    #[serde(skip)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub locked_fields: Vec<String>,
     /// Specifies settings schema version
     #[cfg_attr(target_os = "android", jnix(skip))]
     settings_version: migrations::SettingsVersion,
@@ -69,9 +187,18 @@ impl Default for Settings {
             bridge_state: BridgeState::Auto,
             allow_lan: false,
             block_when_disconnected: false,
+            firewall_exemptions: Vec::new(),
             auto_connect: false,
             tunnel_options: TunnelOptions::default(),
+            custom_lists: CustomListsSettings::default(),
+            split_tunnel: SplitTunnelSettings::default(),
+            trusted_networks: Vec::new(),
+            schedule: Vec::new(),
             show_beta_releases: false,
+            tunnel_stats_events: false,
+            socks_proxy: false,
+            metrics: false,
+            locked_fields: Vec::new(),
             settings_version: migrations::SettingsVersion::V2,
         }
     }
@@ -86,6 +213,85 @@ impl Settings {
         migrations::try_migrate_settings(&bytes)
     }
 
+    /// Returns a copy of the settings suitable for exporting to another device. The account
+    /// token is only kept if `include_account_token` is set.
+    pub fn export(&self, include_account_token: bool) -> Self {
+        let mut settings = self.clone();
+        if !include_account_token {
+            settings.account_token = None;
+        }
+        settings
+    }
+
+    /// Reads exported settings of any supported version and checks that they are valid. Settings
+    /// without a version predate versioning and are migrated, since reading them as the current
+    /// version would silently replace the fields that have changed with default values.
+    pub fn import_from_bytes(bytes: &[u8]) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_slice(bytes).map_err(Error::ParseError)?;
+        let settings = if value.get("settings_version").is_some() {
+            Self::load_from_bytes(bytes)?
+        } else {
+            Self::migrate_from_bytes(bytes)?
+        };
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Checks the values that deserialization alone does not constrain. Uses the same checks as
+    /// the management interface does for the individual settings.
+    pub fn validate(&self) -> Result<()> {
+        if self.account_token.as_ref().map(String::len) == Some(0) {
+            return Err(Error::InvalidValue(
+                "account token",
+                "the token is empty".to_owned(),
+            ));
+        }
+        validate_openvpn_mssfix(self.tunnel_options.openvpn.mssfix)?;
+        validate_wireguard_mtu(self.tunnel_options.wireguard.mtu)?;
+        self.tunnel_options.dns_options.validate()?;
+        if let BridgeSettings::Custom(proxy) = &self.bridge_settings {
+            openvpn::validate_proxy_settings(proxy)
+                .map_err(|message| Error::InvalidValue("custom bridge", message))?;
+        }
+        if let Some(app) = self.split_tunnel.apps.iter().find(|app| !app.is_absolute()) {
+            return Err(Error::InvalidValue(
+                "split tunneling application",
+                format!("{} is not an absolute path", app.display()),
+            ));
+        }
+        if let RelaySettings::CustomTunnelEndpoint(endpoint) = &self.relay_settings {
+            endpoint
+                .validate()
+                .map_err(|error| Error::InvalidValue("custom relay", error.to_string()))?;
+        }
+        for relay_settings in self
+            .schedule
+            .iter()
+            .filter_map(|rule| rule.relay_settings.as_ref())
+        {
+            validate_relay_settings_update(relay_settings)?;
+        }
+        validate_firewall_exemptions(&self.firewall_exemptions)?;
+        if self.trusted_networks.iter().any(TrustedNetwork::is_empty) {
+            return Err(Error::InvalidValue(
+                "trusted network",
+                "a rule must match at least one property".to_owned(),
+            ));
+        }
+
+        self.custom_lists
+            .validate()
+            .map_err(Error::InvalidCustomLists)?;
+        let custom_lists = &self.custom_lists;
+        if self
+            .used_custom_lists()
+            .any(|id| custom_lists.get(id).is_none())
+        {
+            return Err(Error::UnknownCustomList);
+        }
+        Ok(())
+    }
+
     pub fn get_account_token(&self) -> Option<String> {
         self.account_token.clone()
     }
@@ -135,6 +341,34 @@ impl Settings {
         }
     }
 
+    /// Returns whether the custom list with the given ID is used as a location constraint.
+    pub fn is_custom_list_in_use(&self, id: &str) -> bool {
+        self.used_custom_lists().any(|list_id| list_id == id)
+    }
+
+    /// Returns the IDs of the custom lists that are used as location constraints, including by
+    /// the relay settings of schedule rules.
+    fn used_custom_lists(&self) -> impl Iterator<Item = &custom_list::Id> {
+        let mut locations = vec![];
+        if let RelaySettings::Normal(constraints) = &self.relay_settings {
+            locations.push(&constraints.location);
+            locations.extend(constraints.entry_location.as_ref());
+        }
+        if let BridgeSettings::Normal(constraints) = &self.bridge_settings {
+            locations.push(&constraints.location);
+        }
+        for rule in &self.schedule {
+            if let Some(RelaySettingsUpdate::Normal(update)) = &rule.relay_settings {
+                locations.extend(update.location.as_ref());
+                locations.extend(update.entry_location.as_ref().and_then(Option::as_ref));
+            }
+        }
+        locations.into_iter().filter_map(|location| match location {
+            Constraint::Only(LocationConstraint::CustomList(list_id)) => Some(list_id),
+            _ => None,
+        })
+    }
+
     pub fn get_bridge_state(&self) -> &BridgeState {
         &self.bridge_state
     }
@@ -152,6 +386,17 @@ impl Settings {
     }
 }
 
+/// Split tunneling settings.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SplitTunnelSettings {
+    /// Paths of executables whose processes are split from the tunnel.
+    pub apps: Vec<PathBuf>,
+    /// Whether the split processes are the only ones that bypass the tunnel, or the only ones
+    /// that use it.
+    pub mode: SplitTunnelMode,
+}
+
 /// TunnelOptions holds configuration data that applies to all kinds of tunnels.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(default)]
@@ -166,6 +411,9 @@ pub struct TunnelOptions {
     /// Contains generic tunnel options that may apply to more than a single tunnel type.
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub generic: GenericTunnelOptions,
+    /// DNS servers to use in the tunnel.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub dns_options: DnsOptions,
 }
 
 impl Default for TunnelOptions {
@@ -180,6 +428,115 @@ impl Default for TunnelOptions {
                 // Enable IPv6 be default on Android
                 enable_ipv6: cfg!(target_os = "android"),
             },
+            dns_options: DnsOptions::default(),
+        }
+    }
+}
+
+/// DNS servers to use instead of the default DNS server of the relay.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DnsOptions {
+    /// Whether to use `addresses` instead of the DNS server of the relay.
+    pub custom: bool,
+    /// Servers on the local network are only used if LAN access is allowed.
+    pub addresses: Vec<IpAddr>,
+    /// Content blocking done by the DNS server of the relay. Not used with custom servers.
+    pub content_blockers: DnsContentBlockers,
+}
+
+impl DnsOptions {
+    /// Returns the servers to use, or `None` if the default DNS server of the relay should be
+    /// used.
+    pub fn servers(&self) -> Option<Vec<IpAddr>> {
+        if self.custom {
+            // The firewall on Windows only allows DNS requests to the relay
+            if self.addresses.is_empty() || cfg!(windows) {
+                None
+            } else {
+                Some(self.addresses.clone())
+            }
+        } else if cfg!(windows) {
+            None
+        } else {
+            self.content_blockers
+                .resolver_address()
+                .map(|address| vec![address.into()])
+        }
+    }
+
+    /// Checks that the options can be used on this platform.
+    pub fn validate(&self) -> Result<()> {
+        if self.custom {
+            if cfg!(windows) {
+                return Err(Error::InvalidValue(
+                    "custom DNS servers",
+                    "custom DNS servers are not supported on Windows".to_owned(),
+                ));
+            }
+            if self.addresses.is_empty() {
+                return Err(Error::InvalidValue(
+                    "custom DNS servers",
+                    "at least one server is required".to_owned(),
+                ));
+            }
+        } else if cfg!(windows) && self.content_blockers.resolver_address().is_some() {
+            return Err(Error::InvalidValue(
+                "DNS content blocking",
+                "content blocking is not supported on Windows".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Types of content that the DNS server of the relay can block.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DnsContentBlockers {
+    pub block_ads: bool,
+    pub block_trackers: bool,
+    pub block_malware: bool,
+}
+
+impl DnsContentBlockers {
+    /// Returns the in-tunnel address of the filtering resolver that blocks the enabled content,
+    /// or `None` if nothing is blocked. The last octet is a bitmask of the enabled blockers.
+    pub fn resolver_address(&self) -> Option<Ipv4Addr> {
+        let mut mask = 0;
+        if self.block_ads {
+            mask |= 1 << 0;
+        }
+        if self.block_trackers {
+            mask |= 1 << 1;
+        }
+        if self.block_malware {
+            mask |= 1 << 2;
+        }
+        if mask == 0 {
+            None
+        } else {
+            Some(Ipv4Addr::new(100, 64, 0, mask))
+        }
+    }
+}
+
+impl fmt::Display for DnsContentBlockers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut blocked = vec![];
+        if self.block_ads {
+            blocked.push("ads");
+        }
+        if self.block_trackers {
+            blocked.push("trackers");
+        }
+        if self.block_malware {
+            blocked.push("malware");
+        }
+        if blocked.is_empty() {
+            write!(f, "nothing")
+        } else {
+            write!(f, "{}", blocked.join(", "))
         }
     }
 }
@@ -195,6 +552,189 @@ fn deserialize_show_beta_releases<'de, D: serde::de::Deserializer<'de>>(
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::custom_tunnel::{self, CustomTunnelEndpoint};
+    use talpid_types::net::{PortRange, TransportProtocol};
+
+    #[test]
+    fn test_dns_servers() {
+        let mut dns_options = DnsOptions::default();
+        assert_eq!(dns_options.servers(), None);
+
+        dns_options.content_blockers.block_ads = true;
+        dns_options.content_blockers.block_malware = true;
+        #[cfg(not(windows))]
+        assert_eq!(
+            dns_options.servers(),
+            Some(vec![IpAddr::from(Ipv4Addr::new(100, 64, 0, 5))])
+        );
+        #[cfg(windows)]
+        assert_eq!(dns_options.servers(), None);
+
+        // Content blocking does not apply to custom servers
+        dns_options.custom = true;
+        dns_options.addresses = vec![IpAddr::from(Ipv4Addr::new(192, 168, 1, 1))];
+        #[cfg(not(windows))]
+        assert_eq!(dns_options.servers(), Some(dns_options.addresses.clone()));
+        #[cfg(windows)]
+        assert_eq!(dns_options.servers(), None);
+    }
+
+    #[test]
+    fn test_dns_options_validation() {
+        let mut dns_options = DnsOptions::default();
+        assert!(dns_options.validate().is_ok());
+
+        dns_options.content_blockers.block_trackers = true;
+        assert_eq!(dns_options.validate().is_ok(), !cfg!(windows));
+
+        dns_options.custom = true;
+        assert!(dns_options.validate().is_err());
+
+        dns_options.addresses = vec![IpAddr::from(Ipv4Addr::new(1, 1, 1, 1))];
+        assert_eq!(dns_options.validate().is_ok(), !cfg!(windows));
+    }
+
+    #[test]
+    fn test_firewall_exemption_validation() {
+        let mut exemption = FirewallExemption {
+            network: "192.168.100.0/24".parse().unwrap(),
+            protocol: Some(TransportProtocol::Tcp),
+            ports: Some(PortRange::single(8080)),
+        };
+        assert_eq!(
+            validate_firewall_exemptions(&[exemption]).is_ok(),
+            cfg!(target_os = "linux")
+        );
+        assert!(validate_firewall_exemptions(&[]).is_ok());
+
+        exemption.protocol = None;
+        assert!(validate_firewall_exemptions(&[exemption]).is_err());
+
+        exemption.protocol = Some(TransportProtocol::Udp);
+        exemption.ports = Some(PortRange { start: 2, end: 1 });
+        assert!(validate_firewall_exemptions(&[exemption]).is_err());
+    }
+
+    #[test]
+    fn test_custom_relay_validation() {
+        let peer = wireguard::PeerConfig {
+            public_key: wireguard::PublicKey::from([1; 32]),
+            allowed_ips: vec!["0.0.0.0/0".parse().unwrap()],
+            endpoint: "192.0.2.1:51820".parse().unwrap(),
+            preshared_key: None,
+            persistent_keepalive: None,
+        };
+        let mut connection = wireguard::ConnectionConfig {
+            tunnel: wireguard::TunnelConfig {
+                private_key: wireguard::PrivateKey::from([2; 32]),
+                addresses: vec!["10.99.0.2".parse().unwrap()],
+            },
+            peer: peer.clone(),
+            additional_peers: vec![],
+            ipv4_gateway: "10.64.0.1".parse().unwrap(),
+            ipv6_gateway: None,
+            entry_endpoint: None,
+            udp_over_tcp_endpoint: None,
+        };
+        let update = |connection| {
+            RelaySettingsUpdate::CustomTunnelEndpoint(CustomTunnelEndpoint::new(
+                "192.0.2.1".to_owned(),
+                custom_tunnel::ConnectionConfig::Wireguard(connection),
+            ))
+        };
+        assert!(validate_relay_settings_update(&update(connection.clone())).is_ok());
+
+        connection.additional_peers.push(wireguard::PeerConfig {
+            public_key: wireguard::PublicKey::from([3; 32]),
+            allowed_ips: vec!["10.100.0.0/16".parse().unwrap()],
+            ..peer
+        });
+        assert_eq!(
+            validate_relay_settings_update(&update(connection)).is_ok(),
+            !cfg!(windows)
+        );
+    }
+
+    #[test]
+    fn test_custom_list_used_by_schedule() {
+        let mut settings = Settings::default();
+        settings
+            .custom_lists
+            .create("1".to_owned(), "favourites".to_owned())
+            .unwrap();
+        assert!(!settings.is_custom_list_in_use("1"));
+
+        settings.schedule.push(ScheduleRule::connect_to(
+            "0 9 * * 1-5".parse().unwrap(),
+            Constraint::Only(LocationConstraint::CustomList("1".to_owned())),
+        ));
+        assert!(settings.is_custom_list_in_use("1"));
+        assert!(settings.validate().is_ok());
+
+        settings.custom_lists.delete("favourites").unwrap();
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_import_validation() {
+        let mut settings = Settings::default();
+        settings.set_account_token(Some("1234".to_owned()));
+        let exported = serde_json::to_vec(&settings.export(false)).unwrap();
+        let imported = Settings::import_from_bytes(&exported).unwrap();
+        assert_eq!(imported.get_account_token(), None);
+
+        settings.tunnel_options.wireguard.mtu = Some(100);
+        let exported = serde_json::to_vec(&settings).unwrap();
+        assert!(Settings::import_from_bytes(&exported).is_err());
+
+        settings.tunnel_options.wireguard.mtu = None;
+        settings.relay_settings = RelaySettings::Normal(RelayConstraints {
+            location: Constraint::Only(LocationConstraint::CustomList("1".to_owned())),
+            ..RelayConstraints::default()
+        });
+        let exported = serde_json::to_vec(&settings).unwrap();
+        assert!(Settings::import_from_bytes(&exported).is_err());
+
+        settings.relay_settings = RelaySettings::Normal(RelayConstraints::default());
+        settings.bridge_settings = BridgeSettings::Custom(openvpn::ProxySettings::Shadowsocks(
+            openvpn::ShadowsocksProxySettings {
+                peer: "1.2.3.4:443".parse().unwrap(),
+                password: "password".to_owned(),
+                cipher: "not-a-cipher".to_owned(),
+            },
+        ));
+        let exported = serde_json::to_vec(&settings).unwrap();
+        assert!(Settings::import_from_bytes(&exported).is_err());
+    }
+
+    #[test]
+    fn test_import_migrates_unversioned_settings() {
+        let old_settings = br#"{
+              "relay_settings": {
+                "normal": {
+                  "location": "any",
+                  "tunnel": {
+                    "only": {
+                      "wireguard": {
+                        "port": "any"
+                      }
+                    }
+                  }
+                }
+              },
+              "allow_lan": true
+        }"#;
+
+        let settings = Settings::import_from_bytes(old_settings).unwrap();
+        assert!(settings.allow_lan);
+        match settings.get_relay_settings() {
+            RelaySettings::Normal(constraints) => assert_eq!(
+                constraints.tunnel_protocol,
+                Constraint::Only(talpid_types::net::TunnelType::Wireguard)
+            ),
+            relay_settings => panic!("Unexpected relay settings: {:?}", relay_settings),
+        }
+    }
 
     #[test]
     fn test_deserialization_of_2020_4_format() {
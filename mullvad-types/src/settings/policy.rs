@@ -0,0 +1,191 @@
+use super::{DnsOptions, Settings};
+use crate::relay_constraints::{Constraint, RelayConstraints, RelaySettings};
+use serde::{Deserialize, Serialize};
+use talpid_types::net::TunnelType;
+
+/// Settings that are pinned by the system administrator. Every field that is set overrides the
+/// corresponding user setting, and the user setting can no longer be changed.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SettingsPolicy {
+    pub allow_lan: Option<bool>,
+    pub block_when_disconnected: Option<bool>,
+    pub auto_connect: Option<bool>,
+    /// Custom tunnel endpoints cannot be used when the tunnel protocol is locked.
+    pub tunnel_protocol: Option<Constraint<TunnelType>>,
+    pub enable_ipv6: Option<bool>,
+    pub dns_options: Option<DnsOptions>,
+}
+
+impl SettingsPolicy {
+    /// Overrides the locked settings with the values pinned by the policy, and records which
+    /// settings are locked. Returns whether any setting changed.
+    pub fn apply(&self, settings: &mut Settings) -> bool {
+        settings.locked_fields = self.locked_fields();
+        let old_settings = settings.clone();
+
+        if let Some(allow_lan) = self.allow_lan {
+            settings.allow_lan = allow_lan;
+        }
+        if let Some(block_when_disconnected) = self.block_when_disconnected {
+            settings.block_when_disconnected = block_when_disconnected;
+        }
+        if let Some(auto_connect) = self.auto_connect {
+            settings.auto_connect = auto_connect;
+        }
+        if let Some(tunnel_protocol) = &self.tunnel_protocol {
+            match &mut settings.relay_settings {
+                RelaySettings::Normal(constraints) => {
+                    constraints.tunnel_protocol = tunnel_protocol.clone();
+                }
+                RelaySettings::CustomTunnelEndpoint(_) => {
+                    settings.relay_settings = RelaySettings::Normal(RelayConstraints {
+                        tunnel_protocol: tunnel_protocol.clone(),
+                        ..RelayConstraints::default()
+                    });
+                }
+            }
+        }
+        if let Some(enable_ipv6) = self.enable_ipv6 {
+            settings.tunnel_options.generic.enable_ipv6 = enable_ipv6;
+        }
+        if let Some(dns_options) = &self.dns_options {
+            settings.tunnel_options.dns_options = dns_options.clone();
+        }
+
+        *settings != old_settings
+    }
+
+    /// Copies the user's own values of the locked settings from `user_settings` to `settings`,
+    /// undoing [`SettingsPolicy::apply`]. The overridden values are kept this way, so that they
+    /// are used again once the policy is removed.
+    pub fn restore(&self, user_settings: &Settings, settings: &mut Settings) {
+        settings.locked_fields = Vec::new();
+
+        if self.allow_lan.is_some() {
+            settings.allow_lan = user_settings.allow_lan;
+        }
+        if self.block_when_disconnected.is_some() {
+            settings.block_when_disconnected = user_settings.block_when_disconnected;
+        }
+        if self.auto_connect.is_some() {
+            settings.auto_connect = user_settings.auto_connect;
+        }
+        if self.tunnel_protocol.is_some() {
+            let mut pinned_settings = user_settings.clone();
+            self.apply(&mut pinned_settings);
+            // Other relay constraints may have been changed while the protocol was locked. The
+            // user's relay settings, which may be a custom tunnel endpoint, are only restored
+            // as a whole if they have not.
+            if settings.relay_settings == pinned_settings.relay_settings {
+                settings.relay_settings = user_settings.relay_settings.clone();
+            } else if let (
+                RelaySettings::Normal(user_constraints),
+                RelaySettings::Normal(constraints),
+            ) = (&user_settings.relay_settings, &mut settings.relay_settings)
+            {
+                constraints.tunnel_protocol = user_constraints.tunnel_protocol.clone();
+            }
+        }
+        if self.enable_ipv6.is_some() {
+            settings.tunnel_options.generic.enable_ipv6 =
+                user_settings.tunnel_options.generic.enable_ipv6;
+        }
+        if self.dns_options.is_some() {
+            settings.tunnel_options.dns_options = user_settings.tunnel_options.dns_options.clone();
+        }
+    }
+
+    /// Returns the name of a locked setting whose value differs from the one pinned by the
+    /// policy, if there is one.
+    pub fn check(&self, settings: &Settings) -> Option<&'static str> {
+        let mut pinned_settings = settings.clone();
+        self.apply(&mut pinned_settings);
+
+        if settings.allow_lan != pinned_settings.allow_lan {
+            Some("allow_lan")
+        } else if settings.block_when_disconnected != pinned_settings.block_when_disconnected {
+            Some("block_when_disconnected")
+        } else if settings.auto_connect != pinned_settings.auto_connect {
+            Some("auto_connect")
+        } else if settings.relay_settings != pinned_settings.relay_settings {
+            Some("tunnel_protocol")
+        } else if settings.tunnel_options.generic != pinned_settings.tunnel_options.generic {
+            Some("enable_ipv6")
+        } else if settings.tunnel_options.dns_options != pinned_settings.tunnel_options.dns_options
+        {
+            Some("dns_options")
+        } else {
+            None
+        }
+    }
+
+    /// Returns the names of the settings that are locked by the policy.
+    pub fn locked_fields(&self) -> Vec<String> {
+        let fields = [
+            ("allow_lan", self.allow_lan.is_some()),
+            (
+                "block_when_disconnected",
+                self.block_when_disconnected.is_some(),
+            ),
+            ("auto_connect", self.auto_connect.is_some()),
+            ("tunnel_protocol", self.tunnel_protocol.is_some()),
+            ("enable_ipv6", self.enable_ipv6.is_some()),
+            ("dns_options", self.dns_options.is_some()),
+        ];
+        fields
+            .iter()
+            .filter(|(_, locked)| *locked)
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_policy_overrides_settings() {
+        let policy: SettingsPolicy = serde_json::from_str(
+            r#"{ "block_when_disconnected": true, "tunnel_protocol": { "only": "wireguard" } }"#,
+        )
+        .unwrap();
+
+        let mut settings = Settings::default();
+        assert_eq!(policy.check(&settings), Some("block_when_disconnected"));
+        assert!(policy.apply(&mut settings));
+        assert!(!policy.apply(&mut settings));
+        assert_eq!(policy.check(&settings), None);
+        assert_eq!(
+            settings.locked_fields,
+            vec!["block_when_disconnected", "tunnel_protocol"]
+        );
+
+        settings.block_when_disconnected = false;
+        assert_eq!(policy.check(&settings), Some("block_when_disconnected"));
+    }
+
+    #[test]
+    fn test_policy_keeps_user_values() {
+        let policy: SettingsPolicy = serde_json::from_str(
+            r#"{ "allow_lan": false, "tunnel_protocol": { "only": "wireguard" } }"#,
+        )
+        .unwrap();
+
+        let mut user_settings = Settings::default();
+        user_settings.allow_lan = true;
+
+        let mut settings = user_settings.clone();
+        policy.apply(&mut settings);
+        assert!(!settings.allow_lan);
+
+        // Unlocked settings changed while the policy is in place are kept
+        settings.auto_connect = true;
+        policy.restore(&user_settings, &mut settings);
+        assert!(settings.allow_lan);
+        assert!(settings.auto_connect);
+        assert_eq!(settings.relay_settings, user_settings.relay_settings);
+        assert!(settings.locked_fields.is_empty());
+    }
+}
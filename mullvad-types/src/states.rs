@@ -1,4 +1,5 @@
 use crate::location::GeoIpLocation;
+use chrono::{offset::Utc, DateTime};
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
 use serde::{Deserialize, Serialize};
@@ -45,4 +46,24 @@ impl TunnelState {
             _ => false,
         }
     }
+
+    /// Returns the name of the state, as it is serialized.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TunnelState::Disconnected => "disconnected",
+            TunnelState::Connecting { .. } => "connecting",
+            TunnelState::Connected { .. } => "connected",
+            TunnelState::Disconnecting(_) => "disconnecting",
+            TunnelState::Error(_) => "error",
+        }
+    }
+}
+
+/// Traffic counters of the current tunnel.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TunnelStats {
+    pub tx_bytes: u64,
+    pub rx_bytes: u64,
+    /// Time of the latest WireGuard handshake. Always `None` for OpenVPN tunnels.
+    pub last_handshake: Option<DateTime<Utc>>,
 }
@@ -0,0 +1,107 @@
+use crate::states::TargetState;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use talpid_types::net::NetworkIdentity;
+
+/// A rule that marks networks as trusted. A network matches the rule if it matches every
+/// property that is set in the rule.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TrustedNetwork {
+    pub ssid: Option<String>,
+    pub gateway_mac: Option<String>,
+    pub interface: Option<String>,
+}
+
+impl TrustedNetwork {
+    /// Returns true if the rule does not set any property. Such rules never match.
+    pub fn is_empty(&self) -> bool {
+        self.ssid.is_none() && self.gateway_mac.is_none() && self.interface.is_none()
+    }
+
+    pub fn matches(&self, network: &NetworkIdentity) -> bool {
+        !self.is_empty()
+            && property_matches(&self.ssid, &network.ssid, |a, b| a == b)
+            && property_matches(&self.gateway_mac, &network.gateway_mac, |a, b| {
+                a.eq_ignore_ascii_case(b)
+            })
+            && property_matches(&self.interface, &network.interface, |a, b| a == b)
+    }
+}
+
+fn property_matches(
+    rule: &Option<String>,
+    value: &Option<String>,
+    eq: impl Fn(&str, &str) -> bool,
+) -> bool {
+    match (rule, value) {
+        (None, _) => true,
+        (Some(rule), Some(value)) => eq(rule, value),
+        (Some(_), None) => false,
+    }
+}
+
+impl fmt::Display for TrustedNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let network = NetworkIdentity {
+            interface: self.interface.clone(),
+            gateway_mac: self.gateway_mac.clone(),
+            ssid: self.ssid.clone(),
+        };
+        network.fmt(f)
+    }
+}
+
+/// Sent when the daemon connects or disconnects because the network changed.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct TrustedNetworkEvent {
+    /// The network that the host connected to.
+    pub network: NetworkIdentity,
+    /// The rule that matched the network, or `None` if the network is not trusted.
+    pub rule: Option<TrustedNetwork>,
+    /// The target state that the daemon switched to.
+    pub target_state: TargetState,
+}
+
+impl fmt::Display for TrustedNetworkEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.rule {
+            Some(ref rule) => write!(
+                f,
+                "Disconnecting because {} matches trusted network rule: {}",
+                self.network, rule
+            ),
+            None => write!(f, "Connecting because {} is not trusted", self.network),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_trusted_network_matches() {
+        let network = NetworkIdentity {
+            interface: Some("wlan0".to_owned()),
+            gateway_mac: Some("aa:bb:cc:dd:ee:ff".to_owned()),
+            ssid: Some("Office".to_owned()),
+        };
+
+        let rule = TrustedNetwork {
+            ssid: Some("Office".to_owned()),
+            gateway_mac: Some("AA:BB:CC:DD:EE:FF".to_owned()),
+            ..TrustedNetwork::default()
+        };
+        assert!(rule.matches(&network));
+
+        let rule = TrustedNetwork {
+            ssid: Some("Office".to_owned()),
+            interface: Some("eth0".to_owned()),
+            ..TrustedNetwork::default()
+        };
+        assert!(!rule.matches(&network));
+
+        assert!(!TrustedNetwork::default().matches(&network));
+    }
+}
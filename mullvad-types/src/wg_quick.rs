@@ -0,0 +1,459 @@
+use crate::CustomTunnelEndpoint;
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    str::FromStr,
+};
+use talpid_types::net::wireguard;
+
+/// Keys that are used by `wg-quick` or `wg` but that do not affect the connection itself.
+const IGNORED_KEYS: &[&str] = &[
+    "listenport",
+    "fwmark",
+    "table",
+    "preup",
+    "postup",
+    "predown",
+    "postdown",
+    "saveconfig",
+];
+
+#[derive(err_derive::Error, Debug)]
+pub enum Error {
+    #[error(
+        display = "Line {}: Expected a section header or a \"Key = Value\" pair",
+        _0
+    )]
+    InvalidLine(usize),
+
+    #[error(display = "Line {}: Unknown section {}", _0, _1)]
+    UnknownSection(usize, String),
+
+    #[error(display = "Line {}: Unsupported key {}", _0, _1)]
+    UnsupportedKey(usize, String),
+
+    #[error(display = "Line {}: Invalid value for {}", _0, _1)]
+    InvalidValue(usize, String),
+
+    #[error(
+        display = "Peer {}: Only the first peer may use a host name as its endpoint",
+        _0
+    )]
+    HostnameEndpoint(usize),
+
+    #[error(display = "The configuration has no {}", _0)]
+    MissingValue(&'static str),
+
+    #[error(display = "The configuration has no IPv4 DNS server to use as the tunnel gateway")]
+    NoGateway,
+}
+
+/// A WireGuard configuration in the format read by `wg-quick`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WgQuickConfig {
+    pub private_key: wireguard::PrivateKey,
+    pub addresses: Vec<IpNetwork>,
+    pub dns_servers: Vec<IpAddr>,
+    pub mtu: Option<u16>,
+    /// The first peer is the primary peer of the tunnel. Any other peers are added to it.
+    pub peers: Vec<WgQuickPeer>,
+}
+
+/// A `[Peer]` section of a `wg-quick` configuration.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WgQuickPeer {
+    pub public_key: wireguard::PublicKey,
+    pub preshared_key: Option<wireguard::PresharedKey>,
+    pub allowed_ips: Vec<IpNetwork>,
+    /// Host name or IP address of the peer.
+    pub endpoint_host: String,
+    pub endpoint_port: u16,
+    pub persistent_keepalive: Option<u16>,
+}
+
+impl WgQuickPeer {
+    fn to_peer_config(&self, endpoint_address: IpAddr) -> wireguard::PeerConfig {
+        wireguard::PeerConfig {
+            public_key: self.public_key.clone(),
+            allowed_ips: self.allowed_ips.clone(),
+            endpoint: SocketAddr::new(endpoint_address, self.endpoint_port),
+            preshared_key: self.preshared_key.clone(),
+            persistent_keepalive: self.persistent_keepalive,
+        }
+    }
+}
+
+impl WgQuickConfig {
+    /// Converts the configuration into a custom relay. The first IPv4 and IPv6 DNS servers are
+    /// used as tunnel gateways, like in the configurations generated by Mullvad.
+    pub fn to_custom_tunnel_endpoint(&self) -> Result<CustomTunnelEndpoint, Error> {
+        let (peer, additional_peers) = self
+            .peers
+            .split_first()
+            .ok_or(Error::MissingValue("Peer"))?;
+        let additional_peers = additional_peers
+            .iter()
+            .enumerate()
+            .map(|(index, peer)| {
+                let address = peer
+                    .endpoint_host
+                    .parse()
+                    .map_err(|_| Error::HostnameEndpoint(index + 2))?;
+                Ok(peer.to_peer_config(address))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let ipv4_gateway = self
+            .dns_servers
+            .iter()
+            .find_map(|server| match server {
+                IpAddr::V4(address) => Some(*address),
+                IpAddr::V6(_) => None,
+            })
+            .ok_or(Error::NoGateway)?;
+        let ipv6_gateway = self.dns_servers.iter().find_map(|server| match server {
+            IpAddr::V4(_) => None,
+            IpAddr::V6(address) => Some(*address),
+        });
+
+        Ok(CustomTunnelEndpoint::new(
+            peer.endpoint_host.clone(),
+            crate::ConnectionConfig::Wireguard(wireguard::ConnectionConfig {
+                tunnel: wireguard::TunnelConfig {
+                    private_key: self.private_key.clone(),
+                    addresses: self.addresses.iter().map(|network| network.ip()).collect(),
+                },
+                peer: peer.to_peer_config(Ipv4Addr::UNSPECIFIED.into()),
+                additional_peers,
+                ipv4_gateway,
+                ipv6_gateway,
+                entry_endpoint: None,
+                udp_over_tcp_endpoint: None,
+            }),
+        ))
+    }
+}
+
+impl fmt::Display for WgQuickConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[Interface]")?;
+        writeln!(f, "PrivateKey = {}", self.private_key)?;
+        writeln!(f, "Address = {}", join(&self.addresses))?;
+        if !self.dns_servers.is_empty() {
+            writeln!(f, "DNS = {}", join(&self.dns_servers))?;
+        }
+        if let Some(mtu) = self.mtu {
+            writeln!(f, "MTU = {}", mtu)?;
+        }
+        for peer in &self.peers {
+            writeln!(f)?;
+            write!(f, "{}", peer)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for WgQuickPeer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[Peer]")?;
+        writeln!(f, "PublicKey = {}", self.public_key)?;
+        if let Some(preshared_key) = &self.preshared_key {
+            writeln!(f, "PresharedKey = {}", preshared_key)?;
+        }
+        writeln!(f, "AllowedIPs = {}", join(&self.allowed_ips))?;
+        if self.endpoint_host.contains(':') {
+            writeln!(
+                f,
+                "Endpoint = [{}]:{}",
+                self.endpoint_host, self.endpoint_port
+            )?;
+        } else {
+            writeln!(
+                f,
+                "Endpoint = {}:{}",
+                self.endpoint_host, self.endpoint_port
+            )?;
+        }
+        if let Some(keepalive) = self.persistent_keepalive {
+            writeln!(f, "PersistentKeepalive = {}", keepalive)?;
+        }
+        Ok(())
+    }
+}
+
+/// Values of a `[Peer]` section that is being parsed.
+#[derive(Default)]
+struct PartialPeer {
+    public_key: Option<wireguard::PublicKey>,
+    preshared_key: Option<wireguard::PresharedKey>,
+    allowed_ips: Vec<IpNetwork>,
+    endpoint: Option<(String, u16)>,
+    persistent_keepalive: Option<u16>,
+}
+
+impl PartialPeer {
+    fn finish(self) -> Result<WgQuickPeer, Error> {
+        let (endpoint_host, endpoint_port) =
+            self.endpoint.ok_or(Error::MissingValue("Endpoint"))?;
+        Ok(WgQuickPeer {
+            public_key: self.public_key.ok_or(Error::MissingValue("PublicKey"))?,
+            preshared_key: self.preshared_key,
+            allowed_ips: self.allowed_ips,
+            endpoint_host,
+            endpoint_port,
+            persistent_keepalive: self.persistent_keepalive,
+        })
+    }
+}
+
+impl FromStr for WgQuickConfig {
+    type Err = Error;
+
+    fn from_str(config: &str) -> Result<Self, Self::Err> {
+        enum Section {
+            None,
+            Interface,
+            Peer,
+        }
+
+        let mut section = Section::None;
+        let mut private_key = None;
+        let mut addresses = Vec::new();
+        let mut dns_servers = Vec::new();
+        let mut mtu = None;
+        let mut peers = Vec::new();
+        let mut peer = PartialPeer::default();
+
+        for (index, line) in config.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                section = match line[1..line.len() - 1].trim().to_lowercase().as_str() {
+                    "interface" => Section::Interface,
+                    "peer" => {
+                        if let Section::Peer = section {
+                            peers.push(std::mem::take(&mut peer).finish()?);
+                        }
+                        Section::Peer
+                    }
+                    _ => return Err(Error::UnknownSection(line_number, line.to_owned())),
+                };
+                continue;
+            }
+
+            let mut pair = line.splitn(2, '=');
+            let (key, value) = match (pair.next(), pair.next()) {
+                (Some(key), Some(value)) => (key.trim(), value.trim()),
+                _ => return Err(Error::InvalidLine(line_number)),
+            };
+            let invalid_value = || Error::InvalidValue(line_number, key.to_owned());
+
+            match (&section, key.to_lowercase().as_str()) {
+                (Section::Interface, "privatekey") => {
+                    private_key = Some(value.parse().map_err(|_| invalid_value())?)
+                }
+                (Section::Interface, "address") => {
+                    addresses.extend(parse_list::<IpNetwork>(value).map_err(|_| invalid_value())?)
+                }
+                (Section::Interface, "dns") => {
+                    // Entries that are not IP addresses are search domains
+                    dns_servers.extend(
+                        value
+                            .split(',')
+                            .filter_map(|entry| entry.trim().parse::<IpAddr>().ok()),
+                    )
+                }
+                (Section::Interface, "mtu") => {
+                    mtu = Some(value.parse().map_err(|_| invalid_value())?)
+                }
+                (Section::Peer, "publickey") => {
+                    peer.public_key = Some(value.parse().map_err(|_| invalid_value())?)
+                }
+                (Section::Peer, "presharedkey") => {
+                    peer.preshared_key = Some(value.parse().map_err(|_| invalid_value())?)
+                }
+                (Section::Peer, "allowedips") => peer
+                    .allowed_ips
+                    .extend(parse_list::<IpNetwork>(value).map_err(|_| invalid_value())?),
+                (Section::Peer, "endpoint") => {
+                    peer.endpoint = Some(parse_endpoint(value).ok_or_else(invalid_value)?)
+                }
+                (Section::Peer, "persistentkeepalive") => {
+                    // `wg` accepts "off" to disable keepalive
+                    peer.persistent_keepalive = match value {
+                        "off" => None,
+                        value => Some(value.parse().map_err(|_| invalid_value())?),
+                    }
+                }
+                (Section::Interface, key) | (Section::Peer, key) if IGNORED_KEYS.contains(&key) => {
+                    log::debug!("Ignoring {} on line {}", key, line_number);
+                }
+                _ => return Err(Error::UnsupportedKey(line_number, key.to_owned())),
+            }
+        }
+
+        if let Section::Peer = section {
+            peers.push(peer.finish()?);
+        }
+        if addresses.is_empty() {
+            return Err(Error::MissingValue("Address"));
+        }
+        if peers.is_empty() {
+            return Err(Error::MissingValue("Peer"));
+        }
+
+        Ok(WgQuickConfig {
+            private_key: private_key.ok_or(Error::MissingValue("PrivateKey"))?,
+            addresses,
+            dns_servers,
+            mtu,
+            peers,
+        })
+    }
+}
+
+fn join<T: fmt::Display>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn parse_list<T: FromStr>(value: &str) -> Result<Vec<T>, T::Err> {
+    value
+        .split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| entry.parse())
+        .collect()
+}
+
+/// Splits `host:port` or `[ipv6]:port` into a host and a port.
+fn parse_endpoint(endpoint: &str) -> Option<(String, u16)> {
+    let separator = endpoint.rfind(':')?;
+    let port = endpoint[separator + 1..].parse().ok()?;
+    let host = endpoint[..separator]
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_owned(), port))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = "\
+[Interface]
+# Device: Example
+PrivateKey = aGVsbG8gd29ybGQsIHRoaXMgaXMgYSB0ZXN0IGtleSE=
+Address = 10.99.0.2/32,fc00:bbbb:bbbb:bb01::2/128
+DNS = 10.64.0.1, example.com
+PostUp = true
+
+[Peer]
+PublicKey = dGhpcyBpcyB0aGUgcHVibGljIGtleSBvZiBhIHBlZXI=
+AllowedIPs = 0.0.0.0/0,::0/0
+Endpoint = [2001:db8::1]:51820
+";
+
+    #[test]
+    fn test_parse_and_format() {
+        let config: WgQuickConfig = CONFIG.parse().unwrap();
+        assert_eq!(config.addresses.len(), 2);
+        assert_eq!(
+            config.dns_servers,
+            vec!["10.64.0.1".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(config.peers.len(), 1);
+        assert_eq!(config.peers[0].allowed_ips.len(), 2);
+        assert_eq!(config.peers[0].endpoint_host, "2001:db8::1");
+        assert_eq!(config.peers[0].endpoint_port, 51820);
+        assert_eq!(config.peers[0].preshared_key, None);
+        assert_eq!(config.mtu, None);
+
+        let formatted = config.to_string();
+        assert!(formatted.contains("Endpoint = [2001:db8::1]:51820\n"));
+        assert_eq!(formatted.parse::<WgQuickConfig>().unwrap(), config);
+    }
+
+    #[test]
+    fn test_multiple_peers() {
+        let config = format!(
+            "{}PresharedKey = aGVsbG8gd29ybGQsIHRoaXMgaXMgYSB0ZXN0IGtleSE=
+PersistentKeepalive = 25
+
+[Peer]
+PublicKey = dGhpcyBpcyB0aGUga2V5IG9mIGFub3RoZXIgcGVlciE=
+AllowedIPs = 10.100.0.0/16
+Endpoint = 192.0.2.1:51820
+",
+            CONFIG
+        );
+        let config: WgQuickConfig = config.parse().unwrap();
+        assert_eq!(config.peers.len(), 2);
+        assert!(config.peers[0].preshared_key.is_some());
+        assert_eq!(config.peers[0].persistent_keepalive, Some(25));
+        assert_eq!(config.to_string().parse::<WgQuickConfig>().unwrap(), config);
+
+        let parameters = config
+            .to_custom_tunnel_endpoint()
+            .unwrap()
+            .to_tunnel_parameters(crate::settings::TunnelOptions::default(), None)
+            .unwrap();
+        match parameters {
+            talpid_types::net::TunnelParameters::Wireguard(parameters) => {
+                let additional_peers = parameters.connection.additional_peers;
+                assert_eq!(additional_peers.len(), 1);
+                assert_eq!(
+                    additional_peers[0].endpoint,
+                    "192.0.2.1:51820".parse().unwrap()
+                );
+            }
+            parameters => panic!("Unexpected parameters: {:?}", parameters),
+        }
+    }
+
+    #[test]
+    fn test_invalid_configs() {
+        let unsupported = CONFIG.replace("PostUp", "PostRoute");
+        match unsupported.parse::<WgQuickConfig>() {
+            Err(Error::UnsupportedKey(6, key)) => assert_eq!(key, "PostRoute"),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        let incomplete_peer = format!(
+            "{}\n[Peer]\nPublicKey = dGhpcyBpcyB0aGUga2V5IG9mIGFub3RoZXIgcGVlciE=\n",
+            CONFIG
+        );
+        match incomplete_peer.parse::<WgQuickConfig>() {
+            Err(Error::MissingValue("Endpoint")) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        let hostname_peer = format!(
+            "{}\n[Peer]\nPublicKey = dGhpcyBpcyB0aGUga2V5IG9mIGFub3RoZXIgcGVlciE=\nEndpoint = \
+             example.com:51820\n",
+            CONFIG
+        );
+        let hostname_peer: WgQuickConfig = hostname_peer.parse().unwrap();
+        match hostname_peer.to_custom_tunnel_endpoint() {
+            Err(Error::HostnameEndpoint(2)) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        let no_gateway: WgQuickConfig = CONFIG.replace("10.64.0.1, ", "").parse().unwrap();
+        match no_gateway.to_custom_tunnel_endpoint() {
+            Err(Error::NoGateway) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+}
@@ -35,6 +35,20 @@ pub struct PublicKey {
     pub created: DateTime<Utc>,
 }
 
+/// A WireGuard key that is either registered on the account or stored by the daemon.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AccountKey {
+    pub key: wireguard::PublicKey,
+    /// When the key was created, if known.
+    pub created: Option<DateTime<Utc>>,
+    /// Whether this is the key that is used for tunnels.
+    pub active: bool,
+    /// Whether the key is registered on the account.
+    pub registered: bool,
+    /// Whether the daemon has the private key, i.e. if the key can be restored.
+    pub stored: bool,
+}
+
 /// Contains a pair of local link addresses that are paired with a specific wireguard
 /// public/private keypair.
 #[derive(Clone, Debug, Deserialize, Serialize)]
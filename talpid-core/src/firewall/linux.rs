@@ -1,5 +1,5 @@
 use super::{FirewallArguments, FirewallPolicy, FirewallT};
-use crate::{split_tunnel, tunnel};
+use crate::{socks_proxy, split_tunnel, tunnel};
 use ipnetwork::IpNetwork;
 use lazy_static::lazy_static;
 use libc;
@@ -14,7 +14,9 @@ use std::{
     io,
     net::{IpAddr, Ipv4Addr},
 };
-use talpid_types::net::{Endpoint, TransportProtocol};
+use talpid_types::net::{
+    Endpoint, FirewallExemption, PortRange, SplitTunnelMode, TransportProtocol,
+};
 
 /// Priority for rules that tag split tunneling packets. Equals NF_IP_PRI_MANGLE.
 const MANGLE_CHAIN_PRIORITY: i32 = libc::NF_IP_PRI_MANGLE;
@@ -89,7 +91,9 @@ enum End {
 }
 
 /// The Linux implementation for the firewall and DNS.
-pub struct Firewall(());
+pub struct Firewall {
+    split_tunnel_mode: SplitTunnelMode,
+}
 
 struct FirewallTables {
     main: Table,
@@ -100,8 +104,10 @@ struct FirewallTables {
 impl FirewallT for Firewall {
     type Error = Error;
 
-    fn new(_args: FirewallArguments) -> Result<Self> {
-        Ok(Firewall(()))
+    fn new(args: FirewallArguments) -> Result<Self> {
+        Ok(Firewall {
+            split_tunnel_mode: args.split_tunnel_mode,
+        })
     }
 
     fn apply_policy(&mut self, policy: FirewallPolicy) -> Result<()> {
@@ -110,7 +116,7 @@ impl FirewallT for Firewall {
             mangle_v4: Table::new(&*MANGLE_TABLE_NAME_V4, ProtoFamily::Ipv4),
             mangle_v6: Table::new(&*MANGLE_TABLE_NAME_V6, ProtoFamily::Ipv6),
         };
-        let batch = PolicyBatch::new(&tables).finalize(&policy)?;
+        let batch = PolicyBatch::new(&tables).finalize(&policy, self.split_tunnel_mode)?;
         self.send_and_process(&batch)?;
         self.verify_tables(&[&TABLE_NAME, &MANGLE_TABLE_NAME_V4, &MANGLE_TABLE_NAME_V6])
     }
@@ -137,6 +143,14 @@ impl FirewallT for Firewall {
 }
 
 impl Firewall {
+    pub fn set_split_tunnel_mode(&mut self, mode: SplitTunnelMode) {
+        self.split_tunnel_mode = mode;
+    }
+
+    pub fn split_tunnel_mode(&self) -> SplitTunnelMode {
+        self.split_tunnel_mode
+    }
+
     fn send_and_process(&self, batch: &FinalizedBatch) -> Result<()> {
         let socket = mnl::Socket::new(mnl::Bus::Netfilter).map_err(Error::NetlinkOpenError)?;
         socket.send_all(batch).map_err(Error::NetlinkSendError)?;
@@ -278,21 +292,78 @@ impl<'a> PolicyBatch<'a> {
 
     /// Finalize the nftnl message batch by adding every firewall rule needed to satisfy the given
     /// policy.
-    pub fn finalize(mut self, policy: &FirewallPolicy) -> Result<FinalizedBatch> {
+    pub fn finalize(
+        mut self,
+        policy: &FirewallPolicy,
+        split_tunnel_mode: SplitTunnelMode,
+    ) -> Result<FinalizedBatch> {
         self.add_loopback_rules()?;
-        self.add_split_tunneling_rules();
+        self.add_socks_proxy_rules();
+        self.add_split_tunneling_dns_rules(policy, split_tunnel_mode);
+        self.add_split_tunneling_rules(split_tunnel_mode);
         self.add_dhcp_client_rules();
         self.add_policy_specific_rules(policy)?;
 
         Ok(self.batch.finalize())
     }
 
-    fn add_split_tunneling_rules(&mut self) {
+    /// Keeps split tunneling from marking the traffic of the SOCKS5 proxy, so that it is never
+    /// excluded from the tunnel. Must be added before the split tunneling rules. Traffic between
+    /// the local clients and the listener is already allowed by the loopback rules.
+    fn add_socks_proxy_rules(&mut self) {
+        for end in &[End::Src, End::Dst] {
+            let mut rule = Rule::new(&self.mangle_chain_v4);
+            check_ip(&mut rule, *end, Ipv4Addr::LOCALHOST);
+            check_port(&mut rule, TransportProtocol::Tcp, *end, socks_proxy::PORT);
+            add_verdict(&mut rule, &Verdict::Accept);
+            self.batch.add(&rule, nftnl::MsgType::Add);
+        }
+
+        let mangle_chains = [&self.mangle_chain_v4, &self.mangle_chain_v6];
+        for chain in &mangle_chains {
+            let mut rule = Rule::new(chain);
+            rule.add_expr(&nft_expr!(meta mark));
+            rule.add_expr(&nft_expr!(cmp == socks_proxy::MARK));
+            add_verdict(&mut rule, &Verdict::Accept);
+            self.batch.add(&rule, nftnl::MsgType::Add);
+        }
+    }
+
+    /// Keeps split tunneling from marking DNS requests to resolvers that are only reachable
+    /// through the tunnel. In include mode, this lets the daemon and other processes that are not
+    /// included resolve names. Must be added before the split tunneling rules.
+    fn add_split_tunneling_dns_rules(&mut self, policy: &FirewallPolicy, mode: SplitTunnelMode) {
+        for server in unmarked_dns_servers(policy, mode) {
+            let chain = match server {
+                IpAddr::V4(_) => &self.mangle_chain_v4,
+                IpAddr::V6(_) => &self.mangle_chain_v6,
+            };
+            for protocol in &[TransportProtocol::Udp, TransportProtocol::Tcp] {
+                let mut rule = Rule::new(chain);
+                check_ip(&mut rule, End::Dst, server);
+                check_port(&mut rule, *protocol, End::Dst, 53);
+                add_verdict(&mut rule, &Verdict::Accept);
+                self.batch.add(&rule, nftnl::MsgType::Add);
+            }
+        }
+    }
+
+    /// Marks the traffic that should bypass the tunnel and allows it. In exclude mode this is the
+    /// traffic of the processes in the split tunneling cgroup, and in include mode it is all other
+    /// traffic.
+    fn add_split_tunneling_rules(&mut self, mode: SplitTunnelMode) {
         let mangle_chains = [&self.mangle_chain_v4, &self.mangle_chain_v6];
         for chain in &mangle_chains {
             let mut rule = Rule::new(chain);
             rule.add_expr(&nft_expr!(meta cgroup));
-            rule.add_expr(&nft_expr!(cmp == split_tunnel::NET_CLS_CLASSID));
+            match mode {
+                SplitTunnelMode::Exclude => {
+                    rule.add_expr(&nft_expr!(cmp == split_tunnel::NET_CLS_CLASSID))
+                }
+                SplitTunnelMode::Include => {
+                    rule.add_expr(&nft_expr!(cmp != split_tunnel::NET_CLS_CLASSID))
+                }
+            }
             rule.add_expr(&nft_expr!(immediate data split_tunnel::MARK));
             rule.add_expr(&nft_expr!(ct mark set));
             rule.add_expr(&nft_expr!(meta mark set));
@@ -443,11 +514,16 @@ impl<'a> PolicyBatch<'a> {
         let allow_lan = match policy {
             FirewallPolicy::Connecting {
                 peer_endpoint,
+                additional_peer_endpoints,
                 pingable_hosts,
                 allow_lan,
+                ..
             } => {
                 self.add_allow_icmp_pingable_hosts(&pingable_hosts);
                 self.add_allow_endpoint_rules(peer_endpoint);
+                for endpoint in additional_peer_endpoints {
+                    self.add_allow_endpoint_rules(endpoint);
+                }
                 // Important to block DNS after allow relay rule (so the relay can operate
                 // over port 53) but before allow LAN (so DNS does not leak to the LAN)
                 self.add_drop_dns_rule();
@@ -455,22 +531,29 @@ impl<'a> PolicyBatch<'a> {
             }
             FirewallPolicy::Connected {
                 peer_endpoint,
+                additional_peer_endpoints,
                 tunnel,
                 allow_lan,
+                dns_servers,
+                forwarded_ports,
+                ..
             } => {
                 self.add_allow_endpoint_rules(peer_endpoint);
-                self.add_allow_dns_rules(tunnel, TransportProtocol::Udp)?;
-                self.add_allow_dns_rules(tunnel, TransportProtocol::Tcp)?;
+                for endpoint in additional_peer_endpoints {
+                    self.add_allow_endpoint_rules(endpoint);
+                }
+                self.add_allow_dns_rules(tunnel, dns_servers, *allow_lan, TransportProtocol::Udp)?;
+                self.add_allow_dns_rules(tunnel, dns_servers, *allow_lan, TransportProtocol::Tcp)?;
                 // Important to block DNS *before* we allow the tunnel and allow LAN. So DNS
                 // can't leak to the wrong IPs in the tunnel or on the LAN.
                 self.add_drop_dns_rule();
-                self.add_allow_tunnel_rules(tunnel)?;
+                self.add_allow_tunnel_rules(tunnel, forwarded_ports)?;
                 if *allow_lan {
                     self.add_block_cve_2019_14899(tunnel);
                 }
                 *allow_lan
             }
-            FirewallPolicy::Blocked { allow_lan } => {
+            FirewallPolicy::Blocked { allow_lan, .. } => {
                 // Important to drop DNS before allowing LAN (to stop DNS leaking to the LAN)
                 self.add_drop_dns_rule();
                 *allow_lan
@@ -480,6 +563,8 @@ impl<'a> PolicyBatch<'a> {
         if allow_lan {
             self.add_allow_lan_rules();
         }
+        // Like LAN traffic, exempted traffic is allowed after DNS has been blocked
+        self.add_exemption_rules(policy.exemptions());
 
         // Reject any remaining outgoing traffic
         let mut reject_rule = Rule::new(&self.out_chain);
@@ -540,19 +625,19 @@ impl<'a> PolicyBatch<'a> {
     fn add_allow_dns_rules(
         &mut self,
         tunnel: &tunnel::TunnelMetadata,
+        dns_servers: &[IpAddr],
+        allow_lan: bool,
         protocol: TransportProtocol,
     ) -> Result<()> {
-        // allow DNS traffic to the tunnel gateway(s)
-        self.add_allow_dns_rule(&tunnel.interface, protocol, tunnel.ipv4_gateway.into())?;
-        if let Some(ipv6_gateway) = tunnel.ipv6_gateway {
-            self.add_allow_dns_rule(&tunnel.interface, protocol, ipv6_gateway.into())?;
-        };
+        for (interface, server) in allowed_dns_destinations(tunnel, dns_servers, allow_lan) {
+            self.add_allow_dns_rule(interface, protocol, server)?;
+        }
         Ok(())
     }
 
     fn add_allow_dns_rule(
         &mut self,
-        interface: &str,
+        interface: Option<&str>,
         protocol: TransportProtocol,
         host: IpAddr,
     ) -> Result<()> {
@@ -562,7 +647,9 @@ impl<'a> PolicyBatch<'a> {
             IpAddr::V6(_) => nft_expr!(payload ipv6 daddr),
         };
 
-        check_iface(&mut allow_rule, Direction::Out, interface)?;
+        if let Some(interface) = interface {
+            check_iface(&mut allow_rule, Direction::Out, interface)?;
+        }
         check_port(&mut allow_rule, protocol, End::Dst, 53);
         check_l3proto(&mut allow_rule, host);
 
@@ -590,7 +677,11 @@ impl<'a> PolicyBatch<'a> {
         self.batch.add(&block_tcp_rule, nftnl::MsgType::Add);
     }
 
-    fn add_allow_tunnel_rules(&mut self, tunnel: &tunnel::TunnelMetadata) -> Result<()> {
+    fn add_allow_tunnel_rules(
+        &mut self,
+        tunnel: &tunnel::TunnelMetadata,
+        forwarded_ports: &[u16],
+    ) -> Result<()> {
         self.batch.add(
             &allow_interface_rule(&self.out_chain, Direction::Out, &tunnel.interface[..])?,
             nftnl::MsgType::Add,
@@ -599,6 +690,18 @@ impl<'a> PolicyBatch<'a> {
             &allow_interface_rule(&self.in_chain, Direction::In, &tunnel.interface[..])?,
             nftnl::MsgType::Add,
         );
+
+        // Forwarded ports are also accepted explicitly, so that they stay reachable if incoming
+        // tunnel traffic is ever restricted
+        for port in forwarded_ports {
+            for protocol in &[TransportProtocol::Tcp, TransportProtocol::Udp] {
+                let mut port_rule = Rule::new(&self.in_chain);
+                check_iface(&mut port_rule, Direction::In, &tunnel.interface)?;
+                check_port(&mut port_rule, *protocol, End::Dst, *port);
+                add_verdict(&mut port_rule, &Verdict::Accept);
+                self.batch.add(&port_rule, nftnl::MsgType::Add);
+            }
+        }
         Ok(())
     }
 
@@ -639,6 +742,34 @@ impl<'a> PolicyBatch<'a> {
         self.add_dhcp_server_rules();
     }
 
+    /// Allows outgoing traffic that matches the exemptions, and incoming traffic belonging to
+    /// those connections.
+    fn add_exemption_rules(&mut self, exemptions: &[FirewallExemption]) {
+        for exemption in exemptions {
+            let mut out_rule = Rule::new(&self.out_chain);
+            check_net(&mut out_rule, End::Dst, exemption.network);
+            match exemption_transport(exemption) {
+                Some((protocol, Some(ports))) => {
+                    check_port_range(&mut out_rule, protocol, End::Dst, ports)
+                }
+                Some((protocol, None)) => check_l4proto(&mut out_rule, protocol),
+                None => (),
+            }
+            add_verdict(&mut out_rule, &Verdict::Accept);
+            self.batch.add(&out_rule, nftnl::MsgType::Add);
+
+            let mut in_rule = Rule::new(&self.in_chain);
+            check_net(&mut in_rule, End::Src, exemption.network);
+            in_rule.add_expr(&nft_expr!(ct state));
+            let allowed_states =
+                (nftnl::expr::ct::States::ESTABLISHED | nftnl::expr::ct::States::RELATED).bits();
+            in_rule.add_expr(&nft_expr!(bitwise mask allowed_states, xor 0u32));
+            in_rule.add_expr(&nft_expr!(cmp != 0u32));
+            add_verdict(&mut in_rule, &Verdict::Accept);
+            self.batch.add(&in_rule, nftnl::MsgType::Add);
+        }
+    }
+
     fn add_dhcp_server_rules(&mut self) {
         use TransportProtocol::Udp;
         // Outgoing DHCPv4 response
@@ -664,6 +795,52 @@ impl<'a> PolicyBatch<'a> {
     }
 }
 
+
+/// Returns the DNS servers that requests may be sent to, together with the interface the requests
+/// have to leave on. `None` means any interface. Servers on the LAN are reached outside the tunnel,
+/// and only if LAN access is allowed.
+fn allowed_dns_destinations<'a>(
+    tunnel: &'a tunnel::TunnelMetadata,
+    dns_servers: &[IpAddr],
+    allow_lan: bool,
+) -> Vec<(Option<&'a str>, IpAddr)> {
+    dns_servers
+        .iter()
+        .filter_map(|server| {
+            if super::is_local_dns_server(*server, tunnel) {
+                if allow_lan {
+                    Some((None, *server))
+                } else {
+                    None
+                }
+            } else {
+                Some((Some(tunnel.interface.as_str()), *server))
+            }
+        })
+        .collect()
+}
+
+/// Returns the DNS servers that split tunneling must not mark, since marked packets bypass the
+/// tunnel. These are the servers reached through the tunnel, in include mode.
+fn unmarked_dns_servers(policy: &FirewallPolicy, mode: SplitTunnelMode) -> Vec<IpAddr> {
+    match (policy, mode) {
+        (
+            FirewallPolicy::Connected {
+                tunnel,
+                dns_servers,
+                allow_lan,
+                ..
+            },
+            SplitTunnelMode::Include,
+        ) => allowed_dns_destinations(tunnel, dns_servers, *allow_lan)
+            .into_iter()
+            .filter(|(interface, _)| interface.is_some())
+            .map(|(_, server)| server)
+            .collect(),
+        _ => vec![],
+    }
+}
+
 fn allow_interface_rule<'a>(
     chain: &'a Chain<'_>,
     direction: Direction,
@@ -740,6 +917,37 @@ fn check_port(rule: &mut Rule<'_>, protocol: TransportProtocol, end: End, port:
     rule.add_expr(&nft_expr!(cmp == port.to_be()));
 }
 
+fn check_port_range(rule: &mut Rule<'_>, protocol: TransportProtocol, end: End, ports: PortRange) {
+    // Must check transport layer protocol before loading transport layer payload
+    check_l4proto(rule, protocol);
+
+    rule.add_expr(&match (protocol, end) {
+        (TransportProtocol::Udp, End::Src) => nft_expr!(payload udp sport),
+        (TransportProtocol::Udp, End::Dst) => nft_expr!(payload udp dport),
+        (TransportProtocol::Tcp, End::Src) => nft_expr!(payload tcp sport),
+        (TransportProtocol::Tcp, End::Dst) => nft_expr!(payload tcp dport),
+    });
+    let (start, end) = port_range_bounds(ports);
+    rule.add_expr(&nft_expr!(cmp >= start));
+    rule.add_expr(&nft_expr!(cmp <= end));
+}
+
+/// Returns the transport protocol and destination ports that an exemption is limited to. Ports
+/// are only matched together with a protocol.
+fn exemption_transport(
+    exemption: &FirewallExemption,
+) -> Option<(TransportProtocol, Option<PortRange>)> {
+    exemption
+        .protocol
+        .map(|protocol| (protocol, exemption.ports))
+}
+
+/// Returns the bounds of `ports` in network byte order. nftables compares registers byte by
+/// byte, so the bounds must be big endian for the comparison to follow the numeric order.
+fn port_range_bounds(ports: PortRange) -> (u16, u16) {
+    (ports.start.to_be(), ports.end.to_be())
+}
+
 fn check_l3proto(rule: &mut Rule<'_>, ip: IpAddr) {
     rule.add_expr(&nft_expr!(meta nfproto));
     rule.add_expr(&nft_expr!(cmp == l3proto(ip)));
@@ -770,3 +978,126 @@ fn add_verdict(rule: &mut Rule<'_>, verdict: &expr::Verdict) {
     }
     rule.add_expr(verdict);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn tunnel_metadata() -> tunnel::TunnelMetadata {
+        tunnel::TunnelMetadata {
+            interface: "wg-mullvad".to_owned(),
+            ips: vec![IpAddr::V4(Ipv4Addr::new(10, 64, 0, 2))],
+            ipv4_gateway: Ipv4Addr::new(10, 64, 0, 1),
+            ipv6_gateway: Some(Ipv6Addr::new(0xfc00, 0xbbbb, 0xbbbb, 0xbb01, 0, 0, 0, 1)),
+        }
+    }
+
+    #[test]
+    fn test_dns_to_gateway_goes_through_tunnel() {
+        let tunnel = tunnel_metadata();
+        let gateway = IpAddr::from(tunnel.ipv4_gateway);
+        assert_eq!(
+            allowed_dns_destinations(&tunnel, &[gateway], false),
+            vec![(Some("wg-mullvad"), gateway)]
+        );
+    }
+
+    #[test]
+    fn test_dns_to_public_server_goes_through_tunnel() {
+        let tunnel = tunnel_metadata();
+        let server = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+        assert_eq!(
+            allowed_dns_destinations(&tunnel, &[server], true),
+            vec![(Some("wg-mullvad"), server)]
+        );
+    }
+
+    #[test]
+    fn test_dns_to_lan_server_requires_allow_lan() {
+        let tunnel = tunnel_metadata();
+        let server = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1));
+        assert_eq!(allowed_dns_destinations(&tunnel, &[server], false), vec![]);
+        assert_eq!(
+            allowed_dns_destinations(&tunnel, &[server], true),
+            vec![(None, server)]
+        );
+    }
+
+    fn connected_policy(dns_servers: Vec<IpAddr>) -> FirewallPolicy {
+        FirewallPolicy::Connected {
+            peer_endpoint: Endpoint::new(
+                Ipv4Addr::new(185, 65, 134, 1),
+                51820,
+                TransportProtocol::Udp,
+            ),
+            additional_peer_endpoints: vec![],
+            tunnel: tunnel_metadata(),
+            allow_lan: true,
+            dns_servers,
+            forwarded_ports: vec![],
+            exemptions: vec![],
+        }
+    }
+
+    #[test]
+    fn test_tunnel_dns_is_unmarked_in_include_mode() {
+        let gateway = IpAddr::V4(Ipv4Addr::new(10, 64, 0, 1));
+        let lan_server = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1));
+        let policy = connected_policy(vec![gateway, lan_server]);
+        assert_eq!(
+            unmarked_dns_servers(&policy, SplitTunnelMode::Include),
+            vec![gateway]
+        );
+    }
+
+    #[test]
+    fn test_dns_is_marked_in_exclude_mode() {
+        let gateway = IpAddr::V4(Ipv4Addr::new(10, 64, 0, 1));
+        let policy = connected_policy(vec![gateway]);
+        assert_eq!(
+            unmarked_dns_servers(&policy, SplitTunnelMode::Exclude),
+            vec![]
+        );
+    }
+
+    fn exemption(
+        protocol: Option<TransportProtocol>,
+        ports: Option<PortRange>,
+    ) -> FirewallExemption {
+        FirewallExemption {
+            network: "192.168.100.0/24".parse().unwrap(),
+            protocol,
+            ports,
+        }
+    }
+
+    #[test]
+    fn test_exemption_transport() {
+        let ports = PortRange {
+            start: 8000,
+            end: 8080,
+        };
+        assert_eq!(exemption_transport(&exemption(None, None)), None);
+        assert_eq!(
+            exemption_transport(&exemption(Some(TransportProtocol::Tcp), None)),
+            Some((TransportProtocol::Tcp, None))
+        );
+        assert_eq!(
+            exemption_transport(&exemption(Some(TransportProtocol::Udp), Some(ports))),
+            Some((TransportProtocol::Udp, Some(ports)))
+        );
+    }
+
+    #[test]
+    fn test_port_range_bounds_compare_bytewise() {
+        // The low byte of 255 is larger than that of 256, so little endian bounds would not match
+        let (start, end) = port_range_bounds(PortRange {
+            start: 255,
+            end: 256,
+        });
+        assert!(start.to_ne_bytes() < end.to_ne_bytes());
+        assert_eq!(start.to_ne_bytes(), [0, 255]);
+        assert_eq!(end.to_ne_bytes(), [1, 0]);
+    }
+}
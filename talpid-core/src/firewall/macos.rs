@@ -97,10 +97,15 @@ impl Firewall {
         match policy {
             FirewallPolicy::Connecting {
                 peer_endpoint,
+                additional_peer_endpoints,
                 allow_lan,
                 pingable_hosts,
+                ..
             } => {
                 let mut rules = vec![self.get_allow_relay_rule(peer_endpoint)?];
+                for endpoint in additional_peer_endpoints {
+                    rules.push(self.get_allow_relay_rule(endpoint)?);
+                }
                 rules.extend(self.get_allow_pingable_hosts(&pingable_hosts)?);
                 if allow_lan {
                     // Important to block DNS after allow relay rule (so the relay can operate
@@ -112,55 +117,18 @@ impl Firewall {
             }
             FirewallPolicy::Connected {
                 peer_endpoint,
+                additional_peer_endpoints,
                 tunnel,
                 allow_lan,
+                dns_servers,
+                ..
             } => {
-                let mut rules = vec![];
-                let allow_tcp_dns_to_relay_rule = self
-                    .create_rule_builder(FilterRuleAction::Pass)
-                    .direction(pfctl::Direction::Out)
-                    .quick(true)
-                    .interface(&tunnel.interface)
-                    .proto(pfctl::Proto::Tcp)
-                    .keep_state(pfctl::StatePolicy::Keep)
-                    .tcp_flags(Self::get_tcp_flags())
-                    .to(pfctl::Endpoint::new(tunnel.ipv4_gateway, 53))
-                    .build()?;
-                rules.push(allow_tcp_dns_to_relay_rule);
-                let allow_udp_dns_to_relay_rule = self
-                    .create_rule_builder(FilterRuleAction::Pass)
-                    .direction(pfctl::Direction::Out)
-                    .quick(true)
-                    .interface(&tunnel.interface)
-                    .proto(pfctl::Proto::Udp)
-                    .to(pfctl::Endpoint::new(tunnel.ipv4_gateway, 53))
-                    .build()?;
-                rules.push(allow_udp_dns_to_relay_rule);
-
-                if let Some(ipv6_gateway) = tunnel.ipv6_gateway {
-                    let v6_dns_rule_tcp = self
-                        .create_rule_builder(FilterRuleAction::Pass)
-                        .direction(pfctl::Direction::Out)
-                        .quick(true)
-                        .interface(&tunnel.interface)
-                        .proto(pfctl::Proto::Tcp)
-                        .keep_state(pfctl::StatePolicy::Keep)
-                        .tcp_flags(Self::get_tcp_flags())
-                        .to(pfctl::Endpoint::new(ipv6_gateway, 53))
-                        .build()?;
-                    rules.push(v6_dns_rule_tcp);
-                    let v6_dns_rule_udp = self
-                        .create_rule_builder(FilterRuleAction::Pass)
-                        .direction(pfctl::Direction::Out)
-                        .quick(true)
-                        .interface(&tunnel.interface)
-                        .proto(pfctl::Proto::Udp)
-                        .to(pfctl::Endpoint::new(ipv6_gateway, 53))
-                        .build()?;
-                    rules.push(v6_dns_rule_udp);
-                }
+                let mut rules = self.get_allow_dns_rules(&tunnel, &dns_servers, allow_lan)?;
 
                 rules.push(self.get_allow_relay_rule(peer_endpoint)?);
+                for endpoint in additional_peer_endpoints {
+                    rules.push(self.get_allow_relay_rule(endpoint)?);
+                }
 
                 // Important to block DNS *before* we allow the tunnel and allow LAN. So DNS
                 // can't leak to the wrong IPs in the tunnel or on the LAN.
@@ -174,7 +142,7 @@ impl Firewall {
 
                 Ok(rules)
             }
-            FirewallPolicy::Blocked { allow_lan } => {
+            FirewallPolicy::Blocked { allow_lan, .. } => {
                 let mut rules = Vec::new();
                 if allow_lan {
                     // Important to block DNS before allow LAN (so DNS does not leak to the LAN)
@@ -186,6 +154,45 @@ impl Firewall {
         }
     }
 
+    fn get_allow_dns_rules(
+        &self,
+        tunnel: &crate::tunnel::TunnelMetadata,
+        dns_servers: &[IpAddr],
+        allow_lan: bool,
+    ) -> Result<Vec<pfctl::FilterRule>> {
+        let mut rules = vec![];
+        for server in dns_servers {
+            // DNS servers on the LAN are reached outside the tunnel
+            let is_local = super::is_local_dns_server(*server, tunnel);
+            if is_local && !allow_lan {
+                continue;
+            }
+
+            let mut tcp_rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
+            tcp_rule_builder
+                .direction(pfctl::Direction::Out)
+                .quick(true)
+                .proto(pfctl::Proto::Tcp)
+                .keep_state(pfctl::StatePolicy::Keep)
+                .tcp_flags(Self::get_tcp_flags())
+                .to(pfctl::Endpoint::new(*server, 53));
+            let mut udp_rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
+            udp_rule_builder
+                .direction(pfctl::Direction::Out)
+                .quick(true)
+                .proto(pfctl::Proto::Udp)
+                .to(pfctl::Endpoint::new(*server, 53));
+            if !is_local {
+                tcp_rule_builder.interface(&tunnel.interface);
+                udp_rule_builder.interface(&tunnel.interface);
+            }
+
+            rules.push(tcp_rule_builder.build()?);
+            rules.push(udp_rule_builder.build()?);
+        }
+        Ok(rules)
+    }
+
     fn get_allow_relay_rule(&self, relay_endpoint: net::Endpoint) -> Result<pfctl::FilterRule> {
         let pfctl_proto = as_pfctl_proto(relay_endpoint.protocol);
 
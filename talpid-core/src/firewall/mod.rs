@@ -1,15 +1,14 @@
-#[cfg(unix)]
 use ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
-#[cfg(unix)]
 use lazy_static::lazy_static;
-use std::fmt;
-#[cfg(windows)]
-use std::net::IpAddr;
-#[cfg(unix)]
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 #[cfg(windows)]
 use std::path::PathBuf;
-use talpid_types::net::Endpoint;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+#[cfg(target_os = "linux")]
+use talpid_types::net::SplitTunnelMode;
+use talpid_types::net::{Endpoint, FirewallExemption};
 
 
 #[cfg(target_os = "macos")]
@@ -30,7 +29,6 @@ mod imp;
 
 pub use self::imp::Error;
 
-#[cfg(unix)]
 lazy_static! {
     /// When "allow local network" is enabled the app will allow traffic to and from these networks.
     pub(crate) static ref ALLOWED_LAN_NETS: [IpNetwork; 6] = [
@@ -41,6 +39,10 @@ lazy_static! {
         IpNetwork::V6(Ipv6Network::new(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 10).unwrap()),
         IpNetwork::V6(Ipv6Network::new(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7).unwrap()),
     ];
+}
+
+#[cfg(unix)]
+lazy_static! {
     /// When "allow local network" is enabled the app will allow traffic to these networks.
     pub(crate) static ref ALLOWED_LAN_MULTICAST_NETS: [IpNetwork; 8] = [
         // Local network broadcast. Not routable
@@ -68,6 +70,18 @@ lazy_static! {
     ];
     static ref ROUTER_SOLICITATION_OUT_DST_ADDR: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 2);
 }
+
+/// Returns true if DNS requests to `server` are sent on the local network rather than through the
+/// tunnel. The tunnel gateways are on private networks, but are reached through the tunnel.
+pub(crate) fn is_local_dns_server(server: IpAddr, tunnel: &crate::tunnel::TunnelMetadata) -> bool {
+    let is_gateway = server == IpAddr::from(tunnel.ipv4_gateway)
+        || tunnel
+            .ipv6_gateway
+            .map(|gateway| server == IpAddr::from(gateway))
+            .unwrap_or(false);
+    !is_gateway && ALLOWED_LAN_NETS.iter().any(|net| net.contains(server))
+}
+
 #[cfg(all(unix, not(target_os = "android")))]
 const DHCPV4_SERVER_PORT: u16 = 67;
 #[cfg(all(unix, not(target_os = "android")))]
@@ -90,10 +104,14 @@ pub enum FirewallPolicy {
     Connecting {
         /// The peer endpoint that should be allowed.
         peer_endpoint: Endpoint,
+        /// Endpoints of additional WireGuard peers that should be allowed.
+        additional_peer_endpoints: Vec<Endpoint>,
         /// Hosts that should be pingable whilst connecting.
         pingable_hosts: Vec<IpAddr>,
         /// Flag setting if communication with LAN networks should be possible.
         allow_lan: bool,
+        /// Traffic that should be allowed regardless of the policy.
+        exemptions: Vec<FirewallExemption>,
         /// A process that is allowed to send packets to the relay.
         #[cfg(windows)]
         relay_client: PathBuf,
@@ -103,10 +121,19 @@ pub enum FirewallPolicy {
     Connected {
         /// The peer endpoint that should be allowed.
         peer_endpoint: Endpoint,
+        /// Endpoints of additional WireGuard peers that should be allowed.
+        additional_peer_endpoints: Vec<Endpoint>,
         /// Metadata about the tunnel and tunnel interface.
         tunnel: crate::tunnel::TunnelMetadata,
         /// Flag setting if communication with LAN networks should be possible.
         allow_lan: bool,
+        /// DNS servers that may be queried. Servers on the local network are only allowed if
+        /// `allow_lan` is set.
+        dns_servers: Vec<IpAddr>,
+        /// Ports that may be connected to over the tunnel interface.
+        forwarded_ports: Vec<u16>,
+        /// Traffic that should be allowed regardless of the policy.
+        exemptions: Vec<FirewallExemption>,
         /// A process that is allowed to send packets to the relay.
         #[cfg(windows)]
         relay_client: PathBuf,
@@ -116,9 +143,22 @@ pub enum FirewallPolicy {
     Blocked {
         /// Flag setting if communication with LAN networks should be possible.
         allow_lan: bool,
+        /// Traffic that should be allowed regardless of the policy.
+        exemptions: Vec<FirewallExemption>,
     },
 }
 
+impl FirewallPolicy {
+    /// Returns the traffic that is allowed regardless of the policy.
+    pub fn exemptions(&self) -> &[FirewallExemption] {
+        match self {
+            FirewallPolicy::Connecting { exemptions, .. }
+            | FirewallPolicy::Connected { exemptions, .. }
+            | FirewallPolicy::Blocked { exemptions, .. } => exemptions,
+        }
+    }
+}
+
 impl fmt::Display for FirewallPolicy {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -142,10 +182,13 @@ impl fmt::Display for FirewallPolicy {
                 peer_endpoint,
                 tunnel,
                 allow_lan,
+                dns_servers,
+                forwarded_ports,
                 ..
             } => write!(
                 f,
-                "Connected to {} over \"{}\" (ip: {}, v4 gw: {}, v6 gw: {:?}), {} LAN",
+                "Connected to {} over \"{}\" (ip: {}, v4 gw: {}, v6 gw: {:?}, dns: {}, forwarded \
+                 ports: {}), {} LAN",
                 peer_endpoint,
                 tunnel.interface,
                 tunnel
@@ -156,14 +199,36 @@ impl fmt::Display for FirewallPolicy {
                     .join(","),
                 tunnel.ipv4_gateway,
                 tunnel.ipv6_gateway,
+                dns_servers
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<String>>()
+                    .join(","),
+                forwarded_ports
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<String>>()
+                    .join(","),
                 if *allow_lan { "Allowing" } else { "Blocking" }
             ),
-            FirewallPolicy::Blocked { allow_lan } => write!(
+            FirewallPolicy::Blocked { allow_lan, .. } => write!(
                 f,
                 "Blocked, {} LAN",
                 if *allow_lan { "Allowing" } else { "Blocking" }
             ),
+        }?;
+        if !self.exemptions().is_empty() {
+            write!(
+                f,
+                ", exempting {}",
+                self.exemptions()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )?;
         }
+        Ok(())
     }
 }
 
@@ -179,6 +244,9 @@ pub struct FirewallArguments {
     pub initialize_blocked: bool,
     /// This argument is required for the blocked state to configure the firewall correctly.
     pub allow_lan: Option<bool>,
+    /// Determines which traffic is routed outside the tunnel by the split tunneling rules.
+    #[cfg(target_os = "linux")]
+    pub split_tunnel_mode: SplitTunnelMode,
 }
 
 impl Firewall {
@@ -196,6 +264,19 @@ impl Firewall {
         self.inner.apply_policy(policy)
     }
 
+    /// Sets which traffic the split tunneling rules let bypass the tunnel. Takes effect the next
+    /// time a policy is applied.
+    #[cfg(target_os = "linux")]
+    pub fn set_split_tunnel_mode(&mut self, mode: SplitTunnelMode) {
+        self.inner.set_split_tunnel_mode(mode)
+    }
+
+    /// Returns which traffic the split tunneling rules let bypass the tunnel.
+    #[cfg(target_os = "linux")]
+    pub fn split_tunnel_mode(&self) -> SplitTunnelMode {
+        self.inner.split_tunnel_mode()
+    }
+
     /// Resets/removes any currently enforced `FirewallPolicy`. Returns the system to the same state
     /// it had before any policy was applied through this `Firewall` instance.
     pub fn reset_policy(&mut self) -> Result<(), Error> {
@@ -219,3 +300,61 @@ trait FirewallT: Sized {
     /// modifying the system.
     fn reset_policy(&mut self) -> Result<(), Self::Error>;
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tunnel::TunnelMetadata;
+
+    fn tunnel_metadata() -> TunnelMetadata {
+        TunnelMetadata {
+            interface: "wg-mullvad".to_owned(),
+            ips: vec![IpAddr::V4(Ipv4Addr::new(10, 64, 0, 2))],
+            ipv4_gateway: Ipv4Addr::new(10, 64, 0, 1),
+            ipv6_gateway: Some(Ipv6Addr::new(0xfc00, 0xbbbb, 0xbbbb, 0xbb01, 0, 0, 0, 1)),
+        }
+    }
+
+    #[test]
+    fn test_gateways_are_not_local_dns_servers() {
+        let tunnel = tunnel_metadata();
+        assert!(!is_local_dns_server(
+            IpAddr::from(tunnel.ipv4_gateway),
+            &tunnel
+        ));
+        assert!(!is_local_dns_server(
+            IpAddr::from(tunnel.ipv6_gateway.unwrap()),
+            &tunnel
+        ));
+    }
+
+    #[test]
+    fn test_private_dns_servers_are_local() {
+        let tunnel = tunnel_metadata();
+        assert!(is_local_dns_server(
+            IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)),
+            &tunnel
+        ));
+        assert!(is_local_dns_server(
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            &tunnel
+        ));
+        assert!(is_local_dns_server(
+            IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+            &tunnel
+        ));
+    }
+
+    #[test]
+    fn test_public_dns_servers_are_not_local() {
+        let tunnel = tunnel_metadata();
+        assert!(!is_local_dns_server(
+            IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+            &tunnel
+        ));
+        assert!(!is_local_dns_server(
+            IpAddr::V6(Ipv6Addr::new(0x2606, 0x4700, 0x4700, 0, 0, 0, 0, 0x1111)),
+            &tunnel
+        ));
+    }
+}
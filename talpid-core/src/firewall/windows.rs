@@ -5,7 +5,7 @@ use std::{net::IpAddr, path::Path, ptr};
 use self::winfw::*;
 use super::{FirewallArguments, FirewallPolicy, FirewallT};
 use crate::winnet;
-use log::{debug, error, trace};
+use log::{debug, error, trace, warn};
 use std::os::windows::ffi::OsStrExt;
 use talpid_types::{net::Endpoint, tunnel::FirewallPolicyError};
 use widestring::WideCString;
@@ -81,10 +81,16 @@ impl FirewallT for Firewall {
         match policy {
             FirewallPolicy::Connecting {
                 peer_endpoint,
+                additional_peer_endpoints,
                 pingable_hosts,
                 allow_lan,
                 relay_client,
+                ..
             } => {
+                // winfw only permits traffic to a single relay
+                if !additional_peer_endpoints.is_empty() {
+                    warn!("Additional WireGuard peers are blocked by the firewall on Windows");
+                }
                 let cfg = &WinFwSettings::new(allow_lan);
                 // TODO: Determine interface alias at runtime
                 self.set_connecting_state(
@@ -97,14 +103,27 @@ impl FirewallT for Firewall {
             }
             FirewallPolicy::Connected {
                 peer_endpoint,
+                additional_peer_endpoints,
                 tunnel,
                 allow_lan,
+                dns_servers,
                 relay_client,
+                ..
             } => {
+                if !additional_peer_endpoints.is_empty() {
+                    warn!("Additional WireGuard peers are blocked by the firewall on Windows");
+                }
+                // winfw only permits DNS requests to the tunnel gateways
+                let gateways = std::iter::once(IpAddr::from(tunnel.ipv4_gateway))
+                    .chain(tunnel.ipv6_gateway.map(IpAddr::from))
+                    .collect::<Vec<_>>();
+                if dns_servers.iter().any(|server| !gateways.contains(server)) {
+                    warn!("Custom DNS servers are blocked by the firewall on Windows");
+                }
                 let cfg = &WinFwSettings::new(allow_lan);
                 self.set_connected_state(&peer_endpoint, &cfg, &tunnel, &relay_client)
             }
-            FirewallPolicy::Blocked { allow_lan } => {
+            FirewallPolicy::Blocked { allow_lan, .. } => {
                 let cfg = &WinFwSettings::new(allow_lan);
                 self.set_blocked_state(&cfg)
             }
@@ -55,9 +55,13 @@ mod proxy;
 #[cfg(not(target_os = "android"))]
 mod mktemp;
 
+#[cfg(not(target_os = "android"))]
+/// Local SOCKS5 server that forwards connections through the tunnel.
+pub mod socks_proxy;
+
 /// Misc utilities for the Linux platform.
 #[cfg(target_os = "linux")]
 mod linux;
 
 /// A pair of functions to monitor and establish connectivity with ICMP
-mod ping_monitor;
+pub mod ping_monitor;
@@ -1,21 +1,48 @@
-use crate::tunnel_state_machine::TunnelCommand;
+use crate::{mpsc::Sender, tunnel_state_machine::TunnelCommand};
+use dbus::{stdintf::*, BusType, Message};
 use futures::{StreamExt, TryStreamExt};
 use futures01::sync::mpsc::UnboundedSender;
 use netlink_packet_route::{
-    constants::{ARPHRD_LOOPBACK, ARPHRD_NONE, IFF_LOWER_UP, IFF_UP},
+    constants::{ARPHRD_LOOPBACK, ARPHRD_NONE, IFF_LOWER_UP, IFF_UP, RT_TABLE_MAIN},
+    route::nlas::Nla as RouteNla,
     rtnl::link::nlas::{Info as LinkInfo, InfoKind, Nla as LinkNla},
-    LinkMessage,
+    LinkMessage, NetlinkMessage, NetlinkPayload, RtnlMessage,
 };
 use netlink_sys::SocketAddr;
 use rtnetlink::{
-    constants::{RTMGRP_IPV4_IFADDR, RTMGRP_IPV6_IFADDR, RTMGRP_LINK, RTMGRP_NOTIFY},
-    Handle,
+    constants::{
+        RTMGRP_IPV4_IFADDR, RTMGRP_IPV4_ROUTE, RTMGRP_IPV6_IFADDR, RTMGRP_LINK, RTMGRP_NOTIFY,
+    },
+    Handle, IpVersion,
 };
-use std::{collections::BTreeSet, io, sync::Weak};
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    net::{IpAddr, Ipv4Addr},
+    sync::{mpsc as sync_mpsc, Weak},
+    thread,
+    time::Duration,
+};
+use talpid_types::{net::NetworkIdentity, ErrorExt};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 const EVENT_LOOP_THREAD_NAME: &str = "mullvad-offline-detection-event-loop";
+const NETWORK_IDENTITY_THREAD_NAME: &str = "mullvad-network-identity";
+
+/// Netlink messages tend to arrive in bursts when a network changes. The network identity is only
+/// looked up once no new link or route message has arrived for this long.
+const NETWORK_IDENTITY_DEBOUNCE: Duration = Duration::from_millis(500);
+
+const ARP_TABLE_PATH: &str = "/proc/net/arp";
+
+const NM_BUS: &str = "org.freedesktop.NetworkManager";
+const NM_OBJECT_PATH: &str = "/org/freedesktop/NetworkManager";
+const NM_DEVICE: &str = "org.freedesktop.NetworkManager.Device";
+const NM_DEVICE_WIRELESS: &str = "org.freedesktop.NetworkManager.Device.Wireless";
+const NM_ACCESS_POINT: &str = "org.freedesktop.NetworkManager.AccessPoint";
+const NM_DEVICE_TYPE_WIFI: u32 = 2;
+const RPC_TIMEOUT_MS: i32 = 1000;
 
 #[derive(err_derive::Error, Debug)]
 #[error(no_from)]
@@ -26,6 +53,21 @@ pub enum Error {
     #[error(display = "Failed to get list of IP addresses")]
     GetAddressesError(#[error(source)] failure::Compat<rtnetlink::Error>),
 
+    #[error(display = "Failed to get list of routes")]
+    GetRoutesError(#[error(source)] failure::Compat<rtnetlink::Error>),
+
+    #[error(display = "Failed to read the ARP table")]
+    ReadArpTableError(#[error(source)] io::Error),
+
+    #[error(display = "Failed to query NetworkManager for the Wi-Fi network")]
+    NetworkManagerError(#[error(source)] dbus::Error),
+
+    #[error(display = "Failed to construct DBus method call message")]
+    DbusMethodCall(String),
+
+    #[error(display = "Failed to match the returned D-Bus object with expected type")]
+    MatchDBusTypeError(#[error(source)] dbus::arg::TypeMismatchError),
+
     #[error(display = "Failed to connect to netlink socket")]
     NetlinkConnectionError(#[error(source)] io::Error),
 
@@ -43,6 +85,9 @@ pub enum Error {
 
     #[error(display = "Failed to initialize event loop")]
     EventLoopError(#[error(source)] io::Error),
+
+    #[error(display = "Failed to spawn the network identity thread")]
+    NetworkIdentityThreadError(#[error(source)] io::Error),
 }
 
 pub struct MonitorHandle {
@@ -65,7 +110,10 @@ impl MonitorHandle {
     }
 }
 
-pub fn spawn_monitor(sender: Weak<UnboundedSender<TunnelCommand>>) -> Result<MonitorHandle> {
+pub fn spawn_monitor(
+    sender: Weak<UnboundedSender<TunnelCommand>>,
+    identity_listener: impl Sender<NetworkIdentity> + Send + 'static,
+) -> Result<MonitorHandle> {
     let mut runtime = tokio02::runtime::Builder::new()
         .threaded_scheduler()
         .core_threads(1)
@@ -78,7 +126,12 @@ pub fn spawn_monitor(sender: Weak<UnboundedSender<TunnelCommand>>) -> Result<Mon
         let (mut connection, handle, messages) =
             rtnetlink::new_connection().map_err(Error::NetlinkConnectionError)?;
 
-        let mgroup_flags = RTMGRP_IPV4_IFADDR | RTMGRP_IPV6_IFADDR | RTMGRP_LINK | RTMGRP_NOTIFY;
+        // Link and route changes are needed to notice when the default gateway changes
+        let mgroup_flags = RTMGRP_IPV4_IFADDR
+            | RTMGRP_IPV6_IFADDR
+            | RTMGRP_IPV4_ROUTE
+            | RTMGRP_LINK
+            | RTMGRP_NOTIFY;
         let addr = SocketAddr::new(0, mgroup_flags);
 
         connection
@@ -93,31 +146,92 @@ pub fn spawn_monitor(sender: Weak<UnboundedSender<TunnelCommand>>) -> Result<Mon
     let _ = runtime.spawn(connection);
     let mut is_offline = runtime.block_on(check_offline_state(&handle))?;
 
+    let identity_tx = spawn_network_identity_thread(identity_listener)?;
+    let _ = identity_tx.send(runtime.block_on(get_default_interface(&handle)));
+
     let monitor_handle = MonitorHandle {
         handle: handle.clone(),
         runtime,
     };
 
-
     let _ = monitor_handle.runtime.spawn(async move {
-        while let Some(_new_message) = messages.next().await {
-            match sender.upgrade() {
-                Some(sender) => {
-                    let new_offline_state = check_offline_state(&handle).await.unwrap_or(false);
-                    if new_offline_state != is_offline {
-                        is_offline = new_offline_state;
-                        let _ = sender.unbounded_send(TunnelCommand::IsOffline(is_offline));
+        while let Some((message, _)) = messages.next().await {
+            let network_changed = is_link_or_route_message(&message);
+            if network_changed {
+                // Wait for the burst of messages to settle before looking up the network again
+                loop {
+                    match tokio02::time::timeout(NETWORK_IDENTITY_DEBOUNCE, messages.next()).await {
+                        Ok(Some(_)) => (),
+                        Ok(None) => return,
+                        Err(_) => break,
                     }
                 }
+            }
+
+            let sender = match sender.upgrade() {
+                Some(sender) => sender,
                 None => return,
+            };
+            let new_offline_state = check_offline_state(&handle).await.unwrap_or(false);
+            if new_offline_state != is_offline {
+                is_offline = new_offline_state;
+                let _ = sender.unbounded_send(TunnelCommand::IsOffline(is_offline));
+            }
+
+            if network_changed {
+                // The gateway MAC and SSID can change without the default route changing, so the
+                // identity thread resolves them again even if the interface is the same.
+                let default_interface = get_default_interface(&handle).await;
+                if identity_tx.send(default_interface).is_err() {
+                    return;
+                }
             }
         }
     });
 
-
     Ok(monitor_handle)
 }
 
+fn is_link_or_route_message(message: &NetlinkMessage<RtnlMessage>) -> bool {
+    match message.payload {
+        NetlinkPayload::InnerMessage(RtnlMessage::NewLink(_))
+        | NetlinkPayload::InnerMessage(RtnlMessage::DelLink(_))
+        | NetlinkPayload::InnerMessage(RtnlMessage::NewRoute(_))
+        | NetlinkPayload::InnerMessage(RtnlMessage::DelRoute(_)) => true,
+        _ => false,
+    }
+}
+
+/// Spawns the thread that resolves the parts of the network identity that require blocking
+/// calls, i.e. reading the ARP table and querying NetworkManager. The thread exits once the
+/// returned sender is dropped.
+fn spawn_network_identity_thread(
+    identity_listener: impl Sender<NetworkIdentity> + Send + 'static,
+) -> Result<sync_mpsc::Sender<Option<DefaultInterface>>> {
+    let (tx, rx) = sync_mpsc::channel::<Option<DefaultInterface>>();
+    thread::Builder::new()
+        .name(NETWORK_IDENTITY_THREAD_NAME.to_string())
+        .spawn(move || {
+            let mut dbus_connection = None;
+            let mut network_identity = None;
+            for default_interface in rx {
+                let new_network_identity =
+                    get_network_identity(default_interface, &mut dbus_connection);
+                if network_identity.as_ref() != Some(&new_network_identity) {
+                    if identity_listener
+                        .send(new_network_identity.clone())
+                        .is_err()
+                    {
+                        return;
+                    }
+                    network_identity = Some(new_network_identity);
+                }
+            }
+        })
+        .map_err(Error::NetworkIdentityThreadError)?;
+    Ok(tx)
+}
+
 async fn check_offline_state(handle: &Handle) -> Result<bool> {
     let mut link_request = handle.link().get().execute();
     let mut links = BTreeSet::new();
@@ -151,6 +265,222 @@ async fn check_offline_state(handle: &Handle) -> Result<bool> {
     Ok(true)
 }
 
+struct DefaultInterface {
+    name: String,
+    gateway: Option<Ipv4Addr>,
+}
+
+/// Returns the name and gateway of the interface that the IPv4 default route goes through.
+async fn get_default_interface(handle: &Handle) -> Option<DefaultInterface> {
+    let default_route = match get_default_route(handle).await {
+        Ok(Some(default_route)) => default_route,
+        Ok(None) => return None,
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to find the default route")
+            );
+            return None;
+        }
+    };
+
+    match get_interface_name(handle, default_route.interface_index).await {
+        Ok(name) => name.map(|name| DefaultInterface {
+            name,
+            gateway: default_route.gateway,
+        }),
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to find the default route interface")
+            );
+            None
+        }
+    }
+}
+
+fn get_network_identity(
+    default_interface: Option<DefaultInterface>,
+    dbus_connection: &mut Option<dbus::Connection>,
+) -> NetworkIdentity {
+    let default_interface = match default_interface {
+        Some(default_interface) => default_interface,
+        None => return NetworkIdentity::default(),
+    };
+
+    let gateway_mac = default_interface.gateway.and_then(|gateway| {
+        get_gateway_mac(gateway, &default_interface.name).unwrap_or_else(|error| {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to find the default gateway MAC address")
+            );
+            None
+        })
+    });
+    let ssid = get_wifi_ssid(dbus_connection, &default_interface.name).unwrap_or_else(|error| {
+        log::debug!(
+            "{}",
+            error.display_chain_with_msg("Unable to get the Wi-Fi SSID")
+        );
+        None
+    });
+
+    NetworkIdentity {
+        interface: Some(default_interface.name),
+        gateway_mac,
+        ssid,
+    }
+}
+
+struct DefaultRoute {
+    interface_index: u32,
+    gateway: Option<Ipv4Addr>,
+}
+
+/// Returns the IPv4 default route in the main routing table with the lowest metric.
+async fn get_default_route(handle: &Handle) -> Result<Option<DefaultRoute>> {
+    let mut route_request = handle.route().get(IpVersion::V4).execute();
+    let mut best_route: Option<(u32, DefaultRoute)> = None;
+
+    while let Some(route) = route_request
+        .try_next()
+        .await
+        .map_err(failure::Fail::compat)
+        .map_err(Error::GetRoutesError)?
+    {
+        if route.header.table != RT_TABLE_MAIN || route.header.destination_prefix_length != 0 {
+            continue;
+        }
+
+        let mut interface_index = None;
+        let mut gateway = None;
+        let mut metric = 0;
+        for nla in route.nlas.iter() {
+            match nla {
+                RouteNla::Oif(index) => interface_index = Some(*index),
+                RouteNla::Gateway(address) if address.len() == 4 => {
+                    gateway = Some(Ipv4Addr::new(
+                        address[0], address[1], address[2], address[3],
+                    ));
+                }
+                RouteNla::Priority(priority) => metric = *priority,
+                _ => (),
+            }
+        }
+
+        if let Some(interface_index) = interface_index {
+            if best_route
+                .as_ref()
+                .map(|(best_metric, _)| metric < *best_metric)
+                .unwrap_or(true)
+            {
+                best_route = Some((
+                    metric,
+                    DefaultRoute {
+                        interface_index,
+                        gateway,
+                    },
+                ));
+            }
+        }
+    }
+
+    Ok(best_route.map(|(_, route)| route))
+}
+
+async fn get_interface_name(handle: &Handle, index: u32) -> Result<Option<String>> {
+    let mut link_request = handle.link().get().execute();
+    while let Some(link) = link_request
+        .try_next()
+        .await
+        .map_err(failure::Fail::compat)
+        .map_err(Error::GetLinksError)?
+    {
+        if link.header.index != index {
+            continue;
+        }
+        for nla in link.nlas {
+            if let LinkNla::IfName(name) = nla {
+                return Ok(Some(name));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Looks up the MAC address of the gateway in the ARP table. Returns `None` if it has not been
+/// resolved yet.
+fn get_gateway_mac(gateway: Ipv4Addr, interface: &str) -> Result<Option<String>> {
+    let arp_table = fs::read_to_string(ARP_TABLE_PATH).map_err(Error::ReadArpTableError)?;
+    Ok(parse_arp_table(&arp_table, IpAddr::V4(gateway), interface))
+}
+
+fn parse_arp_table(arp_table: &str, address: IpAddr, interface: &str) -> Option<String> {
+    // Columns: IP address, HW type, Flags, HW address, Mask, Device
+    arp_table.lines().skip(1).find_map(|line| {
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        match columns.as_slice() {
+            [entry_address, _, flags, mac, _, device]
+                if entry_address.parse::<IpAddr>().ok() == Some(address)
+                    && *device == interface
+                    && *flags != "0x0" =>
+            {
+                Some(mac.to_lowercase())
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Returns the SSID of the access point that `interface` is connected to, if NetworkManager
+/// manages the interface and it is a Wi-Fi device.
+/// The D-Bus connection is opened on first use and kept for later lookups.
+fn get_wifi_ssid(
+    dbus_connection: &mut Option<dbus::Connection>,
+    interface: &str,
+) -> Result<Option<String>> {
+    if !dbus_connection
+        .as_ref()
+        .map(|connection| connection.is_connected())
+        .unwrap_or(false)
+    {
+        *dbus_connection = Some(
+            dbus::Connection::get_private(BusType::System).map_err(Error::NetworkManagerError)?,
+        );
+    }
+    let connection = dbus_connection.as_ref().unwrap();
+
+    let get_device = Message::new_method_call(NM_BUS, NM_OBJECT_PATH, NM_BUS, "GetDeviceByIpIface")
+        .map_err(Error::DbusMethodCall)?
+        .append1(interface);
+    let device: dbus::Path<'static> = connection
+        .send_with_reply_and_block(get_device, RPC_TIMEOUT_MS)
+        .map_err(Error::NetworkManagerError)?
+        .read1()
+        .map_err(Error::MatchDBusTypeError)?;
+
+    let device_type: u32 = connection
+        .with_path(NM_BUS, &device, RPC_TIMEOUT_MS)
+        .get(NM_DEVICE, "DeviceType")
+        .map_err(Error::NetworkManagerError)?;
+    if device_type != NM_DEVICE_TYPE_WIFI {
+        return Ok(None);
+    }
+
+    let access_point: dbus::Path<'static> = connection
+        .with_path(NM_BUS, &device, RPC_TIMEOUT_MS)
+        .get(NM_DEVICE_WIRELESS, "ActiveAccessPoint")
+        .map_err(Error::NetworkManagerError)?;
+    if &*access_point == "/" {
+        return Ok(None);
+    }
+
+    let ssid: Vec<u8> = connection
+        .with_path(NM_BUS, &access_point, RPC_TIMEOUT_MS)
+        .get(NM_ACCESS_POINT, "Ssid")
+        .map_err(Error::NetworkManagerError)?;
+    Ok(Some(String::from_utf8_lossy(&ssid).into_owned()))
+}
 
 // TODO: Improve by allowing bridge links to provide connectivity, will require route checking.
 fn link_provides_connectivity(link: &LinkMessage) -> bool {
@@ -178,3 +508,26 @@ fn is_virtual_interface(link: &LinkMessage) -> bool {
     }
     false
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_arp_table() {
+        let arp_table = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.1      0x1         0x2         AA:BB:CC:DD:EE:FF     *        wlan0
+192.168.1.2      0x1         0x0         00:00:00:00:00:00     *        wlan0
+10.0.0.1         0x1         0x2         11:22:33:44:55:66     *        eth0
+";
+        let gateway: IpAddr = "192.168.1.1".parse().unwrap();
+        assert_eq!(
+            parse_arp_table(arp_table, gateway, "wlan0"),
+            Some("aa:bb:cc:dd:ee:ff".to_string())
+        );
+        assert_eq!(parse_arp_table(arp_table, gateway, "eth0"), None);
+        let incomplete: IpAddr = "192.168.1.2".parse().unwrap();
+        assert_eq!(parse_arp_table(arp_table, incomplete, "wlan0"), None);
+    }
+}
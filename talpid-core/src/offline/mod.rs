@@ -1,8 +1,12 @@
+#[cfg(target_os = "linux")]
+use crate::mpsc::Sender;
 use crate::tunnel_state_machine::TunnelCommand;
 use futures01::sync::mpsc::UnboundedSender;
 use std::sync::Weak;
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
+#[cfg(target_os = "linux")]
+use talpid_types::net::NetworkIdentity;
 
 #[cfg(target_os = "macos")]
 #[path = "macos.rs"]
@@ -30,12 +34,18 @@ impl MonitorHandle {
     }
 }
 
+/// Spawns a monitor that sends `TunnelCommand::IsOffline` when the connectivity changes. On Linux
+/// it also sends the identity of the current network to `identity_listener`, once when spawned
+/// and then every time it changes.
 pub fn spawn_monitor(
     sender: Weak<UnboundedSender<TunnelCommand>>,
+    #[cfg(target_os = "linux")] identity_listener: impl Sender<NetworkIdentity> + Send + 'static,
     #[cfg(target_os = "android")] android_context: AndroidContext,
 ) -> Result<MonitorHandle, Error> {
     Ok(MonitorHandle(imp::spawn_monitor(
         sender,
+        #[cfg(target_os = "linux")]
+        identity_listener,
         #[cfg(target_os = "android")]
         android_context,
     )?))
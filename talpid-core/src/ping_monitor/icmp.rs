@@ -0,0 +1,184 @@
+//! Measures the round-trip time to a host with a single ICMP echo request sent over a raw
+//! socket, without spawning any processes.
+
+use pnet_packet::{
+    icmp::{
+        self,
+        echo_reply::EchoReplyPacket,
+        echo_request::{EchoRequestPacket, MutableEchoRequestPacket},
+        IcmpPacket, IcmpTypes,
+    },
+    ipv4::Ipv4Packet,
+    Packet,
+};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+const ICMP_HEADER_LENGTH: usize = 8;
+const ICMP_PAYLOAD_LENGTH: usize = 32;
+
+/// Latency probe errors
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to open raw socket
+    #[error(display = "Failed to open raw socket")]
+    OpenError(#[error(source)] io::Error),
+
+    /// Failed to read from raw socket
+    #[error(display = "Failed to read from socket")]
+    ReadError(#[error(source)] io::Error),
+
+    /// Failed to write to raw socket
+    #[error(display = "Failed to write to socket")]
+    WriteError(#[error(source)] io::Error),
+
+    /// No reply was received in time
+    #[error(display = "Timed out")]
+    TimeoutError,
+}
+
+/// Sends a single ICMP echo request to `addr` and returns the time it took to receive a reply.
+/// Fails with a timeout error if no reply was received within `timeout`.
+pub fn measure_latency(addr: Ipv4Addr, timeout: Duration) -> Result<Duration, Error> {
+    let sock = Socket::new(Domain::ipv4(), Type::raw(), Some(Protocol::icmpv4()))
+        .map_err(Error::OpenError)?;
+    // Raw sockets have to be bound before anything can be received on them on Windows
+    sock.bind(&SocketAddr::new(IpAddr::from(Ipv4Addr::UNSPECIFIED), 0).into())
+        .map_err(Error::OpenError)?;
+
+    let identifier = rand::random();
+    let sequence_number = rand::random();
+    let request = echo_request(identifier, sequence_number);
+
+    let start = Instant::now();
+    sock.send_to(
+        request.packet(),
+        &SocketAddr::new(IpAddr::from(addr), 0).into(),
+    )
+    .map_err(Error::WriteError)?;
+    wait_for_reply(&sock, addr, identifier, sequence_number, start + timeout)?;
+    Ok(start.elapsed())
+}
+
+fn echo_request(identifier: u16, sequence_number: u16) -> EchoRequestPacket<'static> {
+    let mut packet =
+        MutableEchoRequestPacket::owned(vec![0u8; ICMP_HEADER_LENGTH + ICMP_PAYLOAD_LENGTH])
+            .expect("Failed to construct an empty packet");
+    packet.set_icmp_type(IcmpTypes::EchoRequest);
+    packet.set_identifier(identifier);
+    packet.set_sequence_number(sequence_number);
+    packet.set_checksum(icmp::checksum(&IcmpPacket::new(&packet.packet()).unwrap()));
+    packet.consume_to_immutable()
+}
+
+/// Blocks until the echo reply from `addr` matching the request is received or `deadline` is
+/// reached.
+fn wait_for_reply(
+    sock: &Socket,
+    addr: Ipv4Addr,
+    identifier: u16,
+    sequence_number: u16,
+    deadline: Instant,
+) -> Result<(), Error> {
+    let mut buffer = [0u8; 1024];
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(Error::TimeoutError);
+        }
+        sock.set_read_timeout(Some(deadline - now))
+            .map_err(Error::ReadError)?;
+
+        let len = match sock.recv(&mut buffer) {
+            Ok(len) => len,
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                return Err(Error::TimeoutError);
+            }
+            Err(err) => return Err(Error::ReadError(err)),
+        };
+
+        if is_matching_reply(&buffer[..len], addr, identifier, sequence_number) {
+            return Ok(());
+        }
+    }
+}
+
+/// Returns whether `packet`, an IPv4 packet as received on a raw socket, is the echo reply from
+/// `addr` with the given identifier and sequence number.
+fn is_matching_reply(packet: &[u8], addr: Ipv4Addr, identifier: u16, sequence_number: u16) -> bool {
+    let ip_packet = match Ipv4Packet::new(packet) {
+        Some(packet) => packet,
+        None => return false,
+    };
+    if ip_packet.get_source() != addr {
+        return false;
+    }
+    let header_length = usize::from(ip_packet.get_header_length()) * 4;
+    let reply = match packet.get(header_length..).and_then(EchoReplyPacket::new) {
+        Some(reply) => reply,
+        None => return false,
+    };
+    reply.get_icmp_type() == IcmpTypes::EchoReply
+        && reply.get_identifier() == identifier
+        && reply.get_sequence_number() == sequence_number
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pnet_packet::{
+        icmp::echo_reply::MutableEchoReplyPacket, ip::IpNextHeaderProtocols,
+        ipv4::MutableIpv4Packet,
+    };
+
+    const IPV4_HEADER_LENGTH: usize = 20;
+
+    fn reply_packet(source: Ipv4Addr, identifier: u16, sequence_number: u16) -> Vec<u8> {
+        let mut buffer = vec![0u8; IPV4_HEADER_LENGTH + ICMP_HEADER_LENGTH];
+        {
+            let mut ip_packet = MutableIpv4Packet::new(&mut buffer).unwrap();
+            ip_packet.set_version(4);
+            ip_packet.set_header_length((IPV4_HEADER_LENGTH / 4) as u8);
+            ip_packet.set_total_length((IPV4_HEADER_LENGTH + ICMP_HEADER_LENGTH) as u16);
+            ip_packet.set_next_level_protocol(IpNextHeaderProtocols::Icmp);
+            ip_packet.set_source(source);
+        }
+        let mut reply = MutableEchoReplyPacket::new(&mut buffer[IPV4_HEADER_LENGTH..]).unwrap();
+        reply.set_icmp_type(IcmpTypes::EchoReply);
+        reply.set_identifier(identifier);
+        reply.set_sequence_number(sequence_number);
+        buffer
+    }
+
+    #[test]
+    fn test_echo_request() {
+        let request = echo_request(1234, 5);
+        assert_eq!(request.get_icmp_type(), IcmpTypes::EchoRequest);
+        assert_eq!(request.get_identifier(), 1234);
+        assert_eq!(request.get_sequence_number(), 5);
+        assert_eq!(
+            request.get_checksum(),
+            icmp::checksum(&IcmpPacket::new(request.packet()).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_matching_reply() {
+        let relay = Ipv4Addr::new(10, 0, 0, 1);
+        let other = Ipv4Addr::new(10, 0, 0, 2);
+
+        assert!(is_matching_reply(&reply_packet(relay, 1, 2), relay, 1, 2));
+        assert!(!is_matching_reply(&reply_packet(other, 1, 2), relay, 1, 2));
+        assert!(!is_matching_reply(&reply_packet(relay, 3, 2), relay, 1, 2));
+        assert!(!is_matching_reply(&reply_packet(relay, 1, 3), relay, 1, 2));
+        assert!(!is_matching_reply(&[0u8; 4], relay, 1, 2));
+    }
+}
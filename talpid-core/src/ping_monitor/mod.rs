@@ -7,8 +7,14 @@ mod imp;
 #[path = "win.rs"]
 mod imp;
 
+mod icmp;
+
+pub use icmp::{measure_latency, Error as LatencyError};
 pub use imp::Error;
 
+use std::net::Ipv4Addr;
+
+/// Sends ICMP echo requests to a single host.
 pub trait Pinger: Send {
     /// Sends an ICMP packet
     fn send_icmp(&mut self) -> Result<(), Error>;
@@ -16,9 +22,7 @@ pub trait Pinger: Send {
     fn reset(&mut self) {}
 }
 
-pub fn new_pinger(
-    addr: std::net::Ipv4Addr,
-    interface_name: String,
-) -> Result<Box<dyn Pinger>, Error> {
+/// Returns a pinger that sends ICMP echo requests to `addr` via the given interface.
+pub fn new_pinger(addr: Ipv4Addr, interface_name: String) -> Result<Box<dyn Pinger>, Error> {
     Ok(Box::new(imp::Pinger::new(addr, interface_name)?))
 }
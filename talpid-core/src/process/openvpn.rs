@@ -29,7 +29,6 @@ static BASE_ARGUMENTS: &[&[&str]] = &[
     &["--rcvbuf", "1048576"],
     &["--sndbuf", "1048576"],
     &["--fast-io"],
-    &["--cipher", "AES-256-CBC"],
     &["--tls-version-min", "1.2"],
     &["--verb", "3"],
     #[cfg(windows)]
@@ -44,6 +43,9 @@ static BASE_ARGUMENTS: &[&[&str]] = &[
     ],
 ];
 
+/// Data channel cipher used unless another one is set with `OpenVpnCommand::cipher`.
+static DEFAULT_CIPHER: &str = "AES-256-CBC";
+
 static ALLOWED_TLS1_2_CIPHERS: &[&str] = &[
     "TLS-DHE-RSA-WITH-AES-256-GCM-SHA384",
     "TLS-DHE-RSA-WITH-AES-256-CBC-SHA",
@@ -62,6 +64,16 @@ pub struct OpenVpnCommand {
     proxy_auth_path: Option<PathBuf>,
     ca: Option<PathBuf>,
     crl: Option<PathBuf>,
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+    tls_auth: Option<(PathBuf, Option<u8>)>,
+    tls_crypt: Option<PathBuf>,
+    cipher: Option<String>,
+    data_ciphers: Option<String>,
+    auth: Option<String>,
+    verify_x509_name: Option<(String, Option<String>)>,
+    restrict_tls_ciphers: bool,
+    redirect_gateway: bool,
     iproute_bin: Option<OsString>,
     plugin: Option<(PathBuf, Vec<String>)>,
     log: Option<PathBuf>,
@@ -84,6 +96,16 @@ impl OpenVpnCommand {
             proxy_auth_path: None,
             ca: None,
             crl: None,
+            cert: None,
+            key: None,
+            tls_auth: None,
+            tls_crypt: None,
+            cipher: None,
+            data_ciphers: None,
+            auth: None,
+            verify_x509_name: None,
+            restrict_tls_ciphers: true,
+            redirect_gateway: false,
             iproute_bin: None,
             plugin: None,
             log: None,
@@ -133,6 +155,68 @@ impl OpenVpnCommand {
         self
     }
 
+    /// Sets the paths to the client certificate and its private key.
+    pub fn client_cert(&mut self, cert: impl AsRef<Path>, key: impl AsRef<Path>) -> &mut Self {
+        self.cert = Some(cert.as_ref().to_path_buf());
+        self.key = Some(key.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the path to the static key used to authenticate control channel packets. See the
+    /// `--tls-auth` OpenVPN documentation for details.
+    pub fn tls_auth(&mut self, path: impl AsRef<Path>, key_direction: Option<u8>) -> &mut Self {
+        self.tls_auth = Some((path.as_ref().to_path_buf(), key_direction));
+        self
+    }
+
+    /// Sets the path to the static key used to encrypt and authenticate control channel packets.
+    pub fn tls_crypt(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.tls_crypt = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the cipher used for the data channel.
+    pub fn cipher(&mut self, cipher: impl Into<String>) -> &mut Self {
+        self.cipher = Some(cipher.into());
+        self
+    }
+
+    /// Sets the colon separated list of data channel ciphers that the server may negotiate.
+    pub fn data_ciphers(&mut self, data_ciphers: impl Into<String>) -> &mut Self {
+        self.data_ciphers = Some(data_ciphers.into());
+        self
+    }
+
+    /// Sets the name that the server certificate must have. See the `--verify-x509-name`
+    /// OpenVPN documentation for the name types.
+    pub fn verify_x509_name(
+        &mut self,
+        name: impl Into<String>,
+        name_type: Option<String>,
+    ) -> &mut Self {
+        self.verify_x509_name = Some((name.into(), name_type));
+        self
+    }
+
+    /// Sets the message digest used to authenticate data channel packets.
+    pub fn auth(&mut self, auth: impl Into<String>) -> &mut Self {
+        self.auth = Some(auth.into());
+        self
+    }
+
+    /// Configures if only the TLS ciphers supported by the Mullvad relays are allowed.
+    pub fn restrict_tls_ciphers(&mut self, restrict_tls_ciphers: bool) -> &mut Self {
+        self.restrict_tls_ciphers = restrict_tls_ciphers;
+        self
+    }
+
+    /// Configures if all traffic should be routed through the tunnel regardless of the routes
+    /// pushed by the server.
+    pub fn redirect_gateway(&mut self, redirect_gateway: bool) -> &mut Self {
+        self.redirect_gateway = redirect_gateway;
+        self
+    }
+
     /// Sets the path to the ip route command.
     pub fn iproute_bin(&mut self, iproute_bin: impl Into<OsString>) -> &mut Self {
         self.iproute_bin = Some(iproute_bin.into());
@@ -214,6 +298,44 @@ impl OpenVpnCommand {
             args.push(OsString::from("--crl-verify"));
             args.push(OsString::from(crl.as_os_str()));
         }
+        if let (Some(ref cert), Some(ref key)) = (&self.cert, &self.key) {
+            args.push(OsString::from("--cert"));
+            args.push(OsString::from(cert.as_os_str()));
+            args.push(OsString::from("--key"));
+            args.push(OsString::from(key.as_os_str()));
+        }
+        if let Some((ref tls_auth, key_direction)) = self.tls_auth {
+            args.push(OsString::from("--tls-auth"));
+            args.push(OsString::from(tls_auth.as_os_str()));
+            if let Some(key_direction) = key_direction {
+                args.push(OsString::from(key_direction.to_string()));
+            }
+        }
+        if let Some(ref tls_crypt) = self.tls_crypt {
+            args.push(OsString::from("--tls-crypt"));
+            args.push(OsString::from(tls_crypt.as_os_str()));
+        }
+        if let Some((ref name, ref name_type)) = self.verify_x509_name {
+            args.push(OsString::from("--verify-x509-name"));
+            args.push(OsString::from(name));
+            if let Some(name_type) = name_type {
+                args.push(OsString::from(name_type));
+            }
+        }
+
+        args.push(OsString::from("--cipher"));
+        args.push(OsString::from(
+            self.cipher.as_deref().unwrap_or(DEFAULT_CIPHER),
+        ));
+        if let Some(ref data_ciphers) = self.data_ciphers {
+            // Older OpenVPN versions only know the option by this name, newer ones accept both
+            args.push(OsString::from("--ncp-ciphers"));
+            args.push(OsString::from(data_ciphers));
+        }
+        if let Some(ref auth) = self.auth {
+            args.push(OsString::from("--auth"));
+            args.push(OsString::from(auth));
+        }
 
         if let Some((ref path, ref plugin_args)) = self.plugin {
             args.push(OsString::from("--plugin"));
@@ -231,6 +353,14 @@ impl OpenVpnCommand {
             args.push(OsString::from(mssfix.to_string()));
         }
 
+        if self.redirect_gateway {
+            args.push(OsString::from("--redirect-gateway"));
+            args.push(OsString::from("def1"));
+            if self.enable_ipv6 {
+                args.push(OsString::from("ipv6"));
+            }
+        }
+
         if !self.enable_ipv6 {
             args.push(OsString::from("--pull-filter"));
             args.push(OsString::from("ignore"));
@@ -246,7 +376,9 @@ impl OpenVpnCommand {
             args.push(tunnel_device.clone());
         }
 
-        args.extend(Self::tls_cipher_arguments().iter().map(OsString::from));
+        if self.restrict_tls_ciphers {
+            args.extend(Self::tls_cipher_arguments().iter().map(OsString::from));
+        }
         args.extend(self.proxy_arguments().iter().map(OsString::from));
 
         args
@@ -433,6 +565,33 @@ mod tests {
         assert!(testee_args.contains(&OsString::from("3333")));
     }
 
+    #[test]
+    fn passes_custom_cipher() {
+        let default_args = OpenVpnCommand::new("").get_arguments();
+        assert!(default_args.contains(&OsString::from("AES-256-CBC")));
+
+        let testee_args = OpenVpnCommand::new("")
+            .cipher("AES-256-GCM")
+            .restrict_tls_ciphers(false)
+            .get_arguments();
+        assert!(testee_args.contains(&OsString::from("AES-256-GCM")));
+        assert!(!testee_args.contains(&OsString::from("AES-256-CBC")));
+        assert!(!testee_args.contains(&OsString::from("--tls-cipher")));
+    }
+
+    #[test]
+    fn passes_verify_x509_name() {
+        let testee_args = OpenVpnCommand::new("")
+            .verify_x509_name("C=SE, CN=server", Some("subject".to_owned()))
+            .get_arguments();
+        let position = testee_args
+            .iter()
+            .position(|arg| arg == "--verify-x509-name")
+            .unwrap();
+        assert_eq!(testee_args[position + 1], OsString::from("C=SE, CN=server"));
+        assert_eq!(testee_args[position + 2], OsString::from("subject"));
+    }
+
     #[test]
     fn passes_plugin_path() {
         let path = "./a/path";
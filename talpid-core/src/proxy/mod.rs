@@ -1,11 +1,26 @@
 mod shadowsocks;
+pub mod socks5;
+mod udp_over_tcp;
 
 pub use std::io::Result;
 
-use self::shadowsocks::ShadowsocksProxyMonitor;
-use std::{fmt, path::PathBuf, sync::mpsc};
+use self::{shadowsocks::ShadowsocksProxyMonitor, udp_over_tcp::UdpOverTcpProxyMonitor};
+use std::{fmt, io, net::SocketAddr, sync::mpsc};
 use talpid_types::net::openvpn;
 
+/// Errors that can happen when starting a proxy.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to start a proxy.
+    #[error(display = "Failed to start proxy")]
+    Io(#[error(source)] io::Error),
+
+    /// Failed to start the Shadowsocks client.
+    #[error(display = "Failed to start Shadowsocks client")]
+    Shadowsocks(#[error(source)] shadowsocks::Error),
+}
+
 pub enum WaitResult {
     UnexpectedExit(String),
     ProperShutdown,
@@ -73,31 +88,30 @@ impl ProxyMonitorCloseHandle for NoopProxyMonitorCloseHandle {
     }
 }
 
-/// Variables that define the environment to help
-/// proxy implementations find their way around.
-/// TODO: Move struct to wider scope and use more generic name.
-pub struct ProxyResourceData {
-    pub resource_dir: PathBuf,
-    pub log_dir: Option<PathBuf>,
-}
-
 pub fn start_proxy(
     settings: &openvpn::ProxySettings,
-    resource_data: &ProxyResourceData,
-) -> Result<Box<dyn ProxyMonitor>> {
+) -> std::result::Result<Box<dyn ProxyMonitor>, Error> {
     match settings {
         openvpn::ProxySettings::Local(local_settings) => {
             // These are generic proxy settings with the proxy client not managed by us.
-            Ok(Box::new(NoopProxyMonitor::start(local_settings.port)?))
+            Ok(Box::new(
+                NoopProxyMonitor::start(local_settings.port).map_err(Error::Io)?,
+            ))
         }
         openvpn::ProxySettings::Remote(remote_settings) => {
             // These are generic proxy settings with the proxy client not managed by us.
-            Ok(Box::new(NoopProxyMonitor::start(
-                remote_settings.address.port(),
-            )?))
+            Ok(Box::new(
+                NoopProxyMonitor::start(remote_settings.address.port()).map_err(Error::Io)?,
+            ))
         }
         openvpn::ProxySettings::Shadowsocks(ss_settings) => Ok(Box::new(
-            ShadowsocksProxyMonitor::start(ss_settings, resource_data)?,
+            ShadowsocksProxyMonitor::start(ss_settings).map_err(Error::Shadowsocks)?,
         )),
     }
 }
+
+/// Starts a proxy that forwards UDP datagrams sent to `127.0.0.1:<port>` over a TCP connection
+/// to `endpoint`.
+pub fn start_udp_over_tcp_proxy(endpoint: SocketAddr) -> Result<Box<dyn ProxyMonitor>> {
+    Ok(Box::new(UdpOverTcpProxyMonitor::start(endpoint)?))
+}
@@ -0,0 +1,249 @@
+//! The AEAD stream construction used by Shadowsocks. Each direction of a connection starts with a
+//! random salt, from which a session key is derived, followed by length-prefixed chunks that are
+//! sealed with an incrementing nonce.
+
+use super::Error;
+use rand::{rngs::OsRng, RngCore};
+use ring::{aead, hkdf};
+use std::{
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+/// Largest payload that fits in a single chunk.
+pub const MAX_PAYLOAD_SIZE: usize = 0x3fff;
+const TAG_LEN: usize = 16;
+const SUBKEY_INFO: &[u8] = b"ss-subkey";
+
+/// AEAD ciphers supported by the Shadowsocks client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cipher {
+    Aes128Gcm,
+    Aes256Gcm,
+    Chacha20IetfPoly1305,
+}
+
+impl FromStr for Cipher {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "aes-128-gcm" => Ok(Cipher::Aes128Gcm),
+            "aes-256-gcm" => Ok(Cipher::Aes256Gcm),
+            "chacha20-ietf-poly1305" => Ok(Cipher::Chacha20IetfPoly1305),
+            _ => Err(Error::UnsupportedCipher(s.to_owned())),
+        }
+    }
+}
+
+impl Cipher {
+    fn algorithm(self) -> &'static aead::Algorithm {
+        match self {
+            Cipher::Aes128Gcm => &aead::AES_128_GCM,
+            Cipher::Aes256Gcm => &aead::AES_256_GCM,
+            Cipher::Chacha20IetfPoly1305 => &aead::CHACHA20_POLY1305,
+        }
+    }
+
+    /// Length of the key. The salt that starts each stream has the same length.
+    pub fn key_len(self) -> usize {
+        self.algorithm().key_len()
+    }
+}
+
+/// Derives the master key from a password the same way as OpenSSL's `EVP_BytesToKey`, using MD5
+/// and no salt.
+pub fn derive_key(password: &str, key_len: usize) -> Vec<u8> {
+    let mut key = Vec::with_capacity(key_len);
+    let mut previous: Option<md5::Digest> = None;
+    while key.len() < key_len {
+        let mut context = md5::Context::new();
+        if let Some(previous) = previous {
+            context.consume(previous.0);
+        }
+        context.consume(password.as_bytes());
+        let digest = context.compute();
+        key.extend_from_slice(&digest.0);
+        previous = Some(digest);
+    }
+    key.truncate(key_len);
+    key
+}
+
+struct SubkeyLen(usize);
+
+impl hkdf::KeyType for SubkeyLen {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+/// Cipher state of one direction of a connection.
+struct SessionCipher {
+    key: aead::LessSafeKey,
+    nonce: [u8; aead::NONCE_LEN],
+}
+
+impl SessionCipher {
+    fn new(cipher: Cipher, master_key: &[u8], salt: &[u8]) -> Self {
+        let mut subkey = vec![0u8; cipher.key_len()];
+        hkdf::Salt::new(hkdf::HKDF_SHA1_FOR_LEGACY_USE_ONLY, salt)
+            .extract(master_key)
+            .expand(&[SUBKEY_INFO], SubkeyLen(subkey.len()))
+            .and_then(|okm| okm.fill(&mut subkey))
+            .expect("Subkey is too long for HKDF-SHA1");
+        let key = aead::UnboundKey::new(cipher.algorithm(), &subkey)
+            .expect("Subkey length does not match the cipher");
+        SessionCipher {
+            key: aead::LessSafeKey::new(key),
+            nonce: [0u8; aead::NONCE_LEN],
+        }
+    }
+
+    /// Returns the current nonce and increments it as a little-endian integer.
+    fn next_nonce(&mut self) -> aead::Nonce {
+        let nonce = aead::Nonce::assume_unique_for_key(self.nonce);
+        for byte in self.nonce.iter_mut() {
+            *byte = byte.wrapping_add(1);
+            if *byte != 0 {
+                break;
+            }
+        }
+        nonce
+    }
+
+    /// Encrypts `data` and appends it, followed by its tag, to `out`.
+    fn seal(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        let start = out.len();
+        out.extend_from_slice(data);
+        let nonce = self.next_nonce();
+        let tag = self
+            .key
+            .seal_in_place_separate_tag(nonce, aead::Aad::empty(), &mut out[start..])
+            .expect("Chunk is too large to encrypt");
+        out.extend_from_slice(tag.as_ref());
+    }
+
+    /// Decrypts `data`, which ends with the tag, in place. Returns the length of the plaintext.
+    fn open(&mut self, data: &mut [u8]) -> Result<usize, Error> {
+        let nonce = self.next_nonce();
+        self.key
+            .open_in_place(nonce, aead::Aad::empty(), data)
+            .map(|plaintext| plaintext.len())
+            .map_err(|_| Error::DecryptError)
+    }
+}
+
+/// Encrypts everything written to the underlying stream.
+pub struct EncryptedWriter<W> {
+    inner: W,
+    session: SessionCipher,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> EncryptedWriter<W> {
+    /// Creates a writer using a random salt, which is sent together with the first chunk.
+    pub fn new(inner: W, cipher: Cipher, master_key: &[u8]) -> Self {
+        let mut salt = vec![0u8; cipher.key_len()];
+        OsRng.fill_bytes(&mut salt);
+        let session = SessionCipher::new(cipher, master_key, &salt);
+        EncryptedWriter {
+            inner,
+            session,
+            buffer: salt,
+        }
+    }
+
+    /// Encrypts and writes all of `data`, split into as many chunks as needed.
+    pub fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        for payload in data.chunks(MAX_PAYLOAD_SIZE) {
+            let length = (payload.len() as u16).to_be_bytes();
+            self.session.seal(&length, &mut self.buffer);
+            self.session.seal(payload, &mut self.buffer);
+        }
+        self.inner.write_all(&self.buffer)?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+/// Decrypts the chunks read from the underlying stream.
+pub struct DecryptedReader<R> {
+    inner: R,
+    cipher: Cipher,
+    master_key: Vec<u8>,
+    session: Option<SessionCipher>,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> DecryptedReader<R> {
+    pub fn new(inner: R, cipher: Cipher, master_key: &[u8]) -> Self {
+        DecryptedReader {
+            inner,
+            cipher,
+            master_key: master_key.to_vec(),
+            session: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Reads and decrypts the next chunk. Returns `None` if the stream ends between two chunks.
+    pub fn read_chunk(&mut self) -> Result<Option<&[u8]>, Error> {
+        if self.session.is_none() {
+            let mut salt = vec![0u8; self.cipher.key_len()];
+            if !read_exact_or_eof(&mut self.inner, &mut salt)? {
+                return Ok(None);
+            }
+            self.session = Some(SessionCipher::new(self.cipher, &self.master_key, &salt));
+        }
+        let session = self
+            .session
+            .as_mut()
+            .expect("Session cipher is not initialized");
+
+        let mut length = [0u8; 2 + TAG_LEN];
+        if !read_exact_or_eof(&mut self.inner, &mut length)? {
+            return Ok(None);
+        }
+        session.open(&mut length)?;
+        let size = u16::from_be_bytes([length[0], length[1]]) as usize;
+        if size > MAX_PAYLOAD_SIZE {
+            return Err(Error::InvalidChunkSize(size));
+        }
+
+        self.buffer.resize(size + TAG_LEN, 0);
+        self.inner.read_exact(&mut self.buffer).map_err(Error::Io)?;
+        let size = session.open(&mut self.buffer)?;
+        Ok(Some(&self.buffer[..size]))
+    }
+}
+
+/// Like `Read::read_exact`, but returns `false` if the stream ends before anything is read.
+fn read_exact_or_eof(reader: &mut impl Read, buffer: &mut [u8]) -> Result<bool, Error> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(Error::Io(io::ErrorKind::UnexpectedEof.into())),
+            Ok(bytes_read) => read += bytes_read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+            Err(error) => return Err(Error::Io(error)),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_derive_key() {
+        let key = derive_key("mullvad", 32);
+        assert_eq!(
+            hex::encode(&key),
+            "b07d30a4e225b94a6134650e1bce2b35ee8122fab5a6e6e7d47b7babdfce0f98"
+        );
+        assert_eq!(derive_key("mullvad", 16), key[..16].to_vec());
+    }
+}
@@ -0,0 +1,339 @@
+//! In-process Shadowsocks client. It listens for SOCKS5 connections from OpenVPN on localhost and
+//! forwards each of them to the Shadowsocks server, encrypted with an AEAD cipher.
+
+use super::{socks5, ProxyMonitor, ProxyMonitorCloseHandle, WaitResult};
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    thread,
+    time::Duration,
+};
+use talpid_types::{net::openvpn::ShadowsocksProxySettings, ErrorExt};
+
+mod crypto;
+
+use self::crypto::{Cipher, DecryptedReader, EncryptedWriter};
+
+/// Timeout for establishing a connection to the Shadowsocks server.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the listener checks whether the proxy has been stopped.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Errors that can happen in the Shadowsocks client.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// The cipher is not one of the supported AEAD ciphers.
+    #[error(display = "Unsupported Shadowsocks cipher: {}", _0)]
+    UnsupportedCipher(String),
+
+    /// Failed to listen for local connections.
+    #[error(display = "Failed to bind local proxy listener")]
+    BindListener(#[error(source)] io::Error),
+
+    /// Failed to connect to the Shadowsocks server.
+    #[error(display = "Failed to connect to the Shadowsocks server")]
+    ConnectServer(#[error(source)] io::Error),
+
+    /// The SOCKS5 handshake with the local client failed.
+    #[error(display = "SOCKS5 handshake with local client failed")]
+    SocksHandshake(#[error(source)] io::Error),
+
+    /// Data from the Shadowsocks server could not be authenticated.
+    #[error(display = "Failed to decrypt data from the Shadowsocks server")]
+    DecryptError,
+
+    /// The Shadowsocks server sent a chunk larger than the protocol allows.
+    #[error(display = "Invalid chunk size from the Shadowsocks server: {}", _0)]
+    InvalidChunkSize(usize),
+
+    /// Reading from or writing to a connection failed.
+    #[error(display = "Connection failed")]
+    Io(#[error(source)] io::Error),
+}
+
+/// The Shadowsocks server and how to encrypt traffic to it.
+#[derive(Clone)]
+struct Server {
+    address: SocketAddr,
+    cipher: Cipher,
+    key: Arc<Vec<u8>>,
+}
+
+pub struct ShadowsocksProxyMonitor {
+    listener_thread: thread::JoinHandle<io::Result<()>>,
+    closed: Arc<AtomicBool>,
+    connections: Arc<Mutex<Vec<Weak<TcpStream>>>>,
+    port: u16,
+}
+
+impl ShadowsocksProxyMonitor {
+    pub fn start(settings: &ShadowsocksProxySettings) -> Result<Self, Error> {
+        let cipher: Cipher = settings.cipher.parse()?;
+        let server = Server {
+            address: settings.peer,
+            cipher,
+            key: Arc::new(crypto::derive_key(&settings.password, cipher.key_len())),
+        };
+
+        let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))
+            .map_err(Error::BindListener)?;
+        listener
+            .set_nonblocking(true)
+            .map_err(Error::BindListener)?;
+        let port = listener.local_addr().map_err(Error::BindListener)?.port();
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let connections = Arc::new(Mutex::new(Vec::new()));
+        let listener_thread = {
+            let closed = closed.clone();
+            let connections = connections.clone();
+            thread::spawn(move || accept_connections(listener, server, &closed, &connections))
+        };
+
+        Ok(ShadowsocksProxyMonitor {
+            listener_thread,
+            closed,
+            connections,
+            port,
+        })
+    }
+}
+
+fn accept_connections(
+    listener: TcpListener,
+    server: Server,
+    closed: &AtomicBool,
+    connections: &Mutex<Vec<Weak<TcpStream>>>,
+) -> io::Result<()> {
+    while !closed.load(Ordering::SeqCst) {
+        let client = match listener.accept() {
+            Ok((client, _)) => client,
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        client.set_nonblocking(false)?;
+        let client = Arc::new(client);
+        {
+            let mut connections = connections.lock().expect("Connections lock poisoned");
+            connections.retain(|connection| connection.strong_count() > 0);
+            connections.push(Arc::downgrade(&client));
+        }
+
+        let server = server.clone();
+        thread::spawn(move || {
+            if let Err(error) = handle_connection(client, &server) {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Shadowsocks connection failed")
+                );
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(client: Arc<TcpStream>, server: &Server) -> Result<(), Error> {
+    let target = socks5::read_request(&client).map_err(Error::SocksHandshake)?;
+
+    let server_stream = match TcpStream::connect_timeout(&server.address, CONNECT_TIMEOUT) {
+        Ok(stream) => stream,
+        Err(error) => {
+            let _ = socks5::send_reply(&client, socks5::REPLY_GENERAL_FAILURE);
+            return Err(Error::ConnectServer(error));
+        }
+    };
+    server_stream.set_nodelay(true).map_err(Error::Io)?;
+    socks5::send_reply(&client, socks5::REPLY_SUCCEEDED).map_err(Error::Io)?;
+
+    // Shadowsocks expects the target address in the same format as in the SOCKS5 request
+    let mut writer = EncryptedWriter::new(
+        server_stream.try_clone().map_err(Error::Io)?,
+        server.cipher,
+        &server.key,
+    );
+    writer.write_all(&target).map_err(Error::Io)?;
+    let mut reader = DecryptedReader::new(
+        server_stream.try_clone().map_err(Error::Io)?,
+        server.cipher,
+        &server.key,
+    );
+
+    let upload = {
+        let client = client.clone();
+        let server_stream = server_stream.try_clone().map_err(Error::Io)?;
+        thread::spawn(move || {
+            let result = forward_to_server(&client, &mut writer);
+            shutdown(&client, &server_stream);
+            result
+        })
+    };
+    let download_result = forward_to_client(&mut reader, &client);
+    shutdown(&client, &server_stream);
+
+    let upload_result = upload.join().expect("Shadowsocks upload thread panicked");
+    download_result.and(upload_result)
+}
+
+fn forward_to_server(
+    mut client: &TcpStream,
+    writer: &mut EncryptedWriter<TcpStream>,
+) -> Result<(), Error> {
+    let mut buffer = vec![0u8; crypto::MAX_PAYLOAD_SIZE];
+    loop {
+        let size = match client.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(size) => size,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(Error::Io(error)),
+        };
+        writer.write_all(&buffer[..size]).map_err(Error::Io)?;
+    }
+}
+
+fn forward_to_client(
+    reader: &mut DecryptedReader<TcpStream>,
+    mut client: &TcpStream,
+) -> Result<(), Error> {
+    while let Some(payload) = reader.read_chunk()? {
+        client.write_all(payload).map_err(Error::Io)?;
+    }
+    Ok(())
+}
+
+fn shutdown(client: &TcpStream, server_stream: &TcpStream) {
+    let _ = client.shutdown(Shutdown::Both);
+    let _ = server_stream.shutdown(Shutdown::Both);
+}
+
+impl ProxyMonitor for ShadowsocksProxyMonitor {
+    fn close_handle(&mut self) -> Box<dyn ProxyMonitorCloseHandle> {
+        Box::new(ShadowsocksProxyMonitorCloseHandle {
+            closed: self.closed.clone(),
+            connections: self.connections.clone(),
+        })
+    }
+
+    fn wait(self: Box<Self>) -> io::Result<WaitResult> {
+        // The listener only stops without an error once the proxy has been closed
+        match self
+            .listener_thread
+            .join()
+            .expect("Shadowsocks listener thread panicked")
+        {
+            Ok(()) => Ok(WaitResult::ProperShutdown),
+            Err(error) => Ok(WaitResult::UnexpectedExit(error.to_string())),
+        }
+    }
+
+    fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+pub struct ShadowsocksProxyMonitorCloseHandle {
+    closed: Arc<AtomicBool>,
+    connections: Arc<Mutex<Vec<Weak<TcpStream>>>>,
+}
+
+impl ProxyMonitorCloseHandle for ShadowsocksProxyMonitorCloseHandle {
+    fn close(self: Box<Self>) -> io::Result<()> {
+        if !self.closed.swap(true, Ordering::SeqCst) {
+            let connections = self.connections.lock().expect("Connections lock poisoned");
+            for connection in connections.iter().filter_map(Weak::upgrade) {
+                let _ = connection.shutdown(Shutdown::Both);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PASSWORD: &str = "mullvad";
+
+    /// Stand-in for a Shadowsocks server that echoes everything back to the client. Returns the
+    /// target address that the client asked for once the client disconnects.
+    fn start_echo_server(cipher: Cipher) -> (SocketAddr, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let key = crypto::derive_key(PASSWORD, cipher.key_len());
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = DecryptedReader::new(stream.try_clone().unwrap(), cipher, &key);
+            let mut writer = EncryptedWriter::new(stream, cipher, &key);
+
+            let target = reader.read_chunk().unwrap().unwrap().to_vec();
+            while let Some(payload) = reader.read_chunk().unwrap() {
+                writer.write_all(payload).unwrap();
+            }
+            target
+        });
+        (address, handle)
+    }
+
+    #[test]
+    fn test_forwards_through_server() {
+        let (server_address, server) = start_echo_server(Cipher::Chacha20IetfPoly1305);
+        let settings = ShadowsocksProxySettings {
+            peer: server_address,
+            password: PASSWORD.to_string(),
+            cipher: "chacha20-ietf-poly1305".to_string(),
+        };
+        let mut monitor = ShadowsocksProxyMonitor::start(&settings).unwrap();
+
+        let mut client = TcpStream::connect((Ipv4Addr::LOCALHOST, monitor.port())).unwrap();
+        client
+            .write_all(&[socks5::VERSION, 1, socks5::NO_AUTHENTICATION])
+            .unwrap();
+        let mut method = [0u8; 2];
+        client.read_exact(&mut method).unwrap();
+        assert_eq!(method, [socks5::VERSION, socks5::NO_AUTHENTICATION]);
+
+        // 10.8.0.1:1194
+        let target = [socks5::ADDRESS_IPV4, 10, 8, 0, 1, 0x04, 0xaa];
+        client
+            .write_all(&[socks5::VERSION, socks5::COMMAND_CONNECT, 0])
+            .unwrap();
+        client.write_all(&target).unwrap();
+        let mut reply = [0u8; 10];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(reply[1], socks5::REPLY_SUCCEEDED);
+
+        client.write_all(b"ping").unwrap();
+        let mut echo = [0u8; 4];
+        client.read_exact(&mut echo).unwrap();
+        assert_eq!(&echo, b"ping");
+
+        drop(client);
+        assert_eq!(server.join().unwrap(), target);
+
+        monitor.close_handle().close().unwrap();
+        match Box::new(monitor).wait().unwrap() {
+            WaitResult::ProperShutdown => (),
+            WaitResult::UnexpectedExit(reason) => panic!("Proxy exited unexpectedly: {}", reason),
+        }
+    }
+
+    #[test]
+    fn test_rejects_stream_cipher() {
+        let settings = ShadowsocksProxySettings {
+            peer: SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 443),
+            password: PASSWORD.to_string(),
+            cipher: "aes-256-cfb".to_string(),
+        };
+        match ShadowsocksProxyMonitor::start(&settings) {
+            Err(Error::UnsupportedCipher(cipher)) => assert_eq!(cipher, "aes-256-cfb"),
+            _ => panic!("Expected unsupported cipher error"),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Server side of the SOCKS5 handshake, limited to unauthenticated CONNECT requests.
+
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
+};
+
+pub const VERSION: u8 = 5;
+pub const NO_AUTHENTICATION: u8 = 0;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+pub const COMMAND_CONNECT: u8 = 1;
+pub const ADDRESS_IPV4: u8 = 1;
+pub const ADDRESS_DOMAIN: u8 = 3;
+pub const ADDRESS_IPV6: u8 = 4;
+pub const REPLY_SUCCEEDED: u8 = 0;
+pub const REPLY_GENERAL_FAILURE: u8 = 1;
+pub const REPLY_NOT_ALLOWED: u8 = 2;
+pub const REPLY_HOST_UNREACHABLE: u8 = 4;
+pub const REPLY_COMMAND_NOT_SUPPORTED: u8 = 7;
+pub const REPLY_ADDRESS_NOT_SUPPORTED: u8 = 8;
+
+/// Performs the SOCKS5 handshake with a client and returns the requested address, encoded as in
+/// the request: the address type, the address and the port.
+pub fn read_request(mut client: &TcpStream) -> io::Result<Vec<u8>> {
+    let mut header = [0u8; 2];
+    client.read_exact(&mut header)?;
+    if header[0] != VERSION {
+        return Err(invalid_request("Unsupported SOCKS version"));
+    }
+    let mut methods = vec![0u8; header[1] as usize];
+    client.read_exact(&mut methods)?;
+    if !methods.contains(&NO_AUTHENTICATION) {
+        let _ = client.write_all(&[VERSION, NO_ACCEPTABLE_METHODS]);
+        return Err(invalid_request("Client requires authentication"));
+    }
+    client.write_all(&[VERSION, NO_AUTHENTICATION])?;
+
+    let mut request = [0u8; 4];
+    client.read_exact(&mut request)?;
+    if request[0] != VERSION {
+        return Err(invalid_request("Unsupported SOCKS version"));
+    }
+    if request[1] != COMMAND_CONNECT {
+        let _ = send_reply(client, REPLY_COMMAND_NOT_SUPPORTED);
+        return Err(invalid_request("Unsupported SOCKS command"));
+    }
+
+    let address_type = request[3];
+    let mut address = vec![address_type];
+    let address_len = match address_type {
+        ADDRESS_IPV4 => 4,
+        ADDRESS_IPV6 => 16,
+        ADDRESS_DOMAIN => {
+            let mut domain_len = [0u8; 1];
+            client.read_exact(&mut domain_len)?;
+            address.push(domain_len[0]);
+            domain_len[0] as usize
+        }
+        _ => {
+            let _ = send_reply(client, REPLY_ADDRESS_NOT_SUPPORTED);
+            return Err(invalid_request("Unsupported address type"));
+        }
+    };
+    // The address is followed by the port
+    let start = address.len();
+    address.resize(start + address_len + 2, 0);
+    client.read_exact(&mut address[start..])?;
+    Ok(address)
+}
+
+/// Sends a reply to a connect request.
+pub fn send_reply(mut client: &TcpStream, reply: u8) -> io::Result<()> {
+    // Clients have no use for the bound address, so it is left unspecified
+    client.write_all(&[VERSION, reply, 0, ADDRESS_IPV4, 0, 0, 0, 0, 0, 0])
+}
+
+/// Returns the socket address of an address returned by `read_request`, or `None` if it is a
+/// domain name. Domain names are not resolved, since that would leak the lookup outside the
+/// tunnel.
+pub fn parse_address(address: &[u8]) -> Option<SocketAddr> {
+    let (host, port) = address[1..].split_at(address.len() - 3);
+    let port = u16::from_be_bytes([port[0], port[1]]);
+    match address[0] {
+        ADDRESS_IPV4 => {
+            let mut octets = [0u8; 4];
+            octets.copy_from_slice(host);
+            Some(SocketAddr::new(Ipv4Addr::from(octets).into(), port))
+        }
+        ADDRESS_IPV6 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(host);
+            Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port))
+        }
+        _ => None,
+    }
+}
+
+fn invalid_request(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_address() {
+        let address = [ADDRESS_IPV4, 10, 64, 0, 1, 0x04, 0x38];
+        assert_eq!(
+            parse_address(&address),
+            Some("10.64.0.1:1080".parse().unwrap())
+        );
+
+        let mut address = vec![ADDRESS_IPV6];
+        address.extend_from_slice(
+            &"fc00:bbbb:bbbb:bb01::1"
+                .parse::<Ipv6Addr>()
+                .unwrap()
+                .octets(),
+        );
+        address.extend_from_slice(&443u16.to_be_bytes());
+        assert_eq!(
+            parse_address(&address),
+            Some("[fc00:bbbb:bbbb:bb01::1]:443".parse().unwrap())
+        );
+
+        let mut address = vec![ADDRESS_DOMAIN, 11];
+        address.extend_from_slice(b"example.com");
+        address.extend_from_slice(&80u16.to_be_bytes());
+        assert_eq!(parse_address(&address), None);
+    }
+}
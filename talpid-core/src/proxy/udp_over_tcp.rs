@@ -0,0 +1,264 @@
+use super::{ProxyMonitor, ProxyMonitorCloseHandle, Result, WaitResult};
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpStream, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Timeout for establishing the TCP connection to the relay.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the UDP socket checks whether the proxy has been stopped.
+const UDP_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Each datagram is prefixed with its length as a big-endian `u16`.
+const MAX_DATAGRAM_SIZE: usize = u16::MAX as usize;
+
+/// Forwards datagrams received on a local UDP socket over a TCP connection to a relay, and
+/// datagrams received from the relay back to the last local UDP peer. Used to reach WireGuard
+/// relays from networks that block UDP.
+pub struct UdpOverTcpProxyMonitor {
+    tcp_stream: Arc<TcpStream>,
+    closed: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+    udp_to_tcp: thread::JoinHandle<io::Result<()>>,
+    tcp_to_udp: thread::JoinHandle<io::Result<()>>,
+    port: u16,
+}
+
+impl UdpOverTcpProxyMonitor {
+    pub fn start(endpoint: SocketAddr) -> Result<Self> {
+        let udp_socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))?;
+        udp_socket.set_read_timeout(Some(UDP_POLL_INTERVAL))?;
+        let port = udp_socket.local_addr()?.port();
+
+        let tcp_stream = TcpStream::connect_timeout(&endpoint, CONNECT_TIMEOUT)?;
+        tcp_stream.set_nodelay(true)?;
+        let tcp_stream = Arc::new(tcp_stream);
+
+        log::debug!(
+            "Forwarding UDP traffic on port {} over TCP to {}",
+            port,
+            endpoint
+        );
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let udp_peer = Arc::new(Mutex::new(None));
+
+        let udp_to_tcp = {
+            let udp_socket = udp_socket.try_clone()?;
+            let tcp_stream = tcp_stream.clone();
+            let stopped = stopped.clone();
+            let udp_peer = udp_peer.clone();
+            thread::spawn(move || {
+                let result = forward_udp_to_tcp(&udp_socket, &tcp_stream, &stopped, &udp_peer);
+                stop(&tcp_stream, &stopped);
+                result
+            })
+        };
+        let tcp_to_udp = {
+            let tcp_stream = tcp_stream.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                let result = forward_tcp_to_udp(&tcp_stream, &udp_socket, &udp_peer);
+                stop(&tcp_stream, &stopped);
+                result
+            })
+        };
+
+        Ok(Self {
+            tcp_stream,
+            closed,
+            stopped,
+            udp_to_tcp,
+            tcp_to_udp,
+            port,
+        })
+    }
+}
+
+fn stop(tcp_stream: &TcpStream, stopped: &AtomicBool) {
+    if !stopped.swap(true, Ordering::SeqCst) {
+        let _ = tcp_stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn forward_udp_to_tcp(
+    udp_socket: &UdpSocket,
+    mut tcp_stream: &TcpStream,
+    stopped: &AtomicBool,
+    udp_peer: &Mutex<Option<SocketAddr>>,
+) -> io::Result<()> {
+    let mut buffer = vec![0u8; 2 + MAX_DATAGRAM_SIZE];
+    while !stopped.load(Ordering::SeqCst) {
+        let (size, peer) = match udp_socket.recv_from(&mut buffer[2..]) {
+            Ok(received) => received,
+            Err(error)
+                if error.kind() == io::ErrorKind::WouldBlock
+                    || error.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue
+            }
+            Err(error) => return Err(error),
+        };
+        *udp_peer.lock().expect("UDP peer lock poisoned") = Some(peer);
+        buffer[..2].copy_from_slice(&(size as u16).to_be_bytes());
+        tcp_stream.write_all(&buffer[..2 + size])?;
+    }
+    Ok(())
+}
+
+fn forward_tcp_to_udp(
+    mut tcp_stream: &TcpStream,
+    udp_socket: &UdpSocket,
+    udp_peer: &Mutex<Option<SocketAddr>>,
+) -> io::Result<()> {
+    let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        let mut header = [0u8; 2];
+        tcp_stream.read_exact(&mut header)?;
+        let size = u16::from_be_bytes(header) as usize;
+        tcp_stream.read_exact(&mut buffer[..size])?;
+
+        let peer = *udp_peer.lock().expect("UDP peer lock poisoned");
+        match peer {
+            Some(peer) => {
+                udp_socket.send_to(&buffer[..size], peer)?;
+            }
+            None => log::trace!("Dropping datagram received before any local peer was known"),
+        }
+    }
+}
+
+impl ProxyMonitor for UdpOverTcpProxyMonitor {
+    fn close_handle(&mut self) -> Box<dyn ProxyMonitorCloseHandle> {
+        Box::new(UdpOverTcpProxyMonitorCloseHandle {
+            tcp_stream: self.tcp_stream.clone(),
+            closed: self.closed.clone(),
+            stopped: self.stopped.clone(),
+        })
+    }
+
+    fn wait(self: Box<Self>) -> Result<WaitResult> {
+        let udp_to_tcp_result = self.udp_to_tcp.join().expect("UDP forwarder panicked");
+        let tcp_to_udp_result = self.tcp_to_udp.join().expect("TCP forwarder panicked");
+
+        if self.closed.load(Ordering::SeqCst) {
+            return Ok(WaitResult::ProperShutdown);
+        }
+        let reason = match udp_to_tcp_result.and(tcp_to_udp_result) {
+            Ok(()) => "Proxy stopped".to_string(),
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                "Connection closed by the relay".to_string()
+            }
+            Err(error) => error.to_string(),
+        };
+        Ok(WaitResult::UnexpectedExit(reason))
+    }
+
+    fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+pub struct UdpOverTcpProxyMonitorCloseHandle {
+    tcp_stream: Arc<TcpStream>,
+    closed: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl ProxyMonitorCloseHandle for UdpOverTcpProxyMonitorCloseHandle {
+    fn close(self: Box<Self>) -> Result<()> {
+        self.closed.store(true, Ordering::SeqCst);
+        stop(&self.tcp_stream, &self.stopped);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn start_proxy() -> (UdpOverTcpProxyMonitor, TcpStream, UdpSocket) {
+        let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+        let proxy = UdpOverTcpProxyMonitor::start(listener.local_addr().unwrap()).unwrap();
+        let (relay, _) = listener.accept().unwrap();
+        relay.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+        let client = UdpSocket::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+        client.set_read_timeout(Some(TIMEOUT)).unwrap();
+        client
+            .connect(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), proxy.port()))
+            .unwrap();
+        (proxy, relay, client)
+    }
+
+    #[test]
+    fn test_datagrams_are_length_prefixed() {
+        let (_proxy, mut relay, client) = start_proxy();
+
+        client.send(b"first").unwrap();
+        client.send(&[]).unwrap();
+        client.send(&[0xab; 300]).unwrap();
+
+        let mut expected = vec![0, 5];
+        expected.extend_from_slice(b"first");
+        expected.extend_from_slice(&[0, 0]);
+        expected.extend_from_slice(&[1, 44]);
+        expected.extend_from_slice(&[0xab; 300]);
+        let mut received = vec![0u8; expected.len()];
+        relay.read_exact(&mut received).unwrap();
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn test_frames_are_split_into_datagrams() {
+        let (_proxy, mut relay, client) = start_proxy();
+
+        // The proxy only knows where to send datagrams once the client has sent one
+        client.send(b"hello").unwrap();
+        let mut request = [0u8; 7];
+        relay.read_exact(&mut request).unwrap();
+
+        // Two frames in one write, with the second one split across writes
+        relay
+            .write_all(&[0, 3, b'a', b'b', b'c', 0, 2, b'd'])
+            .unwrap();
+        relay.write_all(&[b'e']).unwrap();
+
+        let mut buffer = [0u8; 16];
+        let size = client.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..size], b"abc");
+        let size = client.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..size], b"de");
+    }
+
+    #[test]
+    fn test_relay_disconnect_stops_proxy() {
+        let (proxy, relay, _client) = start_proxy();
+        drop(relay);
+
+        match Box::new(proxy).wait().unwrap() {
+            WaitResult::UnexpectedExit(_) => (),
+            WaitResult::ProperShutdown => panic!("Proxy should not have been closed"),
+        }
+    }
+
+    #[test]
+    fn test_close_handle_shuts_down_proxy() {
+        let (mut proxy, _relay, _client) = start_proxy();
+        proxy.close_handle().close().unwrap();
+
+        match Box::new(proxy).wait().unwrap() {
+            WaitResult::ProperShutdown => (),
+            WaitResult::UnexpectedExit(reason) => panic!("Unexpected exit: {}", reason),
+        }
+    }
+}
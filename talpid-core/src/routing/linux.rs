@@ -3,7 +3,7 @@ use crate::{
     split_tunnel,
 };
 
-use talpid_types::ErrorExt;
+use talpid_types::{net::SplitTunnelMode, ErrorExt};
 
 use ipnetwork::IpNetwork;
 use regex::Regex;
@@ -11,7 +11,7 @@ use std::{
     collections::{BTreeMap, HashSet},
     fs,
     io::{self, BufRead, BufReader, Read, Seek, Write},
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
     process::Command,
 };
 
@@ -108,6 +108,8 @@ pub struct RouteManagerImpl {
     default_routes: HashSet<Route>,
     best_default_node_v4: Option<Node>,
     best_default_node_v6: Option<Node>,
+    // networks that are routed through the default route to bypass the tunnel
+    bypass_networks: HashSet<IpNetwork>,
 
     split_table_id: i32,
 }
@@ -140,6 +142,7 @@ impl RouteManagerImpl {
             default_routes: HashSet::new(),
             best_default_node_v4: None,
             best_default_node_v6: None,
+            bypass_networks: HashSet::new(),
 
             split_table_id,
         };
@@ -225,60 +228,107 @@ impl RouteManagerImpl {
         Ok(table_id)
     }
 
-    /// Route PID-associated packets through the physical interface.
-    async fn enable_exclusions_routes(&mut self) -> Result<()> {
-        // TODO: IPv6
+    /// Route marked packets through the physical interface. Which packets are marked depends on
+    /// the split tunneling mode that the firewall is configured with.
+    async fn enable_exclusions_routes(&mut self, mode: SplitTunnelMode) -> Result<()> {
+        self.add_exclusions_rules("-4", mode)?;
+        // IPv6 may be disabled on the host, which should not prevent IPv4 split tunneling
+        if let Err(error) = self.add_exclusions_rules("-6", mode) {
+            log::warn!(
+                "{}",
+                error.display_chain_with_msg("Failed to set up IPv6 split tunneling rules")
+            );
+        }
 
-        let table_id_str = &self.split_table_id.to_string();
+        // Add default routes for the exclusions table
+        let mut required_routes = HashSet::new();
+        for zero_network in &[
+            IpNetwork::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0).unwrap(),
+            IpNetwork::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0).unwrap(),
+        ] {
+            required_routes.insert(
+                RequiredRoute::new(*zero_network, NetNode::DefaultNode)
+                    .table(self.split_table_id as u8),
+            );
+        }
+        self.add_required_routes(required_routes).await
+    }
 
-        // Create the rule if it does not exist
-        let mut cmd = Command::new("ip");
-        cmd.args(&["-4", "rule", "list", "table", table_id_str]);
-        log::trace!("running cmd - {:?}", &cmd);
-        let out = cmd.output().map_err(Error::ExecFailed)?;
+    /// Creates the routing policies for marked packets of the given address family (`-4` or
+    /// `-6`), if they do not exist.
+    fn add_exclusions_rules(&self, family: &str, mode: SplitTunnelMode) -> Result<()> {
+        let table_id_str = &self.split_table_id.to_string();
+        let mark_str = &split_tunnel::MARK.to_string();
 
-        let missing_rule =
-            !out.status.success() || String::from_utf8_lossy(&out.stdout).trim().is_empty();
-        if missing_rule {
+        if Self::missing_rule(family, &["table", table_id_str])? {
             exec_ip(&[
-                "-4",
+                family,
                 "rule",
                 "add",
                 "from",
                 "all",
                 "fwmark",
-                &split_tunnel::MARK.to_string(),
+                mark_str,
                 "lookup",
                 table_id_str,
             ])?;
         }
 
-        // Add default route for the exclusions table
-        let zero_network =
-            ipnetwork::IpNetwork::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0).unwrap();
-        let mut required_routes = HashSet::new();
-        required_routes.insert(
-            RequiredRoute::new(zero_network, NetNode::DefaultNode).table(self.split_table_id as u8),
-        );
-        self.add_required_routes(required_routes).await
+        // In include mode, all traffic except that of the included processes is marked. The rule
+        // that looks up the main table is added last so that it takes precedence. It ignores the
+        // default routes and thereby lets marked packets reach the LAN without going through the
+        // default gateway. In exclude mode, it would let excluded processes use the routes that
+        // point into the tunnel, so it must not exist.
+        let main_rule = exclusions_main_rule(mark_str);
+        let missing_main_rule = Self::missing_rule(family, &main_rule[..4])?;
+        match mode {
+            SplitTunnelMode::Include if missing_main_rule => {
+                exec_ip(&[&[family, "rule", "add"][..], &main_rule[..]].concat())
+            }
+            SplitTunnelMode::Exclude if !missing_main_rule => {
+                exec_ip(&[&[family, "rule", "del"][..], &main_rule[..]].concat())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns whether there is no routing policy matching the given selector.
+    fn missing_rule(family: &str, selector: &[&str]) -> Result<bool> {
+        let mut cmd = Command::new("ip");
+        cmd.args(&[family, "rule", "list"]).args(selector);
+        log::trace!("running cmd - {:?}", &cmd);
+        let out = cmd.output().map_err(Error::ExecFailed)?;
+
+        Ok(!out.status.success() || String::from_utf8_lossy(&out.stdout).trim().is_empty())
     }
 
-    /// Stop routing PID-associated packets through the physical interface.
+    /// Stop routing marked packets through the physical interface.
     async fn disable_exclusions_routes(&self) {
-        // TODO: IPv6
+        let mark_str = &split_tunnel::MARK.to_string();
+        let table_id_str = &self.split_table_id.to_string();
+        let main_rule = exclusions_main_rule(mark_str);
+
+        for &family in &["-4", "-6"] {
+            if let Err(e) = exec_ip(&[
+                family,
+                "rule",
+                "del",
+                "from",
+                "all",
+                "fwmark",
+                mark_str,
+                "lookup",
+                table_id_str,
+            ]) {
+                log::warn!("Failed to delete routing policy: {}", e);
+            }
 
-        if let Err(e) = exec_ip(&[
-            "-4",
-            "rule",
-            "del",
-            "from",
-            "all",
-            "fwmark",
-            &split_tunnel::MARK.to_string(),
-            "lookup",
-            &self.split_table_id.to_string(),
-        ]) {
-            log::warn!("Failed to delete routing policy: {}", e);
+            // Only exists in include mode
+            if let Ok(false) = Self::missing_rule(family, &main_rule[..4]) {
+                if let Err(e) = exec_ip(&[&[family, "rule", "del"][..], &main_rule[..]].concat()) {
+                    log::warn!("Failed to delete routing policy: {}", e);
+                }
+            }
         }
     }
 
@@ -304,6 +354,48 @@ impl RouteManagerImpl {
         self.add_required_routes(dns_routes).await
     }
 
+    /// Route traffic to the given networks through the default route. Routes to networks that
+    /// were set previously but are not in `networks` are removed.
+    async fn set_bypass_routes(&mut self, networks: HashSet<IpNetwork>) -> Result<()> {
+        let removed_networks: Vec<IpNetwork> = self
+            .bypass_networks
+            .difference(&networks)
+            .cloned()
+            .collect();
+        for destination in removed_networks {
+            self.remove_required_default_route(RequiredDefaultRoute {
+                table_id: RT_TABLE_MAIN,
+                destination,
+            })
+            .await;
+        }
+
+        let new_routes = networks
+            .difference(&self.bypass_networks)
+            .map(|network| RequiredRoute::new(*network, NetNode::DefaultNode))
+            .collect();
+        self.bypass_networks = networks;
+        self.add_required_routes(new_routes).await
+    }
+
+    async fn remove_required_default_route(&mut self, required_route: RequiredDefaultRoute) {
+        self.required_default_routes.remove(&required_route);
+
+        let best_node = if required_route.destination.is_ipv4() {
+            self.best_default_node_v4.clone()
+        } else {
+            self.best_default_node_v6.clone()
+        };
+        if let Some(best_node) = best_node {
+            let route =
+                Route::new(best_node, required_route.destination).table(required_route.table_id);
+            if let Err(e) = self.delete_route(&route).await {
+                log::error!("Failed to remove route - {} - {}", route, e);
+            }
+            self.added_routes.remove(&route);
+        }
+    }
+
     async fn add_required_default_routes(
         &mut self,
         required_default_routes: HashSet<RequiredDefaultRoute>,
@@ -547,6 +639,7 @@ impl RouteManagerImpl {
             }
         }
         self.required_default_routes.clear();
+        self.bypass_networks.clear();
 
         for route in self.added_routes.drain().collect::<Vec<_>>().iter() {
             if let Err(e) = self.delete_route(&route).await {
@@ -593,12 +686,16 @@ impl RouteManagerImpl {
                 log::debug!("Adding routes: {:?}", routes);
                 let _ = result_rx.send(self.add_required_routes(routes.clone()).await);
             }
-            RouteManagerCommand::EnableExclusionsRoutes(result_rx) => {
-                let _ = result_rx.send(self.enable_exclusions_routes().await);
+            RouteManagerCommand::EnableExclusionsRoutes(mode, result_rx) => {
+                let _ = result_rx.send(self.enable_exclusions_routes(mode).await);
             }
             RouteManagerCommand::DisableExclusionsRoutes => {
                 self.disable_exclusions_routes().await;
             }
+            RouteManagerCommand::SetBypassRoutes(networks, result_rx) => {
+                log::debug!("Setting bypass routes: {:?}", networks);
+                let _ = result_rx.send(self.set_bypass_routes(networks).await);
+            }
             RouteManagerCommand::RouteExclusionsDns(tunnel_alias, dns_servers, result_rx) => {
                 let _ =
                     result_rx.send(self.route_exclusions_dns(&tunnel_alias, &dns_servers).await);
@@ -880,6 +977,22 @@ fn ip_to_bytes(addr: IpAddr) -> Vec<u8> {
     }
 }
 
+/// Returns the arguments of `ip rule add` or `ip rule del` for the rule that lets marked packets
+/// use the non-default routes in the main table. The first four arguments select the rule in
+/// `ip rule list`.
+fn exclusions_main_rule(mark: &str) -> [&str; 8] {
+    [
+        "fwmark",
+        mark,
+        "table",
+        "main",
+        "from",
+        "all",
+        "suppress_prefixlength",
+        "1",
+    ]
+}
+
 fn exec_ip(args: &[&str]) -> Result<()> {
     let mut cmd = Command::new("ip");
     cmd.args(args);
@@ -923,4 +1036,13 @@ mod test {
         });
         std::mem::drop(manager);
     }
+
+    #[test]
+    fn test_exclusions_main_rule() {
+        let rule = exclusions_main_rule("3905");
+        // The selector must identify the rule in `ip rule list`
+        assert_eq!(&rule[..4], &["fwmark", "3905", "table", "main"]);
+        // Default routes must be ignored, including the two halves that WireGuard adds
+        assert_eq!(&rule[6..], &["suppress_prefixlength", "1"]);
+    }
 }
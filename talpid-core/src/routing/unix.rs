@@ -10,8 +10,12 @@ use futures::channel::{
 use std::collections::HashSet;
 use talpid_types::ErrorExt;
 
+#[cfg(target_os = "linux")]
+use ipnetwork::IpNetwork;
 #[cfg(target_os = "linux")]
 use std::net::IpAddr;
+#[cfg(target_os = "linux")]
+use talpid_types::net::SplitTunnelMode;
 
 #[cfg(target_os = "macos")]
 #[path = "macos.rs"]
@@ -53,10 +57,15 @@ pub enum RouteManagerCommand {
     ClearRoutes,
     Shutdown(oneshot::Sender<()>),
     #[cfg(target_os = "linux")]
-    EnableExclusionsRoutes(oneshot::Sender<Result<(), PlatformError>>),
+    EnableExclusionsRoutes(SplitTunnelMode, oneshot::Sender<Result<(), PlatformError>>),
     #[cfg(target_os = "linux")]
     DisableExclusionsRoutes,
     #[cfg(target_os = "linux")]
+    SetBypassRoutes(
+        HashSet<IpNetwork>,
+        oneshot::Sender<Result<(), PlatformError>>,
+    ),
+    #[cfg(target_os = "linux")]
     RouteExclusionsDns(
         String,
         Vec<IpAddr>,
@@ -146,13 +155,14 @@ impl RouteManager {
         }
     }
 
-    /// Route PID-associated packets through the physical interface.
+    /// Route marked packets through the physical interface. Can be called again to switch to
+    /// another split tunneling mode.
     #[cfg(target_os = "linux")]
-    pub fn enable_exclusions_routes(&mut self) -> Result<(), Error> {
+    pub fn enable_exclusions_routes(&mut self, mode: SplitTunnelMode) -> Result<(), Error> {
         if let Some(tx) = &self.manage_tx {
             let (result_tx, result_rx) = oneshot::channel();
             if tx
-                .unbounded_send(RouteManagerCommand::EnableExclusionsRoutes(result_tx))
+                .unbounded_send(RouteManagerCommand::EnableExclusionsRoutes(mode, result_tx))
                 .is_err()
             {
                 return Err(Error::RouteManagerDown);
@@ -186,6 +196,32 @@ impl RouteManager {
         }
     }
 
+    /// Route traffic to the given networks through the default route instead of the tunnel.
+    /// Replaces any networks set previously. The routes are removed along with all other routes
+    /// by [`RouteManager::clear_routes`].
+    #[cfg(target_os = "linux")]
+    pub fn set_bypass_routes(&mut self, networks: HashSet<IpNetwork>) -> Result<(), Error> {
+        if let Some(tx) = &self.manage_tx {
+            let (result_tx, result_rx) = oneshot::channel();
+            if tx
+                .unbounded_send(RouteManagerCommand::SetBypassRoutes(networks, result_tx))
+                .is_err()
+            {
+                return Err(Error::RouteManagerDown);
+            }
+
+            match self.runtime.block_on(result_rx) {
+                Ok(result) => result.map_err(Error::PlatformError),
+                Err(error) => {
+                    log::trace!("{}", error.display_chain_with_msg("channel is closed"));
+                    Ok(())
+                }
+            }
+        } else {
+            Err(Error::RouteManagerDown)
+        }
+    }
+
     /// Route DNS requests through the tunnel interface.
     #[cfg(target_os = "linux")]
     pub fn route_exclusions_dns(
@@ -0,0 +1,431 @@
+use crate::{proxy::socks5, tunnel::TunnelMetadata};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    thread,
+    time::Duration,
+};
+use talpid_types::ErrorExt;
+
+/// Port on localhost that the proxy listens on.
+pub const PORT: u16 = 1080;
+/// Value used to mark connections made by the proxy, so that they are never excluded from the
+/// tunnel by split tunneling.
+/// This should be an arbitrary but unique integer.
+#[cfg(target_os = "linux")]
+pub const MARK: i32 = 0xf42;
+
+/// Timeout for establishing a connection to the requested address.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often the listener checks whether the proxy has been disabled.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[cfg(target_os = "macos")]
+const IP_BOUND_IF: libc::c_int = 25;
+#[cfg(target_os = "macos")]
+const IPV6_BOUND_IF: libc::c_int = 125;
+
+/// Errors that can happen in the SOCKS5 proxy.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to listen for local connections.
+    #[error(display = "Failed to listen on port {}", PORT)]
+    BindListener(#[error(source)] io::Error),
+
+    /// The SOCKS5 handshake with the local client failed.
+    #[error(display = "SOCKS5 handshake with local client failed")]
+    SocksHandshake(#[error(source)] io::Error),
+
+    /// The connection was refused since the tunnel is not up.
+    #[error(display = "Refused connection since the tunnel is not connected")]
+    NotConnected,
+
+    /// The client requested a domain name, which is not resolved by the proxy.
+    #[error(display = "Refused connection to a domain name")]
+    DomainNotSupported,
+
+    /// Failed to connect to the requested address through the tunnel.
+    #[error(display = "Failed to connect through the tunnel")]
+    Connect(#[error(source)] io::Error),
+
+    /// Reading from or writing to a connection failed.
+    #[error(display = "Connection failed")]
+    Io(#[error(source)] io::Error),
+}
+
+/// Local SOCKS5 server that lets individual applications send traffic through the tunnel. The
+/// server only listens on localhost, and connections are only accepted while there is a tunnel
+/// to forward them through.
+#[derive(Default)]
+pub struct SocksProxy {
+    tunnel: Arc<Mutex<Option<TunnelMetadata>>>,
+    connections: Arc<Mutex<Vec<Weak<TcpStream>>>>,
+    listener: Option<Listener>,
+}
+
+struct Listener {
+    closed: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+}
+
+/// Binds the listener that the proxy accepts local connections on.
+pub fn bind() -> Result<TcpListener, Error> {
+    TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT))
+        .map_err(Error::BindListener)
+}
+
+impl SocksProxy {
+    /// Starts accepting connections on the given listener, or stops the proxy if there is none.
+    /// Stopping the proxy closes all connections.
+    pub fn set_listener(&mut self, listener: Option<TcpListener>) {
+        if let Some(old_listener) = self.listener.take() {
+            old_listener.closed.store(true, Ordering::SeqCst);
+            if old_listener.thread.join().is_err() {
+                log::error!("SOCKS5 proxy listener thread panicked");
+            }
+            self.close_connections();
+            log::info!("Stopped SOCKS5 proxy");
+        }
+        if let Some(listener) = listener {
+            let address = listener.local_addr();
+            match self.start_listener(listener) {
+                Ok(listener) => {
+                    if let Ok(address) = address {
+                        log::info!("SOCKS5 proxy listening on {}", address);
+                    }
+                    self.listener = Some(listener);
+                }
+                Err(error) => {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to start SOCKS5 proxy")
+                    )
+                }
+            }
+        }
+    }
+
+    /// Sets the tunnel to forward connections through. While there is no tunnel, new connections
+    /// are refused and existing ones are closed.
+    pub fn set_tunnel(&self, tunnel: Option<TunnelMetadata>) {
+        let disconnected = tunnel.is_none();
+        *self.tunnel.lock().expect("Tunnel lock poisoned") = tunnel;
+        if disconnected {
+            self.close_connections();
+        }
+    }
+
+    fn start_listener(&self, listener: TcpListener) -> Result<Listener, Error> {
+        listener
+            .set_nonblocking(true)
+            .map_err(Error::BindListener)?;
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let closed = closed.clone();
+            let tunnel = self.tunnel.clone();
+            let connections = self.connections.clone();
+            thread::spawn(move || {
+                if let Err(error) = accept_connections(listener, &closed, tunnel, &connections) {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("SOCKS5 proxy stopped accepting connections")
+                    );
+                }
+            })
+        };
+        Ok(Listener { closed, thread })
+    }
+
+    fn close_connections(&self) {
+        let mut connections = self.connections.lock().expect("Connections lock poisoned");
+        for connection in connections
+            .drain(..)
+            .filter_map(|connection| connection.upgrade())
+        {
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl Drop for SocksProxy {
+    fn drop(&mut self) {
+        self.set_listener(None);
+    }
+}
+
+fn accept_connections(
+    listener: TcpListener,
+    closed: &AtomicBool,
+    tunnel: Arc<Mutex<Option<TunnelMetadata>>>,
+    connections: &Mutex<Vec<Weak<TcpStream>>>,
+) -> io::Result<()> {
+    while !closed.load(Ordering::SeqCst) {
+        let client = match listener.accept() {
+            Ok((client, _)) => client,
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        client.set_nonblocking(false)?;
+        let client = Arc::new(client);
+        {
+            let mut connections = connections.lock().expect("Connections lock poisoned");
+            connections.retain(|connection| connection.strong_count() > 0);
+            connections.push(Arc::downgrade(&client));
+        }
+
+        let tunnel = tunnel.clone();
+        thread::spawn(move || match handle_connection(client, &tunnel) {
+            Ok(()) => (),
+            Err(error @ Error::NotConnected) | Err(error @ Error::DomainNotSupported) => {
+                log::debug!("{}", error)
+            }
+            Err(error) => log::warn!(
+                "{}",
+                error.display_chain_with_msg("SOCKS5 connection failed")
+            ),
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(
+    client: Arc<TcpStream>,
+    tunnel: &Mutex<Option<TunnelMetadata>>,
+) -> Result<(), Error> {
+    let address = socks5::read_request(&client).map_err(Error::SocksHandshake)?;
+
+    let tunnel = match tunnel.lock().expect("Tunnel lock poisoned").clone() {
+        Some(tunnel) => tunnel,
+        None => {
+            let _ = socks5::send_reply(&client, socks5::REPLY_NOT_ALLOWED);
+            return Err(Error::NotConnected);
+        }
+    };
+    let target = match socks5::parse_address(&address) {
+        Some(target) => target,
+        None => {
+            let _ = socks5::send_reply(&client, socks5::REPLY_ADDRESS_NOT_SUPPORTED);
+            return Err(Error::DomainNotSupported);
+        }
+    };
+    let server = match connect(target, &tunnel) {
+        Ok(server) => Arc::new(server),
+        Err(error) => {
+            let _ = socks5::send_reply(&client, socks5::REPLY_HOST_UNREACHABLE);
+            return Err(Error::Connect(error));
+        }
+    };
+    socks5::send_reply(&client, socks5::REPLY_SUCCEEDED).map_err(Error::Io)?;
+
+    let upload = {
+        let client = client.clone();
+        let server = server.clone();
+        thread::spawn(move || {
+            let result = forward(&client, &server);
+            shutdown(&client, &server);
+            result
+        })
+    };
+    let download_result = forward(&server, &client);
+    shutdown(&client, &server);
+
+    let upload_result = upload.join().expect("SOCKS5 upload thread panicked");
+    download_result.and(upload_result).map_err(Error::Io)
+}
+
+/// Connects to the address, making sure that the connection can only leave through the tunnel
+/// interface.
+fn connect(target: SocketAddr, tunnel: &TunnelMetadata) -> io::Result<TcpStream> {
+    let domain = if target.is_ipv4() {
+        Domain::ipv4()
+    } else {
+        Domain::ipv6()
+    };
+    let socket = Socket::new(domain, Type::stream(), Some(Protocol::tcp()))?;
+    bind_to_tunnel(&socket, target, tunnel)?;
+    socket.connect_timeout(&target.into(), CONNECT_TIMEOUT)?;
+    socket.set_nodelay(true)?;
+    Ok(socket.into_tcp_stream())
+}
+
+#[cfg(target_os = "linux")]
+fn bind_to_tunnel(socket: &Socket, _target: SocketAddr, tunnel: &TunnelMetadata) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let interface = std::ffi::CString::new(tunnel.interface.as_bytes())
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    set_socket_option(
+        socket.as_raw_fd(),
+        libc::SOL_SOCKET,
+        libc::SO_BINDTODEVICE,
+        interface.as_bytes_with_nul(),
+    )?;
+    set_socket_option(
+        socket.as_raw_fd(),
+        libc::SOL_SOCKET,
+        libc::SO_MARK,
+        &MARK.to_ne_bytes(),
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn bind_to_tunnel(socket: &Socket, target: SocketAddr, tunnel: &TunnelMetadata) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let interface = std::ffi::CString::new(tunnel.interface.as_bytes())
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    let index = unsafe { libc::if_nametoindex(interface.as_ptr()) };
+    if index == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let (level, option) = if target.is_ipv4() {
+        (libc::IPPROTO_IP, IP_BOUND_IF)
+    } else {
+        (libc::IPPROTO_IPV6, IPV6_BOUND_IF)
+    };
+    set_socket_option(socket.as_raw_fd(), level, option, &index.to_ne_bytes())
+}
+
+#[cfg(windows)]
+fn bind_to_tunnel(socket: &Socket, target: SocketAddr, tunnel: &TunnelMetadata) -> io::Result<()> {
+    // Binding to an address on the tunnel interface makes the connection leave through it
+    let address = tunnel
+        .ips
+        .iter()
+        .find(|ip| ip.is_ipv4() == target.is_ipv4())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                "The tunnel has no address of the same family as the target",
+            )
+        })?;
+    socket.bind(&SocketAddr::new(*address, 0).into())
+}
+
+#[cfg(unix)]
+fn set_socket_option(
+    fd: std::os::unix::io::RawFd,
+    level: libc::c_int,
+    option: libc::c_int,
+    value: &[u8],
+) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            option,
+            value.as_ptr() as *const libc::c_void,
+            value.len() as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+fn forward(mut from: &TcpStream, mut to: &TcpStream) -> io::Result<()> {
+    let mut buffer = vec![0u8; 16 * 1024];
+    loop {
+        let size = match from.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(size) => size,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        to.write_all(&buffer[..size])?;
+    }
+}
+
+fn shutdown(client: &TcpStream, server: &TcpStream) {
+    let _ = client.shutdown(Shutdown::Both);
+    let _ = server.shutdown(Shutdown::Both);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn start_proxy() -> (SocksProxy, SocketAddr) {
+        let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut proxy = SocksProxy::default();
+        proxy.set_listener(Some(listener));
+        (proxy, address)
+    }
+
+    fn connect_client(address: SocketAddr) -> TcpStream {
+        let mut client = TcpStream::connect(address).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        client
+            .write_all(&[socks5::VERSION, 1, socks5::NO_AUTHENTICATION])
+            .unwrap();
+        let mut reply = [0u8; 2];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(reply, [socks5::VERSION, socks5::NO_AUTHENTICATION]);
+        client
+    }
+
+    fn request(client: &mut TcpStream, address: &[u8]) -> u8 {
+        client
+            .write_all(&[socks5::VERSION, socks5::COMMAND_CONNECT, 0])
+            .unwrap();
+        client.write_all(address).unwrap();
+        let mut reply = [0u8; 10];
+        client.read_exact(&mut reply).unwrap();
+        reply[1]
+    }
+
+    #[test]
+    fn test_refuses_connections_without_tunnel() {
+        let (_proxy, address) = start_proxy();
+        let mut client = connect_client(address);
+        assert_eq!(
+            request(&mut client, &[socks5::ADDRESS_IPV4, 10, 64, 0, 1, 0, 80]),
+            socks5::REPLY_NOT_ALLOWED
+        );
+    }
+
+    #[test]
+    fn test_refuses_domains() {
+        let (proxy, address) = start_proxy();
+        proxy.set_tunnel(Some(TunnelMetadata {
+            interface: "lo".to_owned(),
+            ips: vec![Ipv4Addr::LOCALHOST.into()],
+            ipv4_gateway: Ipv4Addr::LOCALHOST,
+            ipv6_gateway: None,
+        }));
+
+        let mut client = connect_client(address);
+        let mut domain = vec![socks5::ADDRESS_DOMAIN, 11];
+        domain.extend_from_slice(b"example.com");
+        domain.extend_from_slice(&80u16.to_be_bytes());
+        assert_eq!(
+            request(&mut client, &domain),
+            socks5::REPLY_ADDRESS_NOT_SUPPORTED
+        );
+    }
+
+    #[test]
+    fn test_closes_connections_without_tunnel() {
+        let (proxy, address) = start_proxy();
+        let mut client = connect_client(address);
+
+        proxy.set_tunnel(None);
+        let mut buffer = [0u8; 1];
+        assert_eq!(client.read(&mut buffer).unwrap(), 0);
+    }
+}
@@ -1,9 +1,19 @@
 use std::{
-    env, fs,
+    collections::{HashMap, HashSet},
+    env,
+    ffi::OsStr,
+    fs,
     io::{self, BufRead, BufReader, BufWriter, Write},
+    os::unix::ffi::OsStrExt,
     path::PathBuf,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+use talpid_types::{
+    cgroup::{find_net_cls_mount, SPLIT_TUNNEL_CGROUP_NAME},
+    ErrorExt,
 };
-use talpid_types::cgroup::{find_net_cls_mount, SPLIT_TUNNEL_CGROUP_NAME};
 
 const DEFAULT_NET_CLS_DIR: &str = "/sys/fs/cgroup/net_cls";
 const NET_CLS_DIR_OVERRIDE_ENV_VAR: &str = "TALPID_NET_CLS_MOUNT_DIR";
@@ -15,6 +25,9 @@ pub const NET_CLS_CLASSID: u32 = 0x4d9f41;
 /// This should be an arbitrary but unique integer.
 pub const MARK: i32 = 0xf41;
 
+/// How often `/proc` is scanned for new processes started from excluded executables.
+const APP_SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Errors related to split tunneling.
 #[derive(err_derive::Error, Debug)]
 #[error(no_from)]
@@ -46,9 +59,14 @@ pub enum Error {
     /// Unable to read /proc/mounts
     #[error(display = "Failed to read /proc/mounts")]
     ListMounts(#[error(source)] io::Error),
+
+    /// Unable to list running processes.
+    #[error(display = "Failed to list processes in /proc")]
+    ListProcesses(#[error(source)] io::Error),
 }
 
 /// Manages PIDs to exclude from the tunnel.
+#[derive(Clone)]
 pub struct PidManager {
     net_cls_path: PathBuf,
 }
@@ -180,3 +198,259 @@ impl PidManager {
         Ok(())
     }
 }
+
+/// Excludes all processes started from a set of executables from the tunnel. Running processes
+/// are found by periodically scanning `/proc`, so new processes are excluded with a small delay.
+/// Processes started by an excluded process inherit its cgroup, and are excluded immediately.
+pub struct AppWatcher {
+    command_tx: mpsc::Sender<Vec<PathBuf>>,
+}
+
+impl AppWatcher {
+    /// Starts watching for processes started from any of `apps`.
+    pub fn start(pid_manager: PidManager, apps: Vec<PathBuf>) -> Self {
+        let (command_tx, command_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut scanner = AppScanner {
+                pid_manager,
+                apps: canonicalize_apps(apps),
+                excluded_pids: HashMap::new(),
+            };
+            loop {
+                if scanner.apps.is_empty() {
+                    // Nothing to scan for. Wait until apps are added.
+                    match command_rx.recv() {
+                        Ok(apps) => scanner.apps = canonicalize_apps(apps),
+                        Err(mpsc::RecvError) => break,
+                    }
+                }
+                scanner.scan();
+                match command_rx.recv_timeout(APP_SCAN_INTERVAL) {
+                    Ok(apps) => scanner.apps = canonicalize_apps(apps),
+                    Err(mpsc::RecvTimeoutError::Timeout) => (),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+        AppWatcher { command_tx }
+    }
+
+    /// Replaces the executables to exclude. Processes started from executables that are no longer
+    /// excluded are moved back into the tunnel.
+    pub fn set_apps(&self, apps: Vec<PathBuf>) {
+        if self.command_tx.send(apps).is_err() {
+            log::error!("Split tunnel app watcher has stopped");
+        }
+    }
+}
+
+struct AppScanner {
+    pid_manager: PidManager,
+    apps: HashSet<PathBuf>,
+    /// Processes that have been added to the cgroup by the scanner, and their executables.
+    excluded_pids: HashMap<i32, PathBuf>,
+}
+
+impl AppScanner {
+    fn scan(&mut self) {
+        if let Err(error) = self.try_scan() {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to exclude split tunnel apps")
+            );
+        }
+    }
+
+    fn try_scan(&mut self) -> Result<(), Error> {
+        let processes = list_processes().map_err(Error::ListProcesses)?;
+        // PIDs may have been removed from the cgroup by other means, so check what it contains
+        let cgroup_pids: HashSet<i32> = self.pid_manager.list()?.into_iter().collect();
+
+        let (removed_pids, added_pids) = self.find_changes(processes, &cgroup_pids);
+
+        // Processes may exit at any time, so a failure for one PID should not affect the others
+        for pid in removed_pids {
+            if let Err(error) = self.pid_manager.remove(pid) {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg(&format!(
+                        "Failed to move process {} back into the tunnel",
+                        pid
+                    ))
+                );
+            }
+        }
+        for (pid, exe) in added_pids {
+            log::debug!(
+                "Excluding process {} ({}) from the tunnel",
+                pid,
+                exe.display()
+            );
+            match self.pid_manager.add(pid) {
+                Ok(()) => {
+                    self.excluded_pids.insert(pid, exe);
+                }
+                Err(error) => log::warn!(
+                    "{}",
+                    error.display_chain_with_msg(&format!(
+                        "Failed to exclude process {} from the tunnel",
+                        pid
+                    ))
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the PIDs to move back into the tunnel, and the processes to exclude from it.
+    /// Exited processes and processes of apps that are no longer excluded are forgotten.
+    fn find_changes(
+        &mut self,
+        processes: HashMap<i32, PathBuf>,
+        cgroup_pids: &HashSet<i32>,
+    ) -> (Vec<i32>, Vec<(i32, PathBuf)>) {
+        let mut removed_pids = vec![];
+        let apps = &self.apps;
+        self.excluded_pids.retain(|pid, exe| {
+            if !processes.contains_key(pid) || !cgroup_pids.contains(pid) {
+                return false;
+            }
+            if !apps.contains(exe) {
+                removed_pids.push(*pid);
+                return false;
+            }
+            true
+        });
+
+        let added_pids = processes
+            .into_iter()
+            .filter(|(pid, exe)| apps.contains(exe) && !cgroup_pids.contains(pid))
+            .collect();
+
+        (removed_pids, added_pids)
+    }
+}
+
+/// Resolves symlinks, so that the paths can be compared to the executables in `/proc`.
+fn canonicalize_apps(apps: Vec<PathBuf>) -> HashSet<PathBuf> {
+    apps.into_iter()
+        .map(|app| fs::canonicalize(&app).unwrap_or(app))
+        .collect()
+}
+
+/// Returns the PIDs of all running processes and their executables. Processes whose executable
+/// cannot be read, such as kernel threads, are skipped.
+fn list_processes() -> io::Result<HashMap<i32, PathBuf>> {
+    let mut processes = HashMap::new();
+    for entry in fs::read_dir("/proc")? {
+        let entry = entry?;
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+        if let Ok(exe) = fs::read_link(entry.path().join("exe")) {
+            processes.insert(pid, strip_deleted_suffix(exe));
+        }
+    }
+    Ok(processes)
+}
+
+/// The kernel appends " (deleted)" to the executable path of processes whose executable has been
+/// replaced, such as after an upgrade. Strips the suffix so that these still match the app path.
+fn strip_deleted_suffix(exe: PathBuf) -> PathBuf {
+    const DELETED_SUFFIX: &[u8] = b" (deleted)";
+
+    let bytes = exe.as_os_str().as_bytes();
+    if bytes.ends_with(DELETED_SUFFIX) {
+        let stripped = &bytes[..bytes.len() - DELETED_SUFFIX.len()];
+        PathBuf::from(OsStr::from_bytes(stripped))
+    } else {
+        exe
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scanner(apps: &[&str], excluded_pids: &[(i32, &str)]) -> AppScanner {
+        AppScanner {
+            pid_manager: PidManager {
+                net_cls_path: PathBuf::new(),
+            },
+            apps: apps.iter().map(PathBuf::from).collect(),
+            excluded_pids: excluded_pids
+                .iter()
+                .map(|(pid, exe)| (*pid, PathBuf::from(exe)))
+                .collect(),
+        }
+    }
+
+    fn processes(processes: &[(i32, &str)]) -> HashMap<i32, PathBuf> {
+        processes
+            .iter()
+            .map(|(pid, exe)| (*pid, PathBuf::from(exe)))
+            .collect()
+    }
+
+    #[test]
+    fn test_new_processes_are_excluded() {
+        let mut scanner = scanner(&["/usr/bin/app"], &[]);
+        let cgroup_pids = [2].iter().cloned().collect();
+
+        let (removed, added) = scanner.find_changes(
+            processes(&[
+                (1, "/usr/bin/app"),
+                (2, "/usr/bin/app"),
+                (3, "/usr/bin/other"),
+            ]),
+            &cgroup_pids,
+        );
+
+        assert!(removed.is_empty());
+        assert_eq!(added, vec![(1, PathBuf::from("/usr/bin/app"))]);
+    }
+
+    #[test]
+    fn test_removed_apps_are_moved_back() {
+        let mut scanner = scanner(&[], &[(1, "/usr/bin/app"), (2, "/usr/bin/app")]);
+        // Process 2 has exited
+        let cgroup_pids = [1, 2].iter().cloned().collect();
+
+        let (removed, added) =
+            scanner.find_changes(processes(&[(1, "/usr/bin/app")]), &cgroup_pids);
+
+        assert_eq!(removed, vec![1]);
+        assert!(added.is_empty());
+        assert!(scanner.excluded_pids.is_empty());
+    }
+
+    #[test]
+    fn test_pids_removed_from_cgroup_are_forgotten() {
+        let mut scanner = scanner(&["/usr/bin/app"], &[(1, "/usr/bin/app")]);
+        let cgroup_pids = HashSet::new();
+
+        let (removed, added) =
+            scanner.find_changes(processes(&[(1, "/usr/bin/app")]), &cgroup_pids);
+
+        assert!(removed.is_empty());
+        assert_eq!(added, vec![(1, PathBuf::from("/usr/bin/app"))]);
+        assert!(scanner.excluded_pids.is_empty());
+    }
+
+    #[test]
+    fn test_strip_deleted_suffix() {
+        assert_eq!(
+            strip_deleted_suffix(PathBuf::from("/usr/bin/app (deleted)")),
+            PathBuf::from("/usr/bin/app")
+        );
+        assert_eq!(
+            strip_deleted_suffix(PathBuf::from("/usr/bin/app")),
+            PathBuf::from("/usr/bin/app")
+        );
+    }
+}
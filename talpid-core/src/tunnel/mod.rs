@@ -6,6 +6,7 @@ use std::{
     io,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 #[cfg(not(target_os = "android"))]
 use talpid_types::net::openvpn as openvpn_types;
@@ -179,21 +180,16 @@ impl TunnelMonitor {
     /// Returns a path to an executable that communicates with relay servers.
     #[cfg(windows)]
     pub fn get_relay_client(resource_dir: &Path, params: &TunnelParameters) -> PathBuf {
-        let resource_dir = resource_dir.to_path_buf();
-        let process_string = match params {
-            TunnelParameters::OpenVpn(params) => {
-                if let Some(proxy) = &params.proxy {
-                    match proxy {
-                        openvpn_types::ProxySettings::Shadowsocks(..) => "sslocal.exe",
-                        _ => "openvpn.exe",
-                    }
-                } else {
-                    "openvpn.exe"
+        match params {
+            // The Shadowsocks client runs in the daemon
+            TunnelParameters::OpenVpn(params) => match &params.proxy {
+                Some(openvpn_types::ProxySettings::Shadowsocks(..)) => {
+                    std::env::current_exe().unwrap()
                 }
-            }
-            _ => return std::env::current_exe().unwrap(),
-        };
-        resource_dir.join(process_string)
+                _ => resource_dir.join("openvpn.exe"),
+            },
+            _ => std::env::current_exe().unwrap(),
+        }
     }
 
     fn start_wireguard_tunnel<L>(
@@ -304,6 +300,11 @@ impl TunnelMonitor {
         self.monitor.close_handle()
     }
 
+    /// Returns a handle for reading the traffic counters of the tunnel while it is running.
+    pub fn stats_handle(&self) -> StatsHandle {
+        self.monitor.stats_handle()
+    }
+
     /// Consumes the monitor and blocks until the tunnel exits or there is an error.
     pub fn wait(self) -> Result<()> {
         self.monitor.wait().map_err(Error::from)
@@ -334,6 +335,38 @@ impl CloseHandle {
     }
 }
 
+/// Traffic counters of a running tunnel.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TunnelStats {
+    /// Number of bytes sent through the tunnel.
+    pub tx_bytes: u64,
+    /// Number of bytes received through the tunnel.
+    pub rx_bytes: u64,
+    /// When the latest handshake with the peer was completed. Only reported by WireGuard.
+    pub last_handshake: Option<SystemTime>,
+}
+
+/// A handle for reading the traffic counters of a `TunnelMonitor`.
+#[derive(Clone)]
+pub enum StatsHandle {
+    #[cfg(not(target_os = "android"))]
+    /// OpenVpn stats handle
+    OpenVpn(openvpn::OpenVpnStatsHandle),
+    /// Wireguard stats handle
+    Wireguard(wireguard::StatsHandle),
+}
+
+impl StatsHandle {
+    /// Returns the current traffic counters, or `None` if they are not available.
+    pub fn get(&self) -> Option<TunnelStats> {
+        match self {
+            #[cfg(not(target_os = "android"))]
+            StatsHandle::OpenVpn(handle) => handle.get(),
+            StatsHandle::Wireguard(handle) => handle.get(),
+        }
+    }
+}
+
 enum InternalTunnelMonitor {
     #[cfg(not(target_os = "android"))]
     OpenVpn(openvpn::OpenVpnMonitor),
@@ -349,6 +382,14 @@ impl InternalTunnelMonitor {
         }
     }
 
+    fn stats_handle(&self) -> StatsHandle {
+        match self {
+            #[cfg(not(target_os = "android"))]
+            InternalTunnelMonitor::OpenVpn(tun) => StatsHandle::OpenVpn(tun.stats_handle()),
+            InternalTunnelMonitor::Wireguard(tun) => StatsHandle::Wireguard(tun.stats_handle()),
+        }
+    }
+
     fn wait(self) -> Result<()> {
         match self {
             #[cfg(not(target_os = "android"))]
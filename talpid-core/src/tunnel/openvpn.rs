@@ -1,11 +1,11 @@
-use super::TunnelEvent;
+use super::{TunnelEvent, TunnelStats};
 use crate::{
     mktemp,
     process::{
         openvpn::{OpenVpnCommand, OpenVpnProcHandle},
         stoppable_process::StoppableProcess,
     },
-    proxy::{self, ProxyMonitor, ProxyResourceData},
+    proxy::{self, ProxyMonitor},
 };
 use std::{
     collections::HashMap,
@@ -15,7 +15,7 @@ use std::{
     process::ExitStatus,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc, Arc,
+        mpsc, Arc, Mutex,
     },
     thread,
     time::Duration,
@@ -80,9 +80,13 @@ pub enum Error {
     #[error(display = "Error while writing credentials to temporary file")]
     CredentialsWriteError(#[error(source)] io::Error),
 
+    /// Error while writing the certificates and keys of a custom server to temporary files.
+    #[error(display = "Error while writing custom server configuration to temporary files")]
+    CustomConfigWriteError(#[error(source)] io::Error),
+
     /// Failures related to the proxy service.
     #[error(display = "Unable to start the proxy service")]
-    StartProxyError(#[error(source)] io::Error),
+    StartProxyError(#[error(source)] proxy::Error),
 
     /// Error while monitoring proxy service
     #[error(display = "Error while monitoring proxy service")]
@@ -127,10 +131,14 @@ pub struct OpenVpnMonitor<C: OpenVpnBuilder = OpenVpnCommand> {
     proxy_monitor: Option<Box<dyn ProxyMonitor>>,
     log_path: Option<PathBuf>,
     closed: Arc<AtomicBool>,
+    stats: Arc<Mutex<Option<TunnelStats>>>,
     /// Keep the `TempFile` for the user-pass file in the struct, so it's removed on drop.
     _user_pass_file: mktemp::TempFile,
     /// Keep the 'TempFile' for the proxy user-pass file in the struct, so it's removed on drop.
     _proxy_auth_file: Option<mktemp::TempFile>,
+    /// Keep the `TempFile`s of a custom server configuration in the struct, so they're removed
+    /// on drop.
+    _custom_config_files: Option<CustomConfigFiles>,
 
     runtime: tokio02::runtime::Runtime,
     event_server_abort_tx: triggered::Trigger,
@@ -156,6 +164,13 @@ impl OpenVpnMonitor<OpenVpnCommand> {
         let proxy_auth_file =
             Self::create_proxy_auth_file(&params.proxy).map_err(Error::CredentialsWriteError)?;
 
+        let custom_config_files = match params.config.custom_config {
+            Some(ref custom_config) => {
+                Some(CustomConfigFiles::new(custom_config).map_err(Error::CustomConfigWriteError)?)
+            }
+            None => None,
+        };
+
         let user_pass_file_path = user_pass_file.to_path_buf();
 
         let proxy_auth_file_path = match proxy_auth_file {
@@ -163,7 +178,13 @@ impl OpenVpnMonitor<OpenVpnCommand> {
             _ => None,
         };
 
-        let on_openvpn_event = move |event, env| {
+        let stats = Arc::new(Mutex::new(None));
+        let event_stats = stats.clone();
+
+        let on_openvpn_event = move |event, env: HashMap<String, String>| {
+            if let Some(new_stats) = parse_traffic_counters(&env) {
+                *event_stats.lock().expect("Stats lock poisoned") = Some(new_stats);
+            }
             if event == openvpn_plugin::EventType::RouteUp {
                 // The user-pass file has been read. Try to delete it early.
                 let _ = fs::remove_file(&user_pass_file_path);
@@ -179,18 +200,7 @@ impl OpenVpnMonitor<OpenVpnCommand> {
             }
         };
 
-        let log_dir: Option<PathBuf> = if let Some(ref log_path) = log_path {
-            Some(log_path.parent().expect("log_path has no parent").into())
-        } else {
-            None
-        };
-
-        let proxy_resources = proxy::ProxyResourceData {
-            resource_dir: resource_dir.to_path_buf(),
-            log_dir,
-        };
-
-        let proxy_monitor = Self::start_proxy(&params.proxy, &proxy_resources)?;
+        let proxy_monitor = Self::start_proxy(&params.proxy)?;
 
         let cmd = Self::create_openvpn_cmd(
             params,
@@ -199,13 +209,14 @@ impl OpenVpnMonitor<OpenVpnCommand> {
                 Some(ref file) => Some(file.as_ref()),
                 _ => None,
             },
+            custom_config_files.as_ref(),
             resource_dir,
             &proxy_monitor,
         )?;
 
         let plugin_path = Self::get_plugin_path(resource_dir)?;
 
-        Self::new_internal(
+        let mut monitor = Self::new_internal(
             cmd,
             on_openvpn_event,
             &plugin_path,
@@ -213,10 +224,25 @@ impl OpenVpnMonitor<OpenVpnCommand> {
             user_pass_file,
             proxy_auth_file,
             proxy_monitor,
-        )
+        )?;
+        monitor.stats = stats;
+        monitor._custom_config_files = custom_config_files;
+        Ok(monitor)
     }
 }
 
+/// Reads the `bytes_sent` and `bytes_received` counters that OpenVPN includes in the environment
+/// of some plugin events. Returns `None` if the event does not carry any counters.
+fn parse_traffic_counters(env: &HashMap<String, String>) -> Option<TunnelStats> {
+    let tx_bytes = env.get("bytes_sent")?.parse().ok()?;
+    let rx_bytes = env.get("bytes_received")?.parse().ok()?;
+    Some(TunnelStats {
+        tx_bytes,
+        rx_bytes,
+        last_handshake: None,
+    })
+}
+
 impl<C: OpenVpnBuilder + 'static> OpenVpnMonitor<C> {
     fn new_internal<L>(
         mut cmd: C,
@@ -272,8 +298,10 @@ impl<C: OpenVpnBuilder + 'static> OpenVpnMonitor<C> {
             proxy_monitor,
             log_path,
             closed: Arc::new(AtomicBool::new(false)),
+            stats: Arc::new(Mutex::new(None)),
             _user_pass_file: user_pass_file,
             _proxy_auth_file: proxy_auth_file,
+            _custom_config_files: None,
 
             runtime,
             event_server_abort_tx,
@@ -290,6 +318,13 @@ impl<C: OpenVpnBuilder + 'static> OpenVpnMonitor<C> {
         }
     }
 
+    /// Creates a handle for reading the latest traffic counters reported by OpenVPN.
+    pub fn stats_handle(&self) -> OpenVpnStatsHandle {
+        OpenVpnStatsHandle {
+            stats: self.stats.clone(),
+        }
+    }
+
     /// Consumes the monitor and waits for both proxy and tunnel, as applicable.
     pub fn wait(mut self) -> Result<()> {
         if let Some(mut proxy_monitor) = self.proxy_monitor.take() {
@@ -434,38 +469,20 @@ impl<C: OpenVpnBuilder + 'static> OpenVpnMonitor<C> {
     /// Starts a proxy service, as applicable.
     fn start_proxy(
         proxy_settings: &Option<openvpn::ProxySettings>,
-        proxy_resources: &ProxyResourceData,
     ) -> Result<Option<Box<dyn ProxyMonitor>>> {
         if let Some(ref settings) = proxy_settings {
-            let proxy_monitor =
-                proxy::start_proxy(settings, proxy_resources).map_err(Error::StartProxyError)?;
+            let proxy_monitor = proxy::start_proxy(settings).map_err(Error::StartProxyError)?;
             return Ok(Some(proxy_monitor));
         }
         Ok(None)
     }
 
     fn create_credentials_file(username: &str, password: &str) -> io::Result<mktemp::TempFile> {
-        let temp_file = mktemp::TempFile::new();
-        log::debug!("Writing credentials to {}", temp_file.as_ref().display());
-        let mut file = fs::File::create(&temp_file)?;
-        Self::set_user_pass_file_permissions(&file)?;
-        write!(file, "{}\n{}\n", username, password)?;
+        let temp_file = create_private_file(&format!("{}\n{}\n", username, password))?;
+        log::debug!("Wrote credentials to {}", temp_file.as_ref().display());
         Ok(temp_file)
     }
 
-
-    #[cfg(unix)]
-    fn set_user_pass_file_permissions(file: &fs::File) -> io::Result<()> {
-        use std::os::unix::fs::PermissionsExt;
-        file.set_permissions(PermissionsExt::from_mode(0o400))
-    }
-
-    #[cfg(windows)]
-    fn set_user_pass_file_permissions(_file: &fs::File) -> io::Result<()> {
-        // TODO(linus): Lock permissions correctly on Windows.
-        Ok(())
-    }
-
     fn get_plugin_path(resource_dir: &Path) -> Result<PathBuf> {
         let path = resource_dir.join(OPENVPN_PLUGIN_FILENAME);
         if path.exists() {
@@ -480,6 +497,7 @@ impl<C: OpenVpnBuilder + 'static> OpenVpnMonitor<C> {
         params: &openvpn::TunnelParameters,
         user_pass_file: &Path,
         proxy_auth_file: Option<&Path>,
+        custom_config_files: Option<&CustomConfigFiles>,
         resource_dir: &Path,
         proxy_monitor: &Option<Box<dyn ProxyMonitor>>,
     ) -> Result<OpenVpnCommand> {
@@ -490,10 +508,20 @@ impl<C: OpenVpnBuilder + 'static> OpenVpnMonitor<C> {
         #[cfg(target_os = "linux")]
         cmd.iproute_bin(which::which("ip").map_err(Error::IpRouteNotFound)?);
         cmd.remote(params.config.endpoint)
-            .user_pass(user_pass_file)
             .tunnel_options(&params.options)
-            .enable_ipv6(params.generic_options.enable_ipv6)
-            .ca(resource_dir.join("ca.crt"));
+            .enable_ipv6(params.generic_options.enable_ipv6);
+        match (&params.config.custom_config, custom_config_files) {
+            (Some(custom_config), Some(files)) => {
+                if custom_config.auth_user_pass {
+                    cmd.user_pass(user_pass_file);
+                }
+                files.apply(&mut cmd, custom_config);
+            }
+            _ => {
+                cmd.user_pass(user_pass_file)
+                    .ca(resource_dir.join("ca.crt"));
+            }
+        }
         #[cfg(windows)]
         cmd.tunnel_alias(Some(
             crate::winnet::get_tap_interface_alias().map_err(Error::WinnetError)?,
@@ -531,6 +559,92 @@ impl<C: OpenVpnBuilder + 'static> OpenVpnMonitor<C> {
     }
 }
 
+/// Temporary files holding the certificates and keys of a custom server, since OpenVPN only
+/// accepts inline blocks in configuration files.
+#[derive(Debug)]
+struct CustomConfigFiles {
+    ca: mktemp::TempFile,
+    client_cert: Option<(mktemp::TempFile, mktemp::TempFile)>,
+    tls_auth: Option<mktemp::TempFile>,
+}
+
+impl CustomConfigFiles {
+    fn new(config: &openvpn::CustomConfig) -> io::Result<Self> {
+        log::debug!("Writing custom server configuration to temporary files");
+        let client_cert = match (&config.cert, &config.key) {
+            (Some(cert), Some(key)) => {
+                Some((create_private_file(cert)?, create_private_file(key)?))
+            }
+            _ => None,
+        };
+        let tls_auth = match &config.tls_auth {
+            Some(openvpn::TlsAuth::Auth { key, .. }) | Some(openvpn::TlsAuth::Crypt { key }) => {
+                Some(create_private_file(key)?)
+            }
+            None => None,
+        };
+        Ok(CustomConfigFiles {
+            ca: create_private_file(&config.ca)?,
+            client_cert,
+            tls_auth,
+        })
+    }
+
+    fn apply(&self, cmd: &mut OpenVpnCommand, config: &openvpn::CustomConfig) {
+        cmd.ca(&self.ca)
+            .restrict_tls_ciphers(false)
+            .redirect_gateway(true);
+        if let Some((ref cert, ref key)) = self.client_cert {
+            cmd.client_cert(cert, key);
+        }
+        match (&config.tls_auth, &self.tls_auth) {
+            (Some(openvpn::TlsAuth::Auth { key_direction, .. }), Some(file)) => {
+                cmd.tls_auth(file, *key_direction);
+            }
+            (Some(openvpn::TlsAuth::Crypt { .. }), Some(file)) => {
+                cmd.tls_crypt(file);
+            }
+            _ => (),
+        }
+        if let Some(ref cipher) = config.cipher {
+            cmd.cipher(cipher.as_str());
+        }
+        if let Some(ref data_ciphers) = config.data_ciphers {
+            cmd.data_ciphers(data_ciphers.as_str());
+        }
+        if let Some(ref verify_x509_name) = config.verify_x509_name {
+            cmd.verify_x509_name(
+                verify_x509_name.name.as_str(),
+                verify_x509_name.name_type.clone(),
+            );
+        }
+        if let Some(ref auth) = config.auth {
+            cmd.auth(auth.as_str());
+        }
+    }
+}
+
+/// Writes `contents` to a new temporary file that only the owner can read.
+fn create_private_file(contents: &str) -> io::Result<mktemp::TempFile> {
+    let temp_file = mktemp::TempFile::new();
+    let mut file = fs::File::create(&temp_file)?;
+    set_private_file_permissions(&file)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(temp_file)
+}
+
+#[cfg(unix)]
+fn set_private_file_permissions(file: &fs::File) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    file.set_permissions(PermissionsExt::from_mode(0o400))
+}
+
+#[cfg(windows)]
+fn set_private_file_permissions(_file: &fs::File) -> io::Result<()> {
+    // TODO(linus): Lock permissions correctly on Windows.
+    Ok(())
+}
+
 /// A handle to an `OpenVpnMonitor` for closing it.
 #[derive(Debug, Clone)]
 pub struct OpenVpnCloseHandle<H: ProcessHandle = OpenVpnProcHandle> {
@@ -549,6 +663,19 @@ impl<H: ProcessHandle> OpenVpnCloseHandle<H> {
     }
 }
 
+/// A handle to an `OpenVpnMonitor` for reading traffic counters.
+#[derive(Debug, Clone)]
+pub struct OpenVpnStatsHandle {
+    stats: Arc<Mutex<Option<TunnelStats>>>,
+}
+
+impl OpenVpnStatsHandle {
+    /// Returns the latest traffic counters reported by OpenVPN, if any have been reported.
+    pub fn get(&self) -> Option<TunnelStats> {
+        *self.stats.lock().expect("Stats lock poisoned")
+    }
+}
+
 /// Internal enum to differentiate between if the child process or the event dispatcher died first.
 #[derive(Debug)]
 enum WaitResult {
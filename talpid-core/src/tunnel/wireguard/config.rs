@@ -1,11 +1,13 @@
 use std::{
     borrow::Cow,
+    collections::HashSet,
     ffi::CString,
-    net::{Ipv4Addr, Ipv6Addr},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
 };
 use talpid_types::net::{wireguard, GenericTunnelOptions};
 
 /// Config required to set up a single WireGuard tunnel
+#[derive(Clone)]
 pub struct Config {
     /// Contains tunnel endpoint specific config
     pub tunnel: wireguard::TunnelConfig,
@@ -17,6 +19,8 @@ pub struct Config {
     pub ipv6_gateway: Option<Ipv6Addr>,
     /// Maximum transmission unit for the tunnel
     pub mtu: u16,
+    /// TCP endpoint to tunnel the traffic of the first peer through
+    pub udp_over_tcp_endpoint: Option<SocketAddr>,
 }
 
 const DEFAULT_MTU: u16 = 1380;
@@ -35,16 +39,26 @@ pub enum Error {
     /// Parameters don't contain any peers
     #[error(display = "No peers supplied")]
     NoPeersSuppliedError,
+
+    /// More than one peer uses the same allowed IP
+    #[error(display = "Allowed IP {} is used by more than one peer", _0)]
+    DuplicateAllowedIpError(ipnetwork::IpNetwork),
 }
 
 impl Config {
     /// Constructs a Config from parameters
     pub fn from_parameters(params: &wireguard::TunnelParameters) -> Result<Config, Error> {
         let tunnel = params.connection.tunnel.clone();
-        let peer = vec![params.connection.peer.clone()];
+        let mut peer = params.connection.peer.clone();
+        // When using multihop, the entry relay forwards the traffic to the exit peer
+        if let Some(entry_endpoint) = params.connection.entry_endpoint {
+            peer.endpoint = entry_endpoint;
+        }
+        let mut peers = vec![peer];
+        peers.extend(params.connection.additional_peers.iter().cloned());
         Self::new(
             tunnel,
-            peer,
+            peers,
             &params.connection,
             &params.options,
             &params.generic_options,
@@ -63,6 +77,7 @@ impl Config {
             return Err(Error::NoPeersSuppliedError);
         }
         let mtu = wg_options.mtu.unwrap_or(DEFAULT_MTU);
+        let mut allowed_ips = HashSet::new();
         for peer in &mut peers {
             peer.allowed_ips = peer
                 .allowed_ips
@@ -73,6 +88,11 @@ impl Config {
             if peer.allowed_ips.is_empty() {
                 return Err(Error::InvalidPeerIpError);
             }
+            for allowed_ip in &peer.allowed_ips {
+                if !allowed_ips.insert(*allowed_ip) {
+                    return Err(Error::DuplicateAllowedIpError(*allowed_ip));
+                }
+            }
         }
 
         if tunnel.addresses.is_empty() {
@@ -96,6 +116,7 @@ impl Config {
             ipv4_gateway: connection_config.ipv4_gateway,
             ipv6_gateway,
             mtu,
+            udp_over_tcp_endpoint: connection_config.udp_over_tcp_endpoint,
         })
     }
 
@@ -111,10 +132,18 @@ impl Config {
         wg_conf.add("replace_peers", "true");
 
         for peer in &self.peers {
-            wg_conf
-                .add("public_key", peer.public_key.as_bytes().as_ref())
-                .add("endpoint", peer.endpoint.to_string().as_str())
-                .add("replace_allowed_ips", "true");
+            wg_conf.add("public_key", peer.public_key.as_bytes().as_ref());
+            if let Some(preshared_key) = &peer.preshared_key {
+                wg_conf.add("preshared_key", preshared_key.as_bytes().as_ref());
+            }
+            wg_conf.add("endpoint", peer.endpoint.to_string().as_str());
+            if let Some(interval) = peer.persistent_keepalive {
+                wg_conf.add(
+                    "persistent_keepalive_interval",
+                    interval.to_string().as_str(),
+                );
+            }
+            wg_conf.add("replace_allowed_ips", "true");
             for addr in &peer.allowed_ips {
                 wg_conf.add("allowed_ip", addr.to_string().as_str());
             }
@@ -174,3 +203,79 @@ impl WgConfigBuffer {
         self.buf
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn peer(key: u8, allowed_ip: &str) -> wireguard::PeerConfig {
+        wireguard::PeerConfig {
+            public_key: wireguard::PublicKey::from([key; 32]),
+            allowed_ips: vec![allowed_ip.parse().unwrap()],
+            endpoint: "192.0.2.1:51820".parse().unwrap(),
+            preshared_key: None,
+            persistent_keepalive: None,
+        }
+    }
+
+    fn parameters(
+        peer: wireguard::PeerConfig,
+        additional_peers: Vec<wireguard::PeerConfig>,
+    ) -> wireguard::TunnelParameters {
+        wireguard::TunnelParameters {
+            connection: wireguard::ConnectionConfig {
+                tunnel: wireguard::TunnelConfig {
+                    private_key: wireguard::PrivateKey::from([1; 32]),
+                    addresses: vec!["10.99.0.2".parse().unwrap()],
+                },
+                peer,
+                additional_peers,
+                ipv4_gateway: "10.64.0.1".parse().unwrap(),
+                ipv6_gateway: None,
+                entry_endpoint: None,
+                udp_over_tcp_endpoint: None,
+            },
+            options: wireguard::TunnelOptions {
+                mtu: None,
+                automatic_rotation: None,
+            },
+            generic_options: GenericTunnelOptions { enable_ipv6: false },
+        }
+    }
+
+    #[test]
+    fn test_userspace_format_with_additional_peers() {
+        let mut primary = peer(2, "0.0.0.0/0");
+        primary.preshared_key = Some(wireguard::PresharedKey::from([3; 32]));
+        primary.persistent_keepalive = Some(25);
+        let config =
+            Config::from_parameters(&parameters(primary, vec![peer(4, "10.100.0.0/16")])).unwrap();
+        assert_eq!(config.peers.len(), 2);
+
+        let userspace_config = config.to_userspace_format().into_string().unwrap();
+        assert!(userspace_config.contains(&format!(
+            "public_key={}\npreshared_key={}\nendpoint=192.0.2.1:51820\n\
+             persistent_keepalive_interval=25\n",
+            hex::encode([2; 32]),
+            hex::encode([3; 32])
+        )));
+        assert!(userspace_config.contains(&format!(
+            "public_key={}\nendpoint=192.0.2.1:51820\nreplace_allowed_ips=true\n\
+             allowed_ip=10.100.0.0/16\n",
+            hex::encode([4; 32])
+        )));
+    }
+
+    #[test]
+    fn test_duplicate_allowed_ips() {
+        let result = Config::from_parameters(&parameters(
+            peer(2, "0.0.0.0/0"),
+            vec![peer(4, "0.0.0.0/0")],
+        ));
+        match result {
+            Err(Error::DuplicateAllowedIpError(_)) => (),
+            Err(error) => panic!("Unexpected error: {}", error),
+            Ok(_) => panic!("Expected an error"),
+        }
+    }
+}
@@ -386,6 +386,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 0,
+                last_handshake: None,
             },
         );
 
@@ -409,6 +410,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 0,
+                last_handshake: None,
             },
         );
 
@@ -431,6 +433,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 0,
+                last_handshake: None,
             },
         );
 
@@ -440,6 +443,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 1,
+                last_handshake: None,
             },
         );
 
@@ -477,6 +481,7 @@ mod test {
             let traffic = Mutex::new(stats::Stats {
                 tx_bytes: 0,
                 rx_bytes: 0,
+                last_handshake: None,
             });
             Self {
                 on_get_stats: Box::new(move || {
@@ -495,6 +500,7 @@ mod test {
                     Ok(stats::Stats {
                         tx_bytes: 0,
                         rx_bytes: 0,
+                        last_handshake: None,
                     })
                 }),
             }
@@ -550,6 +556,7 @@ mod test {
             stats: stats::Stats {
                 tx_bytes: 0,
                 rx_bytes: 0,
+                last_handshake: None,
             },
         }
     }
@@ -641,6 +648,7 @@ mod test {
         let tunnel_stats = Mutex::new(stats::Stats {
             rx_bytes: 0,
             tx_bytes: 0,
+            last_handshake: None,
         });
 
         let pinger = MockPinger::default();
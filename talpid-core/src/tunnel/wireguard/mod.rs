@@ -1,12 +1,18 @@
 use self::config::Config;
 #[cfg(not(windows))]
 use super::tun_provider;
-use super::{tun_provider::TunProvider, TunnelEvent, TunnelMetadata};
-use crate::routing::{self, RequiredRoute};
+use super::{tun_provider::TunProvider, TunnelEvent, TunnelMetadata, TunnelStats};
+use crate::{
+    proxy::{self, ProxyMonitorCloseHandle, WaitResult},
+    routing::{self, RequiredRoute},
+};
 use std::{
+    borrow::Cow,
     collections::HashSet,
+    io,
+    net::{Ipv4Addr, SocketAddr},
     path::Path,
-    sync::{mpsc, Arc, Mutex},
+    sync::{mpsc, Arc, Mutex, Weak},
 };
 use talpid_types::ErrorExt;
 
@@ -14,7 +20,8 @@ use talpid_types::ErrorExt;
 pub mod config;
 mod connectivity_check;
 mod logging;
-mod stats;
+/// Traffic counters of a WireGuard tunnel
+pub mod stats;
 mod wireguard_go;
 
 use self::wireguard_go::WgGoTunnel;
@@ -39,6 +46,14 @@ pub enum Error {
     /// Failed to setup connectivity monitor
     #[error(display = "Connectivity monitor failed")]
     ConnectivityMonitorError(#[error(source)] connectivity_check::Error),
+
+    /// Failed to start the UDP-over-TCP proxy
+    #[error(display = "Failed to start UDP-over-TCP proxy")]
+    StartProxyError(#[error(source)] io::Error),
+
+    /// The UDP-over-TCP proxy exited unexpectedly
+    #[error(display = "UDP-over-TCP proxy exited unexpectedly: {}", _0)]
+    ProxyExited(String),
 }
 
 
@@ -51,6 +66,8 @@ pub struct WireguardMonitor {
     close_msg_sender: mpsc::Sender<CloseMsg>,
     close_msg_receiver: mpsc::Receiver<CloseMsg>,
     pinger_stop_sender: mpsc::Sender<()>,
+    /// Close handle for the UDP-over-TCP proxy, if one is used
+    proxy_close_handle: Option<Box<dyn ProxyMonitorCloseHandle>>,
 }
 
 impl WireguardMonitor {
@@ -62,16 +79,33 @@ impl WireguardMonitor {
         tun_provider: &mut TunProvider,
         route_manager: &mut routing::RouteManager,
     ) -> Result<WireguardMonitor> {
-        let tunnel = Box::new(WgGoTunnel::start_tunnel(
-            &config,
-            log_path,
-            tun_provider,
-            Self::get_tunnel_routes(config),
-        )?);
+        let mut proxy = match config.udp_over_tcp_endpoint {
+            Some(endpoint) => {
+                Some(proxy::start_udp_over_tcp_proxy(endpoint).map_err(Error::StartProxyError)?)
+            }
+            None => None,
+        };
+        let mut proxy_close_handle = proxy.as_mut().map(|proxy| proxy.close_handle());
+
+        // When using UDP-over-TCP, WireGuard talks to the local end of the proxy instead
+        let config = match &proxy {
+            Some(proxy) => {
+                let mut config = config.clone();
+                config.peers[0].endpoint =
+                    SocketAddr::new(Ipv4Addr::LOCALHOST.into(), proxy.port());
+                Cow::Owned(config)
+            }
+            None => Cow::Borrowed(config),
+        };
+
+        let tunnel = match Self::start_tunnel(&config, log_path, tun_provider, route_manager) {
+            Ok(tunnel) => tunnel,
+            Err(error) => {
+                Self::close_proxy(&mut proxy_close_handle);
+                return Err(error);
+            }
+        };
         let iface_name = tunnel.get_interface_name().to_string();
-        route_manager
-            .add_routes(Self::get_routes(&iface_name, &config))
-            .map_err(Error::SetupRoutingError)?;
 
         #[cfg(target_os = "windows")]
         route_manager
@@ -86,8 +120,21 @@ impl WireguardMonitor {
             close_msg_sender,
             close_msg_receiver,
             pinger_stop_sender: pinger_tx,
+            proxy_close_handle,
         };
 
+        if let Some(proxy) = proxy {
+            let close_sender = monitor.close_msg_sender.clone();
+            std::thread::spawn(move || {
+                let reason = match proxy.wait() {
+                    Ok(WaitResult::ProperShutdown) => return,
+                    Ok(WaitResult::UnexpectedExit(reason)) => reason,
+                    Err(error) => error.to_string(),
+                };
+                let _ = close_sender.send(CloseMsg::ProxyExited(reason));
+            });
+        }
+
         let metadata = Self::tunnel_metadata(&iface_name, &config);
         let gateway = config.ipv4_gateway;
         let close_sender = monitor.close_msg_sender.clone();
@@ -125,6 +172,35 @@ impl WireguardMonitor {
         Ok(monitor)
     }
 
+    fn start_tunnel(
+        config: &Config,
+        log_path: Option<&Path>,
+        tun_provider: &mut TunProvider,
+        route_manager: &mut routing::RouteManager,
+    ) -> Result<Box<dyn Tunnel>> {
+        let tunnel = Box::new(WgGoTunnel::start_tunnel(
+            config,
+            log_path,
+            tun_provider,
+            Self::get_tunnel_routes(config),
+        )?);
+        route_manager
+            .add_routes(Self::get_routes(tunnel.get_interface_name(), config))
+            .map_err(Error::SetupRoutingError)?;
+        Ok(tunnel)
+    }
+
+    fn close_proxy(proxy_close_handle: &mut Option<Box<dyn ProxyMonitorCloseHandle>>) {
+        if let Some(close_handle) = proxy_close_handle.take() {
+            if let Err(error) = close_handle.close() {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to stop UDP-over-TCP proxy")
+                );
+            }
+        }
+    }
+
     /// Returns a close handle for the tunnel
     pub fn close_handle(&self) -> CloseHandle {
         CloseHandle {
@@ -132,17 +208,26 @@ impl WireguardMonitor {
         }
     }
 
+    /// Returns a handle for reading the traffic counters of the tunnel
+    pub fn stats_handle(&self) -> StatsHandle {
+        StatsHandle {
+            tunnel: Arc::downgrade(&self.tunnel),
+        }
+    }
+
     /// Blocks the current thread until tunnel disconnects
     pub fn wait(mut self) -> Result<()> {
         let wait_result = match self.close_msg_receiver.recv() {
             Ok(CloseMsg::PingErr) => Err(Error::TimeoutError),
             Ok(CloseMsg::Stop) => Ok(()),
+            Ok(CloseMsg::ProxyExited(reason)) => Err(Error::ProxyExited(reason)),
             Err(_) => Ok(()),
         };
 
         let _ = self.pinger_stop_sender.send(());
 
         self.stop_tunnel();
+        Self::close_proxy(&mut self.proxy_close_handle);
 
         (self.event_callback)(TunnelEvent::Down);
         wait_result
@@ -188,11 +273,21 @@ impl WireguardMonitor {
 
         // route endpoints with specific routes
         for peer in config.peers.iter() {
+            // Traffic to the local end of the UDP-over-TCP proxy must not leave the host
+            if peer.endpoint.ip().is_loopback() {
+                continue;
+            }
             routes.insert(RequiredRoute::new(
                 peer.endpoint.ip().into(),
                 routing::NetNode::DefaultNode,
             ));
         }
+        if let Some(endpoint) = config.udp_over_tcp_endpoint {
+            routes.insert(RequiredRoute::new(
+                endpoint.ip().into(),
+                routing::NetNode::DefaultNode,
+            ));
+        }
 
         routes
     }
@@ -210,6 +305,7 @@ impl WireguardMonitor {
 enum CloseMsg {
     Stop,
     PingErr,
+    ProxyExited(String),
 }
 
 /// Close handle for a WireGuard tunnel.
@@ -227,6 +323,34 @@ impl CloseHandle {
     }
 }
 
+/// Reads the traffic counters of a WireGuard tunnel.
+#[derive(Clone)]
+pub struct StatsHandle {
+    tunnel: Weak<Mutex<Option<Box<dyn Tunnel>>>>,
+}
+
+impl StatsHandle {
+    /// Returns the current traffic counters, or `None` if the tunnel is no longer running.
+    pub fn get(&self) -> Option<TunnelStats> {
+        let tunnel = self.tunnel.upgrade()?;
+        let tunnel = tunnel.lock().expect("Tunnel lock poisoned");
+        match tunnel.as_ref()?.get_tunnel_stats() {
+            Ok(stats) => Some(TunnelStats {
+                tx_bytes: stats.tx_bytes,
+                rx_bytes: stats.rx_bytes,
+                last_handshake: stats.last_handshake,
+            }),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to read tunnel stats")
+                );
+                None
+            }
+        }
+    }
+}
+
 pub(crate) trait Tunnel: Send {
     fn get_interface_name(&self) -> &str;
     fn stop(self: Box<Self>) -> std::result::Result<(), TunnelError>;
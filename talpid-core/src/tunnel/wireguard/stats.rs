@@ -1,8 +1,13 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Errors that can happen when parsing tunnel stats
 #[derive(err_derive::Error, Debug, PartialEq)]
 pub enum Error {
+    /// A counter in the config could not be parsed
     #[error(display = "Failed to parse integer from string \"_0\"")]
     IntParseError(String, #[error(source)] std::num::ParseIntError),
 
+    /// A counter is missing from the config
     #[error(display = "Config key not found")]
     KeyNotFoundError,
 }
@@ -10,14 +15,23 @@ pub enum Error {
 /// Contains bytes sent and received through a tunnel
 #[derive(Default, Debug, PartialEq, Clone, Copy)]
 pub struct Stats {
+    /// Number of bytes sent through the tunnel
     pub tx_bytes: u64,
+    /// Number of bytes received through the tunnel
     pub rx_bytes: u64,
+    /// When the latest handshake with the peer was completed, if one has been completed
+    pub last_handshake: Option<SystemTime>,
 }
 
 impl Stats {
+    /// Reads the traffic counters from a WireGuard userspace configuration string. The counters
+    /// of all peers are summed, and the most recent handshake of any peer is used.
     pub fn parse_config_str(config: &str) -> Result<Self, Error> {
-        let mut tx_bytes = None;
-        let mut rx_bytes = None;
+        let mut tx_bytes: Option<u64> = None;
+        let mut rx_bytes: Option<u64> = None;
+        let mut last_handshake = None;
+        let mut handshake_sec = 0;
+        let mut handshake_nsec = 0;
 
         // parts iterates over keys and values
         let parts = config.split('\n').filter_map(|line| {
@@ -29,38 +43,67 @@ impl Stats {
 
         for (key, value) in parts {
             match key {
+                // Every peer starts with its public key
+                "public_key" => {
+                    last_handshake =
+                        newest_handshake(last_handshake, handshake_sec, handshake_nsec);
+                    handshake_sec = 0;
+                    handshake_nsec = 0;
+                }
                 "rx_bytes" => {
-                    rx_bytes = Some(
-                        value
-                            .trim()
-                            .parse()
-                            .map_err(|err| Error::IntParseError(value.to_string(), err))?,
-                    );
+                    rx_bytes = Some(rx_bytes.unwrap_or(0) + parse_int::<u64>(value)?);
                 }
                 "tx_bytes" => {
-                    tx_bytes = Some(
-                        value
-                            .trim()
-                            .parse()
-                            .map_err(|err| Error::IntParseError(value.to_string(), err))?,
-                    );
+                    tx_bytes = Some(tx_bytes.unwrap_or(0) + parse_int::<u64>(value)?);
+                }
+                "last_handshake_time_sec" => {
+                    handshake_sec = parse_int(value)?;
+                }
+                "last_handshake_time_nsec" => {
+                    handshake_nsec = parse_int(value)?;
                 }
 
                 _ => continue,
             }
         }
+        let last_handshake = newest_handshake(last_handshake, handshake_sec, handshake_nsec);
 
         match (tx_bytes, rx_bytes) {
-            (Some(tx_bytes), Some(rx_bytes)) => Ok(Self { tx_bytes, rx_bytes }),
+            (Some(tx_bytes), Some(rx_bytes)) => Ok(Self {
+                tx_bytes,
+                rx_bytes,
+                last_handshake,
+            }),
             _ => Err(Error::KeyNotFoundError),
         }
     }
 }
 
+fn parse_int<T: std::str::FromStr<Err = std::num::ParseIntError>>(value: &str) -> Result<T, Error> {
+    value
+        .trim()
+        .parse()
+        .map_err(|err| Error::IntParseError(value.to_string(), err))
+}
+
+/// Returns the most recent of `newest` and the handshake of a peer.
+fn newest_handshake(
+    newest: Option<SystemTime>,
+    handshake_sec: u64,
+    handshake_nsec: u32,
+) -> Option<SystemTime> {
+    // A handshake time of zero means that no handshake has been completed
+    if handshake_sec == 0 && handshake_nsec == 0 {
+        return newest;
+    }
+    let handshake = UNIX_EPOCH + Duration::new(handshake_sec, handshake_nsec);
+    Some(newest.map_or(handshake, |newest| newest.max(handshake)))
+}
 
 #[cfg(test)]
 mod test {
     use super::{Error, Stats};
+    use std::time::{Duration, UNIX_EPOCH};
 
     #[test]
     fn test_parsing() {
@@ -69,6 +112,23 @@ mod test {
         let stats = Stats::parse_config_str(valid_input).expect("Failed to parse valid input");
         assert_eq!(stats.rx_bytes, 2396);
         assert_eq!(stats.tx_bytes, 2740);
+        assert_eq!(
+            stats.last_handshake,
+            Some(UNIX_EPOCH + Duration::new(1578420649, 369416131))
+        );
+    }
+
+    #[test]
+    fn test_parsing_multiple_peers() {
+        let valid_input = "private_key=0000000000000000000000000000000000000000000000000000000000000000\npublic_key=0000000000000000000000000000000000000000000000000000000000000000\nprotocol_version=1\nendpoint=000.000.000.000:00000\nlast_handshake_time_sec=1578420649\nlast_handshake_time_nsec=369416131\ntx_bytes=2740\nrx_bytes=2396\npersistent_keepalive_interval=0\nallowed_ip=0.0.0.0/0\npublic_key=1111111111111111111111111111111111111111111111111111111111111111\nprotocol_version=1\nendpoint=000.000.000.000:00000\nlast_handshake_time_sec=0\nlast_handshake_time_nsec=0\ntx_bytes=100\nrx_bytes=0\npersistent_keepalive_interval=0\nallowed_ip=10.0.0.0/8\n";
+
+        let stats = Stats::parse_config_str(valid_input).expect("Failed to parse valid input");
+        assert_eq!(stats.rx_bytes, 2396);
+        assert_eq!(stats.tx_bytes, 2840);
+        assert_eq!(
+            stats.last_handshake,
+            Some(UNIX_EPOCH + Duration::new(1578420649, 369416131))
+        );
     }
 
     #[test]
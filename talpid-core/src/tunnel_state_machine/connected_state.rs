@@ -4,12 +4,13 @@ use super::{
 };
 use crate::{
     firewall::FirewallPolicy,
-    tunnel::{CloseHandle, TunnelEvent, TunnelMetadata},
+    tunnel::{CloseHandle, StatsHandle, TunnelEvent, TunnelMetadata},
 };
 use futures01::{
     sync::{mpsc, oneshot},
     Async, Future, Stream,
 };
+use std::net::IpAddr;
 use talpid_types::{
     net::{Endpoint, TunnelParameters},
     tunnel::{ErrorStateCause, FirewallPolicyError},
@@ -26,6 +27,7 @@ pub struct ConnectedStateBootstrap {
     pub tunnel_parameters: TunnelParameters,
     pub tunnel_close_event: Option<oneshot::Receiver<Option<ErrorStateCause>>>,
     pub close_handle: Option<CloseHandle>,
+    pub stats_handle: Option<StatsHandle>,
 }
 
 /// The tunnel is up and working.
@@ -35,6 +37,7 @@ pub struct ConnectedState {
     tunnel_parameters: TunnelParameters,
     tunnel_close_event: Option<oneshot::Receiver<Option<ErrorStateCause>>>,
     close_handle: Option<CloseHandle>,
+    stats_handle: Option<StatsHandle>,
 }
 
 impl ConnectedState {
@@ -45,9 +48,15 @@ impl ConnectedState {
             tunnel_parameters: bootstrap.tunnel_parameters,
             tunnel_close_event: bootstrap.tunnel_close_event,
             close_handle: bootstrap.close_handle,
+            stats_handle: bootstrap.stats_handle,
         }
     }
 
+    #[cfg(not(target_os = "android"))]
+    pub(super) fn metadata(&self) -> &TunnelMetadata {
+        &self.metadata
+    }
+
     fn set_firewall_policy(
         &self,
         shared_values: &mut SharedTunnelStateValues,
@@ -57,8 +66,12 @@ impl ConnectedState {
 
         let policy = FirewallPolicy::Connected {
             peer_endpoint,
+            additional_peer_endpoints: self.tunnel_parameters.get_additional_peer_endpoints(),
             tunnel: self.metadata.clone(),
             allow_lan: shared_values.allow_lan,
+            dns_servers: self.get_dns_servers(shared_values),
+            forwarded_ports: shared_values.forwarded_ports.clone(),
+            exemptions: shared_values.firewall_exemptions.clone(),
             #[cfg(windows)]
             relay_client: TunnelMonitor::get_relay_client(
                 &shared_values.resource_dir,
@@ -95,11 +108,33 @@ impl ConnectedState {
         }
     }
 
-    fn set_dns(&self, shared_values: &mut SharedTunnelStateValues) -> Result<(), BoxedError> {
+    /// Returns the custom DNS servers if any are set, or the tunnel gateways otherwise. Custom
+    /// servers on the local network are only used if LAN access is allowed.
+    fn get_dns_servers(&self, shared_values: &SharedTunnelStateValues) -> Vec<IpAddr> {
+        if let Some(ref custom_dns) = shared_values.custom_dns {
+            let servers: Vec<IpAddr> = custom_dns
+                .iter()
+                .cloned()
+                .filter(|server| {
+                    shared_values.allow_lan
+                        || !crate::firewall::is_local_dns_server(*server, &self.metadata)
+                })
+                .collect();
+            if !servers.is_empty() {
+                return servers;
+            }
+            log::warn!("All custom DNS servers are on the blocked LAN, using the relay instead");
+        }
+
         let mut dns_ips = vec![self.metadata.ipv4_gateway.into()];
         if let Some(ipv6_gateway) = self.metadata.ipv6_gateway {
             dns_ips.push(ipv6_gateway.into());
         };
+        dns_ips
+    }
+
+    fn set_dns(&self, shared_values: &mut SharedTunnelStateValues) -> Result<(), BoxedError> {
+        let dns_ips = self.get_dns_servers(shared_values);
 
         shared_values
             .dns_monitor
@@ -107,14 +142,40 @@ impl ConnectedState {
             .map_err(BoxedError::new)?;
 
         #[cfg(target_os = "linux")]
-        shared_values
-            .route_manager
-            .route_exclusions_dns(&self.metadata.interface, &dns_ips)
-            .map_err(BoxedError::new)?;
+        {
+            // Only servers that are reached through the tunnel should be routed through it
+            let tunnel_dns_ips: Vec<IpAddr> = dns_ips
+                .into_iter()
+                .filter(|server| !crate::firewall::is_local_dns_server(*server, &self.metadata))
+                .collect();
+            shared_values
+                .route_manager
+                .route_exclusions_dns(&self.metadata.interface, &tunnel_dns_ips)
+                .map_err(BoxedError::new)?;
+        }
 
         Ok(())
     }
 
+    /// Applies the firewall policy and DNS settings again after the allowed DNS servers may have
+    /// changed.
+    fn update_dns(self, shared_values: &mut SharedTunnelStateValues) -> EventConsequence<Self> {
+        if let Err(error) = self.set_firewall_policy(shared_values) {
+            return self.disconnect(
+                shared_values,
+                AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+            );
+        }
+        if let Err(error) = self.set_dns(shared_values) {
+            log::error!("{}", error.display_chain_with_msg("Failed to set DNS"));
+            return self.disconnect(
+                shared_values,
+                AfterDisconnect::Block(ErrorStateCause::SetDnsError),
+            );
+        }
+        EventConsequence::SameState(self)
+    }
+
     fn reset_dns(shared_values: &mut SharedTunnelStateValues) {
         if let Err(error) = shared_values.dns_monitor.reset() {
             log::error!("{}", error.display_chain_with_msg("Unable to reset DNS"));
@@ -154,6 +215,9 @@ impl ConnectedState {
             Ok(TunnelCommand::AllowLan(allow_lan)) => {
                 if let Err(error_cause) = shared_values.set_allow_lan(allow_lan) {
                     self.disconnect(shared_values, AfterDisconnect::Block(error_cause))
+                } else if shared_values.custom_dns.is_some() {
+                    // Whether custom DNS servers on the LAN are used depends on LAN access
+                    self.update_dns(shared_values)
                 } else {
                     match self.set_firewall_policy(shared_values) {
                         Ok(()) => SameState(self),
@@ -168,6 +232,57 @@ impl ConnectedState {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self)
             }
+            Ok(TunnelCommand::CustomDns(servers)) => {
+                if shared_values.custom_dns != servers {
+                    shared_values.custom_dns = servers;
+                    self.update_dns(shared_values)
+                } else {
+                    SameState(self)
+                }
+            }
+            Ok(TunnelCommand::ForwardedPorts(ports)) => {
+                if shared_values.forwarded_ports != ports {
+                    shared_values.forwarded_ports = ports;
+                    match self.set_firewall_policy(shared_values) {
+                        Ok(()) => SameState(self),
+                        Err(error) => self.disconnect(
+                            shared_values,
+                            AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                        ),
+                    }
+                } else {
+                    SameState(self)
+                }
+            }
+            Ok(TunnelCommand::FirewallExemptions(exemptions)) => {
+                shared_values.firewall_exemptions = exemptions;
+                #[cfg(target_os = "linux")]
+                shared_values.set_bypass_routes();
+                match self.set_firewall_policy(shared_values) {
+                    Ok(()) => SameState(self),
+                    Err(error) => self.disconnect(
+                        shared_values,
+                        AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                    ),
+                }
+            }
+            #[cfg(target_os = "linux")]
+            Ok(TunnelCommand::SplitTunnelMode(mode)) => {
+                shared_values.firewall.set_split_tunnel_mode(mode);
+                shared_values.enable_exclusions_routes();
+                match self.set_firewall_policy(shared_values) {
+                    Ok(()) => SameState(self),
+                    Err(error) => self.disconnect(
+                        shared_values,
+                        AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                    ),
+                }
+            }
+            #[cfg(not(target_os = "android"))]
+            Ok(TunnelCommand::SocksProxy(listener)) => {
+                shared_values.socks_proxy.set_listener(listener);
+                SameState(self)
+            }
             Ok(TunnelCommand::IsOffline(is_offline)) => {
                 shared_values.is_offline = is_offline;
                 if is_offline {
@@ -188,6 +303,10 @@ impl ConnectedState {
             Ok(TunnelCommand::Block(reason)) => {
                 self.disconnect(shared_values, AfterDisconnect::Block(reason))
             }
+            Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                let _ = tx.send(self.stats_handle.as_ref().and_then(StatsHandle::get));
+                SameState(self)
+            }
         }
     }
 
@@ -7,7 +7,8 @@ use crate::{
     firewall::FirewallPolicy,
     routing::RouteManager,
     tunnel::{
-        self, tun_provider::TunProvider, CloseHandle, TunnelEvent, TunnelMetadata, TunnelMonitor,
+        self, tun_provider::TunProvider, CloseHandle, StatsHandle, TunnelEvent, TunnelMetadata,
+        TunnelMonitor,
     },
 };
 use futures01::{
@@ -40,6 +41,7 @@ pub struct ConnectingState {
     tunnel_parameters: TunnelParameters,
     tunnel_close_event: Option<oneshot::Receiver<Option<ErrorStateCause>>>,
     close_handle: Option<CloseHandle>,
+    stats_handle: Option<StatsHandle>,
     retry_attempt: u32,
 }
 
@@ -49,17 +51,25 @@ impl ConnectingState {
         params: &TunnelParameters,
     ) -> Result<(), FirewallPolicyError> {
         let proxy = &get_openvpn_proxy_settings(&params);
-        let endpoint = params.get_tunnel_endpoint().endpoint;
+        let tunnel_endpoint = params.get_tunnel_endpoint();
+        let endpoint = tunnel_endpoint
+            .entry_endpoint
+            .unwrap_or(tunnel_endpoint.endpoint);
 
         let peer_endpoint = match proxy {
             Some(proxy_settings) => proxy_settings.get_endpoint().endpoint,
-            None => endpoint,
+            None => tunnel_endpoint
+                .proxy
+                .map(|proxy| proxy.endpoint)
+                .unwrap_or(endpoint),
         };
 
         let policy = FirewallPolicy::Connecting {
             peer_endpoint,
+            additional_peer_endpoints: params.get_additional_peer_endpoints(),
             pingable_hosts: gateway_list_from_params(params),
             allow_lan: shared_values.allow_lan,
+            exemptions: shared_values.firewall_exemptions.clone(),
             #[cfg(windows)]
             relay_client: TunnelMonitor::get_relay_client(&shared_values.resource_dir, &params),
         };
@@ -103,6 +113,7 @@ impl ConnectingState {
             route_manager,
         )?;
         let close_handle = Some(monitor.close_handle());
+        let stats_handle = Some(monitor.stats_handle());
         let tunnel_close_event = Self::spawn_tunnel_monitor_wait_thread(monitor);
 
         Ok(ConnectingState {
@@ -110,6 +121,7 @@ impl ConnectingState {
             tunnel_parameters: parameters,
             tunnel_close_event,
             close_handle,
+            stats_handle,
             retry_attempt,
         })
     }
@@ -183,6 +195,7 @@ impl ConnectingState {
             tunnel_parameters: self.tunnel_parameters,
             tunnel_close_event: self.tunnel_close_event,
             close_handle: self.close_handle,
+            stats_handle: self.stats_handle,
         }
     }
 
@@ -232,6 +245,43 @@ impl ConnectingState {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self)
             }
+            Ok(TunnelCommand::CustomDns(servers)) => {
+                shared_values.custom_dns = servers;
+                SameState(self)
+            }
+            Ok(TunnelCommand::ForwardedPorts(ports)) => {
+                shared_values.forwarded_ports = ports;
+                SameState(self)
+            }
+            Ok(TunnelCommand::FirewallExemptions(exemptions)) => {
+                shared_values.firewall_exemptions = exemptions;
+                #[cfg(target_os = "linux")]
+                shared_values.set_bypass_routes();
+                match Self::set_firewall_policy(shared_values, &self.tunnel_parameters) {
+                    Ok(()) => SameState(self),
+                    Err(error) => self.disconnect(
+                        shared_values,
+                        AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                    ),
+                }
+            }
+            #[cfg(target_os = "linux")]
+            Ok(TunnelCommand::SplitTunnelMode(mode)) => {
+                shared_values.firewall.set_split_tunnel_mode(mode);
+                shared_values.enable_exclusions_routes();
+                match Self::set_firewall_policy(shared_values, &self.tunnel_parameters) {
+                    Ok(()) => SameState(self),
+                    Err(error) => self.disconnect(
+                        shared_values,
+                        AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                    ),
+                }
+            }
+            #[cfg(not(target_os = "android"))]
+            Ok(TunnelCommand::SocksProxy(listener)) => {
+                shared_values.socks_proxy.set_listener(listener);
+                SameState(self)
+            }
             Ok(TunnelCommand::IsOffline(is_offline)) => {
                 shared_values.is_offline = is_offline;
                 if is_offline {
@@ -252,6 +302,10 @@ impl ConnectingState {
             Ok(TunnelCommand::Block(reason)) => {
                 self.disconnect(shared_values, AfterDisconnect::Block(reason))
             }
+            Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                let _ = tx.send(None);
+                SameState(self)
+            }
         }
     }
 
@@ -364,12 +418,9 @@ impl TunnelState for ConnectingState {
                     )
                 } else {
                     #[cfg(target_os = "linux")]
-                    if let Err(error) = shared_values.route_manager.enable_exclusions_routes() {
-                        error!(
-                            "{}",
-                            error.display_chain_with_msg("Failed to set up split tunneling")
-                        );
-                    }
+                    shared_values.enable_exclusions_routes();
+                    #[cfg(target_os = "linux")]
+                    shared_values.set_bypass_routes();
 
                     #[cfg(target_os = "android")]
                     {
@@ -14,6 +14,7 @@ impl DisconnectedState {
         let result = if shared_values.block_when_disconnected {
             let policy = FirewallPolicy::Blocked {
                 allow_lan: shared_values.allow_lan,
+                exemptions: shared_values.firewall_exemptions.clone(),
             };
             shared_values.firewall.apply_policy(policy).map_err(|e| {
                 e.display_chain_with_msg(
@@ -83,12 +84,42 @@ impl TunnelState for DisconnectedState {
                 }
                 SameState(self)
             }
+            Ok(TunnelCommand::CustomDns(servers)) => {
+                shared_values.custom_dns = servers;
+                SameState(self)
+            }
+            Ok(TunnelCommand::ForwardedPorts(ports)) => {
+                shared_values.forwarded_ports = ports;
+                SameState(self)
+            }
+            Ok(TunnelCommand::FirewallExemptions(exemptions)) => {
+                if shared_values.firewall_exemptions != exemptions {
+                    shared_values.firewall_exemptions = exemptions;
+                    Self::set_firewall_policy(shared_values);
+                }
+                SameState(self)
+            }
+            #[cfg(target_os = "linux")]
+            Ok(TunnelCommand::SplitTunnelMode(mode)) => {
+                shared_values.firewall.set_split_tunnel_mode(mode);
+                Self::set_firewall_policy(shared_values);
+                SameState(self)
+            }
+            #[cfg(not(target_os = "android"))]
+            Ok(TunnelCommand::SocksProxy(listener)) => {
+                shared_values.socks_proxy.set_listener(listener);
+                SameState(self)
+            }
             Ok(TunnelCommand::IsOffline(is_offline)) => {
                 shared_values.is_offline = is_offline;
                 SameState(self)
             }
             Ok(TunnelCommand::Connect) => NewState(ConnectingState::enter(shared_values, 0)),
             Ok(TunnelCommand::Block(reason)) => NewState(ErrorState::enter(shared_values, reason)),
+            Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                let _ = tx.send(None);
+                SameState(self)
+            }
             Ok(_) => SameState(self),
             Err(_) => Finished,
         }
@@ -39,12 +39,38 @@ impl DisconnectingState {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Nothing
                 }
+                Ok(TunnelCommand::CustomDns(servers)) => {
+                    shared_values.custom_dns = servers;
+                    AfterDisconnect::Nothing
+                }
+                Ok(TunnelCommand::FirewallExemptions(exemptions)) => {
+                    shared_values.firewall_exemptions = exemptions;
+                    AfterDisconnect::Nothing
+                }
+                Ok(TunnelCommand::ForwardedPorts(ports)) => {
+                    shared_values.forwarded_ports = ports;
+                    AfterDisconnect::Nothing
+                }
+                #[cfg(target_os = "linux")]
+                Ok(TunnelCommand::SplitTunnelMode(mode)) => {
+                    shared_values.firewall.set_split_tunnel_mode(mode);
+                    AfterDisconnect::Nothing
+                }
+                #[cfg(not(target_os = "android"))]
+                Ok(TunnelCommand::SocksProxy(listener)) => {
+                    shared_values.socks_proxy.set_listener(listener);
+                    AfterDisconnect::Nothing
+                }
                 Ok(TunnelCommand::IsOffline(is_offline)) => {
                     shared_values.is_offline = is_offline;
                     AfterDisconnect::Nothing
                 }
                 Ok(TunnelCommand::Connect) => AfterDisconnect::Reconnect(0),
                 Ok(TunnelCommand::Block(reason)) => AfterDisconnect::Block(reason),
+                Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                    let _ = tx.send(None);
+                    AfterDisconnect::Nothing
+                }
                 _ => AfterDisconnect::Nothing,
             },
             AfterDisconnect::Block(reason) => match event {
@@ -56,6 +82,28 @@ impl DisconnectingState {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Block(reason)
                 }
+                Ok(TunnelCommand::CustomDns(servers)) => {
+                    shared_values.custom_dns = servers;
+                    AfterDisconnect::Block(reason)
+                }
+                Ok(TunnelCommand::FirewallExemptions(exemptions)) => {
+                    shared_values.firewall_exemptions = exemptions;
+                    AfterDisconnect::Block(reason)
+                }
+                Ok(TunnelCommand::ForwardedPorts(ports)) => {
+                    shared_values.forwarded_ports = ports;
+                    AfterDisconnect::Block(reason)
+                }
+                #[cfg(target_os = "linux")]
+                Ok(TunnelCommand::SplitTunnelMode(mode)) => {
+                    shared_values.firewall.set_split_tunnel_mode(mode);
+                    AfterDisconnect::Block(reason)
+                }
+                #[cfg(not(target_os = "android"))]
+                Ok(TunnelCommand::SocksProxy(listener)) => {
+                    shared_values.socks_proxy.set_listener(listener);
+                    AfterDisconnect::Block(reason)
+                }
                 Ok(TunnelCommand::IsOffline(is_offline)) => {
                     shared_values.is_offline = is_offline;
                     if !is_offline && reason == ErrorStateCause::IsOffline {
@@ -67,6 +115,10 @@ impl DisconnectingState {
                 Ok(TunnelCommand::Connect) => AfterDisconnect::Reconnect(0),
                 Ok(TunnelCommand::Disconnect) => AfterDisconnect::Nothing,
                 Ok(TunnelCommand::Block(new_reason)) => AfterDisconnect::Block(new_reason),
+                Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                    let _ = tx.send(None);
+                    AfterDisconnect::Block(reason)
+                }
                 Err(_) => AfterDisconnect::Block(reason),
             },
             AfterDisconnect::Reconnect(retry_attempt) => match event {
@@ -78,6 +130,28 @@ impl DisconnectingState {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
+                Ok(TunnelCommand::CustomDns(servers)) => {
+                    shared_values.custom_dns = servers;
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Ok(TunnelCommand::FirewallExemptions(exemptions)) => {
+                    shared_values.firewall_exemptions = exemptions;
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Ok(TunnelCommand::ForwardedPorts(ports)) => {
+                    shared_values.forwarded_ports = ports;
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                #[cfg(target_os = "linux")]
+                Ok(TunnelCommand::SplitTunnelMode(mode)) => {
+                    shared_values.firewall.set_split_tunnel_mode(mode);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                #[cfg(not(target_os = "android"))]
+                Ok(TunnelCommand::SocksProxy(listener)) => {
+                    shared_values.socks_proxy.set_listener(listener);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
                 Ok(TunnelCommand::IsOffline(is_offline)) => {
                     shared_values.is_offline = is_offline;
                     if is_offline {
@@ -89,6 +163,10 @@ impl DisconnectingState {
                 Ok(TunnelCommand::Connect) => AfterDisconnect::Reconnect(retry_attempt),
                 Ok(TunnelCommand::Disconnect) | Err(_) => AfterDisconnect::Nothing,
                 Ok(TunnelCommand::Block(reason)) => AfterDisconnect::Block(reason),
+                Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                    let _ = tx.send(None);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
             },
         };
 
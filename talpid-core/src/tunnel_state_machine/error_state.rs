@@ -21,6 +21,7 @@ impl ErrorState {
     ) -> Result<(), FirewallPolicyError> {
         let policy = FirewallPolicy::Blocked {
             allow_lan: shared_values.allow_lan,
+            exemptions: shared_values.firewall_exemptions.clone(),
         };
 
         shared_values
@@ -105,6 +106,30 @@ impl TunnelState for ErrorState {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self)
             }
+            Ok(TunnelCommand::CustomDns(servers)) => {
+                shared_values.custom_dns = servers;
+                SameState(self)
+            }
+            Ok(TunnelCommand::ForwardedPorts(ports)) => {
+                shared_values.forwarded_ports = ports;
+                SameState(self)
+            }
+            Ok(TunnelCommand::FirewallExemptions(exemptions)) => {
+                shared_values.firewall_exemptions = exemptions;
+                let _ = Self::set_firewall_policy(shared_values);
+                SameState(self)
+            }
+            #[cfg(target_os = "linux")]
+            Ok(TunnelCommand::SplitTunnelMode(mode)) => {
+                shared_values.firewall.set_split_tunnel_mode(mode);
+                let _ = Self::set_firewall_policy(shared_values);
+                SameState(self)
+            }
+            #[cfg(not(target_os = "android"))]
+            Ok(TunnelCommand::SocksProxy(listener)) => {
+                shared_values.socks_proxy.set_listener(listener);
+                SameState(self)
+            }
             Ok(TunnelCommand::IsOffline(is_offline)) => {
                 shared_values.is_offline = is_offline;
                 if !is_offline && self.block_reason == ErrorStateCause::IsOffline {
@@ -118,6 +143,10 @@ impl TunnelState for ErrorState {
                 NewState(DisconnectedState::enter(shared_values, ()))
             }
             Ok(TunnelCommand::Block(reason)) => NewState(ErrorState::enter(shared_values, reason)),
+            Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                let _ = tx.send(None);
+                SameState(self)
+            }
         }
     }
 }
@@ -20,7 +20,12 @@ use crate::{
     mpsc::Sender,
     offline,
     routing::RouteManager,
-    tunnel::tun_provider::TunProvider,
+    tunnel::{tun_provider::TunProvider, TunnelStats},
+};
+#[cfg(not(target_os = "android"))]
+use crate::{
+    socks_proxy::{self, SocksProxy},
+    tunnel::TunnelMetadata,
 };
 
 use futures01::{
@@ -30,14 +35,19 @@ use futures01::{
 use std::{
     collections::HashSet,
     io,
+    net::IpAddr,
     path::{Path, PathBuf},
     sync::{mpsc as sync_mpsc, Arc},
     thread,
 };
+#[cfg(not(target_os = "android"))]
+use std::net::TcpListener;
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
+#[cfg(target_os = "linux")]
+use talpid_types::net::{NetworkIdentity, SplitTunnelMode};
 use talpid_types::{
-    net::TunnelParameters,
+    net::{FirewallExemption, TunnelParameters},
     tunnel::{ErrorStateCause, ParameterGenerationError, TunnelStateTransition},
     ErrorExt,
 };
@@ -80,11 +90,16 @@ pub enum Error {
 pub fn spawn(
     allow_lan: bool,
     block_when_disconnected: bool,
+    custom_dns: Option<Vec<IpAddr>>,
+    firewall_exemptions: Vec<FirewallExemption>,
+    #[cfg(target_os = "linux")] split_tunnel_mode: SplitTunnelMode,
+    #[cfg(not(target_os = "android"))] socks_proxy: bool,
     tunnel_parameters_generator: impl TunnelParametersGenerator,
     log_dir: Option<PathBuf>,
     resource_dir: PathBuf,
     cache_dir: impl AsRef<Path> + Send + 'static,
     state_change_listener: impl Sender<TunnelStateTransition> + Send + 'static,
+    #[cfg(target_os = "linux")] identity_listener: impl Sender<NetworkIdentity> + Send + 'static,
     shutdown_tx: oneshot::Sender<()>,
     #[cfg(target_os = "android")] android_context: AndroidContext,
 ) -> Result<Arc<mpsc::UnboundedSender<TunnelCommand>>, Error> {
@@ -92,6 +107,8 @@ pub fn spawn(
     let command_tx = Arc::new(command_tx);
     let mut offline_monitor = offline::spawn_monitor(
         Arc::downgrade(&command_tx),
+        #[cfg(target_os = "linux")]
+        identity_listener,
         #[cfg(target_os = "android")]
         android_context.clone(),
     )
@@ -110,6 +127,12 @@ pub fn spawn(
         match create_event_loop(
             allow_lan,
             block_when_disconnected,
+            custom_dns,
+            firewall_exemptions,
+            #[cfg(target_os = "linux")]
+            split_tunnel_mode,
+            #[cfg(not(target_os = "android"))]
+            socks_proxy,
             is_offline,
             tunnel_parameters_generator,
             tun_provider,
@@ -150,6 +173,10 @@ pub fn spawn(
 fn create_event_loop(
     allow_lan: bool,
     block_when_disconnected: bool,
+    custom_dns: Option<Vec<IpAddr>>,
+    firewall_exemptions: Vec<FirewallExemption>,
+    #[cfg(target_os = "linux")] split_tunnel_mode: SplitTunnelMode,
+    #[cfg(not(target_os = "android"))] socks_proxy: bool,
     is_offline: bool,
     tunnel_parameters_generator: impl TunnelParametersGenerator,
     tun_provider: TunProvider,
@@ -164,6 +191,12 @@ fn create_event_loop(
     let state_machine = TunnelStateMachine::new(
         allow_lan,
         block_when_disconnected,
+        custom_dns,
+        firewall_exemptions,
+        #[cfg(target_os = "linux")]
+        split_tunnel_mode,
+        #[cfg(not(target_os = "android"))]
+        socks_proxy,
         is_offline,
         tunnel_parameters_generator,
         tun_provider,
@@ -195,6 +228,18 @@ pub enum TunnelCommand {
     AllowLan(bool),
     /// Enable or disable the block_when_disconnected feature.
     BlockWhenDisconnected(bool),
+    /// Set the DNS servers to use in the tunnel. `None` uses the DNS server of the relay.
+    CustomDns(Option<Vec<IpAddr>>),
+    /// Set the traffic that bypasses the tunnel and the firewall.
+    FirewallExemptions(Vec<FirewallExemption>),
+    /// Set the ports that may be connected to over the tunnel.
+    ForwardedPorts(Vec<u16>),
+    /// Set which traffic bypasses the tunnel when split tunneling.
+    #[cfg(target_os = "linux")]
+    SplitTunnelMode(SplitTunnelMode),
+    /// Start the local SOCKS5 proxy on the given listener, or stop it if there is none.
+    #[cfg(not(target_os = "android"))]
+    SocksProxy(Option<TcpListener>),
     /// Notify the state machine of the connectivity of the device.
     IsOffline(bool),
     /// Open tunnel connection.
@@ -203,6 +248,9 @@ pub enum TunnelCommand {
     Disconnect,
     /// Disconnect any open tunnel and block all network access
     Block(ErrorStateCause),
+    /// Read the traffic counters of the tunnel. Replies with `None` if the tunnel is not
+    /// connected or does not report traffic counters.
+    GetTunnelStats(oneshot::Sender<Option<TunnelStats>>),
 }
 
 /// Asynchronous handling of the tunnel state machine.
@@ -221,6 +269,10 @@ impl TunnelStateMachine {
     fn new(
         allow_lan: bool,
         block_when_disconnected: bool,
+        custom_dns: Option<Vec<IpAddr>>,
+        firewall_exemptions: Vec<FirewallExemption>,
+        #[cfg(target_os = "linux")] split_tunnel_mode: SplitTunnelMode,
+        #[cfg(not(target_os = "android"))] socks_proxy: bool,
         is_offline: bool,
         tunnel_parameters_generator: impl TunnelParametersGenerator,
         tun_provider: TunProvider,
@@ -233,11 +285,15 @@ impl TunnelStateMachine {
             FirewallArguments {
                 initialize_blocked: true,
                 allow_lan: Some(allow_lan),
+                #[cfg(target_os = "linux")]
+                split_tunnel_mode,
             }
         } else {
             FirewallArguments {
                 initialize_blocked: false,
                 allow_lan: None,
+                #[cfg(target_os = "linux")]
+                split_tunnel_mode,
             }
         };
 
@@ -245,12 +301,31 @@ impl TunnelStateMachine {
         let dns_monitor = DnsMonitor::new(cache_dir).map_err(Error::InitDnsMonitorError)?;
         let route_manager =
             RouteManager::new(HashSet::new()).map_err(Error::InitRouteManagerError)?;
+        #[cfg(not(target_os = "android"))]
+        let socks_proxy = {
+            let mut proxy = SocksProxy::default();
+            if socks_proxy {
+                match socks_proxy::bind() {
+                    Ok(listener) => proxy.set_listener(Some(listener)),
+                    Err(error) => log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to start SOCKS5 proxy")
+                    ),
+                }
+            }
+            proxy
+        };
         let mut shared_values = SharedTunnelStateValues {
             firewall,
             dns_monitor,
             route_manager,
             allow_lan,
             block_when_disconnected,
+            custom_dns,
+            firewall_exemptions,
+            forwarded_ports: Vec::new(),
+            #[cfg(not(target_os = "android"))]
+            socks_proxy,
             is_offline,
             tunnel_parameters_generator: Box::new(tunnel_parameters_generator),
             tun_provider,
@@ -278,6 +353,15 @@ impl Stream for TunnelStateMachine {
                     self.current_state = Some(repeat_state_wrapper);
                 }
                 TunnelStateMachineAction::Notify(state_wrapper, result) => {
+                    #[cfg(not(target_os = "android"))]
+                    {
+                        if let Ok(Async::Ready(Some(_))) = result {
+                            let tunnel = state_wrapper
+                                .as_ref()
+                                .and_then(TunnelStateWrapper::connected_tunnel);
+                            self.shared_values.socks_proxy.set_tunnel(tunnel.cloned());
+                        }
+                    }
                     self.current_state = state_wrapper;
                     return result;
                 }
@@ -337,6 +421,15 @@ struct SharedTunnelStateValues {
     allow_lan: bool,
     /// Should network access be allowed when in the disconnected state.
     block_when_disconnected: bool,
+    /// DNS servers to use instead of the DNS server of the relay.
+    custom_dns: Option<Vec<IpAddr>>,
+    /// Traffic that bypasses the tunnel and is allowed by the firewall in every state.
+    firewall_exemptions: Vec<FirewallExemption>,
+    /// Ports that may be connected to over the tunnel.
+    forwarded_ports: Vec<u16>,
+    /// Local SOCKS5 server that forwards connections through the tunnel.
+    #[cfg(not(target_os = "android"))]
+    socks_proxy: SocksProxy,
     /// True when the computer is known to be offline.
     is_offline: bool,
     /// The generator of new `TunnelParameter`s
@@ -371,6 +464,35 @@ impl SharedTunnelStateValues {
 
         Ok(())
     }
+
+    /// Routes the traffic that split tunneling marks outside the tunnel, according to the mode
+    /// that the firewall is configured with.
+    #[cfg(target_os = "linux")]
+    pub fn enable_exclusions_routes(&mut self) {
+        let mode = self.firewall.split_tunnel_mode();
+        if let Err(error) = self.route_manager.enable_exclusions_routes(mode) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to set up split tunneling")
+            );
+        }
+    }
+
+    /// Routes traffic to the exempted networks outside the tunnel.
+    #[cfg(target_os = "linux")]
+    pub fn set_bypass_routes(&mut self) {
+        let networks = self
+            .firewall_exemptions
+            .iter()
+            .map(|exemption| exemption.network)
+            .collect();
+        if let Err(error) = self.route_manager.set_bypass_routes(networks) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to route exempted traffic outside the tunnel")
+            );
+        }
+    }
 }
 
 /// Asynchronous result of an attempt to progress a state.
@@ -479,3 +601,14 @@ state_wrapper! {
         Error(ErrorState),
     }
 }
+
+impl TunnelStateWrapper {
+    /// Returns the tunnel that traffic can be sent through, if it is up.
+    #[cfg(not(target_os = "android"))]
+    fn connected_tunnel(&self) -> Option<&TunnelMetadata> {
+        match self {
+            TunnelStateWrapper::Connected(state) => Some(state.metadata()),
+            _ => None,
+        }
+    }
+}
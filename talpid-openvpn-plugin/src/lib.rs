@@ -32,11 +32,13 @@ pub enum Error {
 
 
 /// All the OpenVPN events this plugin will register for listening to. Edit this variable to change
-/// events.
+/// events. `Down` is not used for tracking the tunnel state, but is the event that carries the
+/// `bytes_sent` and `bytes_received` traffic counters.
 pub static INTERESTING_EVENTS: &'static [EventType] = &[
     EventType::AuthFailed,
     EventType::RouteUp,
     EventType::RoutePredown,
+    EventType::Down,
 ];
 
 openvpn_plugin!(
@@ -28,15 +28,27 @@ impl TunnelParameters {
                 tunnel_type: TunnelType::OpenVpn,
                 endpoint: params.config.endpoint,
                 proxy: params.proxy.as_ref().map(|proxy| proxy.get_endpoint()),
+                entry_endpoint: None,
             },
             TunnelParameters::Wireguard(params) => TunnelEndpoint {
                 tunnel_type: TunnelType::Wireguard,
-                endpoint: params.connection.get_endpoint(),
-                proxy: None,
+                endpoint: params.connection.get_exit_endpoint(),
+                proxy: params.connection.get_udp_over_tcp_endpoint(),
+                entry_endpoint: params.connection.get_entry_endpoint(),
             },
         }
     }
 
+    /// Returns the endpoints of any peers in addition to the one in the tunnel endpoint.
+    pub fn get_additional_peer_endpoints(&self) -> Vec<Endpoint> {
+        match self {
+            TunnelParameters::OpenVpn(_) => vec![],
+            TunnelParameters::Wireguard(params) => {
+                params.connection.get_additional_peer_endpoints()
+            }
+        }
+    }
+
     pub fn get_generic_options(&self) -> &GenericTunnelOptions {
         match &self {
             TunnelParameters::OpenVpn(params) => &params.generic_options,
@@ -89,11 +101,18 @@ pub struct TunnelEndpoint {
     pub tunnel_type: TunnelType,
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub proxy: Option<proxy::ProxyEndpoint>,
+    /// Entry relay that traffic passes through before reaching `endpoint`, when using multihop.
+    #[serde(default)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub entry_endpoint: Option<Endpoint>,
 }
 
 impl fmt::Display for TunnelEndpoint {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         write!(f, "{} - {}", self.tunnel_type, self.endpoint)?;
+        if let Some(ref entry_endpoint) = self.entry_endpoint {
+            write!(f, " via entry relay {}", entry_endpoint)?;
+        }
         if let Some(ref proxy) = self.proxy {
             write!(
                 f,
@@ -186,6 +205,155 @@ pub struct GenericTunnelOptions {
     pub enable_ipv6: bool,
 }
 
+/// Determines what happens to the traffic of processes that are split from the tunnel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitTunnelMode {
+    /// The split processes bypass the tunnel. All other traffic uses the tunnel.
+    Exclude,
+    /// Only the split processes use the tunnel. All other traffic bypasses it.
+    Include,
+}
+
+impl Default for SplitTunnelMode {
+    fn default() -> Self {
+        SplitTunnelMode::Exclude
+    }
+}
+
+impl fmt::Display for SplitTunnelMode {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SplitTunnelMode::Exclude => "exclude".fmt(fmt),
+            SplitTunnelMode::Include => "include".fmt(fmt),
+        }
+    }
+}
+
+/// Traffic that is allowed to bypass the tunnel and the firewall, regardless of the tunnel state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FirewallExemption {
+    /// Destination network of the traffic.
+    pub network: ipnetwork::IpNetwork,
+    /// Transport protocol of the traffic. `None` matches all protocols.
+    #[serde(default)]
+    pub protocol: Option<TransportProtocol>,
+    /// Destination ports of the traffic. Requires `protocol` to be set. `None` matches all ports.
+    #[serde(default)]
+    pub ports: Option<PortRange>,
+}
+
+impl fmt::Display for FirewallExemption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.network)?;
+        if let Some(protocol) = self.protocol {
+            write!(f, " over {}", protocol)?;
+        }
+        if let Some(ports) = self.ports {
+            write!(f, " port {}", ports)?;
+        }
+        Ok(())
+    }
+}
+
+/// An inclusive range of ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PortRange {
+    /// First port in the range.
+    pub start: u16,
+    /// Last port in the range.
+    pub end: u16,
+}
+
+impl PortRange {
+    /// Returns a range with a single port.
+    pub fn single(port: u16) -> Self {
+        PortRange {
+            start: port,
+            end: port,
+        }
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = PortRangeParseError;
+
+    fn from_str(s: &str) -> std::result::Result<PortRange, Self::Err> {
+        let mut ports = s.splitn(2, '-');
+        let start = ports
+            .next()
+            .and_then(|port| port.trim().parse().ok())
+            .ok_or(PortRangeParseError)?;
+        let end = match ports.next() {
+            Some(port) => port.trim().parse().map_err(|_| PortRangeParseError)?,
+            None => start,
+        };
+        if start > end {
+            return Err(PortRangeParseError);
+        }
+        Ok(PortRange { start, end })
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+/// Returned when `PortRange::from_str` fails to convert a string into a [`PortRange`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortRangeParseError;
+
+impl fmt::Display for PortRangeParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Not a valid port or port range, such as 443 or 8000-8080")
+    }
+}
+
+/// Properties that identify the network the host is connected to, as seen through the default
+/// route. Properties that cannot be determined are `None`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkIdentity {
+    /// Name of the interface used by the default route.
+    pub interface: Option<String>,
+    /// MAC address of the default gateway, as colon separated lowercase hex.
+    pub gateway_mac: Option<String>,
+    /// SSID of the Wi-Fi network, if the default route uses a wireless interface.
+    pub ssid: Option<String>,
+}
+
+impl NetworkIdentity {
+    /// Returns true if no property of the network is known, e.g. when offline.
+    pub fn is_unknown(&self) -> bool {
+        self.interface.is_none() && self.gateway_mac.is_none() && self.ssid.is_none()
+    }
+}
+
+impl fmt::Display for NetworkIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_unknown() {
+            return write!(f, "no network");
+        }
+        let mut properties = Vec::new();
+        if let Some(ref ssid) = self.ssid {
+            properties.push(format!("SSID \"{}\"", ssid));
+        }
+        if let Some(ref gateway_mac) = self.gateway_mac {
+            properties.push(format!("gateway {}", gateway_mac));
+        }
+        if let Some(ref interface) = self.interface {
+            properties.push(format!("interface {}", interface));
+        }
+        write!(f, "{}", properties.join(", "))
+    }
+}
+
 /// Returns a vector of IP networks representing all of the internet, 0.0.0.0/0.
 /// This may be used in [`crate::net::wireguard::PeerConfig`] to route all traffic
 /// to the tunnel interface.
@@ -195,3 +363,48 @@ pub fn all_of_the_internet() -> Vec<ipnetwork::IpNetwork> {
         "::0/0".parse().expect("Failed to parse ipv6 network"),
     ]
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_port_range() {
+        assert_eq!("443".parse(), Ok(PortRange::single(443)));
+        assert_eq!(
+            "8000-8080".parse(),
+            Ok(PortRange {
+                start: 8000,
+                end: 8080
+            })
+        );
+        assert_eq!(" 53 - 54 ".parse(), Ok(PortRange { start: 53, end: 54 }));
+        assert_eq!("80-80".parse(), Ok(PortRange::single(80)));
+    }
+
+    #[test]
+    fn test_parse_invalid_port_range() {
+        for input in &["", "-", "80-", "-80", "8080-8000", "65536", "1-2-3", "http"] {
+            assert_eq!(
+                input.parse::<PortRange>(),
+                Err(PortRangeParseError),
+                "{:?} should not parse",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_port_range_display_round_trip() {
+        let ports = [
+            PortRange::single(443),
+            PortRange {
+                start: 1,
+                end: 1024,
+            },
+        ];
+        for ports in &ports {
+            assert_eq!(ports.to_string().parse(), Ok(*ports));
+        }
+    }
+}
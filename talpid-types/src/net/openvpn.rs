@@ -21,6 +21,10 @@ pub struct ConnectionConfig {
     pub endpoint: Endpoint,
     pub username: String,
     pub password: String,
+    /// Server-specific settings of a custom OpenVPN server. If unset, the settings of the
+    /// Mullvad relays are used.
+    #[serde(default)]
+    pub custom_config: Option<CustomConfig>,
 }
 
 impl ConnectionConfig {
@@ -29,10 +33,62 @@ impl ConnectionConfig {
             endpoint,
             username,
             password,
+            custom_config: None,
         }
     }
 }
 
+/// Settings for connecting to an OpenVPN server that is not a Mullvad relay, usually read from
+/// an `.ovpn` file.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub struct CustomConfig {
+    /// PEM encoded CA certificates used to verify the server.
+    pub ca: String,
+    /// PEM encoded client certificate.
+    pub cert: Option<String>,
+    /// PEM encoded private key of the client certificate.
+    pub key: Option<String>,
+    /// Static key used to authenticate or encrypt the control channel.
+    pub tls_auth: Option<TlsAuth>,
+    /// Cipher used for the data channel if the server does not negotiate one. AES-256-CBC is
+    /// used if unset, like for Mullvad relays.
+    pub cipher: Option<String>,
+    /// Colon separated list of data channel ciphers that the server may negotiate, like
+    /// `--data-ciphers`. The OpenVPN default list is used if unset.
+    #[serde(default)]
+    pub data_ciphers: Option<String>,
+    /// Message digest used to authenticate data channel packets.
+    pub auth: Option<String>,
+    /// Whether the server authenticates the client with the username and password in
+    /// [`ConnectionConfig`].
+    pub auth_user_pass: bool,
+    /// Name that the server certificate must have, in addition to being signed by `ca`.
+    #[serde(default)]
+    pub verify_x509_name: Option<VerifyX509Name>,
+}
+
+/// Restricts which server certificates are accepted, like `--verify-x509-name`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub struct VerifyX509Name {
+    pub name: String,
+    /// What `name` is compared to. One of `subject`, `name` and `name-prefix`. The whole
+    /// subject is compared if unset.
+    pub name_type: Option<String>,
+}
+
+/// Static key for the OpenVPN control channel.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TlsAuth {
+    /// HMAC authentication of control channel packets, like `--tls-auth`.
+    Auth {
+        key: String,
+        key_direction: Option<u8>,
+    },
+    /// Encryption and authentication of control channel packets, like `--tls-crypt`.
+    Crypt { key: String },
+}
+
 /// `TunnelOptions` contains options for an OpenVPN tunnel that should be applied
 /// irrespective of the relay parameters - i.e. have nothing to do with the particular
 /// OpenVPN server, but do affect the connection.
@@ -129,31 +185,10 @@ impl ShadowsocksProxySettings {
     }
 }
 
-/// List of ciphers usable by a Shadowsocks proxy.
+/// List of ciphers usable by a Shadowsocks proxy. Only AEAD ciphers are supported, since the
+/// stream ciphers do not authenticate the traffic.
 /// Cf. [`ShadowsocksProxySettings::cipher`].
-pub static SHADOWSOCKS_CIPHERS: &[&str] = &[
-    // Stream ciphers.
-    "aes-128-cfb",
-    "aes-128-cfb1",
-    "aes-128-cfb8",
-    "aes-128-cfb128",
-    "aes-256-cfb",
-    "aes-256-cfb1",
-    "aes-256-cfb8",
-    "aes-256-cfb128",
-    "rc4",
-    "rc4-md5",
-    "chacha20",
-    "salsa20",
-    "chacha20-ietf",
-    // AEAD ciphers.
-    "aes-128-gcm",
-    "aes-256-gcm",
-    "chacha20-ietf-poly1305",
-    "xchacha20-ietf-poly1305",
-    "aes-128-pmac-siv",
-    "aes-256-pmac-siv",
-];
+pub static SHADOWSOCKS_CIPHERS: &[&str] = &["aes-128-gcm", "aes-256-gcm", "chacha20-ietf-poly1305"];
 
 /// Checks whether the proxy settings to be used by `OpenVpnMonitor` are valid.
 pub fn validate_proxy_settings(proxy: &ProxySettings) -> Result<(), String> {
@@ -1,4 +1,7 @@
-use crate::net::{Endpoint, GenericTunnelOptions, TransportProtocol};
+use crate::net::{
+    proxy::{ProxyEndpoint, ProxyType},
+    Endpoint, GenericTunnelOptions, TransportProtocol,
+};
 use ipnetwork::IpNetwork;
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
@@ -8,6 +11,7 @@ use std::{
     cmp, fmt,
     hash::{Hash, Hasher},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
 };
 
 
@@ -25,18 +29,71 @@ pub struct TunnelParameters {
 pub struct ConnectionConfig {
     pub tunnel: TunnelConfig,
     pub peer: PeerConfig,
+    /// Peers that are used in addition to `peer`. Every peer must have distinct `allowed_ips`.
+    #[serde(default)]
+    pub additional_peers: Vec<PeerConfig>,
     /// Gateway used by the tunnel (a private address).
     pub ipv4_gateway: Ipv4Addr,
     pub ipv6_gateway: Option<Ipv6Addr>,
+    /// Address that forwards traffic to the peer when connecting through an entry relay
+    /// (multihop). If unset, traffic is sent directly to `peer.endpoint`.
+    #[serde(default)]
+    pub entry_endpoint: Option<SocketAddr>,
+    /// TCP endpoint on the first hop relay that WireGuard traffic is tunneled through. If unset,
+    /// WireGuard traffic is sent over UDP as usual.
+    #[serde(default)]
+    pub udp_over_tcp_endpoint: Option<SocketAddr>,
 }
 
 impl ConnectionConfig {
+    /// Returns the endpoint that tunnel traffic is sent to.
     pub fn get_endpoint(&self) -> Endpoint {
+        match self.get_udp_over_tcp_endpoint() {
+            Some(proxy) => proxy.endpoint,
+            None => Endpoint {
+                address: self.entry_endpoint.unwrap_or(self.peer.endpoint),
+                protocol: TransportProtocol::Udp,
+            },
+        }
+    }
+
+    /// Returns the TCP endpoint that traffic is tunneled through, if UDP-over-TCP is used.
+    pub fn get_udp_over_tcp_endpoint(&self) -> Option<ProxyEndpoint> {
+        self.udp_over_tcp_endpoint.map(|address| ProxyEndpoint {
+            endpoint: Endpoint {
+                address,
+                protocol: TransportProtocol::Tcp,
+            },
+            proxy_type: ProxyType::UdpOverTcp,
+        })
+    }
+
+    /// Returns the endpoint of the peer, i.e. the exit relay when using multihop.
+    pub fn get_exit_endpoint(&self) -> Endpoint {
         Endpoint {
             address: self.peer.endpoint,
             protocol: TransportProtocol::Udp,
         }
     }
+
+    /// Returns the endpoints of the additional peers.
+    pub fn get_additional_peer_endpoints(&self) -> Vec<Endpoint> {
+        self.additional_peers
+            .iter()
+            .map(|peer| Endpoint {
+                address: peer.endpoint,
+                protocol: TransportProtocol::Udp,
+            })
+            .collect()
+    }
+
+    /// Returns the entry endpoint, if multihop is used.
+    pub fn get_entry_endpoint(&self) -> Option<Endpoint> {
+        self.entry_endpoint.map(|address| Endpoint {
+            address,
+            protocol: TransportProtocol::Udp,
+        })
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Deserialize, Serialize, Debug, Hash)]
@@ -47,6 +104,12 @@ pub struct PeerConfig {
     pub allowed_ips: Vec<IpNetwork>,
     /// IP address of the WireGuard server.
     pub endpoint: SocketAddr,
+    /// Symmetric key that is mixed into the handshake for additional security.
+    #[serde(default)]
+    pub preshared_key: Option<PresharedKey>,
+    /// Interval in seconds between keepalive packets sent to the peer. Disabled if unset.
+    #[serde(default)]
+    pub persistent_keepalive: Option<u16>,
 }
 
 #[derive(Clone, Eq, PartialEq, Deserialize, Serialize, Debug)]
@@ -121,6 +184,14 @@ impl fmt::Display for PrivateKey {
     }
 }
 
+impl FromStr for PrivateKey {
+    type Err = InvalidKey;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        decode_key(key).map(Self::from)
+    }
+}
+
 impl Serialize for PrivateKey {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -155,6 +226,61 @@ impl PublicKey {
 }
 
 
+/// Wireguard symmetric pre-shared key
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct PresharedKey([u8; 32]);
+
+impl PresharedKey {
+    /// Get the pre-shared key as bytes
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl From<[u8; 32]> for PresharedKey {
+    fn from(key: [u8; 32]) -> PresharedKey {
+        PresharedKey(key)
+    }
+}
+
+impl FromStr for PresharedKey {
+    type Err = InvalidKey;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        decode_key(key).map(Self::from)
+    }
+}
+
+impl Serialize for PresharedKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_key(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PresharedKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize_key(deserializer)
+    }
+}
+
+impl fmt::Debug for PresharedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", &self)
+    }
+}
+
+impl fmt::Display for PresharedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", &base64::encode(&self.0))
+    }
+}
+
 impl<'a> From<&'a x25519_dalek::StaticSecret> for PublicKey {
     fn from(private_key: &'a x25519_dalek::StaticSecret) -> PublicKey {
         PublicKey(x25519_dalek::PublicKey::from(private_key))
@@ -167,6 +293,14 @@ impl From<[u8; 32]> for PublicKey {
     }
 }
 
+impl FromStr for PublicKey {
+    type Err = InvalidKey;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        decode_key(key).map(Self::from)
+    }
+}
+
 impl Serialize for PublicKey {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -226,16 +360,26 @@ where
     use serde::de::Error;
 
     String::deserialize(deserializer)
-        .and_then(|string| base64::decode(&string).map_err(|err| Error::custom(err.to_string())))
-        .and_then(|buffer| {
-            let mut key = [0u8; 32];
-            if buffer.len() != 32 {
-                return Err(Error::custom(format!(
-                    "Key has unexpected length: {}",
-                    buffer.len()
-                )));
-            }
-            key.copy_from_slice(&buffer);
-            Ok(From::from(key))
-        })
+        .and_then(|string| decode_key(&string).map_err(|err| Error::custom(err.to_string())))
+        .map(From::from)
+}
+
+/// Error returned when a base64 encoded key cannot be parsed.
+#[derive(err_derive::Error, Debug)]
+pub enum InvalidKey {
+    #[error(display = "Key is not valid base64")]
+    Base64(#[error(source)] base64::DecodeError),
+
+    #[error(display = "Key has unexpected length: {}", _0)]
+    Length(usize),
+}
+
+fn decode_key(key: &str) -> Result<[u8; 32], InvalidKey> {
+    let buffer = base64::decode(key).map_err(InvalidKey::Base64)?;
+    if buffer.len() != 32 {
+        return Err(InvalidKey::Length(buffer.len()));
+    }
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&buffer);
+    Ok(key)
 }
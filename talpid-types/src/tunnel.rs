@@ -117,6 +117,11 @@ pub enum ParameterGenerationError {
     /// Failure to resolve the hostname of a custom tunnel configuration
     #[error(display = "Can't resolve hostname for custom tunnel host")]
     CustomTunnelHostResultionError,
+    /// The custom tunnel configuration has more than one WireGuard peer, which the firewall does
+    /// not allow on Windows
+    #[cfg(windows)]
+    #[error(display = "Custom WireGuard relays with more than one peer are not supported")]
+    CustomTunnelMultiplePeers,
 }
 
 /// Application that prevents setting the firewall policy.